@@ -0,0 +1,48 @@
+//! Benchmarks of the wire encoding stages and a full in-memory tool call.
+//! Run with `cargo bench --features testing`.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use toolapi::{
+    MessageFn, ToolError, Value,
+    testing::{bench, spawn_test_server},
+};
+
+fn echo(input: Value, _: &mut MessageFn) -> Result<Value, ToolError> {
+    Ok(input)
+}
+
+fn encoding(c: &mut Criterion) {
+    for (name, value) in bench::bench_payloads() {
+        let serialized = bench::serialize(&value).unwrap();
+        let compressed = bench::compress(&serialized);
+
+        let mut group = c.benchmark_group(name);
+        group.sample_size(10);
+        group.throughput(Throughput::Bytes(serialized.len() as u64));
+
+        group.bench_function("serialize", |b| {
+            b.iter(|| bench::serialize(black_box(&value)).unwrap())
+        });
+        group.bench_function("compress", |b| {
+            b.iter(|| bench::compress(black_box(&serialized)))
+        });
+        group.bench_function("decompress", |b| {
+            b.iter(|| bench::decompress(black_box(&compressed)).unwrap())
+        });
+        group.bench_function("deserialize", |b| {
+            b.iter(|| bench::deserialize(black_box(&serialized)).unwrap())
+        });
+        group.bench_function("call_memory", |b| {
+            b.iter(|| {
+                spawn_test_server(echo)
+                    .call(value.clone(), |_| true)
+                    .unwrap()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, encoding);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//! Files registered by tools with [`crate::ToolCtx::artifact`], served over
+//! HTTP at `/jobs/{id}/artifacts/{name}` until they expire.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{ArtifactConfig, ToolError, Value, value::dynamic::Dict};
+
+struct Artifact {
+    file: NamedTempFile,
+    size: usize,
+    expires: Instant,
+}
+
+/// Shared by all connections of a server.
+pub struct ArtifactStore {
+    config: ArtifactConfig,
+    entries: Mutex<HashMap<(String, String), Artifact>>,
+}
+
+impl ArtifactStore {
+    pub fn new(config: ArtifactConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Store `data` on disk, `write` is called with the temporary file.
+    /// Returns the reference to put in the result of the tool.
+    pub fn insert(
+        &self,
+        job_id: &str,
+        name: &str,
+        write: impl FnOnce(&mut NamedTempFile) -> std::io::Result<()>,
+    ) -> Result<Value, ToolError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            return Err(ToolError::internal(format!(
+                "invalid artifact name `{name}`, only use letters, digits, `.`, `_` and `-`"
+            )));
+        }
+        let io_err = |err: std::io::Error| {
+            ToolError::internal(format!("failed to store artifact `{name}`: {err}"))
+        };
+        // Written outside of the lock, artifacts are usually large
+        let mut file = tempfile::Builder::new()
+            .prefix(&format!("toolapi-artifact-{job_id}-"))
+            .tempfile()
+            .map_err(io_err)?;
+        write(&mut file).map_err(io_err)?;
+        file.flush().map_err(io_err)?;
+        let size = file.as_file().metadata().map_err(io_err)?.len() as usize;
+
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, artifact| artifact.expires > now);
+        let total: usize = entries.values().map(|artifact| artifact.size).sum();
+        if total + size > self.config.max_size {
+            return Err(ToolError::resource_exhausted(format!(
+                "artifact `{name}` ({size} bytes) exceeds the free artifact storage ({} bytes)",
+                self.config.max_size.saturating_sub(total)
+            )));
+        }
+        let artifact = Artifact {
+            file,
+            size,
+            expires: now + self.config.ttl,
+        };
+        entries.insert((job_id.to_string(), name.to_string()), artifact);
+
+        Ok(reference(job_id, name, size, self.config.ttl))
+    }
+
+    /// Memory map of the artifact, which stays valid after it expired
+    pub fn get(&self, job_id: &str, name: &str) -> Option<memmap2::Mmap> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, artifact| artifact.expires > now);
+
+        let artifact = entries.get(&(job_id.to_string(), name.to_string()))?;
+        // SAFETY: the file is private to the store and never written again
+        unsafe { memmap2::Mmap::map(artifact.file.as_file()) }.ok()
+    }
+}
+
+/// Entries of the reference returned to the tool, see [`crate::ToolCtx::artifact`]
+fn reference(job_id: &str, name: &str, size: usize, ttl: Duration) -> Value {
+    Value::Dict(Dict(
+        [
+            (
+                "path".to_string(),
+                Value::Str(format!("/jobs/{job_id}/artifacts/{name}")),
+            ),
+            ("size".to_string(), Value::Int(size as i64)),
+            ("ttl".to_string(), Value::Float(ttl.as_secs_f64())),
+        ]
+        .into(),
+    ))
+}
+
+/// Copy the file at `path` into an artifact
+pub(crate) fn copy_file(path: &Path) -> impl FnOnce(&mut NamedTempFile) -> std::io::Result<()> {
+    move |file| {
+        let mut source = std::fs::File::open(path)?;
+        std::io::copy(&mut source, file).map(|_| ())
+    }
+}
@@ -0,0 +1,71 @@
+//! Encryption of files written by the server, see [`crate::ServerConfig::storage_key`].
+//!
+//! Encrypted files start with [`MAGIC`], followed by the random nonce and the
+//! ChaCha20-Poly1305 ciphertext (with its tag) of the plain content.
+
+use std::io;
+
+use ring::{
+    aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::StorageKey;
+
+/// Also authenticated, so that the format can't be swapped
+const MAGIC: &[u8] = b"MRXE1";
+
+fn cipher(key: &StorageKey) -> LessSafeKey {
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key.0).expect("keys have 32 bytes");
+    LessSafeKey::new(key)
+}
+
+/// `data` as it is without a key
+pub(crate) fn seal(key: Option<&StorageKey>, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let Some(key) = key else {
+        return Ok(data);
+    };
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| io::Error::other("no random numbers for the nonce"))?;
+    let mut sealed = data;
+    cipher(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut sealed,
+        )
+        .map_err(|_| io::Error::other("encryption failed"))?;
+    Ok([MAGIC, &nonce, &sealed].concat())
+}
+
+/// Plain files are only returned without a key. With a key they are
+/// rejected, so that a forged plain file can't replace an encrypted one.
+pub(crate) fn open(key: Option<&StorageKey>, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let Some(sealed) = data.strip_prefix(MAGIC) else {
+        return match key {
+            None => Ok(data),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file is not encrypted, but a StorageKey was given",
+            )),
+        };
+    };
+    let Some(key) = key else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the file is encrypted, its StorageKey is needed",
+        ));
+    };
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "wrong key or corrupted file");
+    let (nonce, ciphertext) = sealed.split_at_checked(NONCE_LEN).ok_or_else(invalid)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid())?;
+    let mut plain = ciphertext.to_vec();
+    let len = cipher(key)
+        .open_in_place(nonce, Aad::from(MAGIC), &mut plain)
+        .map_err(|_| invalid())?
+        .len();
+    plain.truncate(len);
+    Ok(plain)
+}
@@ -0,0 +1,174 @@
+//! Records of every call for data provenance, see [`crate::ServerConfig::audit`].
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AuditConfig, JobMeta, ServerConfig, StorageKey, ToolError, Value, at_rest,
+    connection::websocket::{decode, encode},
+};
+
+/// What the server recorded about a single call, see [`crate::AuditConfig`].
+///
+/// Records are stored with the same encoding as the wire protocol (zstd
+/// compressed msgpack), like [`crate::testing::Recording`]s, and encrypted
+/// if the server has a [`crate::ServerConfig::storage_key`].
+///
+/// # Examples
+/// ```no_run
+/// use toolapi::AuditRecord;
+///
+/// let record = AuditRecord::load("/var/lib/tool/audit/0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24.audit")?;
+/// println!("{} processed input {}", record.job_id, record.input_hash);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub job_id: String,
+    /// See [`crate::ServerConfig::tool_name`], the route of a [`crate::Routes`] tool
+    pub tool: Option<String>,
+    /// See [`crate::ServerConfig::tool_version`]
+    pub tool_version: Option<String>,
+    /// When the input was received, in seconds since the Unix epoch
+    pub received: f64,
+    /// Hex encoded [`Value::canonical_hash`] of the input as received
+    pub input_hash: String,
+    /// Same for the result, `None` if the call failed
+    pub output_hash: Option<String>,
+    /// Why the call failed
+    pub error: Option<ToolError>,
+    /// Cost and seed of the call. The sizes are 0, they are only known once
+    /// the result was sent.
+    pub meta: JobMeta,
+    /// Only recorded with [`crate::AuditConfig::payloads`]
+    pub input: Option<Value>,
+    /// Only recorded with [`crate::AuditConfig::payloads`] if the call succeeded
+    pub output: Option<Value>,
+}
+
+impl AuditRecord {
+    /// Fails for encrypted records, see [`Self::load_with_key`]
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::read(path, None)
+    }
+
+    /// Load a record written with [`crate::ServerConfig::storage_key`]. Fails
+    /// for plain records, which are only read by [`Self::load`].
+    pub fn load_with_key(path: impl AsRef<Path>, key: &StorageKey) -> std::io::Result<Self> {
+        Self::read(path, Some(key))
+    }
+
+    fn read(path: impl AsRef<Path>, key: Option<&StorageKey>) -> std::io::Result<Self> {
+        let raw = at_rest::open(key, std::fs::read(path)?)?;
+        decode(&raw).map_err(std::io::Error::other)
+    }
+}
+
+/// Record of a running call, written by [`Self::finish`].
+pub(crate) struct AuditEntry {
+    config: AuditConfig,
+    key: Option<StorageKey>,
+    tool: Option<String>,
+    received: f64,
+    input_hash: String,
+    input: Option<Value>,
+}
+
+impl AuditEntry {
+    /// Called with the input as received and its hex encoded hash, before
+    /// anything changes it
+    pub(crate) fn start(
+        audit: &AuditConfig,
+        config: &ServerConfig,
+        input: &Value,
+        input_hash: String,
+    ) -> Self {
+        Self {
+            config: audit.clone(),
+            key: config.storage_key.clone(),
+            tool: config.tool_name.clone(),
+            received: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            input_hash,
+            input: audit.payloads.then(|| input.clone()),
+        }
+    }
+
+    /// Write the record in the background, failures are only logged.
+    /// `output_hash` is the hex encoded hash of a successful result.
+    pub(crate) fn finish(
+        self,
+        result: &Result<Value, ToolError>,
+        output_hash: Option<String>,
+        meta: &JobMeta,
+    ) {
+        let record = AuditRecord {
+            job_id: meta.job_id.clone(),
+            tool: self.tool,
+            tool_version: meta.tool_version.clone(),
+            received: self.received,
+            input_hash: self.input_hash,
+            output_hash,
+            error: result.as_ref().err().cloned(),
+            meta: meta.clone(),
+            input: self.input,
+            output: result
+                .as_ref()
+                .ok()
+                .filter(|_| self.config.payloads)
+                .cloned(),
+        };
+        let (config, key) = (self.config, self.key);
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = write(&config, key.as_ref(), &record) {
+                println!("AUDIT {} failed to write the record: {err}", record.job_id);
+            }
+        });
+    }
+}
+
+fn write(
+    config: &AuditConfig,
+    key: Option<&StorageKey>,
+    record: &AuditRecord,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(&config.dir)?;
+    let raw = at_rest::seal(key, encode(record).map_err(std::io::Error::other)?)?;
+    // Renamed once complete, so that readers never see a partial record
+    let path = config.dir.join(format!("{}.audit", record.job_id));
+    let partial = path.with_extension("audit.partial");
+    std::fs::write(&partial, raw)?;
+    std::fs::rename(&partial, &path)?;
+    rotate(&config.dir, config.max_size)
+}
+
+/// Delete the oldest records until all of them fit into `max_size`
+fn rotate(dir: &Path, max_size: u64) -> std::io::Result<()> {
+    let mut records = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|ext| ext == "audit") {
+            let metadata = entry.metadata()?;
+            records.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+    let mut total: u64 = records.iter().map(|(_, size, _)| size).sum();
+    records.sort();
+    for (_, size, path) in records {
+        if total <= max_size {
+            break;
+        }
+        // Another call might be rotating at the same time
+        if std::fs::remove_file(&path).is_ok() {
+            println!("AUDIT deleted {}", path.display());
+        }
+        total -= size;
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Command line client for tools, to replace ad-hoc Python scripts during development.
+//!
+//! ```text
+//! toolapi-cli call wss://tool-xxx-flyio.fly.dev/tool --input params.json --out result.msgpack
+//! toolapi-cli serve --exec ./my_tool -- --some-arg
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::OnceLock,
+};
+
+use clap::{Parser, Subcommand};
+use toolapi::{
+    MessageFn, ToolError, Value,
+    value::dynamic::{Dict, List},
+};
+
+#[derive(Parser)]
+#[command(version, about = "MRX ToolAPI command line interface")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Call a remote tool, printing its messages live. Ctrl-C aborts the tool.
+    Call {
+        /// WebSocket url of the tool, e.g. wss://tool-xxx-flyio.fly.dev/tool,
+        /// or its name in ~/.config/toolapi/tools.toml
+        addr: String,
+        /// Tool input: plain JSON (.json) or a msgpack encoded Value (.msgpack)
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        /// Write the msgpack encoded result to this file instead of printing it
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Host an executable speaking the stdio protocol (see `toolapi::stdio`)
+    /// behind the standard WebSocket server. It is started once per call.
+    Serve {
+        /// Path of the tool executable
+        #[arg(long)]
+        exec: PathBuf,
+        /// Arguments passed to the tool executable
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+}
+
+/// The [`toolapi::ToolFn`] is a plain function, so the command is global
+static EXEC: OnceLock<(PathBuf, Vec<String>)> = OnceLock::new();
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Call { addr, input, out } => call(&addr, input.as_deref(), out.as_deref()),
+        Command::Serve { exec, args } => serve(exec, args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn call(addr: &str, input: Option<&Path>, out: Option<&Path>) -> Result<(), String> {
+    let input = match input {
+        Some(path) => read_input(path)?,
+        None => Value::None(()),
+    };
+
+    let addr = match addr.contains("://") {
+        true => addr.to_string(),
+        false => toolapi::tool_url(Some(addr)).map_err(|err| err.to_string())?,
+    };
+    let result = toolapi::call_with_ctrlc(&addr, input, |msg| {
+        eprintln!(" > {msg}");
+        true
+    })
+    .map_err(|err| err.to_string())?;
+
+    match out {
+        Some(path) => {
+            let raw = rmp_serde::to_vec(&result).map_err(|err| err.to_string())?;
+            std::fs::write(path, raw).map_err(|err| format!("{}: {err}", path.display()))
+        }
+        None => {
+            println!("{result:?}");
+            Ok(())
+        }
+    }
+}
+
+fn serve(exec: PathBuf, args: Vec<String>) -> Result<(), String> {
+    EXEC.set((exec, args)).expect("serve is only called once");
+    toolapi::run_server(exec_tool, None).map_err(|err| err.to_string())
+}
+
+fn exec_tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+    let (exec, args) = EXEC.get().expect("set before the server is started");
+    let mut command = std::process::Command::new(exec);
+    command.args(args);
+    toolapi::stdio::exec(command, input, send_msg)
+}
+
+fn read_input(path: &Path) -> Result<Value, String> {
+    let raw = std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_slice(&raw)
+            .map(from_json)
+            .map_err(|err| err.to_string()),
+        Some("msgpack") => rmp_serde::from_slice(&raw).map_err(|err| err.to_string()),
+        _ => Err("unknown file type, expected .json or .msgpack".to_string()),
+    };
+    value.map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Plain JSON can only express dynamic types, structured types need msgpack.
+fn from_json(json: serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+    match json {
+        Json::Null => Value::None(()),
+        Json::Bool(x) => Value::Bool(x),
+        Json::Number(x) => match x.as_i64() {
+            Some(x) => Value::Int(x),
+            None => Value::Float(x.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(x) => Value::Str(x),
+        Json::Array(x) => Value::List(List(x.into_iter().map(from_json).collect())),
+        Json::Object(x) => Value::Dict(Dict(
+            x.into_iter().map(|(key, x)| (key, from_json(x))).collect(),
+        )),
+    }
+}
@@ -0,0 +1,5 @@
+//! Prints the TypeScript definitions of the wire protocol to stdout.
+
+fn main() {
+    print!("{}", toolapi::TYPESCRIPT_DEFINITIONS);
+}
@@ -0,0 +1,23 @@
+//! Helpers for browser frontends to prepare tool inputs fully client-side.
+//!
+//! Files selected by the user (e.g. a NIfTI volume or a Pulseq `.seq` file)
+//! are sent to the tool as raw [`Value::Bytes`], parsing them into structured
+//! types is the job of the tool that consumes them.
+
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::Value;
+
+/// Copy the contents of an `ArrayBuffer` into a [`Value::Bytes`].
+pub fn value_from_array_buffer(buffer: &ArrayBuffer) -> Value {
+    Value::Bytes(Uint8Array::new(buffer).to_vec())
+}
+
+/// Read a user-selected file (e.g. from an `<input type="file">`) into a
+/// [`Value::Bytes`]. Resolves when the browser has loaded the whole file.
+pub async fn value_from_file(file: &web_sys::File) -> Result<Value, JsValue> {
+    let buffer = JsFuture::from(file.array_buffer()).await?;
+    Ok(value_from_array_buffer(&buffer.into()))
+}
@@ -0,0 +1,428 @@
+//! Enforcement of [`ServerConfig::max_job_memory`](crate::ServerConfig::max_job_memory),
+//! [`ServerConfig::max_cpu_time`](crate::ServerConfig::max_cpu_time) and
+//! [`ServerConfig::timeout`](crate::ServerConfig::timeout).
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, AtomicIsize, AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{TIMEOUT_KEY, ToolError, Value};
+
+/// How often the budgets are checked while a tool runs
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Global allocator that counts the memory each tool allocates, so that
+/// [`ServerConfig::max_job_memory`](crate::ServerConfig::max_job_memory) limits
+/// every call on its own. Allocations are passed on to [`System`].
+///
+/// Only allocations of the thread running the tool are counted, not those of
+/// threads it spawns. They count until they are freed, on whichever thread:
+/// every allocation carries a tag of 8 bytes (more if it is aligned to more)
+/// naming the call that made it. Freeing memory that the tool didn't allocate,
+/// like its input, doesn't make room for more. The messages of resumable
+/// calls count until the client acknowledged them, the server keeps them
+/// until then (see [`crate::JobStoreConfig`]). Without this allocator the
+/// limit applies to the growth of the whole server, see `max_job_memory`.
+///
+/// # Example
+/// ```no_run
+/// #[global_allocator]
+/// static ALLOCATOR: toolapi::JobAllocator = toolapi::JobAllocator;
+/// ```
+pub struct JobAllocator;
+
+/// Set by the first allocation through [`JobAllocator`]
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Calls whose memory is counted at the same time, further ones aren't limited
+const SLOTS: usize = 1024;
+
+/// Allocated bytes of the calls that run, see [`Slot`]
+static COUNTERS: [Slot; SLOTS] = [const { Slot::new() }; SLOTS];
+
+/// Counter of one call at a time. Memory freed after the call ended (when
+/// the generation changed) isn't counted, neither for the next call.
+struct Slot {
+    in_use: AtomicBool,
+    generation: AtomicU32,
+    bytes: AtomicIsize,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            in_use: AtomicBool::new(false),
+            generation: AtomicU32::new(0),
+            bytes: AtomicIsize::new(0),
+        }
+    }
+}
+
+/// Stored in front of every allocation: the index of its [`Slot`] plus one
+/// in the upper and the generation in the lower half, 0 if it isn't counted
+type Tag = u64;
+
+thread_local! {
+    /// Tag of the call whose tool runs on this thread, see [`Counting`]
+    static CURRENT: Cell<Tag> = const { Cell::new(0) };
+}
+
+impl JobAllocator {
+    /// If this is the global allocator of the program
+    pub(crate) fn installed() -> bool {
+        INSTALLED.load(Ordering::Relaxed)
+    }
+
+    fn install() {
+        if !INSTALLED.load(Ordering::Relaxed) {
+            INSTALLED.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Layout with room for the tag in front, and the offset of the memory
+    /// handed out (the tag is right before it)
+    fn tagged(layout: Layout) -> Option<(Layout, usize)> {
+        let offset = layout.align().max(size_of::<Tag>());
+        let size = layout.size().checked_add(offset)?;
+        Some((Layout::from_size_align(size, offset).ok()?, offset))
+    }
+
+    /// Tag the memory at `ptr` with the call of this thread and count it
+    ///
+    /// # Safety
+    /// `ptr` is aligned to at least a [`Tag`] and has room for one in front
+    unsafe fn own(ptr: *mut u8, size: usize) {
+        // Fails while the thread is torn down, its allocations aren't a tool's
+        let tag = CURRENT.try_with(Cell::get).unwrap_or(0);
+        // SAFETY: guaranteed by the caller
+        unsafe { ptr.cast::<Tag>().sub(1).write(tag) };
+        Self::count(tag, size as isize);
+    }
+
+    /// Stop counting the memory at `ptr` for the call that allocated it
+    ///
+    /// # Safety
+    /// `ptr` was tagged with [`Self::own`]
+    unsafe fn disown(ptr: *mut u8, size: usize) {
+        // SAFETY: guaranteed by the caller
+        let tag = unsafe { ptr.cast::<Tag>().sub(1).read() };
+        Self::count(tag, -(size as isize));
+    }
+
+    fn count(tag: Tag, bytes: isize) {
+        let Some(index) = ((tag >> 32) as usize).checked_sub(1) else {
+            return;
+        };
+        let slot = &COUNTERS[index];
+        if slot.generation.load(Ordering::Acquire) == tag as u32 {
+            slot.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+// SAFETY: all allocations are done by `System`, with room for a tag in front
+unsafe impl GlobalAlloc for JobAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::install();
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            return std::ptr::null_mut();
+        };
+        // SAFETY: `tagged` isn't smaller than `layout`
+        let base = unsafe { System.alloc(tagged) };
+        if base.is_null() {
+            return base;
+        }
+        // SAFETY: `offset` is within the allocation and aligned like `layout`
+        let ptr = unsafe { base.add(offset) };
+        // SAFETY: the tag fits in the offset and is aligned
+        unsafe { Self::own(ptr, layout.size()) };
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::install();
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            return std::ptr::null_mut();
+        };
+        // SAFETY: `tagged` isn't smaller than `layout`
+        let base = unsafe { System.alloc_zeroed(tagged) };
+        if base.is_null() {
+            return base;
+        }
+        // SAFETY: as in `alloc`
+        let ptr = unsafe { base.add(offset) };
+        // SAFETY: as in `alloc`
+        unsafe { Self::own(ptr, layout.size()) };
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // The layout was tagged the same way when `ptr` was allocated
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            unreachable!("allocated with this layout");
+        };
+        // SAFETY: `ptr` was allocated (and tagged) by `alloc` or `realloc`
+        unsafe {
+            Self::disown(ptr, layout.size());
+            System.dealloc(ptr.sub(offset), tagged);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::install();
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            unreachable!("allocated with this layout");
+        };
+        let Some(new_tagged_size) = new_size.checked_add(offset) else {
+            return std::ptr::null_mut();
+        };
+        // SAFETY: `ptr` was allocated by `alloc`, the tag moves along
+        let base = unsafe { System.realloc(ptr.sub(offset), tagged, new_tagged_size) };
+        if base.is_null() {
+            return base;
+        }
+        // SAFETY: as in `alloc`. The memory now belongs to the call of this
+        // thread, like a new allocation that the old one was copied to.
+        unsafe {
+            let new_ptr = base.add(offset);
+            Self::disown(new_ptr, layout.size());
+            Self::own(new_ptr, new_size);
+            new_ptr
+        }
+    }
+}
+
+/// Claim of a [`Slot`] for the allocations of one call, released when dropped
+struct SlotClaim {
+    index: usize,
+    tag: Tag,
+}
+
+impl SlotClaim {
+    /// `None` if all slots are in use
+    fn new() -> Option<Self> {
+        let index = COUNTERS.iter().position(|slot| {
+            slot.in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })?;
+        let slot = &COUNTERS[index];
+        slot.bytes.store(0, Ordering::Relaxed);
+        let generation = slot.generation.load(Ordering::Acquire);
+        let tag = ((index as u64 + 1) << 32) | generation as u64;
+        Some(Self { index, tag })
+    }
+
+    fn bytes(&self) -> isize {
+        COUNTERS[self.index].bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SlotClaim {
+    fn drop(&mut self) {
+        let slot = &COUNTERS[self.index];
+        // Memory of this call that is freed later isn't counted anymore
+        slot.generation.fetch_add(1, Ordering::AcqRel);
+        slot.in_use.store(false, Ordering::Release);
+    }
+}
+
+/// Memory used by one call, checked by the server while the tool runs
+#[derive(Clone)]
+pub(crate) struct MemoryBudget {
+    usage: MemoryUsage,
+    max: usize,
+}
+
+#[derive(Clone)]
+enum MemoryUsage {
+    /// Bytes allocated by the tool thread and not freed yet, counted by [`JobAllocator`]
+    Allocated(Arc<SlotClaim>),
+    /// Resident memory of the server when the call started, only meaningful
+    /// if no other call runs at the same time
+    Resident(usize),
+}
+
+impl MemoryBudget {
+    /// `None` if the memory of a call can't be measured: [`JobAllocator`]
+    /// isn't installed and the resident memory can't be read on this platform,
+    /// or too many calls are counted at the same time
+    pub(crate) fn new(max: usize) -> Option<Self> {
+        let usage = match JobAllocator::installed() {
+            true => MemoryUsage::Allocated(Arc::new(SlotClaim::new()?)),
+            false => MemoryUsage::Resident(resident_memory()?),
+        };
+        Some(Self { usage, max })
+    }
+
+    /// Called on the tool thread right before the tool runs, its allocations
+    /// are counted until the returned guard is dropped
+    pub(crate) fn start(&self) -> Counting {
+        let claim = match &self.usage {
+            MemoryUsage::Allocated(claim) => {
+                CURRENT.with(|current| current.set(claim.tag));
+                Some(claim.clone())
+            }
+            MemoryUsage::Resident(_) => None,
+        };
+        Counting(claim)
+    }
+
+    /// Message for the client if the budget is exceeded
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let grown = match &self.usage {
+            // Only negative while frees race with the allocations they free
+            MemoryUsage::Allocated(claim) => usize::try_from(claim.bytes()).unwrap_or(0),
+            MemoryUsage::Resident(baseline) => resident_memory()
+                .unwrap_or(*baseline)
+                .saturating_sub(*baseline),
+        };
+        match grown > self.max {
+            true => Err(format!(
+                "the tool used {grown} bytes of memory, the server allows at most {} bytes",
+                self.max
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Returned by [`MemoryBudget::start`], stops counting the allocations of the
+/// tool thread when dropped (the blocking thread is reused by other calls)
+pub(crate) struct Counting(Option<Arc<SlotClaim>>);
+
+impl Drop for Counting {
+    fn drop(&mut self) {
+        if self.0.is_some() {
+            CURRENT.with(|current| current.set(0));
+        }
+    }
+}
+
+/// Timeout of a call: the one of the server, shortened by the client with the
+/// [`TIMEOUT_KEY`] entry of the input, which is removed.
+pub(crate) fn take_timeout(
+    input: &mut Value,
+    max: Option<Duration>,
+) -> Result<Option<Duration>, ToolError> {
+    let Value::Dict(dict) = input else {
+        return Ok(max);
+    };
+    let requested = match dict.0.remove(TIMEOUT_KEY) {
+        None => return Ok(max),
+        Some(Value::Float(secs)) => Duration::try_from_secs_f64(secs).ok(),
+        Some(Value::Int(secs)) => u64::try_from(secs).ok().map(Duration::from_secs),
+        Some(_) => None,
+    };
+    match requested {
+        Some(requested) if !requested.is_zero() => {
+            Ok(Some(max.map_or(requested, |max| max.min(requested))))
+        }
+        _ => Err(ToolError::invalid_input(
+            TIMEOUT_KEY,
+            "must be a positive number of seconds",
+        )),
+    }
+}
+
+/// CPU time of the thread running the tool, read by the server while it runs
+#[derive(Clone)]
+pub(crate) struct CpuBudget {
+    max: Duration,
+    /// Clock of the tool thread and its time when the tool started
+    start: Arc<OnceLock<(ThreadClock, Duration)>>,
+}
+
+impl CpuBudget {
+    pub(crate) fn new(max: Duration) -> Self {
+        Self {
+            max,
+            start: Arc::default(),
+        }
+    }
+
+    /// Called on the tool thread right before the tool runs. Returns `false`
+    /// if the CPU time of threads can't be read on this platform.
+    pub(crate) fn start(&self) -> bool {
+        let Some(clock) = ThreadClock::current() else {
+            return false;
+        };
+        let Some(time) = clock.time() else {
+            return false;
+        };
+        let _ = self.start.set((clock, time));
+        true
+    }
+
+    /// Message for the client if the budget is exceeded
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let Some((clock, start)) = self.start.get() else {
+            return Ok(());
+        };
+        let used = clock.time().unwrap_or(*start).saturating_sub(*start);
+        match used > self.max {
+            true => Err(format!(
+                "the tool used {:.1} s of CPU time, the server allows at most {:.1} s",
+                used.as_secs_f64(),
+                self.max.as_secs_f64()
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// CPU clock of a thread, which other threads can read
+struct ThreadClock(#[cfg(target_os = "linux")] libc::clockid_t);
+
+impl ThreadClock {
+    fn current() -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let mut clock = 0;
+            // SAFETY: the current thread is a valid thread, the pointer is valid for writes
+            let ret = unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock) };
+            if ret == 0 {
+                return Some(Self(clock));
+            }
+        }
+        None
+    }
+
+    /// CPU time of the thread since it was created
+    fn time(&self) -> Option<Duration> {
+        #[cfg(target_os = "linux")]
+        {
+            let mut time = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            // SAFETY: the pointer is valid for writes for the duration of the call
+            let ret = unsafe { libc::clock_gettime(self.0, &mut time) };
+            if ret == 0 {
+                return Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32));
+            }
+        }
+        None
+    }
+}
+
+/// Resident set size of this process in bytes
+fn resident_memory() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        // Sizes in pages: total program size, resident set size, ...
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+        // SAFETY: sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        return Some(pages * usize::try_from(page_size).ok()?);
+    }
+    #[allow(unreachable_code)]
+    None
+}
@@ -0,0 +1,129 @@
+//! Server side cache of large input values, see [`crate::CacheConfig`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{CacheConfig, Value, connection::websocket::BlobHash};
+
+struct Entry {
+    value: Arc<Value>,
+    size: usize,
+    last_used: Instant,
+}
+
+/// Encoding of a value that is still being uploaded
+struct Upload {
+    data: Vec<u8>,
+    size: usize,
+    last_used: Instant,
+}
+
+/// Shared by all connections of a server.
+pub struct BlobCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<BlobHash, Entry>>,
+    /// Kept after the connection dropped, so that the client can resume
+    uploads: Mutex<HashMap<BlobHash, Upload>>,
+}
+
+impl BlobCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Number of bytes of `hash` that were already uploaded
+    pub fn received(&self, hash: &BlobHash) -> usize {
+        let mut uploads = self.uploads.lock().unwrap();
+        let now = Instant::now();
+        uploads.retain(|_, upload| now - upload.last_used < self.config.ttl);
+        uploads.get(hash).map_or(0, |upload| upload.data.len())
+    }
+
+    /// Append a chunk of the encoding of `hash`, which must continue where the
+    /// last one ended. Returns the whole encoding once it is complete.
+    /// Interrupted uploads are evicted like values, oldest first.
+    pub fn append(
+        &self,
+        hash: BlobHash,
+        offset: usize,
+        size: usize,
+        chunk: &[u8],
+    ) -> Result<Option<Vec<u8>>, String> {
+        let mut uploads = self.uploads.lock().unwrap();
+        if offset == 0 && !uploads.contains_key(&hash) {
+            let mut reserved: usize = uploads.values().map(|upload| upload.size).sum();
+            while reserved + size > self.config.max_size {
+                let Some((&oldest, _)) = uploads.iter().min_by_key(|(_, upload)| upload.last_used)
+                else {
+                    break;
+                };
+                reserved -= uploads.remove(&oldest).map_or(0, |upload| upload.size);
+            }
+            let upload = Upload {
+                data: Vec::with_capacity(size),
+                size,
+                last_used: Instant::now(),
+            };
+            uploads.insert(hash, upload);
+        }
+
+        let received = uploads.get(&hash).map_or(0, |upload| upload.data.len());
+        let upload = match uploads.get_mut(&hash) {
+            Some(upload) if offset == received && upload.size == size => upload,
+            _ => return Err(format!("blob chunk at {offset}, expected {received}")),
+        };
+        if offset + chunk.len() > size {
+            return Err(format!("blob chunk exceeds the size of {size} bytes"));
+        }
+        upload.data.extend_from_slice(chunk);
+        upload.last_used = Instant::now();
+        if upload.data.len() < size {
+            return Ok(None);
+        }
+        Ok(uploads.remove(&hash).map(|upload| upload.data))
+    }
+
+    pub fn get(&self, hash: &BlobHash) -> Option<Arc<Value>> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| now - entry.last_used < self.config.ttl);
+
+        let entry = entries.get_mut(hash)?;
+        entry.last_used = now;
+        Some(entry.value.clone())
+    }
+
+    /// `size` is the length of the encoding the hash was computed from.
+    /// Values larger than the whole cache are not stored.
+    pub fn insert(&self, hash: BlobHash, value: &Value, size: usize) {
+        if size > self.config.max_size || self.entries.lock().unwrap().contains_key(&hash) {
+            return;
+        }
+        // Clone outside of the lock, this can take a while for large values
+        let value = Arc::new(value.clone());
+        let mut entries = self.entries.lock().unwrap();
+
+        // Evict least recently used values until the new one fits
+        let mut total: usize = entries.values().map(|entry| entry.size).sum();
+        while total + size > self.config.max_size {
+            let Some((&oldest, _)) = entries.iter().min_by_key(|(_, entry)| entry.last_used) else {
+                break;
+            };
+            total -= entries.remove(&oldest).map_or(0, |entry| entry.size);
+        }
+
+        let entry = Entry {
+            value,
+            size,
+            last_used: Instant::now(),
+        };
+        entries.insert(hash, entry);
+    }
+}
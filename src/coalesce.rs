@@ -0,0 +1,76 @@
+//! Rate limiting of the messages and progress reports of chatty tools, see
+//! [`crate::ServerConfig::coalesce_interval`].
+
+use std::time::{Duration, Instant};
+
+use crate::connection::websocket::Message;
+
+/// Collects messages and progress reports of the tool until the interval since
+/// the last ones passed. Messages are joined with newlines, of the progress
+/// only the latest report is kept. Emitted outputs and partial results are
+/// never held back.
+pub(crate) struct Coalescer {
+    interval: Option<Duration>,
+    last_sent: Option<Instant>,
+    messages: Vec<String>,
+    progress: Option<(f64, Option<String>)>,
+}
+
+impl Coalescer {
+    /// Without an `interval`, all messages are passed through
+    pub(crate) fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            last_sent: None,
+            messages: Vec::new(),
+            progress: None,
+        }
+    }
+
+    /// Messages to send to the client now, which might be none
+    pub(crate) fn push(&mut self, msg: Message) -> Vec<Message> {
+        let Some(interval) = self.interval else {
+            return vec![msg];
+        };
+        match msg {
+            Message::ToolMsg(msg) => self.messages.push(msg),
+            Message::Progress { fraction, message } => self.progress = Some((fraction, message)),
+            // Sent in order, after everything that came before
+            msg => {
+                let mut pending = self.flush();
+                pending.push(msg);
+                return pending;
+            }
+        }
+        match self.last_sent {
+            Some(last_sent) if last_sent.elapsed() < interval => Vec::new(),
+            _ => self.flush(),
+        }
+    }
+
+    /// When the held back messages are due, `None` if there are none
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let pending = !self.messages.is_empty() || self.progress.is_some();
+        match (pending, self.last_sent, self.interval) {
+            (true, Some(last_sent), Some(interval)) => Some(last_sent + interval),
+            (true, _, _) => Some(Instant::now()),
+            (false, _, _) => None,
+        }
+    }
+
+    /// All held back messages, to be sent now
+    pub(crate) fn flush(&mut self) -> Vec<Message> {
+        let mut pending = Vec::new();
+        if !self.messages.is_empty() {
+            pending.push(Message::ToolMsg(self.messages.join("\n")));
+            self.messages.clear();
+        }
+        if let Some((fraction, message)) = self.progress.take() {
+            pending.push(Message::Progress { fraction, message });
+        }
+        if !pending.is_empty() {
+            self.last_sent = Some(Instant::now());
+        }
+        pending
+    }
+}
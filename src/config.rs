@@ -0,0 +1,556 @@
+//! Configuration of the tool server, see [`crate::run_server_with_config`].
+
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    Compression, JobStore, MemoryJobStore, ToolError, Value,
+    value::{FloatPolicy, dynamic::Dict, schema::ValueSchema},
+};
+
+/// How much information about a panicking tool is sent to the client.
+/// The full report (message, location and backtrace) is always logged on the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicDetail {
+    /// Only report that the tool panicked
+    Hidden,
+    /// Panic message and source location
+    #[default]
+    Message,
+    /// Panic message, source location and backtrace (in the error details)
+    Backtrace,
+}
+
+/// What the server does with results above [`ServerConfig::max_output_size`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputPolicy {
+    /// Replace the result with a [`ToolError::ResourceExhausted`]
+    #[default]
+    Error,
+    /// Shorten all TypedLists (in nested Lists and Dicts) by the same factor
+    /// until the result fits, listed in [`crate::JobMeta::truncated`].
+    /// Results that don't fit without their lists are still an error.
+    Truncate,
+}
+
+/// What happens when a tool sends messages faster than the client reads them,
+/// after [`ServerConfig`] buffered 1024 of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferPolicy {
+    /// The tool waits in `send_msg` (and the other methods sending to the
+    /// client) until the client caught up
+    Block,
+    /// The tool waits up to this long, then the oldest message or progress
+    /// report is dropped and the client is told how many were dropped.
+    /// Emitted outputs and partial results are never dropped.
+    DropOldest(Duration),
+}
+
+impl Default for BufferPolicy {
+    fn default() -> Self {
+        Self::DropOldest(Duration::from_secs(1))
+    }
+}
+
+/// Settings of the tool server. The default is used by [`crate::run_server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub panic_detail: PanicDetail,
+    /// Compression of messages and results sent to the client
+    pub compression: Compression,
+    pub cache: CacheConfig,
+    /// Messages of at least this (compressed) size are staged in a memory
+    /// mapped temporary file instead of memory while being sent or received.
+    /// Keeps the server stable for datasets that barely fit in memory once.
+    /// `None` (default) always keeps messages in memory.
+    pub spool_min_size: Option<usize>,
+    /// Reported on the `/info` route (see [`crate::ServerInfo`]),
+    /// e.g. `Some(env!("CARGO_PKG_NAME").into())`
+    pub tool_name: Option<String>,
+    /// Reported on `/info` and to clients with the cost of every call (see [`crate::JobMeta`]),
+    /// e.g. `Some(env!("CARGO_PKG_VERSION").into())`
+    pub tool_version: Option<String>,
+    /// Transformations of every input and output, see [`ServerConfig::on_input`]
+    pub hooks: Hooks,
+    /// Inputs that don't match are rejected with a [`ToolError::InvalidInput`]
+    /// listing all violations, before the tool (or any input hook) runs.
+    pub input_schema: Option<ValueSchema>,
+    /// Results with a larger (uncompressed) encoding are handled according to
+    /// `output_policy`, so that a buggy tool can't exhaust the memory of the
+    /// server or the client. `None` (default) allows results of any size.
+    pub max_output_size: Option<usize>,
+    pub output_policy: OutputPolicy,
+    /// The tool is aborted and the client gets a [`ToolError::ResourceExhausted`]
+    /// once its call used more than this many bytes, before one greedy call
+    /// gets the whole server killed. Checked every 100 ms. `None` (default)
+    /// sets no limit.
+    ///
+    /// Memory is counted per call if the program installs [`crate::JobAllocator`]
+    /// as its global allocator. Otherwise the growth of the resident memory of
+    /// the server is measured (only on Linux, other platforms ignore the limit),
+    /// which includes all calls running at the same time: starting the server
+    /// then fails unless `max_concurrent_tools` is `Some(1)`.
+    pub max_job_memory: Option<usize>,
+    /// Like `max_job_memory` for the CPU time of the thread running the tool
+    /// (reported to clients in [`crate::JobMeta::cpu_time`]), so that shared
+    /// deployments can cap their users. Threads spawned by the tool are not
+    /// included. Only enforced on Linux. `None` (default) sets no limit.
+    pub max_cpu_time: Option<Duration>,
+    /// Wall time the tool may run, not counting the time the call waited for
+    /// a slot of `max_concurrent_tools`. The tool is then aborted with [`crate::AbortReason::Timeout`] and the
+    /// client gets a [`ToolError::Timeout`]. Clients can shorten it for their
+    /// call with the [`crate::TIMEOUT_KEY`] entry of the input, but not
+    /// extend it. `None` (default) sets no limit.
+    pub timeout: Option<Duration>,
+    /// Tools that may run at the same time, further calls wait in a queue
+    /// and are told their position in it (see [`crate::ToolEventKind::Queued`]).
+    /// Tools abandoned after the `abort_grace_period` keep their slot until
+    /// their thread actually ends. `None` (default) runs every call right away.
+    pub max_concurrent_tools: Option<usize>,
+    pub artifacts: ArtifactConfig,
+    /// Settings of this deployment, see [`ServerConfig::with_operator_config`]
+    pub operator_config: OperatorConfig,
+    /// Time the tool has to return after the client aborted, before the
+    /// client gets a [`ToolError::Abort`] and the tool thread is abandoned
+    /// (it keeps running until it returns, logged as `STUCK`). Tools that never
+    /// check for an abort would otherwise keep the call open forever.
+    /// `None` waits for the tool, the default is 30 seconds.
+    pub abort_grace_period: Option<Duration>,
+    /// Messages of the tool are dropped instead of stalling it if the client
+    /// is too slow, see [`BufferPolicy`]
+    pub buffer_policy: BufferPolicy,
+    /// Messages and progress reports of the tool are sent at most once per
+    /// interval: messages in between are joined with newlines and only the
+    /// latest progress is kept. Reduces the traffic of tools that report
+    /// e.g. every k-space line. `None` (default) sends all of them right away.
+    pub coalesce_interval: Option<Duration>,
+    /// Add a `provenance` entry to Dict results, with the tool, its version,
+    /// the hash of the input (as received), when and where it ran (see
+    /// [`crate::value::structured::Provenance`]). Other results are sent unchanged.
+    pub provenance: bool,
+    /// NaN and infinite floats in inputs and results. Inputs that contain them
+    /// are rejected with a [`ToolError::InvalidInput`] by [`FloatPolicy::Error`],
+    /// results are replaced with a [`ToolError::Internal`]. Both list every
+    /// place. The default allows them.
+    pub float_policy: FloatPolicy,
+    /// Log the type, shape and size of every input and result and of their
+    /// top-level entries (lines starting with `SIZE`), e.g. to find out
+    /// which inputs exhaust the memory of the server. Costs an extra
+    /// encoding of both, so it is off by default.
+    pub log_sizes: bool,
+    /// Serve `https://` and `wss://` with this certificate instead of plain
+    /// HTTP, for deployments that are not behind a TLS-terminating proxy.
+    /// `None` (default) serves plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// Requests of `/tool`, `/tool/{name}`, `/info` and
+    /// `/jobs/{id}/artifacts/{name}` must send this token as
+    /// `Authorization: Bearer` header (see [`crate::call_with_auth`]), others
+    /// are rejected with HTTP 401 (calls before the WebSocket upgrade). Only
+    /// the page on `/` stays public. Browsers can't set the header, so wasm
+    /// clients can't call protected tools. `None` (default) allows everyone.
+    pub auth_token: Option<String>,
+    /// Write a record of every call to a directory, for labs that have to
+    /// document which data was processed how (see [`AuditConfig`]). `None`
+    /// (default) keeps no records besides the log.
+    pub audit: Option<AuditConfig>,
+    /// Encrypt the payloads the server writes to disk (e.g. the
+    /// [`AuditConfig`] records), which may be derived from patient data.
+    /// Messages staged by `spool_min_size` only live in unlinked temporary
+    /// files and are not encrypted, neither are artifacts (they are downloaded
+    /// as they are). `None` (default) writes plain files.
+    pub storage_key: Option<StorageKey>,
+    /// Ping the client this often, so that proxies don't close the connection
+    /// while a long simulation sends no messages. Clients that don't answer
+    /// until the next ping is due are considered gone and their tool is
+    /// aborted ([`crate::ConnectionError::HeartbeatTimeout`]). `None` sends no
+    /// pings, the default is 30 seconds.
+    pub heartbeat: Option<Duration>,
+    /// Where the results of finished calls are kept, see [`JobStoreConfig`]
+    pub job_store: JobStoreConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            panic_detail: PanicDetail::default(),
+            compression: Compression::default(),
+            cache: CacheConfig::default(),
+            spool_min_size: None,
+            tool_name: None,
+            tool_version: None,
+            hooks: Hooks::default(),
+            input_schema: None,
+            max_output_size: None,
+            output_policy: OutputPolicy::default(),
+            max_job_memory: None,
+            max_cpu_time: None,
+            timeout: None,
+            max_concurrent_tools: None,
+            artifacts: ArtifactConfig::default(),
+            operator_config: OperatorConfig::default(),
+            abort_grace_period: Some(Duration::from_secs(30)),
+            buffer_policy: BufferPolicy::default(),
+            coalesce_interval: None,
+            provenance: false,
+            float_policy: FloatPolicy::default(),
+            log_sizes: false,
+            tls: None,
+            auth_token: None,
+            audit: None,
+            storage_key: None,
+            heartbeat: Some(Duration::from_secs(30)),
+            job_store: JobStoreConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Transform the input of every call before the tool sees it, e.g. to
+    /// normalize units. An error is returned to the client without running
+    /// the tool. Hooks run in the order they were added.
+    ///
+    /// ```no_run
+    /// # use toolapi::{Value, MessageFn, ToolError};
+    /// use toolapi::{ServerConfig, run_server_with_config};
+    ///
+    /// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+    ///     Ok(input)
+    /// }
+    ///
+    /// fn main() -> Result<(), std::io::Error> {
+    ///     let config = ServerConfig::default()
+    ///         .on_input(|input| {
+    ///             // Reject inputs early, for all tools in this binary
+    ///             input.require::<f64>("fov")?;
+    ///             Ok(input)
+    ///         })
+    ///         .on_output(|mut output| {
+    ///             if let Value::Dict(dict) = &mut output {
+    ///                 dict.0.remove("debug");
+    ///             }
+    ///             Ok(output)
+    ///         });
+    ///     run_server_with_config(tool, None, config)
+    /// }
+    /// ```
+    pub fn on_input(
+        mut self,
+        hook: impl Fn(Value) -> Result<Value, ToolError> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.input.push(Arc::new(hook));
+        self
+    }
+
+    /// Transform the value returned by the tool before it is sent to the
+    /// client, e.g. to add version information. Errors of the tool are sent
+    /// unchanged. Hooks run in the order they were added.
+    pub fn on_output(
+        mut self,
+        hook: impl Fn(Value) -> Result<Value, ToolError> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.output.push(Arc::new(hook));
+        self
+    }
+
+    /// Settings of this deployment (e.g. data paths, the GPU index or license
+    /// keys), which every call can read with [`crate::ToolCtx::setting`].
+    /// They are passed alongside the input, so clients can neither see nor
+    /// override them and the same tool binary can run in every environment.
+    ///
+    /// ```no_run
+    /// # use toolapi::{Value, ToolError};
+    /// use toolapi::{ServerConfig, ToolCtx, run_server_with_config, value::dynamic::Dict};
+    ///
+    /// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     let data_dir: String = ctx.setting("data_dir")?;
+    ///     let gpu: i64 = ctx.setting_or("gpu", 0)?;
+    ///     // ...
+    ///     Ok(input)
+    /// }
+    ///
+    /// fn main() -> Result<(), std::io::Error> {
+    ///     let data_dir = std::env::var("DATA_DIR").unwrap_or("/data".into());
+    ///     let settings = Dict([("data_dir".to_string(), Value::Str(data_dir))].into());
+    ///     let config = ServerConfig::default().with_operator_config(settings);
+    ///     run_server_with_config(tool, None, config)
+    /// }
+    /// ```
+    pub fn with_operator_config(mut self, settings: Dict) -> Self {
+        self.operator_config = OperatorConfig(Arc::new(settings));
+        self
+    }
+}
+
+/// Input or output transformation, see [`ServerConfig::on_input`]
+pub type Hook = Arc<dyn Fn(Value) -> Result<Value, ToolError> + Send + Sync>;
+
+/// Hooks registered with [`ServerConfig::on_input`] and [`ServerConfig::on_output`].
+#[derive(Clone, Default)]
+pub struct Hooks {
+    input: Vec<Hook>,
+    output: Vec<Hook>,
+}
+
+impl Hooks {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty() && self.output.is_empty()
+    }
+
+    pub(crate) fn apply_input(&self, input: Value) -> Result<Value, ToolError> {
+        self.input.iter().try_fold(input, |value, hook| hook(value))
+    }
+
+    pub(crate) fn apply_output(&self, output: Value) -> Result<Value, ToolError> {
+        self.output
+            .iter()
+            .try_fold(output, |value, hook| hook(value))
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("input", &self.input.len())
+            .field("output", &self.output.len())
+            .finish()
+    }
+}
+
+/// Settings registered with [`ServerConfig::with_operator_config`], shared by
+/// all calls. Only the keys are printed, values can be secrets.
+#[derive(Clone)]
+pub struct OperatorConfig(Arc<Dict>);
+
+impl OperatorConfig {
+    pub(crate) fn settings(&self) -> &Dict {
+        &self.0
+    }
+}
+
+impl Default for OperatorConfig {
+    fn default() -> Self {
+        Self(Arc::new(Dict(Default::default())))
+    }
+}
+
+impl fmt::Debug for OperatorConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys: Vec<&String> = self.0.0.keys().collect();
+        keys.sort();
+        f.debug_tuple("OperatorConfig").field(&keys).finish()
+    }
+}
+
+/// Cache of large input values on the server. Clients first send only the
+/// hashes of these and transfer the values the server doesn't have cached,
+/// which avoids sending e.g. the same phantom for every call of a parameter sweep.
+///
+/// The Rust client hashes values with [`crate::Value::canonical_hash`], which
+/// treats `-0.0` and `0.0` as equal, as well as all NaNs. A call can then get
+/// the cached value of an earlier call that differs only in these floats.
+/// Clients that hash the msgpack encoding instead get exactly what they sent.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Upper bound for the total (msgpack encoded) size of all cached values,
+    /// the least recently used ones are evicted first. 0 disables the cache.
+    /// Uploads interrupted by a dropped connection are kept within the same
+    /// bound, so that the client only sends the rest after reconnecting.
+    pub max_size: usize,
+    /// Values (and interrupted uploads) that were not used for this long are evicted
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 1024 * 1024 * 1024,
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Storage of files registered with [`crate::ToolCtx::artifact`], which are
+/// downloaded over HTTP instead of being sent through the WebSocket.
+#[derive(Debug, Clone)]
+pub struct ArtifactConfig {
+    /// Upper bound for the total size of all artifacts on disk, registering
+    /// more fails with a [`ToolError::ResourceExhausted`]. 0 disables artifacts.
+    pub max_size: usize,
+    /// Artifacts are deleted this long after they were registered
+    pub ttl: Duration,
+}
+
+impl Default for ArtifactConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 16 * 1024 * 1024 * 1024,
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Results of finished calls (successful or not) with their [`crate::JobMeta`],
+/// kept by the server for a while after they were sent, so that clients can
+/// resume calls whose connection dropped (see [`crate::resume`]). Off by
+/// default, set a `retention` to enable it.
+///
+/// Every kept result is a copy: [`crate::MemoryJobStore`] (the default store)
+/// holds it in memory for the whole `retention`, on top of the result that is
+/// being sent. Servers with large results should use [`crate::FileJobStore`],
+/// which also keeps them across restarts of the server, or implement their
+/// own [`crate::JobStore`].
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use toolapi::{JobStoreConfig, ServerConfig};
+///
+/// let config = ServerConfig {
+///     job_store: JobStoreConfig {
+///         retention: Duration::from_secs(10 * 60),
+///         ..JobStoreConfig::default()
+///     },
+///     ..ServerConfig::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct JobStoreConfig {
+    pub store: Arc<dyn JobStore>,
+    /// Results are deleted this long after they were ready, checked once a
+    /// minute (or more often for a shorter retention). Zero (default) keeps none.
+    pub retention: Duration,
+    /// Messages of a running call are kept until its client acknowledged
+    /// them, so that they can be sent again if it resumes the call. Beyond this
+    /// (uncompressed) size in bytes the oldest ones are dropped, like the
+    /// messages of [`BufferPolicy::DropOldest`]. The default is 16 MiB.
+    pub max_log_size: usize,
+}
+
+impl Default for JobStoreConfig {
+    fn default() -> Self {
+        Self {
+            store: Arc::new(MemoryJobStore::default()),
+            retention: Duration::ZERO,
+            max_log_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl fmt::Debug for JobStoreConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobStoreConfig")
+            .field("retention", &self.retention)
+            .field("max_log_size", &self.max_log_size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Records of calls written by the server, see [`ServerConfig::audit`].
+///
+/// Every call gets a file `{job_id}.audit` with an [`crate::AuditRecord`]:
+/// the hashes of the input and the result, the seed, the error and the cost,
+/// written once the result is ready (also for rejected inputs and aborts).
+///
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError, ServerConfig, AuditConfig};
+/// # fn tool(input: Value, _: &mut MessageFn) -> Result<Value, ToolError> { Ok(input) }
+/// let config = ServerConfig {
+///     audit: Some(AuditConfig {
+///         payloads: true,
+///         ..AuditConfig::new("/var/lib/tool/audit")
+///     }),
+///     ..Default::default()
+/// };
+/// toolapi::run_server_with_config(tool, None, config)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// Created if it doesn't exist
+    pub dir: PathBuf,
+    /// Also record the full input and result, not only their hashes
+    pub payloads: bool,
+    /// The oldest records are deleted once all of them take more bytes
+    pub max_size: u64,
+}
+
+impl AuditConfig {
+    /// Without payloads, keeping at most 1 GiB of records
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            payloads: false,
+            max_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// 256 bit key for [`ServerConfig::storage_key`], files are encrypted with
+/// ChaCha20-Poly1305. Losing the key loses the files, it is never printed.
+///
+/// ```no_run
+/// # use toolapi::{ServerConfig, StorageKey};
+/// // e.g. generated with `openssl rand -hex 32`
+/// let key = StorageKey::from_hex(&std::env::var("TOOL_STORAGE_KEY").unwrap())
+///     .expect("64 hex digits");
+/// let config = ServerConfig {
+///     storage_key: Some(key),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct StorageKey(pub(crate) [u8; 32]);
+
+impl StorageKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// `None` unless `hex` consists of exactly 64 hex digits
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        }
+        Some(Self(key))
+    }
+}
+
+impl fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StorageKey(..)")
+    }
+}
+
+/// Certificate of the server, see [`ServerConfig::tls`]. Both files are
+/// read when the server starts, errors are returned by [`crate::run_server_with_config`].
+///
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError, ServerConfig, TlsConfig};
+/// # fn tool(input: Value, _: &mut MessageFn) -> Result<Value, ToolError> { Ok(input) }
+/// // Clients call wss://tool.example.org:8080/tool
+/// let config = ServerConfig {
+///     tls: Some(TlsConfig::new("/etc/tool/fullchain.pem", "/etc/tool/privkey.pem")),
+///     ..Default::default()
+/// };
+/// toolapi::run_server_with_config(tool, None, config)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate, followed by its intermediate certificates
+    pub cert_path: PathBuf,
+    /// PEM file with the private key of the certificate (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Large [`Value::Bytes`] sent as raw frames before the message that contains
+//! them, see `Message::Attachment`. The msgpack encoding of the message stays
+//! small and the bytes are neither encoded nor compressed.
+
+use super::{
+    Transport, send_message,
+    websocket::{Compression, FRAME_SIZE, Message},
+};
+use crate::{ConnectionError, Value, value::dynamic::Dict};
+
+/// `Bytes` with at least this many bytes are sent as attachments
+pub(crate) const ATTACHMENT_MIN_SIZE: usize = 256 * 1024;
+
+/// Replace large `Bytes` in `value` and its nested Dicts and Lists by
+/// `AttachmentRef`s, returns the removed bytes in the order of their ids.
+pub(crate) fn detach(value: &mut Value) -> Vec<Vec<u8>> {
+    fn visit(value: &mut Value, attachments: &mut Vec<Vec<u8>>) {
+        match value {
+            Value::Bytes(bytes) if bytes.len() >= ATTACHMENT_MIN_SIZE => {
+                let id = attachments.len() as u32;
+                attachments.push(std::mem::take(bytes));
+                *value = Value::AttachmentRef(id);
+            }
+            Value::Dict(Dict(entries)) => entries
+                .values_mut()
+                .for_each(|entry| visit(entry, attachments)),
+            Value::List(list) => list.0.iter_mut().for_each(|item| visit(item, attachments)),
+            _ => {}
+        }
+    }
+
+    let mut attachments = Vec::new();
+    visit(value, &mut attachments);
+    attachments
+}
+
+/// Replace all `AttachmentRef`s in `value` by the received bytes, each
+/// attachment must be referenced exactly once.
+pub(crate) fn attach(value: &mut Value, attachments: Vec<Vec<u8>>) -> Result<(), ConnectionError> {
+    fn visit(
+        value: &mut Value,
+        attachments: &mut [Option<Vec<u8>>],
+    ) -> Result<(), ConnectionError> {
+        match value {
+            Value::AttachmentRef(id) => {
+                let bytes = attachments
+                    .get_mut(*id as usize)
+                    .and_then(Option::take)
+                    .ok_or_else(|| violation(format!("attachment {id} is missing")))?;
+                *value = Value::Bytes(bytes);
+            }
+            Value::Dict(Dict(entries)) => {
+                for entry in entries.values_mut() {
+                    visit(entry, attachments)?;
+                }
+            }
+            Value::List(list) => {
+                for item in &mut list.0 {
+                    visit(item, attachments)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    let mut attachments: Vec<_> = attachments.into_iter().map(Some).collect();
+    visit(value, &mut attachments)?;
+    match attachments.iter().position(Option::is_some) {
+        Some(id) => Err(violation(format!("attachment {id} is not referenced"))),
+        None => Ok(()),
+    }
+}
+
+/// Send each attachment as `Message::Attachment` followed by its raw frames,
+/// returns the number of bytes sent.
+pub(crate) async fn send_attachments(
+    transport: &mut impl Transport,
+    attachments: &[Vec<u8>],
+    compression: &Compression,
+) -> Result<usize, ConnectionError> {
+    let mut sent = 0;
+    for (id, bytes) in attachments.iter().enumerate() {
+        let header = Message::Attachment {
+            id: id as u32,
+            size: bytes.len() as u64,
+        };
+        send_message(transport, &header, compression, None).await?;
+        for chunk in bytes.chunks(FRAME_SIZE) {
+            transport.send(chunk.to_vec()).await?;
+        }
+        sent += bytes.len();
+    }
+    Ok(sent)
+}
+
+/// Receive the bytes following a `Message::Attachment` and append them to the
+/// attachments of the next message, whose ids must be sent in order.
+pub(crate) async fn recv_attachment(
+    transport: &mut impl Transport,
+    id: u32,
+    size: u64,
+    attachments: &mut Vec<Vec<u8>>,
+) -> Result<(), ConnectionError> {
+    if id as usize != attachments.len() {
+        return Err(violation(format!("attachment {id} out of order")));
+    }
+    let size = size as usize;
+    let mut bytes = Vec::with_capacity(size.min(FRAME_SIZE));
+    while bytes.len() < size {
+        let frame = transport
+            .recv()
+            .await?
+            .ok_or(ConnectionError::ConnectionClosed)?;
+        if frame.is_empty() || bytes.len() + frame.len() > size {
+            return Err(violation(format!(
+                "attachment frames don't add up to {size} bytes"
+            )));
+        }
+        bytes.extend_from_slice(&frame);
+    }
+    attachments.push(bytes);
+    Ok(())
+}
+
+fn violation(msg: String) -> ConnectionError {
+    ConnectionError::ProtocolViolation(msg)
+}
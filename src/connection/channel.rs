@@ -1,22 +1,65 @@
-use crate::error::AbortReason;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    time::Instant,
+};
 
+use super::websocket::Message;
+use crate::{BufferPolicy, Value, context::AbortSignal, error::AbortReason, value::dynamic::Dict};
+
+/// Number of messages buffered for a slow client
+const CAPACITY: usize = 1024;
+
+struct Queue {
+    messages: VecDeque<Message>,
+    /// Messages dropped by [`BufferPolicy::DropOldest`] since the last notice
+    dropped: u64,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signals the senders that a message was taken or the receiver is gone
+    space: Condvar,
+    /// Signals the receiver that a message arrived or all senders are gone
+    ready: tokio::sync::Notify,
+    policy: BufferPolicy,
+}
+
+/// Can be cloned to send from multiple places, e.g. messages and progress
 pub struct Sender {
-    msg_tx: tokio::sync::mpsc::Sender<String>,
-    abort_rx: tokio::sync::oneshot::Receiver<AbortReason>,
+    shared: Arc<Shared>,
+    abort: AbortSignal,
 }
 
 pub struct Receiver {
-    msg_rx: tokio::sync::mpsc::Receiver<String>,
-    abort_tx: tokio::sync::oneshot::Sender<AbortReason>,
+    shared: Arc<Shared>,
+    abort: AbortSignal,
 }
 
-pub fn connect() -> (Sender, Receiver) {
-    // Channel for sending messages to the client
-    let (msg_tx, msg_rx) = tokio::sync::mpsc::channel(1024);
-    // Channel for sending an abort message to the server
-    let (abort_tx, abort_rx) = tokio::sync::oneshot::channel();
+/// `abort` is triggered by the receiving side, see [`Receiver::abort`].
+/// `policy` decides what happens when the receiver falls behind.
+pub fn connect(abort: AbortSignal, policy: BufferPolicy) -> (Sender, Receiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            messages: VecDeque::new(),
+            dropped: 0,
+            senders: 1,
+            receiver_alive: true,
+        }),
+        space: Condvar::new(),
+        ready: tokio::sync::Notify::new(),
+        policy,
+    });
 
-    (Sender { msg_tx, abort_rx }, Receiver { msg_rx, abort_tx })
+    (
+        Sender {
+            shared: shared.clone(),
+            abort: abort.clone(),
+        },
+        Receiver { shared, abort },
+    )
 }
 
 impl Sender {
@@ -26,31 +69,130 @@ impl Sender {
     /// # Blocking
     /// This function blocks on sending the message and should not be used in an `async` context.
     pub fn send(&mut self, msg: String) -> Result<(), AbortReason> {
-        self.msg_tx
-            .blocking_send(msg)
-            .map_err(|err| AbortReason::ChannelError(err.to_string()))?;
-
-        use tokio::sync::oneshot::error::TryRecvError;
-        match self.abort_rx.try_recv() {
-            Ok(reason) => Err(reason),
-            Err(err) => match err {
-                TryRecvError::Empty => Ok(()),
-                TryRecvError::Closed => Err(AbortReason::ConnectionClosed),
-            },
+        self.send_raw(Message::ToolMsg(msg))
+    }
+
+    /// Like [`Sender::send`], for [`Message::Progress`]
+    pub fn send_progress(
+        &mut self,
+        fraction: f64,
+        message: Option<String>,
+    ) -> Result<(), AbortReason> {
+        self.send_raw(Message::Progress { fraction, message })
+    }
+
+    /// Like [`Sender::send`], for [`Message::Emit`]
+    pub fn emit(&mut self, name: String, value: Value) -> Result<(), AbortReason> {
+        self.send_raw(Message::Emit { name, value })
+    }
+
+    /// Like [`Sender::send`], for [`Message::PartialResult`]
+    pub fn send_partial(&mut self, partial: Dict) -> Result<(), AbortReason> {
+        self.send_raw(Message::PartialResult(partial))
+    }
+
+    /// Waits while the buffer is full, see [`BufferPolicy`]
+    fn send_raw(&mut self, msg: Message) -> Result<(), AbortReason> {
+        let started = Instant::now();
+        let mut queue = self.shared.queue.lock().unwrap();
+        loop {
+            if !queue.receiver_alive {
+                return Err(AbortReason::ChannelError("channel closed".into()));
+            }
+            if queue.messages.len() < CAPACITY {
+                break;
+            }
+            let waited = started.elapsed();
+            match self.shared.policy {
+                // Once dropping, the tool doesn't wait again until the client caught up
+                BufferPolicy::DropOldest(timeout) if waited >= timeout || queue.dropped > 0 => {
+                    // Emitted outputs and partial results are never dropped
+                    let oldest = queue.messages.iter().position(|msg| {
+                        !matches!(msg, Message::Emit { .. } | Message::PartialResult(_))
+                    });
+                    if let Some(index) = oldest {
+                        queue.messages.remove(index);
+                        queue.dropped += 1;
+                        break;
+                    }
+                    queue = self.shared.space.wait(queue).unwrap();
+                }
+                BufferPolicy::DropOldest(timeout) => {
+                    queue = self
+                        .shared
+                        .space
+                        .wait_timeout(queue, timeout - waited)
+                        .unwrap()
+                        .0;
+                }
+                BufferPolicy::Block => queue = self.shared.space.wait(queue).unwrap(),
+            }
+        }
+        queue.messages.push_back(msg);
+        drop(queue);
+        self.shared.ready.notify_one();
+        self.abort.check()
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        self.shared.queue.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+            abort: self.abort.clone(),
         }
     }
 }
 
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().senders -= 1;
+        self.shared.ready.notify_one();
+    }
+}
+
 impl Receiver {
+    /// Returns `None` once all senders are gone and the buffer is empty.
+    /// Dropped messages are reported with a [`Message::ToolMsg`] in their place.
     /// # Cancel safety
-    /// Uses `tokio::sync::mpsc::bounded::Receiver`, which is cancel safe.
-    pub async fn recv(&mut self) -> Option<String> {
-        self.msg_rx.recv().await
+    /// Messages are only taken from the buffer when they are returned.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            // Registered before checking, so that no notification is missed
+            let notified = self.shared.ready.notified();
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if queue.dropped > 0 {
+                    let dropped = std::mem::take(&mut queue.dropped);
+                    return Some(Message::ToolMsg(format!(
+                        "{dropped} messages were dropped, the client reads them slower than the tool sends them"
+                    )));
+                }
+                if let Some(msg) = queue.messages.pop_front() {
+                    self.shared.space.notify_one();
+                    return Some(msg);
+                }
+                if queue.senders == 0 {
+                    return None;
+                }
+            }
+            notified.await;
+        }
     }
 
-    /// Next time the tool calls Sender::send() it will recieve the abort reason.
+    /// The tool sees the abort reason on its next Sender::send() or check_abort().
     pub fn abort(self, reason: AbortReason) {
-        // Ignore error: if we can't send, the tool probably has quit already
-        let _ = self.abort_tx.send(reason);
+        self.abort.trigger(reason);
+    }
+}
+
+impl Drop for Receiver {
+    /// The server stopped listening (e.g. the connection failed), only has an
+    /// effect if the tool is still running and was not aborted before
+    fn drop(&mut self) {
+        self.abort.trigger(AbortReason::ConnectionClosed);
+        self.shared.queue.lock().unwrap().receiver_alive = false;
+        self.shared.space.notify_all();
     }
 }
@@ -0,0 +1,606 @@
+//! Client side of the protocol, shared by all targets.
+//! The target specific part is only the [`Transport`] used to connect.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    time::Duration,
+};
+
+use serde_bytes::ByteBuf;
+
+use super::{
+    Transport,
+    attachment::{attach, detach, recv_attachment, send_attachments},
+    recv_message, send_message,
+    websocket::{BlobHash, Capabilities, Compression, JobMeta, Message, MissingBlob, peek_hello},
+};
+use crate::{
+    ConnectionError, ErrorKind, PROTOCOL_VERSION, ParseError, ToolCallError, ToolError, Value,
+    value::dynamic::Dict,
+};
+
+/// Message, progress report, emitted output, partial result or completion of a tool,
+/// see [`crate::call_with_events`].
+#[derive(Debug, Clone)]
+pub struct ToolEvent {
+    /// Counts all events of a call, starting at 0
+    pub seq: u64,
+    /// Time since the server received the input, measured by the server
+    pub time: Duration,
+    pub kind: ToolEventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum ToolEventKind {
+    /// Sent by the tool with [`crate::ToolCtx::send_msg`] (or its `MessageFn`)
+    Message(String),
+    /// Sent by the tool with [`crate::ToolCtx::send_progress`]
+    Progress {
+        fraction: f64,
+        message: Option<String>,
+    },
+    /// Sent by the tool with [`crate::ToolCtx::emit`]
+    Emitted { name: String, value: Box<Value> },
+    /// Sent by the tool with [`crate::ToolCtx::send_partial`]
+    Partial(Dict),
+    /// The call waits for a free slot of the server (see
+    /// [`crate::ServerConfig::max_concurrent_tools`]), `position` calls are
+    /// ahead of it. Sent whenever it changes, the tool didn't start yet.
+    Queued { position: u32 },
+    /// The server received the input, the first event of a call. If the
+    /// connection drops later, the call is resumed with this id and secret
+    /// (see [`crate::resume`]). Only sent by servers that keep results.
+    Accepted { job_id: String, secret: String },
+    /// Last event of a call, sent by the server right before the result.
+    /// The return value of the callback is ignored for it.
+    Finished(JobMeta),
+}
+
+/// WebSocket client, the API is identical for native and wasm targets.
+///
+/// Use [`WsChannelClient::connect`] to create a client connected to a server
+/// with the default transport of the current target.
+pub struct WsChannelClient<T: Transport> {
+    transport: T,
+    /// If we tried to read a message of one type but received another, the message is buffered here.
+    buffer: Option<Message>,
+    /// Announced by the server in the handshake
+    server_capabilities: Capabilities,
+    /// Received for the next `Output`
+    attachments: Vec<Vec<u8>>,
+    /// Sent with the input, see [`Self::with_seed`]
+    seed: Option<u64>,
+    /// Job id and secret announced by the server with `Accepted`, to resume the call
+    resume: Option<(String, String)>,
+    /// `seq` of the next stamped message, where a resumed call continues
+    next_seq: u64,
+    /// Stamped messages received since the last `Ack`
+    unacked: usize,
+    /// The `JobMeta` was received, it is sent again if the call is resumed late
+    finished: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WsChannelClient<super::websocket::WsTransportNative> {
+    pub async fn connect(addr: &str) -> Result<Self, ConnectionError> {
+        Ok(Self::new(super::websocket::WsTransportNative::connect(
+            addr,
+        )?))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WsChannelClient<super::websocket::WsTransportWasm> {
+    /// Connecting is retried with the default [`super::websocket::RetryPolicy`].
+    pub async fn connect(addr: &str) -> Result<Self, ConnectionError> {
+        let policy = super::websocket::RetryPolicy::default();
+        Ok(Self::new(
+            super::websocket::WsTransportWasm::connect_with_retry(addr, &policy).await?,
+        ))
+    }
+}
+
+impl<T: Transport> WsChannelClient<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            buffer: None,
+            server_capabilities: Capabilities::default(),
+            attachments: Vec::new(),
+            seed: None,
+            resume: None,
+            next_seq: 0,
+            unacked: 0,
+            finished: false,
+        }
+    }
+
+    /// Run the tool with this seed instead of a random one chosen by the
+    /// server, e.g. the [`JobMeta::seed`] of an earlier call.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub async fn close(self) -> Result<(), ConnectionError> {
+        self.transport.close().await
+    }
+
+    /// Exchange protocol versions and [`Capabilities`] with the server, which
+    /// must be the first thing on every connection (the `call*` methods do it).
+    /// Fails with [`ConnectionError::ProtocolMismatch`] if the versions differ.
+    pub async fn handshake(&mut self) -> Result<(), ConnectionError> {
+        let hello = Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Capabilities::client(),
+        };
+        send_message(&mut self.transport, &hello, &Compression::default(), None).await?;
+
+        // Servers before the handshake fail to decode it and close the connection
+        let Some(frame) = self.transport.recv().await? else {
+            return Err(ConnectionError::ProtocolViolation(
+                "server closed the connection during the handshake, it might use an older protocol version".into(),
+            ));
+        };
+        match peek_hello(&frame) {
+            Some((PROTOCOL_VERSION, capabilities)) => {
+                self.server_capabilities = capabilities;
+                Ok(())
+            }
+            Some((server, _)) => Err(ConnectionError::ProtocolMismatch {
+                client: PROTOCOL_VERSION,
+                server,
+            }),
+            None => Err(ConnectionError::ProtocolViolation("expected hello".into())),
+        }
+    }
+
+    fn compression(&self) -> Compression {
+        Compression::default().for_peer(&self.server_capabilities)
+    }
+
+    pub async fn send_abort(&mut self) -> Result<(), ConnectionError> {
+        let compression = self.compression();
+        send_message(&mut self.transport, &Message::Abort, &compression, None).await
+    }
+
+    /// Large top-level entries of a [`Dict`] input are first announced by
+    /// their hash and only transferred if the server doesn't have them cached.
+    /// Without a cache, large `Bytes` are sent as attachments.
+    pub async fn send_input(&mut self, input: Value) -> Result<(), ConnectionError> {
+        let compression = self.compression();
+        if let Some(seed) = self.seed {
+            send_message(
+                &mut self.transport,
+                &Message::Seed(seed),
+                &compression,
+                None,
+            )
+            .await?;
+        }
+        let cached = self.server_capabilities.contains(Capabilities::INPUT_CACHE);
+        let mut entries = match input {
+            Value::Dict(Dict(entries)) if cached => entries,
+            mut input => {
+                if self.server_capabilities.contains(Capabilities::ATTACHMENTS) {
+                    let attachments = detach(&mut input);
+                    send_attachments(&mut self.transport, &attachments, &compression).await?;
+                }
+                return send_message(
+                    &mut self.transport,
+                    &Message::Input(input),
+                    &compression,
+                    None,
+                )
+                .await;
+            }
+        };
+
+        let mut refs = HashMap::new();
+        let mut blobs = HashMap::new();
+        let keys: Vec<String> = entries.keys().cloned().collect();
+        for key in keys {
+            let mut size = EncodedSize::default();
+            rmp_serde::encode::write(&mut size, &entries[&key])
+                .map_err(ParseError::SerializationError)?;
+            if size.0 >= BLOB_MIN_SIZE {
+                // Equal for equal values, unlike a hash of the encoding of a Dict
+                let hash = BlobHash(entries[&key].canonical_hash());
+                refs.insert(key.clone(), hash);
+                blobs.insert(hash, entries.remove(&key).unwrap());
+            }
+        }
+        let input = Value::Dict(Dict(entries));
+        if refs.is_empty() {
+            return send_message(
+                &mut self.transport,
+                &Message::Input(input),
+                &compression,
+                None,
+            )
+            .await;
+        }
+
+        // Encoded once, uploads interrupted by a dropped connection are resumed
+        let mut encoded = HashMap::new();
+        let mut resumed = 0;
+        loop {
+            match self.upload(&input, &refs, &blobs, &mut encoded).await {
+                Err(err) if err.kind() == ErrorKind::ConnectionLost && resumed < UPLOAD_RESUMES => {
+                    resumed += 1;
+                    self.transport.reconnect().await?;
+                    self.handshake().await?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send the `CachedInput` and the missing blobs in chunks, starting where
+    /// the server's copy of an interrupted upload ends.
+    async fn upload(
+        &mut self,
+        input: &Value,
+        refs: &HashMap<String, BlobHash>,
+        blobs: &HashMap<BlobHash, Value>,
+        encoded: &mut HashMap<BlobHash, Vec<u8>>,
+    ) -> Result<(), ConnectionError> {
+        let compression = self.compression();
+        let msg = Message::CachedInput {
+            input: input.clone(),
+            refs: refs.clone(),
+        };
+        send_message(&mut self.transport, &msg, &compression, None).await?;
+        let missing = match recv_message(&mut self.transport, None).await? {
+            Some(Message::Missing(missing)) => missing,
+            Some(_) => {
+                return Err(ConnectionError::ProtocolViolation(
+                    "expected missing".into(),
+                ));
+            }
+            None => return Err(ConnectionError::ConnectionClosed),
+        };
+
+        for MissingBlob { hash, received } in missing {
+            let value = blobs.get(&hash).ok_or_else(|| {
+                ConnectionError::ProtocolViolation("server requested an unknown blob".into())
+            })?;
+            let bytes = match encoded.entry(hash) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(rmp_serde::to_vec(value).map_err(ParseError::SerializationError)?)
+                }
+            };
+            let mut offset = received as usize;
+            if offset >= bytes.len() {
+                return Err(ConnectionError::ProtocolViolation(
+                    "server received more than the whole blob".into(),
+                ));
+            }
+            while offset < bytes.len() {
+                let end = (offset + UPLOAD_CHUNK_SIZE).min(bytes.len());
+                let chunk = Message::BlobChunk {
+                    hash,
+                    offset: offset as u64,
+                    size: bytes.len() as u64,
+                    data: ByteBuf::from(bytes[offset..end].to_vec()),
+                };
+                send_message(&mut self.transport, &chunk, &compression, None).await?;
+                offset = end;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::receive`], but if the connection drops after the server
+    /// accepted the input, reconnect and resume the call where it left off.
+    async fn read(&mut self) -> Result<(), ConnectionError> {
+        let mut result = self.receive().await;
+        let mut resumes = 0;
+        while let Err(err) = &result
+            && err.is_retryable()
+            && resumes < CALL_RESUMES
+            && let Some((job_id, secret)) = self.resume.clone()
+        {
+            resumes += 1;
+            result = match self.resume(job_id, secret).await {
+                Ok(()) => self.receive().await,
+                Err(err) => Err(err),
+            };
+        }
+        result
+    }
+
+    /// Tell the server that all stamped messages before `next_seq` arrived.
+    /// Errors are ignored, a dropped connection fails the next read.
+    async fn ack(&mut self) {
+        self.unacked = 0;
+        let compression = self.compression();
+        let msg = Message::Ack { seq: self.next_seq };
+        let _ = send_message(&mut self.transport, &msg, &compression, None).await;
+    }
+
+    /// Connect again and ask the server for the messages from `next_seq` on
+    async fn resume(&mut self, job_id: String, secret: String) -> Result<(), ConnectionError> {
+        self.transport.reconnect().await?;
+        self.handshake().await?;
+        // Whatever was received of an interrupted message is sent again
+        self.buffer = None;
+        self.attachments.clear();
+        let compression = self.compression();
+        let msg = Message::Resume {
+            job_id,
+            secret,
+            seq: self.next_seq,
+        };
+        send_message(&mut self.transport, &msg, &compression, None).await
+    }
+
+    /// Fill the message buffer, error on connection failure (but not on closed stream).
+    /// Attachments are collected until the message they belong to arrives.
+    async fn receive(&mut self) -> Result<(), ConnectionError> {
+        while self.buffer.is_none() {
+            match recv_message(&mut self.transport, None).await? {
+                Some(Message::Attachment { id, size }) => {
+                    recv_attachment(&mut self.transport, id, size, &mut self.attachments).await?
+                }
+                msg => {
+                    self.buffer = msg;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn read_output(
+        &mut self,
+    ) -> Result<Option<Result<Value, ToolError>>, ConnectionError> {
+        self.read().await?;
+        match self.buffer.take() {
+            Some(Message::Output(Ok(mut value))) => {
+                attach(&mut value, std::mem::take(&mut self.attachments))?;
+                Ok(Some(Ok(value)))
+            }
+            Some(Message::Output(x)) => Ok(Some(x)),
+            Some(msg) => {
+                self.buffer = Some(msg);
+                Ok(None)
+            }
+            None => Err(ConnectionError::ConnectionClosed),
+        }
+    }
+
+    /// Run a whole tool call over this connection, see [`crate::call`].
+    pub async fn call(
+        self,
+        input: Value,
+        on_message: impl FnMut(String) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        self.call_with_progress(input, on_message, |_, _| true)
+            .await
+    }
+
+    /// Like [`Self::call`], see [`crate::call_with_progress`].
+    pub async fn call_with_progress(
+        self,
+        input: Value,
+        mut on_message: impl FnMut(String) -> bool,
+        mut on_progress: impl FnMut(f64, Option<String>) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        self.call_with_events(input, |event| match event.kind {
+            ToolEventKind::Message(msg) => on_message(msg),
+            ToolEventKind::Progress { fraction, message } => on_progress(fraction, message),
+            ToolEventKind::Emitted { .. }
+            | ToolEventKind::Partial(_)
+            | ToolEventKind::Queued { .. }
+            | ToolEventKind::Accepted { .. }
+            | ToolEventKind::Finished(_) => true,
+        })
+        .await
+    }
+
+    /// Like [`Self::call`], see [`crate::call_with_partial`].
+    pub async fn call_with_partial(
+        self,
+        input: Value,
+        mut on_message: impl FnMut(String) -> bool,
+        mut on_partial: impl FnMut(Dict) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        self.call_with_events(input, |event| match event.kind {
+            ToolEventKind::Message(msg) => on_message(msg),
+            ToolEventKind::Partial(partial) => on_partial(partial),
+            _ => true,
+        })
+        .await
+    }
+
+    /// Like [`Self::call`], see [`crate::call_with_events`].
+    pub async fn call_with_events(
+        mut self,
+        input: Value,
+        on_event: impl FnMut(ToolEvent) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        let started = async {
+            self.handshake().await?;
+            // Send the input parameters to the server
+            self.send_input(input).await
+        }
+        .await;
+        match started {
+            // The tool didn't start yet, closing the connection is enough
+            Err(ConnectionError::Interrupted) => {
+                self.close().await?;
+                return Err(ToolCallError::OnMessageAbort);
+            }
+            result => result?,
+        }
+        self.follow(on_event).await
+    }
+
+    /// Like [`Self::call_with_events`], but continues a call that another
+    /// connection started, see [`crate::resume`]. All events are sent again.
+    pub async fn resume_with_events(
+        mut self,
+        job_id: &str,
+        secret: &str,
+        on_event: impl FnMut(ToolEvent) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        self.handshake().await?;
+        let compression = self.compression();
+        let msg = Message::Resume {
+            job_id: job_id.to_string(),
+            secret: secret.to_string(),
+            seq: 0,
+        };
+        send_message(&mut self.transport, &msg, &compression, None).await?;
+        self.resume = Some((job_id.to_string(), secret.to_string()));
+        self.follow(on_event).await
+    }
+
+    /// Pass the events of a started call to `on_event` and return its result
+    async fn follow(
+        mut self,
+        mut on_event: impl FnMut(ToolEvent) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        // Loop over messages sent by the server and ask the callback if we should abort
+        loop {
+            match self.read().await {
+                Err(ConnectionError::Interrupted) => return self.abort().await,
+                result => result?,
+            }
+            let (seq, time, message) = match self.buffer.take() {
+                Some(Message::Stamped { seq, time, message }) => (seq, time, message),
+                Some(msg) => {
+                    self.buffer = Some(msg);
+                    break;
+                }
+                None => return Err(ConnectionError::ConnectionClosed.into()),
+            };
+            // Already received before the call was resumed
+            if seq < self.next_seq {
+                continue;
+            }
+            self.next_seq = seq + 1;
+            // Kept by the server until they are acknowledged, for a resume
+            self.unacked += 1;
+            let large = matches!(*message, Message::Emit { .. } | Message::PartialResult(_));
+            if self.resume.is_some() && (large || self.unacked >= ACK_INTERVAL) {
+                self.ack().await;
+            }
+            let kind = match *message {
+                Message::ToolMsg(msg) => ToolEventKind::Message(msg),
+                Message::Progress { fraction, message } => {
+                    ToolEventKind::Progress { fraction, message }
+                }
+                Message::Emit { name, value } => ToolEventKind::Emitted {
+                    name,
+                    value: Box::new(value),
+                },
+                Message::PartialResult(partial) => ToolEventKind::Partial(partial),
+                Message::Queued { position } => ToolEventKind::Queued { position },
+                Message::Accepted { job_id, secret } => {
+                    self.resume = Some((job_id.clone(), secret.clone()));
+                    ToolEventKind::Accepted { job_id, secret }
+                }
+                Message::JobMeta(_) if self.finished => continue,
+                Message::JobMeta(meta) => ToolEventKind::Finished(meta),
+                _ => return Err(ToolCallError::ProtocolError),
+            };
+            let finished = matches!(kind, ToolEventKind::Finished(_));
+            self.finished |= finished;
+            let event = ToolEvent {
+                seq,
+                time: Duration::try_from_secs_f64(time).unwrap_or_default(),
+                kind,
+            };
+            // The tool already returned, there is nothing left to abort
+            if !on_event(event) && !finished {
+                // abort was requested by client callback
+                return self.abort().await;
+            }
+        }
+
+        // Read result, handle shutdown, return result
+        let result = self
+            .read_output()
+            .await?
+            .ok_or(ToolCallError::ProtocolError)?
+            .map_err(ToolCallError::ToolReturnedError)?;
+
+        // We successfully computed a result - return it even on error!
+        match self.close().await {
+            Ok(()) => Ok(result),
+            Err(err) => Err(ToolCallError::CloseFailed {
+                result: Box::new(result),
+                err,
+            }),
+        }
+    }
+
+    /// Abort the running tool and close the connection. The tool might have
+    /// finished meanwhile and the server closed the connection, which is fine.
+    async fn abort(mut self) -> Result<Value, ToolCallError> {
+        if self.send_abort().await.is_ok() {
+            let _ = self.close().await;
+        }
+        Err(ToolCallError::OnMessageAbort)
+    }
+}
+
+/// Input entries with an encoding of at least this size are sent by hash first
+const BLOB_MIN_SIZE: usize = 1024 * 1024;
+/// Blobs are sent in chunks of this size, at most one is lost when the connection drops
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// How often the client reconnects to continue an interrupted upload
+const UPLOAD_RESUMES: usize = 3;
+/// Stamped messages after which the client sends an `Ack`, large ones
+/// (emitted outputs and partial results) are acknowledged right away
+const ACK_INTERVAL: usize = 16;
+/// How often the client reconnects to resume a call, each attempt after
+/// a short delay (see [`Transport::reconnect`])
+const CALL_RESUMES: usize = 30;
+
+/// Counts the bytes of the encoding of a value, without buffering it.
+#[derive(Default)]
+struct EncodedSize(usize);
+
+impl std::io::Write for EncodedSize {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Minimal executor which runs a future to completion on the current thread.
+///
+/// The native transport blocks inside of its futures, so this usually
+/// finishes on the first poll. Other futures park the thread until woken.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut fut = std::pin::pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Transport wrapper which injects network faults into the sent frames.
+//! Faults are random but deterministic for a given seed, so that abort and
+//! error paths of client and server can be tested reproducibly.
+
+use std::time::Duration;
+
+use super::Transport;
+use crate::ConnectionError;
+
+/// Which faults to inject and how often. The default injects no faults.
+///
+/// Rates are probabilities per sent frame in `[0, 1]`. Note that dropped
+/// frames can leave both ends waiting forever (e.g. a dropped input).
+/// `disconnect_after` drops the connection instead, which clients can resume.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// Seed of the random number generator deciding on faults
+    pub seed: u64,
+    /// Fixed delay before every frame is sent. This blocks the calling thread!
+    pub latency: Duration,
+    /// Frame is silently discarded
+    pub drop_rate: f64,
+    /// Frame is held back and sent after the next one
+    pub reorder_rate: f64,
+    /// Frame is cut off at a random length
+    pub truncate_rate: f64,
+    /// Receiving fails with [`ConnectionError::ConnectionClosed`] after this
+    /// many frames, and again after as many frames of every reconnect
+    pub disconnect_after: Option<usize>,
+}
+
+/// Wraps a transport and applies the faults of a [`FaultConfig`] to every
+/// frame it sends. Wrap both ends of a connection to affect both directions.
+pub struct FaultyTransport<T: Transport> {
+    inner: T,
+    config: FaultConfig,
+    rng: u64,
+    held: Option<Vec<u8>>,
+    /// Frames received since the last (re)connect
+    received: usize,
+}
+
+impl<T: Transport> FaultyTransport<T> {
+    pub fn new(inner: T, config: FaultConfig) -> Self {
+        Self {
+            inner,
+            rng: config.seed,
+            config,
+            held: None,
+            received: 0,
+        }
+    }
+
+    /// splitmix64, uniformly distributed in `[0, 1)`
+    fn random(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        (z ^ (z >> 31)) as f64 / (u64::MAX as f64 + 1.0)
+    }
+}
+
+impl<T: Transport> Transport for FaultyTransport<T> {
+    async fn send(&mut self, mut frame: Vec<u8>) -> Result<(), ConnectionError> {
+        if !self.config.latency.is_zero() {
+            std::thread::sleep(self.config.latency);
+        }
+        if self.random() < self.config.drop_rate {
+            return Ok(());
+        }
+        if self.random() < self.config.truncate_rate {
+            let len = (self.random() * frame.len() as f64) as usize;
+            frame.truncate(len);
+        }
+        if self.held.is_none() && self.random() < self.config.reorder_rate {
+            self.held = Some(frame);
+            return Ok(());
+        }
+
+        self.inner.send(frame).await?;
+        match self.held.take() {
+            Some(held) => self.inner.send(held).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        if self
+            .config
+            .disconnect_after
+            .is_some_and(|frames| self.received >= frames)
+        {
+            return Err(ConnectionError::ConnectionClosed);
+        }
+        self.received += 1;
+        self.inner.recv().await
+    }
+
+    async fn close(self) -> Result<(), ConnectionError> {
+        // A frame that is still held back is lost, like in a real network
+        self.inner.close().await
+    }
+
+    #[cfg(feature = "client")]
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        self.inner.reconnect().await?;
+        self.held = None;
+        self.received = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! In-memory transport connecting a client and a server in the same process.
+//! This allows to test the whole protocol without opening sockets or ports.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use super::Transport;
+use crate::ConnectionError;
+
+/// Opens a new connection to the same peer and returns its client end
+pub type Connect = Arc<dyn Fn() -> Result<MemoryTransport, ConnectionError> + Send + Sync>;
+
+/// One end of an in-memory connection, create both ends with [`pair`].
+pub struct MemoryTransport {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    /// Without it, reconnecting fails
+    connect: Option<Connect>,
+}
+
+/// Create two connected transports: frames sent on one end are received by the other.
+pub fn pair() -> (MemoryTransport, MemoryTransport) {
+    let (a_tx, a_rx) = mpsc::unbounded_channel();
+    let (b_tx, b_rx) = mpsc::unbounded_channel();
+    (
+        MemoryTransport {
+            tx: a_tx,
+            rx: b_rx,
+            connect: None,
+        },
+        MemoryTransport {
+            tx: b_tx,
+            rx: a_rx,
+            connect: None,
+        },
+    )
+}
+
+/// Connect with `connect`, which is used again by [`Transport::reconnect`].
+pub fn connect(connect: Connect) -> Result<MemoryTransport, ConnectionError> {
+    let mut transport = connect()?;
+    transport.connect = Some(connect);
+    Ok(transport)
+}
+
+impl Transport for MemoryTransport {
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError> {
+        self.tx
+            .send(frame)
+            .map_err(|_| ConnectionError::ConnectionClosed)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        // Returns None once the other end was dropped / closed
+        Ok(self.rx.recv().await)
+    }
+
+    async fn close(self) -> Result<(), ConnectionError> {
+        // Dropping the sender signals the closed connection to the peer
+        Ok(())
+    }
+
+    #[cfg(feature = "client")]
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        let Some(connect) = self.connect.clone() else {
+            return Err(ConnectionError::ConnectionClosed);
+        };
+        // The old ends are dropped, which the peer sees as a closed connection
+        *self = self::connect(connect)?;
+        Ok(())
+    }
+}
@@ -1,5 +1,112 @@
 //! This module helps sending data between the client and the server via WebSocket,
 //! as well as between the async server and the sync tool via channels.
+#[cfg(any(feature = "server", feature = "client"))]
+mod attachment;
 #[cfg(feature = "server")]
 pub mod channel;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "testing")]
+pub mod faulty;
+#[cfg(feature = "testing")]
+pub mod memory;
+#[cfg(feature = "server")]
+pub mod spool;
 pub mod websocket;
+
+#[cfg(any(feature = "server", feature = "client"))]
+use crate::ConnectionError;
+#[cfg(any(feature = "server", feature = "client"))]
+use websocket::{Compression, Message};
+
+/// Moves serialized protocol frames between the two ends of a connection.
+///
+/// Implementations only deal with raw binary frames, encoding and buffering
+/// of [`websocket::Message`]s is shared and lives on top of this trait. The
+/// API is async on all targets: native implementations may block inside the
+/// returned futures, which is fine as long as they are driven by a blocking
+/// executor (see `client::block_on`).
+#[cfg(any(feature = "server", feature = "client"))]
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Send a single binary frame to the peer.
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError>;
+
+    /// Receive the next binary frame, returns `None` if the peer closed the connection.
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError>;
+
+    /// Gracefully shut down the connection.
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    async fn close(self) -> Result<(), ConnectionError>;
+
+    /// Connect to the same peer again after the connection dropped, so that
+    /// the client can resume an upload. Not supported by default.
+    #[cfg(feature = "client")]
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        Err(ConnectionError::ConnectionClosed)
+    }
+}
+
+/// Send a message, split into as many frames as needed (see `websocket::common`).
+/// Frames are staged in a temporary file if they exceed `spool_min_size`.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) async fn send_message(
+    transport: &mut impl Transport,
+    msg: &Message,
+    compression: &Compression,
+    spool_min_size: Option<usize>,
+) -> Result<(), ConnectionError> {
+    let frames = websocket::encode_frames(msg, compression, spool_min_size)?;
+    send_frames(transport, frames).await
+}
+
+/// Send a message that was already encoded with `websocket::encode_frames`.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) async fn send_frames(
+    transport: &mut impl Transport,
+    mut frames: websocket::Frames,
+) -> Result<(), ConnectionError> {
+    for index in 0..frames.len() {
+        transport.send(frames.take(index)).await?;
+    }
+    Ok(())
+}
+
+/// Receive the frames of the next message and decode it, returns `None` if
+/// the peer closed the connection before sending another message.
+#[cfg(feature = "client")]
+pub(crate) async fn recv_message(
+    transport: &mut impl Transport,
+    spool_min_size: Option<usize>,
+) -> Result<Option<Message>, ConnectionError> {
+    let msg = recv_message_sized(transport, spool_min_size).await?;
+    Ok(msg.map(|(msg, _)| msg))
+}
+
+/// Like [`recv_message`], also returns the total size of the received frames.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) async fn recv_message_sized(
+    transport: &mut impl Transport,
+    spool_min_size: Option<usize>,
+) -> Result<Option<(Message, usize)>, ConnectionError> {
+    let Some(mut frame) = transport.recv().await? else {
+        return Ok(None);
+    };
+    let mut store = websocket::FrameStore::new(spool_min_size);
+    while websocket::is_continued(&frame) {
+        store.push(frame).map_err(spool_error)?;
+        frame = transport
+            .recv()
+            .await?
+            .ok_or(ConnectionError::ConnectionClosed)?;
+    }
+    store.push(frame).map_err(spool_error)?;
+    let frames = store.finish().map_err(spool_error)?;
+    let msg = websocket::decode_frames(&frames.slices())?;
+    Ok(Some((msg, frames.size())))
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+fn spool_error(err: std::io::Error) -> ConnectionError {
+    ConnectionError::SpoolError(err)
+}
@@ -0,0 +1,62 @@
+//! Staging of large messages in temporary files instead of memory, see
+//! [`crate::ServerConfig::spool_min_size`]. The files are memory mapped, so
+//! the OS can page their content in and out as needed.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    ops::Range,
+};
+
+use memmap2::Mmap;
+
+/// Frames written to an anonymous temporary file, deleted when dropped.
+pub struct Spool {
+    file: BufWriter<File>,
+    ranges: Vec<Range<usize>>,
+    len: usize,
+}
+
+impl Spool {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(tempfile::tempfile()?),
+            ranges: Vec::new(),
+            len: 0,
+        })
+    }
+
+    pub fn push(&mut self, frame: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(frame)?;
+        self.ranges.push(self.len..self.len + frame.len());
+        self.len += frame.len();
+        Ok(())
+    }
+
+    pub fn map(self) -> std::io::Result<Spooled> {
+        let file = self.file.into_inner().map_err(|err| err.into_error())?;
+        // SAFETY: the file is anonymous (already unlinked) and private to this
+        // process, nothing else can modify it while it is mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Spooled {
+            map,
+            ranges: self.ranges,
+        })
+    }
+}
+
+/// Memory mapped content of a [`Spool`].
+pub struct Spooled {
+    map: Mmap,
+    ranges: Vec<Range<usize>>,
+}
+
+impl Spooled {
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn frame(&self, index: usize) -> &[u8] {
+        &self.map[self.ranges[index].clone()]
+    }
+}
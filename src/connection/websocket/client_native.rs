@@ -1,87 +1,334 @@
-//! Sync / blocking implementation of the WebSocket communication.
+//! Sync / blocking implementation of the WebSocket transport.
 //! This is used by the client (usually some Python script).
 
-use crate::{ToolError, Value, error::ConnectionError};
-use std::net::TcpStream;
-use tungstenite::{client::IntoClientRequest, protocol::WebSocketConfig, stream::MaybeTlsStream};
+use super::common::{Heartbeat, MAX_FRAME_SIZE, WsMessageTung, WsMessageType};
+use crate::{ParseError, connection::Transport, error::ConnectionError};
+use std::{
+    io::ErrorKind,
+    net::TcpStream,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    client::IntoClientRequest,
+    http::{HeaderMap, HeaderValue, Request, Uri, header},
+    protocol::WebSocketConfig,
+    stream::MaybeTlsStream,
+};
 
-pub struct WsChannelClientNative {
+/// Blocking WebSocket transport based on [`tungstenite`].
+///
+/// The [`Transport`] methods are `async` but block the calling thread until
+/// they are done, they never return `Poll::Pending`.
+pub struct WsTransportNative {
     socket: tungstenite::WebSocket<MaybeTlsStream<TcpStream>>,
-    /// If we tried to read a message of one type but received another, the message is buffered here.
-    buffer: Option<super::common::Message>,
+    /// For [`Transport::reconnect`], with the headers the caller added
+    uri: Uri,
+    headers: HeaderMap,
+    #[cfg(feature = "server")]
+    abort: Option<crate::AbortSignal>,
+    interrupt: Option<Arc<AtomicBool>>,
+    heartbeat: Option<Heartbeat>,
 }
 
-impl WsChannelClientNative {
+impl WsTransportNative {
+    /// Redirects of the WebSocket upgrade are followed if [`REDIRECTS_ENV`] is set.
+    /// The server is pinged as configured by [`HEARTBEAT_ENV`], see [`Self::with_heartbeat`].
     pub fn connect<Req: IntoClientRequest>(request: Req) -> Result<Self, ConnectionError> {
+        Self::connect_with_redirects(request, max_redirects())
+    }
+
+    /// Like [`WsTransportNative::connect`], authenticates with `token` for
+    /// servers with [`crate::ServerConfig::auth_token`]
+    pub fn connect_with_token(addr: &str, token: &str) -> Result<Self, ConnectionError> {
+        Self::connect(bearer_request(addr, token)?)
+    }
+
+    /// Follow up to `max_redirects` redirects of the WebSocket upgrade, more
+    /// fail with [`ConnectionError::Redirect`]. Locations can be relative or
+    /// change the scheme from `ws` to `wss` (`http(s)` is read as `ws(s)`),
+    /// but a redirect from `wss` to `ws` is never followed. Every hop gets its
+    /// own `Host` header, headers added by the caller are sent along. Like
+    /// browsers do, credentials (`Authorization`, `Cookie`) are dropped once a
+    /// redirect leads to another host, also for later reconnects.
+    pub fn connect_with_redirects<Req: IntoClientRequest>(
+        request: Req,
+        max_redirects: u8,
+    ) -> Result<Self, ConnectionError> {
         let config = WebSocketConfig::default()
-            .max_message_size(Some(256 * 1024 * 1024))
-            .max_frame_size(Some(256 * 1024 * 1024));
-        // TODO: should we look at the (ignored _) response?
-        let (socket, _) = tungstenite::client::connect_with_config(request, Some(config), 3)
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
+            .max_message_size(Some(MAX_FRAME_SIZE))
+            .max_frame_size(Some(MAX_FRAME_SIZE));
+        let mut request = request.into_client_request()?;
+        let mut uri = request.uri().clone();
+        let mut headers = added_headers(request.headers());
+        let mut hops = 0;
+        let socket = loop {
+            let err = match tungstenite::client::connect_with_config(request, Some(config), 0) {
+                Ok((socket, _)) => break socket,
+                Err(err) => ConnectionError::from_handshake(err),
+            };
+            match &err {
+                ConnectionError::Redirect { location, .. } if hops < max_redirects => {
+                    let target = redirect_uri(&uri, location).ok_or(err)?;
+                    if target.authority() != uri.authority() {
+                        remove_credentials(&mut headers);
+                    }
+                    uri = target;
+                    request = upgrade_request(&uri, &headers)?;
+                    hops += 1;
+                }
+                _ => return Err(err),
+            }
+        };
 
-        Ok(Self {
+        Self {
             socket,
-            buffer: None,
-        })
+            uri,
+            headers,
+            #[cfg(feature = "server")]
+            abort: None,
+            interrupt: None,
+            heartbeat: None,
+        }
+        .with_heartbeat(heartbeat_interval())
     }
 
-    pub fn close(mut self) -> Result<(), ConnectionError> {
-        self.socket
-            .close(None)
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
+    /// Let blocking reads return regularly to check [`Self::abort`] / [`Self::interrupt`]
+    fn poll_reads(&self) -> Result<(), ConnectionError> {
+        let stream = match self.socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::Rustls(stream) => stream.get_ref(),
+            _ => return Ok(()),
+        };
+        stream
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(tungstenite::Error::Io)?;
         Ok(())
     }
 
-    pub fn send_abort(&mut self) -> Result<(), ConnectionError> {
-        self.socket
-            .send(super::common::Message::Abort.try_into()?)
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
-        Ok(())
+    /// Ping the server every `interval` while waiting for it, so that proxies
+    /// don't close the connection during long calls. If the server doesn't
+    /// answer until the next ping is due, `recv` fails with
+    /// [`ConnectionError::HeartbeatTimeout`]. `None` sends no pings.
+    pub fn with_heartbeat(mut self, interval: Option<Duration>) -> Result<Self, ConnectionError> {
+        if interval.is_some() {
+            self.poll_reads()?;
+        }
+        self.heartbeat = interval.map(Heartbeat::new);
+        Ok(self)
     }
 
-    pub fn send_input(&mut self, input: Value) -> Result<(), ConnectionError> {
-        self.socket
-            .send(super::common::Message::Input(input).try_into()?)
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
-        Ok(())
+    /// Stop waiting for the server as soon as `interrupt` is set, `recv` then
+    /// fails with [`ConnectionError::Interrupted`] and the connection stays
+    /// open to send the abort (see [`crate::call_interruptible`]).
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Result<Self, ConnectionError> {
+        self.poll_reads()?;
+        self.interrupt = Some(interrupt);
+        Ok(self)
     }
 
-    /// Fill the message buffer, error on connection failure (but not on closed stream)
-    fn read(&mut self) -> Result<(), ConnectionError> {
-        // Only try to read if we need to and are able to:
-        if self.buffer.is_none() && self.socket.can_read() {
-            let data = self
-                .socket
-                .read()
-                .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
-            self.buffer = Some(data.try_into()?);
+    /// Close the connection as soon as `abort` is triggered, even while
+    /// waiting for the server. Used for nested calls (see
+    /// [`crate::ToolCtx::call_tool`]), where the server then aborts its tool.
+    #[cfg(feature = "server")]
+    pub fn with_abort(mut self, abort: crate::AbortSignal) -> Result<Self, ConnectionError> {
+        self.poll_reads()?;
+        self.abort = Some(abort);
+        Ok(self)
+    }
+
+    /// Blocking read that gives up when the abort signal or interrupt is set
+    fn read_or_abort(&mut self) -> Result<WsMessageTung, ConnectionError> {
+        loop {
+            match self.socket.read() {
+                // Only returned after a read timeout, which is set by `poll_reads`
+                Err(tungstenite::Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    #[cfg(feature = "server")]
+                    if let Some(abort) = &self.abort
+                        && abort.check().is_err()
+                    {
+                        let _ = self.socket.close(None);
+                        let _ = self.socket.flush();
+                        return Err(ConnectionError::ConnectionClosed);
+                    }
+                    if let Some(interrupt) = &self.interrupt
+                        && interrupt.load(Ordering::Relaxed)
+                    {
+                        return Err(ConnectionError::Interrupted);
+                    }
+                    if let Some(heartbeat) = &mut self.heartbeat
+                        && Instant::now() >= heartbeat.next_ping()
+                    {
+                        heartbeat.ping()?;
+                        self.socket.send(WsMessageTung::Ping(Default::default()))?;
+                    }
+                }
+                result => {
+                    if let (Ok(_), Some(heartbeat)) = (&result, &mut self.heartbeat) {
+                        heartbeat.seen();
+                    }
+                    return result.map_err(ConnectionError::from);
+                }
+            }
         }
+    }
+}
 
-        Ok(())
+/// Environment variable with the number of redirects of the WebSocket upgrade
+/// that [`WsTransportNative::connect`] follows, e.g. for deployments behind a
+/// load balancer. Unset (or not a number) follows none.
+pub const REDIRECTS_ENV: &str = "TOOLAPI_MAX_REDIRECTS";
+
+/// Upgrade request to `addr` with an `Authorization: Bearer` header
+pub(super) fn bearer_request(addr: &str, token: &str) -> Result<Request<()>, ConnectionError> {
+    let mut request = addr.into_client_request()?;
+    let value = HeaderValue::from_str(&format!("Bearer {token}"))
+        .map_err(|err| tungstenite::Error::HttpFormat(err.into()))?;
+    request.headers_mut().insert(header::AUTHORIZATION, value);
+    Ok(request)
+}
+
+/// Headers of an upgrade request that tungstenite generates for its uri
+const GENERATED_HEADERS: [header::HeaderName; 5] = [
+    header::HOST,
+    header::CONNECTION,
+    header::UPGRADE,
+    header::SEC_WEBSOCKET_VERSION,
+    header::SEC_WEBSOCKET_KEY,
+];
+
+/// Headers of `request` that the caller added, e.g. `Authorization`
+pub(super) fn added_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut added = headers.clone();
+    for name in GENERATED_HEADERS {
+        added.remove(name);
     }
+    added
+}
 
-    pub fn read_message(&mut self) -> Result<Option<String>, ConnectionError> {
-        self.read()?;
-        match self.buffer.take() {
-            Some(super::common::Message::ToolMsg(x)) => Ok(Some(x)),
-            Some(msg) => {
-                self.buffer = Some(msg);
-                Ok(None)
-            }
-            None => Err(ConnectionError::ConnectionClosed),
+/// Drop the headers that authenticate with the original host
+pub(super) fn remove_credentials(headers: &mut HeaderMap) {
+    headers.remove(header::AUTHORIZATION);
+    headers.remove(header::COOKIE);
+}
+
+/// New upgrade request to `uri` with the `added` headers, e.g. to follow a
+/// redirect or to reconnect with the same credentials
+pub(super) fn upgrade_request(
+    uri: &Uri,
+    added: &HeaderMap,
+) -> Result<Request<()>, ConnectionError> {
+    let mut request = uri.clone().into_client_request()?;
+    request.headers_mut().extend(added.clone());
+    Ok(request)
+}
+
+/// Environment variable with the number of seconds between the keepalive
+/// pings of [`WsTransportNative`], `0` sends none. Unset (or not a number)
+/// pings every 30 seconds.
+pub const HEARTBEAT_ENV: &str = "TOOLAPI_HEARTBEAT";
+
+/// Read from [`HEARTBEAT_ENV`]
+fn heartbeat_interval() -> Option<Duration> {
+    let secs = std::env::var(HEARTBEAT_ENV)
+        .ok()
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .unwrap_or(30.0);
+    (secs > 0.0).then(|| Duration::from_secs_f64(secs))
+}
+
+/// Read from [`REDIRECTS_ENV`]
+pub(super) fn max_redirects() -> u8 {
+    std::env::var(REDIRECTS_ENV)
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Target of a redirect from `uri`, `None` if it is invalid or would drop TLS
+pub(super) fn redirect_uri(uri: &Uri, location: &str) -> Option<Uri> {
+    let target: Uri = match location.starts_with('/') {
+        true => format!("{}://{}{location}", uri.scheme_str()?, uri.authority()?),
+        false => location.to_string(),
+    }
+    .parse()
+    .ok()?;
+    let scheme = match target.scheme_str()? {
+        "ws" | "http" => "ws",
+        "wss" | "https" => "wss",
+        _ => return None,
+    };
+    if uri.scheme_str() == Some("wss") && scheme == "ws" {
+        return None;
+    }
+    let path = target.path_and_query().map_or("/", |path| path.as_str());
+    format!("{scheme}://{}{path}", target.authority()?)
+        .parse()
+        .ok()
+}
+
+/// How often a blocking read checks for an abort, see [`WsTransportNative::with_abort`]
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Wait before reconnecting, the network might need a moment to recover
+pub(super) const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+impl Transport for WsTransportNative {
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError> {
+        self.socket
+            .send(WsMessageTung::Binary(frame.into()))
+            .map_err(ConnectionError::from)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        // Only try to read if we are able to, a closed stream is not an error
+        if !self.socket.can_read() {
+            return Ok(None);
+        }
+
+        loop {
+            return match self.read_or_abort()? {
+                WsMessageTung::Binary(raw) => Ok(Some(raw.into())),
+                WsMessageTung::Close(_) => Ok(None),
+                // Pings are answered by tungstenite
+                WsMessageTung::Ping(_) | WsMessageTung::Pong(_) => continue,
+                msg => Err(ParseError::WrongMessageType {
+                    expected: WsMessageType::Binary,
+                    found: msg.into(),
+                }
+                .into()),
+            };
         }
     }
 
-    pub fn read_output(&mut self) -> Result<Option<Result<Value, ToolError>>, ConnectionError> {
-        self.read()?;
-        match self.buffer.take() {
-            Some(super::common::Message::Output(x)) => Ok(Some(x)),
-            Some(msg) => {
-                self.buffer = Some(msg);
-                Ok(None)
-            }
-            None => Err(ConnectionError::ConnectionClosed),
+    async fn close(mut self) -> Result<(), ConnectionError> {
+        self.socket.close(None).map_err(ConnectionError::from)
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        std::thread::sleep(RECONNECT_DELAY);
+        if let Some(interrupt) = &self.interrupt
+            && interrupt.load(Ordering::Relaxed)
+        {
+            return Err(ConnectionError::Interrupted);
         }
+        let request = upgrade_request(&self.uri, &self.headers)?;
+        self.socket = Self::connect(request)?.socket;
+        if let Some(heartbeat) = &mut self.heartbeat {
+            heartbeat.seen();
+        }
+        #[cfg(feature = "server")]
+        let polling = self.interrupt.is_some() || self.abort.is_some() || self.heartbeat.is_some();
+        #[cfg(not(feature = "server"))]
+        let polling = self.interrupt.is_some() || self.heartbeat.is_some();
+        if polling {
+            self.poll_reads()?;
+        }
+        Ok(())
     }
 }
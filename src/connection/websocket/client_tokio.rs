@@ -0,0 +1,117 @@
+//! Async implementation of the WebSocket transport on a tokio runtime.
+//! This is used by async services and GUI apps (see [`crate::call_async`]).
+
+use super::{
+    client_native::{
+        RECONNECT_DELAY, added_headers, max_redirects, redirect_uri, remove_credentials,
+        upgrade_request,
+    },
+    common::{MAX_FRAME_SIZE, WsMessageTung, WsMessageType},
+};
+use crate::{ParseError, connection::Transport, error::ConnectionError};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tungstenite::{
+    client::IntoClientRequest,
+    http::{HeaderMap, Uri},
+    protocol::WebSocketConfig,
+};
+
+/// Non-blocking WebSocket transport based on [`tokio_tungstenite`].
+///
+/// Unlike [`super::WsTransportNative`], the [`Transport`] futures wait for
+/// the network without blocking the thread, so they must be driven by a
+/// tokio runtime.
+pub struct WsTransportTokio {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// For [`Transport::reconnect`], with the headers the caller added
+    uri: Uri,
+    headers: HeaderMap,
+}
+
+impl WsTransportTokio {
+    /// Redirects of the WebSocket upgrade are followed if
+    /// [`super::REDIRECTS_ENV`] is set.
+    pub async fn connect<Req: IntoClientRequest>(request: Req) -> Result<Self, ConnectionError> {
+        Self::connect_with_redirects(request, max_redirects()).await
+    }
+
+    /// Follows redirects like [`super::WsTransportNative::connect_with_redirects`].
+    pub async fn connect_with_redirects<Req: IntoClientRequest>(
+        request: Req,
+        max_redirects: u8,
+    ) -> Result<Self, ConnectionError> {
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_FRAME_SIZE))
+            .max_frame_size(Some(MAX_FRAME_SIZE));
+        let mut request = request.into_client_request()?;
+        let mut uri = request.uri().clone();
+        let mut headers = added_headers(request.headers());
+        let mut hops = 0;
+        let socket = loop {
+            let err =
+                match tokio_tungstenite::connect_async_with_config(request, Some(config), false)
+                    .await
+                {
+                    Ok((socket, _)) => break socket,
+                    Err(err) => ConnectionError::from_handshake(err),
+                };
+            match &err {
+                ConnectionError::Redirect { location, .. } if hops < max_redirects => {
+                    let target = redirect_uri(&uri, location).ok_or(err)?;
+                    if target.authority() != uri.authority() {
+                        remove_credentials(&mut headers);
+                    }
+                    uri = target;
+                    request = upgrade_request(&uri, &headers)?;
+                    hops += 1;
+                }
+                _ => return Err(err),
+            }
+        };
+
+        Ok(Self {
+            socket,
+            uri,
+            headers,
+        })
+    }
+}
+
+impl Transport for WsTransportTokio {
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError> {
+        self.socket
+            .send(WsMessageTung::Binary(frame.into()))
+            .await
+            .map_err(ConnectionError::from)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        loop {
+            // A closed stream is not an error
+            return match self.socket.next().await.transpose()? {
+                None | Some(WsMessageTung::Close(_)) => Ok(None),
+                Some(WsMessageTung::Binary(raw)) => Ok(Some(raw.into())),
+                // Keepalive of the server, answered by tungstenite
+                Some(WsMessageTung::Ping(_) | WsMessageTung::Pong(_)) => continue,
+                Some(msg) => Err(ParseError::WrongMessageType {
+                    expected: WsMessageType::Binary,
+                    found: msg.into(),
+                }
+                .into()),
+            };
+        }
+    }
+
+    async fn close(mut self) -> Result<(), ConnectionError> {
+        self.socket.close(None).await.map_err(ConnectionError::from)
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        tokio::time::sleep(RECONNECT_DELAY).await;
+        let request = upgrade_request(&self.uri, &self.headers)?;
+        self.socket = Self::connect(request).await?.socket;
+        Ok(())
+    }
+}
@@ -1,95 +1,122 @@
-//! Async WebSocket client for wasm32 targets using the browser's WebSocket API.
-//! This mirrors the interface of `WsChannelSync` but uses async methods since
-//! blocking is not possible on wasm32-unknown-unknown.
+//! Async WebSocket transport for wasm32 targets using the browser's WebSocket API.
+//! Blocking is not possible on wasm32-unknown-unknown, so this is truly async
+//! while the native transport blocks inside its futures.
 
-use crate::{ToolError, Value, error::ConnectionError};
+use super::common::{WsMessageType, WsMessageWasm};
+use crate::{ParseError, connection::Transport, error::ConnectionError};
 use futures::{SinkExt, StreamExt};
+use std::time::Duration;
 use ws_stream_wasm::{WsMeta, WsStream};
 
-use super::common::Message;
+/// Backoff schedule for retrying a failed connection attempt.
+///
+/// Browser tabs, especially on mobile networks, regularly fail to connect on
+/// the first try. The delay between attempts doubles after every failure.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of connection attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay after the first failed attempt
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between two attempts
+    pub max_delay: Duration,
+}
 
-/// Async WebSocket client for wasm targets.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next attempt after `failed` attempts failed already.
+    fn delay(&self, failed: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failed.saturating_sub(1));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+/// Async WebSocket transport for wasm targets.
 ///
-/// Uses the browser's `WebSocket` API via [`ws_stream_wasm`]. The API mirrors
-/// [`super::sync::WsChannelSync`] but with async methods where the native
-/// version would block.
-pub struct WsChannelClientWasm {
+/// Uses the browser's `WebSocket` API via [`ws_stream_wasm`].
+pub struct WsTransportWasm {
     ws_meta: WsMeta,
     ws_stream: WsStream,
-    /// If we tried to read a message of one type but received another, the message is buffered here.
-    buffer: Option<Message>,
+    /// For [`Transport::reconnect`]
+    addr: String,
 }
 
-impl WsChannelClientWasm {
+impl WsTransportWasm {
     /// Connect to a WebSocket server. Resolves when the connection is open.
     pub async fn connect(addr: &str) -> Result<Self, ConnectionError> {
         let (ws_meta, ws_stream) = WsMeta::connect(addr, None)
             .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
+            .map_err(ConnectionError::from)?;
 
         Ok(Self {
             ws_meta,
             ws_stream,
-            buffer: None,
+            addr: addr.to_string(),
         })
     }
 
-    pub async fn close(self) -> Result<(), ConnectionError> {
-        self.ws_meta
-            .close()
-            .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
-        Ok(())
-    }
-
-    pub async fn send_abort(&mut self) -> Result<(), ConnectionError> {
-        self.ws_stream
-            .send(Message::Abort.try_into()?)
-            .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))
+    /// Like [`Self::connect`], but retries failed attempts according to `policy`.
+    /// Returns the error of the last attempt if all of them failed, or the
+    /// first error that is not [retryable](ConnectionError::is_retryable).
+    pub async fn connect_with_retry(
+        addr: &str,
+        policy: &RetryPolicy,
+    ) -> Result<Self, ConnectionError> {
+        let mut failed = 0;
+        loop {
+            match Self::connect(addr).await {
+                Ok(transport) => return Ok(transport),
+                Err(err) => {
+                    failed += 1;
+                    if failed >= policy.max_attempts || !err.is_retryable() {
+                        return Err(err);
+                    }
+                    gloo_timers::future::sleep(policy.delay(failed)).await;
+                }
+            }
+        }
     }
+}
 
-    pub async fn send_input(&mut self, input: Value) -> Result<(), ConnectionError> {
+impl Transport for WsTransportWasm {
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError> {
         self.ws_stream
-            .send(Message::Input(input).try_into()?)
+            .send(WsMessageWasm::Binary(frame))
             .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))
+            .map_err(ConnectionError::from)
     }
 
-    /// Fill the message buffer by reading the next message from the stream
-    async fn read(&mut self) -> Result<(), ConnectionError> {
-        if self.buffer.is_none() {
-            if let Some(msg) = self.ws_stream.next().await {
-                self.buffer = Some(msg.try_into()?);
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        // The stream ends (returns None) when the connection was closed
+        match self.ws_stream.next().await {
+            Some(WsMessageWasm::Binary(raw)) => Ok(Some(raw)),
+            Some(msg) => Err(ParseError::WrongMessageType {
+                expected: WsMessageType::Binary,
+                found: msg.into(),
             }
+            .into()),
+            None => Ok(None),
         }
-
-        Ok(())
     }
 
-    pub async fn read_message(&mut self) -> Result<Option<String>, ConnectionError> {
-        self.read().await?;
-        match self.buffer.take() {
-            Some(Message::ToolMsg(x)) => Ok(Some(x)),
-            Some(msg) => {
-                self.buffer = Some(msg);
-                Ok(None)
-            }
-            None => Err(ConnectionError::ConnectionClosed),
-        }
+    async fn close(self) -> Result<(), ConnectionError> {
+        self.ws_meta.close().await.map_err(ConnectionError::from)?;
+        Ok(())
     }
 
-    pub async fn read_output(
-        &mut self,
-    ) -> Result<Option<Result<Value, ToolError>>, ConnectionError> {
-        self.read().await?;
-        match self.buffer.take() {
-            Some(Message::Output(x)) => Ok(Some(x)),
-            Some(msg) => {
-                self.buffer = Some(msg);
-                Ok(None)
-            }
-            None => Err(ConnectionError::ConnectionClosed),
-        }
+    async fn reconnect(&mut self) -> Result<(), ConnectionError> {
+        *self = Self::connect_with_retry(&self.addr, &RetryPolicy::default()).await?;
+        Ok(())
     }
 }
@@ -1,24 +1,320 @@
 //! Common structures shared by client and server / sync and async impls.
 //! This is the heart of the communication - both sides have to agree on this!
 
+#[cfg(feature = "server")]
+use crate::connection::spool::{Spool, Spooled};
+#[cfg(any(feature = "server", feature = "client"))]
+use crate::{ParseError, ToolError, Value, value::dynamic::Dict};
 #[cfg(any(feature = "server", feature = "client"))]
-use crate::{ParseError, ToolError, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
 
+// NOTE: changes to the serialized representation must be mirrored in src/protocol.d.ts
 #[cfg(any(feature = "server", feature = "client"))]
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub enum Message {
+    /// First message of both sides on every connection, see [`peek_hello`].
+    /// The client waits for the reply of the server before sending the input.
+    Hello {
+        protocol_version: u32,
+        capabilities: Capabilities,
+    },
     Input(Value),
     Output(Result<Value, ToolError>),
     ToolMsg(String),
     Abort,
+    /// Input dict where large entries are replaced by the hash of their encoding.
+    /// The server replies with `Missing`, the client then sends these in `BlobChunk`s.
+    CachedInput {
+        input: Value,
+        refs: HashMap<String, BlobHash>,
+    },
+    /// Bytes of the `AttachmentRef` with this id in the next `Input` or
+    /// `Output`, followed by `size` bytes in raw frames (neither encoded nor
+    /// compressed). Only sent to peers with [`Capabilities::ATTACHMENTS`].
+    Attachment {
+        id: u32,
+        size: u64,
+    },
+    /// `CachedInput` entries which are not in the server's cache
+    Missing(Vec<MissingBlob>),
+    /// Part of the msgpack encoding of a missing entry, starting at `offset`.
+    /// Chunks of a blob are sent in order, `size` is the length of the whole
+    /// encoding. The server checks the hash once it is complete.
+    BlobChunk {
+        hash: BlobHash,
+        offset: u64,
+        size: u64,
+        data: serde_bytes::ByteBuf,
+    },
+    /// Completed fraction of the work in `[0, 1]`, with an optional description
+    Progress {
+        fraction: f64,
+        message: Option<String>,
+    },
+    /// The server sends `ToolMsg`, `Progress`, `Emit`, `PartialResult`,
+    /// `Queued` and `Accepted` wrapped in this: `seq` counts them per call
+    /// (starting at 0) and `time` is the number of seconds since the input was
+    /// received (monotonic clock of the server)
+    Stamped {
+        seq: u64,
+        time: f64,
+        message: Box<Message>,
+    },
+    /// Sent (stamped) by the server right before a successful or failed `Output`
+    JobMeta(JobMeta),
+    /// Named intermediate result, sent by the tool any number of times
+    Emit {
+        name: String,
+        value: Value,
+    },
+    /// Entries of the result computed so far (e.g. the slabs of a volume that
+    /// are done), sent by the tool any number of times
+    PartialResult(Dict),
+    /// Seed for the random number generators of the tool, sent by the client
+    /// right before its `Input` or `CachedInput`, see [`crate::ToolCtx::seed`]
+    Seed(u64),
+    /// Sent (stamped) by the server while the call waits for a free slot (see
+    /// [`crate::ServerConfig::max_concurrent_tools`]) whenever its place in
+    /// the queue changes, `position` is the number of calls ahead of it
+    Queued {
+        position: u32,
+    },
+    /// Sent (stamped) by the server to clients with [`Capabilities::RESUME`]
+    /// once it received the input. If the connection drops, the tool keeps
+    /// running and the client can `Resume` the call with this id. Only the
+    /// client knows the random `secret`, which other clients can't guess.
+    Accepted {
+        job_id: String,
+        secret: String,
+    },
+    /// Sent by the client instead of an input, to continue a call after its
+    /// connection dropped. The server sends all stamped messages from `seq`
+    /// on (those the client didn't receive), followed by the result.
+    Resume {
+        job_id: String,
+        secret: String,
+        seq: u64,
+    },
+    /// Sent by the client after an `Accepted` every few stamped messages: it
+    /// received all of them before `seq`, the server doesn't keep them for a
+    /// `Resume` anymore
+    Ack {
+        seq: u64,
+    },
+}
+
+/// Optional features of a peer, exchanged in the `Hello`. A peer only uses
+/// features the other side announced and ignores unknown ones, so new features
+/// can be rolled out without a new [`crate::PROTOCOL_VERSION`].
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Capabilities(pub BTreeSet<String>);
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl Capabilities {
+    /// Decompresses zstd, otherwise messages to the peer are sent uncompressed
+    pub const ZSTD: &str = "zstd";
+    /// Client handles `Progress`, otherwise progress is sent as `ToolMsg`
+    pub const PROGRESS: &str = "progress";
+    /// Client handles `Emit`, otherwise emitted outputs are only announced as `ToolMsg`
+    pub const EMIT: &str = "emit";
+    /// Client handles `PartialResult`, otherwise partial results are only announced as `ToolMsg`
+    pub const PARTIAL: &str = "partial";
+    /// Server caches inputs, otherwise clients send `Input` instead of `CachedInput`
+    pub const INPUT_CACHE: &str = "input_cache";
+    /// Receives large `Bytes` as `Attachment`s, otherwise they are part of the message
+    pub const ATTACHMENTS: &str = "attachments";
+    /// Client handles `Queued`, otherwise the position in the queue is sent as `ToolMsg`
+    pub const QUEUED: &str = "queued";
+    /// Client resumes calls after a dropped connection, otherwise the tool is
+    /// aborted when the connection drops and there is no `Accepted`
+    pub const RESUME: &str = "resume";
+    /// Client sends the [`Value::canonical_hash`](crate::Value::canonical_hash)
+    /// of cached inputs, otherwise the blake3 hash of their msgpack encoding
+    pub const CANONICAL_HASH: &str = "canonical_hash";
+
+    #[cfg(feature = "client")]
+    pub(crate) fn client() -> Self {
+        Self::from_iter([
+            Self::ZSTD,
+            Self::PROGRESS,
+            Self::EMIT,
+            Self::PARTIAL,
+            Self::ATTACHMENTS,
+            Self::QUEUED,
+            Self::RESUME,
+            Self::CANONICAL_HASH,
+        ])
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn server(input_cache: bool) -> Self {
+        let cache = input_cache.then_some(Self::INPUT_CACHE);
+        Self::from_iter([Self::ZSTD, Self::ATTACHMENTS].into_iter().chain(cache))
+    }
+
+    pub fn contains(&self, capability: &str) -> bool {
+        self.0.contains(capability)
+    }
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl<'a> FromIterator<&'a str> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        Self(iter.into_iter().map(str::to_string).collect())
+    }
+}
+
+/// Protocol version and capabilities of a `Hello` sent by a peer with any
+/// protocol version. The version is the first field of `Hello` in all of them,
+/// capabilities the second one since version 16 (empty before). `None` for
+/// other messages (e.g. an `Input` of a client from before the handshake).
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) fn peek_hello(frame: &[u8]) -> Option<(u32, Capabilities)> {
+    use serde::de::{IgnoredAny, SeqAccess, Visitor};
+
+    #[derive(serde::Deserialize)]
+    enum Peek {
+        Hello(PeerHello),
+    }
+    struct PeerHello(u32, Capabilities);
+
+    impl<'de> serde::Deserialize<'de> for PeerHello {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(HelloVisitor)
+        }
+    }
+
+    struct HelloVisitor;
+
+    impl<'de> Visitor<'de> for HelloVisitor {
+        type Value = PeerHello;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "a sequence starting with the protocol version")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let version = seq
+                .next_element()?
+                .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+            let capabilities = seq.next_element()?.unwrap_or_default();
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            Ok(PeerHello(version, capabilities))
+        }
+    }
+
+    let Peek::Hello(PeerHello(version, capabilities)) = decode_frames(&[frame]).ok()?;
+    Some((version, capabilities))
+}
+
+/// Cost of a single tool call, measured by the server.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "JobMetaWire", into = "JobMetaWire")]
+pub struct JobMeta {
+    /// Id of the call in the server logs
+    pub job_id: String,
+    /// See [`crate::ServerConfig::tool_version`]
+    pub tool_version: Option<String>,
+    /// Time from receiving the input until the tool returned
+    pub wall_time: Duration,
+    /// CPU time of the thread running the tool, if the platform provides it.
+    /// Threads spawned by the tool are not included.
+    pub cpu_time: Option<Duration>,
+    /// Total size of all messages received from the client (as transferred)
+    pub input_size: u64,
+    /// Size of the `Output` message (as transferred)
+    pub output_size: u64,
+    /// Lists shortened to the size limit of the server, see [`crate::OutputPolicy`]
+    pub truncated: Vec<String>,
+    /// Seed the tool ran with, `None` if it didn't run (e.g. invalid input).
+    /// Passing it to [`crate::call_with_seed`] reproduces the call.
+    pub seed: Option<u64>,
+}
+
+/// Durations are sent as seconds, like the time of `Stamped`
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JobMetaWire {
+    job_id: String,
+    tool_version: Option<String>,
+    wall_time: f64,
+    cpu_time: Option<f64>,
+    input_size: u64,
+    output_size: u64,
+    truncated: Vec<String>,
+    seed: Option<u64>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl From<JobMetaWire> for JobMeta {
+    fn from(wire: JobMetaWire) -> Self {
+        let secs = |secs: f64| Duration::try_from_secs_f64(secs).unwrap_or_default();
+        Self {
+            job_id: wire.job_id,
+            tool_version: wire.tool_version,
+            wall_time: secs(wire.wall_time),
+            cpu_time: wire.cpu_time.map(secs),
+            input_size: wire.input_size,
+            output_size: wire.output_size,
+            truncated: wire.truncated,
+            seed: wire.seed,
+        }
+    }
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl From<JobMeta> for JobMetaWire {
+    fn from(meta: JobMeta) -> Self {
+        Self {
+            job_id: meta.job_id,
+            tool_version: meta.tool_version,
+            wall_time: meta.wall_time.as_secs_f64(),
+            cpu_time: meta.cpu_time.map(|t| t.as_secs_f64()),
+            input_size: meta.input_size,
+            output_size: meta.output_size,
+            truncated: meta.truncated,
+            seed: meta.seed,
+        }
+    }
+}
+
+/// Hash of a cached input value: the [`Value::canonical_hash`](crate::Value::canonical_hash)
+/// for clients with [`Capabilities::CANONICAL_HASH`] (like the Rust client),
+/// which doesn't depend on the order of dict entries. Other clients (e.g. in
+/// JS) send the blake3 hash of the uploaded msgpack encoding (`BlobHash::of`).
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct BlobHash(#[serde(with = "serde_bytes")] pub [u8; 32]);
+
+/// Entry of `Missing`: the server kept the first `received` bytes of an upload
+/// that was interrupted, e.g. by a dropped connection, so that the client only
+/// sends the rest after reconnecting.
+#[cfg(any(feature = "server", feature = "client"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MissingBlob {
+    pub hash: BlobHash,
+    pub received: u64,
+}
+
+#[cfg(feature = "server")]
+impl BlobHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(*blake3::hash(bytes).as_bytes())
+    }
 }
 
 #[cfg(feature = "server")]
-type WsMessageAxum = axum::extract::ws::Message;
+pub type WsMessageAxum = axum::extract::ws::Message;
 #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
-type WsMessageTung = tungstenite::Message;
+pub type WsMessageTung = tungstenite::Message;
 #[cfg(all(feature = "client", target_arch = "wasm32"))]
-type WsMessageWasm = ws_stream_wasm::WsMessage;
+pub type WsMessageWasm = ws_stream_wasm::WsMessage;
 
 /// Used for error messages only on message type mismatch
 #[derive(Debug)]
@@ -67,96 +363,504 @@ impl From<WsMessageWasm> for WsMessageType {
     }
 }
 
+/// Keepalive of a WebSocket connection: idle proxies close connections
+/// without traffic, so a ping is sent every `interval`. The peer answers with
+/// a pong; if it sent nothing at all until the next ping is due, it is gone.
+#[cfg(any(
+    feature = "server",
+    all(feature = "client", not(target_arch = "wasm32"))
+))]
+pub(crate) struct Heartbeat {
+    interval: Duration,
+    next_ping: std::time::Instant,
+    /// Nothing was received since the last ping
+    unanswered: bool,
+}
+
+#[cfg(any(
+    feature = "server",
+    all(feature = "client", not(target_arch = "wasm32"))
+))]
+impl Heartbeat {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_ping: std::time::Instant::now() + interval,
+            unanswered: false,
+        }
+    }
+
+    pub(crate) fn next_ping(&self) -> std::time::Instant {
+        self.next_ping
+    }
+
+    /// Called for every message received from the peer, pongs included
+    pub(crate) fn seen(&mut self) {
+        self.unanswered = false;
+    }
+
+    /// Called once [`Self::next_ping`] passed, before sending the ping.
+    /// Fails if the previous one was not answered.
+    pub(crate) fn ping(&mut self) -> Result<(), crate::ConnectionError> {
+        if self.unanswered {
+            return Err(crate::ConnectionError::HeartbeatTimeout);
+        }
+        self.unanswered = true;
+        self.next_ping = std::time::Instant::now() + self.interval;
+        Ok(())
+    }
+}
+
+/// How frames are compressed before sending, see [`crate::ServerConfig::compression`].
+///
+/// Receivers handle both compressed and uncompressed frames (zstd frames are
+/// recognized by their magic number), so this only affects the sender.
 #[cfg(any(feature = "server", feature = "client"))]
-fn deserialize(raw: &[u8]) -> Result<Message, ParseError> {
-    use ruzstd::io::Read;
-    let mut decoder = ruzstd::decoding::StreamingDecoder::new(raw)
-        .map_err(|e| ParseError::DecompressionError(std::io::Error::other(e)))?;
-    let mut decompressed = Vec::new();
-    decoder
-        .read_to_end(&mut decompressed)
-        .map_err(ParseError::DecompressionError)?;
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// Messages with fewer bytes (up to 1 MiB) are sent uncompressed. The default
+    /// skips compression for small control messages like tool messages or aborts.
+    /// `usize::MAX` disables compression, also used for peers without zstd.
+    pub min_size: usize,
+    /// zstd compression level. Only used with the `zstd` feature, the pure
+    /// Rust encoder always uses its fastest level (roughly level 1).
+    pub level: i32,
+    /// Once a message grew beyond this many bytes, the rest of it is compressed
+    /// on all threads of the rayon pool. Only used with the `parallel` feature.
+    pub parallel_min_size: Option<usize>,
+}
 
-    rmp_serde::from_slice(&decompressed).map_err(ParseError::DeserializationError)
+#[cfg(any(feature = "server", feature = "client"))]
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            min_size: 512,
+            level: 1,
+            parallel_min_size: Some(32 * 1024 * 1024),
+        }
+    }
 }
 
 #[cfg(any(feature = "server", feature = "client"))]
-fn serialize(msg: &Message) -> Result<Vec<u8>, ParseError> {
-    let raw = rmp_serde::to_vec(msg).map_err(ParseError::SerializationError)?;
-    Ok(ruzstd::encoding::compress_to_vec(
-        raw.as_slice(),
-        ruzstd::encoding::CompressionLevel::Fastest,
-    ))
+impl Compression {
+    /// This compression if the peer announced [`Capabilities::ZSTD`], none otherwise
+    pub(crate) fn for_peer(&self, peer: &Capabilities) -> Self {
+        match peer.contains(Capabilities::ZSTD) {
+            true => self.clone(),
+            false => Self {
+                min_size: usize::MAX,
+                ..self.clone()
+            },
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.min_size != usize::MAX
+    }
 }
 
 #[cfg(feature = "server")]
-impl TryFrom<WsMessageAxum> for Message {
-    type Error = ParseError;
+pub fn deserialize(raw: &[u8]) -> Result<Message, ParseError> {
+    decode(raw)
+}
 
-    fn try_from(value: WsMessageAxum) -> Result<Self, Self::Error> {
-        match value {
-            WsMessageAxum::Binary(raw) => deserialize(raw.as_ref()),
-            msg => Err(ParseError::WrongMessageType {
-                expected: WsMessageType::Binary,
-                found: msg.into(),
-            }),
-        }
+#[cfg(feature = "server")]
+pub fn serialize(msg: &Message) -> Result<Vec<u8>, ParseError> {
+    encode(msg)
+}
+
+/// Decode any type from the wire format (optionally zstd compressed msgpack)
+#[cfg(feature = "server")]
+pub(crate) fn decode<T: serde::de::DeserializeOwned>(raw: &[u8]) -> Result<T, ParseError> {
+    decode_frames(&[raw])
+}
+
+/// Encode any type with the wire format and default [`Compression`] into a
+/// single buffer (the concatenated content of all frames).
+#[cfg(feature = "server")]
+pub(crate) fn encode<T: serde::Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, ParseError> {
+    let mut frames = encode_frames(value, &Compression::default(), None)?;
+    if frames.len() == 1 {
+        return Ok(frames.take(0));
     }
+    Ok(frames
+        .slices()
+        .into_iter()
+        .flat_map(frame_content)
+        .copied()
+        .collect())
 }
 
-#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
-impl TryFrom<WsMessageTung> for Message {
-    type Error = ParseError;
+// Messages are streamed from msgpack through the compressor into frames of
+// at most about FRAME_SIZE bytes, so that neither the full msgpack encoding
+// nor a single huge compressed buffer (and its reallocations) is needed.
+// Every block of msgpack is compressed into its own zstd frame, the receiver
+// decompresses all concatenated zstd frames as a single stream.
 
-    fn try_from(value: WsMessageTung) -> Result<Self, Self::Error> {
-        match value {
-            WsMessageTung::Binary(raw) => deserialize(raw.as_ref()),
-            msg => Err(ParseError::WrongMessageType {
-                expected: WsMessageType::Binary,
-                found: msg.into(),
-            }),
+/// Amount of msgpack that is compressed at once
+#[cfg(any(feature = "server", feature = "client"))]
+const BLOCK_SIZE: usize = 1024 * 1024;
+/// Maximum size of a WebSocket frame before a new one is started
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) const FRAME_SIZE: usize = 8 * 1024 * 1024;
+/// Largest WebSocket frame accepted by servers and native clients
+#[cfg(any(
+    feature = "server",
+    all(feature = "client", not(target_arch = "wasm32"))
+))]
+pub(crate) const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+/// Prefix of all but the last frame of a message, not valid msgpack or zstd
+#[cfg(any(feature = "server", feature = "client"))]
+const CONTINUED_MAGIC: [u8; 4] = *b"MRX+";
+/// Start of every zstd frame. Can't be confused with an uncompressed message,
+/// which always starts with a msgpack map or string marker.
+#[cfg(any(feature = "server", feature = "client"))]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// True if more frames follow that belong to the same message
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) fn is_continued(frame: &[u8]) -> bool {
+    frame.starts_with(&CONTINUED_MAGIC)
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+fn frame_content(frame: &[u8]) -> &[u8] {
+    frame.strip_prefix(&CONTINUED_MAGIC).unwrap_or(frame)
+}
+
+/// Encode `value` into the frames of a single message, see [`decode_frames`].
+/// Frames are staged in a temporary file if they exceed `spool_min_size`.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) fn encode_frames<T: serde::Serialize + ?Sized>(
+    value: &T,
+    compression: &Compression,
+    spool_min_size: Option<usize>,
+) -> Result<Frames, ParseError> {
+    let mut writer = FrameWriter {
+        compression,
+        written: 0,
+        block: Vec::new(),
+        pending: Vec::new(),
+        current: Vec::new(),
+        store: FrameStore::new(spool_min_size),
+    };
+    rmp_serde::encode::write(&mut writer, value).map_err(ParseError::SerializationError)?;
+    writer.finish().map_err(ParseError::CompressionError)
+}
+
+/// Decode all frames of a single message, the last one not being continued.
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) fn decode_frames<T: serde::de::DeserializeOwned>(
+    frames: &[impl AsRef<[u8]>],
+) -> Result<T, ParseError> {
+    let reader = FrameReader {
+        parts: frames.iter().map(|f| frame_content(f.as_ref())).collect(),
+    };
+    if reader
+        .parts
+        .front()
+        .is_some_and(|p| p.starts_with(&ZSTD_MAGIC))
+    {
+        let decoder = ZstdDecoder::new(reader).map_err(ParseError::DecompressionError)?;
+        rmp_serde::from_read(std::io::BufReader::with_capacity(64 * 1024, decoder))
+            .map_err(ParseError::DeserializationError)
+    } else {
+        rmp_serde::from_read(reader).map_err(ParseError::DeserializationError)
+    }
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+struct FrameWriter<'a> {
+    compression: &'a Compression,
+    /// Total size of the msgpack encoding so far
+    written: usize,
+    block: Vec<u8>,
+    /// Full blocks waiting to be compressed in parallel
+    #[cfg_attr(
+        not(all(feature = "parallel", not(target_arch = "wasm32"))),
+        allow(dead_code)
+    )]
+    pending: Vec<Vec<u8>>,
+    /// Frame that is currently filled, all frames start with CONTINUED_MAGIC
+    /// which is removed from the last one
+    current: Vec<u8>,
+    store: FrameStore,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl FrameWriter<'_> {
+    fn block_full(&mut self) -> std::io::Result<()> {
+        if !self.compression.enabled() {
+            let block = std::mem::take(&mut self.block);
+            return self.append(&block);
+        }
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        if self
+            .compression
+            .parallel_min_size
+            .is_some_and(|min| self.written >= min)
+        {
+            self.pending.push(std::mem::take(&mut self.block));
+            if self.pending.len() >= rayon::current_num_threads() {
+                self.compress_pending()?;
+            }
+            return Ok(());
         }
+
+        let compressed = compress_block(&self.block, self.compression.level)?;
+        self.block.clear();
+        self.append(&compressed)
+    }
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    fn compress_pending(&mut self) -> std::io::Result<()> {
+        use rayon::prelude::*;
+        let level = self.compression.level;
+        let compressed = self
+            .pending
+            .par_iter()
+            .map(|block| compress_block(block, level))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        self.pending.clear();
+        compressed.iter().try_for_each(|c| self.append(c))
+    }
+
+    fn append(&mut self, compressed: &[u8]) -> std::io::Result<()> {
+        if self.current.len() >= FRAME_SIZE {
+            self.store.push(std::mem::take(&mut self.current))?;
+        }
+        if self.current.is_empty() {
+            self.current.extend_from_slice(&CONTINUED_MAGIC);
+        }
+        self.current.extend_from_slice(compressed);
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<Frames> {
+        // Small messages are sent uncompressed
+        if self.written < self.compression.min_size.min(BLOCK_SIZE) {
+            return Ok(Frames::Memory(vec![self.block]));
+        }
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        self.compress_pending()?;
+        if !self.block.is_empty() {
+            self.block_full()?;
+        }
+        self.current.drain(..CONTINUED_MAGIC.len());
+        self.store.push(self.current)?;
+        self.store.finish()
     }
 }
 
-#[cfg(all(feature = "client", target_arch = "wasm32"))]
-impl TryFrom<WsMessageWasm> for Message {
-    type Error = ParseError;
+#[cfg(any(feature = "server", feature = "client"))]
+impl std::io::Write for FrameWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        self.written += len;
+        if self.block.len() == BLOCK_SIZE {
+            self.block_full()?;
+        }
+        Ok(len)
+    }
 
-    fn try_from(value: WsMessageWasm) -> Result<Self, Self::Error> {
-        match value {
-            WsMessageWasm::Binary(raw) => deserialize(raw.as_ref()),
-            msg => Err(ParseError::WrongMessageType {
-                expected: WsMessageType::Binary,
-                found: msg.into(),
-            }),
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the frames of a single message, in memory or staged in a
+/// temporary file once they exceed `spool_min_size` (server only).
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) struct FrameStore {
+    frames: Vec<Vec<u8>>,
+    #[cfg(feature = "server")]
+    size: usize,
+    #[cfg(feature = "server")]
+    spool_min_size: Option<usize>,
+    #[cfg(feature = "server")]
+    spool: Option<Spool>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl FrameStore {
+    #[cfg_attr(not(feature = "server"), allow(unused_variables))]
+    pub fn new(spool_min_size: Option<usize>) -> Self {
+        Self {
+            frames: Vec::new(),
+            #[cfg(feature = "server")]
+            size: 0,
+            #[cfg(feature = "server")]
+            spool_min_size,
+            #[cfg(feature = "server")]
+            spool: None,
         }
     }
+
+    pub fn push(&mut self, frame: Vec<u8>) -> std::io::Result<()> {
+        #[cfg(feature = "server")]
+        {
+            self.size += frame.len();
+            if self.spool.is_none() && self.spool_min_size.is_some_and(|min| self.size >= min) {
+                let mut spool = Spool::new()?;
+                self.frames.drain(..).try_for_each(|f| spool.push(&f))?;
+                self.spool = Some(spool);
+            }
+            if let Some(spool) = &mut self.spool {
+                return spool.push(&frame);
+            }
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    pub fn finish(self) -> std::io::Result<Frames> {
+        #[cfg(feature = "server")]
+        if let Some(spool) = self.spool {
+            return spool.map().map(Frames::Spooled);
+        }
+        Ok(Frames::Memory(self.frames))
+    }
 }
 
-#[cfg(feature = "server")]
-impl TryFrom<Message> for WsMessageAxum {
-    type Error = ParseError;
+/// All frames of a single message, see [`FrameStore`].
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) enum Frames {
+    Memory(Vec<Vec<u8>>),
+    #[cfg(feature = "server")]
+    Spooled(Spooled),
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl Frames {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Memory(frames) => frames.len(),
+            #[cfg(feature = "server")]
+            Self::Spooled(spooled) => spooled.len(),
+        }
+    }
+
+    /// Move a frame out (copy if spooled), it can't be taken again
+    pub fn take(&mut self, index: usize) -> Vec<u8> {
+        match self {
+            Self::Memory(frames) => std::mem::take(&mut frames[index]),
+            #[cfg(feature = "server")]
+            Self::Spooled(spooled) => spooled.frame(index).to_vec(),
+        }
+    }
+
+    /// Total size of all frames, before any was taken
+    pub fn size(&self) -> usize {
+        self.slices().iter().map(|frame| frame.len()).sum()
+    }
 
-    fn try_from(value: Message) -> Result<Self, Self::Error> {
-        Ok(WsMessageAxum::Binary(serialize(&value)?.into()))
+    pub fn slices(&self) -> Vec<&[u8]> {
+        match self {
+            Self::Memory(frames) => frames.iter().map(Vec::as_slice).collect(),
+            #[cfg(feature = "server")]
+            Self::Spooled(spooled) => (0..spooled.len()).map(|i| spooled.frame(i)).collect(),
+        }
     }
 }
 
-#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
-impl TryFrom<Message> for WsMessageTung {
-    type Error = ParseError;
+/// Reads the content of multiple frames as one continuous stream.
+#[cfg(any(feature = "server", feature = "client"))]
+struct FrameReader<'a> {
+    parts: std::collections::VecDeque<&'a [u8]>,
+}
 
-    fn try_from(value: Message) -> Result<Self, Self::Error> {
-        Ok(WsMessageTung::Binary(serialize(&value)?.into()))
+#[cfg(any(feature = "server", feature = "client"))]
+impl FrameReader<'_> {
+    fn is_empty(&self) -> bool {
+        self.parts.iter().all(|p| p.is_empty())
     }
 }
 
-#[cfg(all(feature = "client", target_arch = "wasm32"))]
-impl TryFrom<Message> for WsMessageWasm {
-    type Error = ParseError;
+#[cfg(any(feature = "server", feature = "client"))]
+impl std::io::Read for FrameReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some(part) = self.parts.front_mut() {
+            if !part.is_empty() {
+                return part.read(buf);
+            }
+            self.parts.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+/// Decompresses any number of concatenated zstd frames.
+#[cfg(any(feature = "server", feature = "client"))]
+struct ZstdDecoder<'a> {
+    decoder:
+        Option<ruzstd::decoding::StreamingDecoder<FrameReader<'a>, ruzstd::decoding::FrameDecoder>>,
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl<'a> ZstdDecoder<'a> {
+    fn new(source: FrameReader<'a>) -> std::io::Result<Self> {
+        let decoder =
+            ruzstd::decoding::StreamingDecoder::new(source).map_err(std::io::Error::other)?;
+        Ok(Self {
+            decoder: Some(decoder),
+        })
+    }
+}
 
-    fn try_from(value: Message) -> Result<Self, Self::Error> {
-        Ok(WsMessageWasm::Binary(serialize(&value)?.into()))
+#[cfg(any(feature = "server", feature = "client"))]
+impl std::io::Read for ZstdDecoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let Some(decoder) = &mut self.decoder else {
+                return Ok(0);
+            };
+            let len = decoder.read(buf)?;
+            if len > 0 || buf.is_empty() {
+                return Ok(len);
+            }
+            // Current zstd frame is finished, continue with the next one
+            let source = self.decoder.take().unwrap().into_inner();
+            if !source.is_empty() {
+                *self = Self::new(source)?;
+            }
+        }
     }
 }
+
+#[cfg(feature = "testing")]
+pub(crate) fn decompress(raw: &[u8]) -> Result<Vec<u8>, ParseError> {
+    use std::io::Read;
+    let source = FrameReader {
+        parts: [raw].into(),
+    };
+    let mut decompressed = Vec::new();
+    ZstdDecoder::new(source)
+        .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+        .map_err(ParseError::DecompressionError)?;
+    Ok(decompressed)
+}
+
+#[cfg(feature = "testing")]
+pub(crate) fn compress(raw: &[u8]) -> Vec<u8> {
+    compress_block(raw, Compression::default().level).expect("compressing into memory can't fail")
+}
+
+#[cfg(all(
+    any(feature = "server", feature = "client"),
+    not(all(feature = "zstd", not(target_arch = "wasm32")))
+))]
+fn compress_block(raw: &[u8], _level: i32) -> std::io::Result<Vec<u8>> {
+    Ok(ruzstd::encoding::compress_to_vec(
+        raw,
+        ruzstd::encoding::CompressionLevel::Fastest,
+    ))
+}
+
+#[cfg(all(
+    any(feature = "server", feature = "client"),
+    all(feature = "zstd", not(target_arch = "wasm32"))
+))]
+fn compress_block(raw: &[u8], level: i32) -> std::io::Result<Vec<u8>> {
+    zstd::bulk::compress(raw, level)
+}
@@ -1,17 +1,36 @@
 mod common;
+#[cfg(feature = "server")]
+pub(crate) use common::{Heartbeat, MAX_FRAME_SIZE};
 pub use common::WsMessageType;
+#[cfg(any(feature = "server", feature = "client"))]
+pub use common::{BlobHash, Capabilities, Compression, JobMeta, Message, MissingBlob};
+#[cfg(any(feature = "server", feature = "client"))]
+pub(crate) use common::{
+    FRAME_SIZE, FrameStore, Frames, decode_frames, encode_frames, is_continued, peek_hello,
+};
+#[cfg(feature = "testing")]
+pub(crate) use common::{compress, decompress};
+#[cfg(feature = "server")]
+pub(crate) use common::{decode, encode};
+#[cfg(feature = "server")]
+pub use common::{deserialize, serialize};
 
 #[cfg(feature = "server")]
 mod server;
 #[cfg(feature = "server")]
-pub use server::WsChannelServer;
+pub use server::{WsChannelServer, WsTransportAxum};
 
 #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
 mod client_native;
 #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
-pub use client_native::WsChannelClientNative;
+pub use client_native::{HEARTBEAT_ENV, REDIRECTS_ENV, WsTransportNative};
+
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+mod client_tokio;
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+pub use client_tokio::WsTransportTokio;
 
 #[cfg(all(feature = "client", target_arch = "wasm32"))]
 mod client_wasm;
 #[cfg(all(feature = "client", target_arch = "wasm32"))]
-pub use client_wasm::WsChannelClientWasm;
+pub use client_wasm::{RetryPolicy, WsTransportWasm};
@@ -1,49 +1,415 @@
 //! Async implementation of the WebSocket communication.
 //! This is used by the server (which hosts the tool).
 
-use crate::{ConnectionError, ToolError, Value};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use super::common::Message;
+use crate::{
+    ConnectionError, ErrorKind, PROTOCOL_VERSION, ParseError, ToolError, Value, cache::BlobCache,
+    connection::Transport, jobs::JobLog, value::dynamic::Dict,
+};
 
-// NOTE: implementation is analoguous to sync, look there for more comments
+use super::common::{WsMessageAxum, WsMessageType};
+use super::{
+    BlobHash, Capabilities, Compression, Frames, Heartbeat, JobMeta, Message, MissingBlob,
+    encode_frames, peek_hello,
+};
+use crate::connection::{
+    attachment::{attach, detach, recv_attachment, send_attachments},
+    recv_message_sized, send_frames, send_message,
+};
 
-pub struct WsChannelServer {
+// NOTE: implementation is analoguous to the client, look there for more comments
+
+/// WebSocket transport based on the socket of an upgraded axum connection.
+pub struct WsTransportAxum {
     socket: axum::extract::ws::WebSocket,
-    buffer: Option<Message>,
+    heartbeat: Option<Heartbeat>,
 }
 
-impl WsChannelServer {
+impl WsTransportAxum {
     pub fn new(socket: axum::extract::ws::WebSocket) -> Self {
         Self {
             socket,
+            heartbeat: None,
+        }
+    }
+
+    /// Ping the client every `interval` while waiting for it, see
+    /// [`crate::ServerConfig::heartbeat`]
+    pub fn with_heartbeat(mut self, interval: Option<Duration>) -> Self {
+        self.heartbeat = interval.map(Heartbeat::new);
+        self
+    }
+}
+
+impl Transport for WsTransportAxum {
+    async fn send(&mut self, frame: Vec<u8>) -> Result<(), ConnectionError> {
+        self.socket
+            .send(WsMessageAxum::Binary(frame.into()))
+            .await
+            .map_err(ConnectionError::from)
+    }
+
+    async fn recv(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        loop {
+            let msg = match &mut self.heartbeat {
+                Some(heartbeat) => tokio::select! {
+                    // Messages that already arrived answer the last ping
+                    biased;
+                    msg = self.socket.recv() => msg,
+                    _ = tokio::time::sleep_until(heartbeat.next_ping().into()) => {
+                        heartbeat.ping()?;
+                        self.socket.send(WsMessageAxum::Ping(Default::default())).await?;
+                        continue;
+                    }
+                },
+                None => self.socket.recv().await,
+            };
+            if let Some(heartbeat) = &mut self.heartbeat {
+                heartbeat.seen();
+            }
+            // Difference to tungstenite: there is no can_read() method;
+            // instead None is returned from a closed stream.
+            return match msg {
+                Some(msg) => match msg.map_err(ConnectionError::from)? {
+                    WsMessageAxum::Binary(raw) => Ok(Some(raw.into())),
+                    WsMessageAxum::Close(_) => Ok(None),
+                    // Pings are answered by axum
+                    WsMessageAxum::Ping(_) | WsMessageAxum::Pong(_) => continue,
+                    msg => Err(ParseError::WrongMessageType {
+                        expected: WsMessageType::Binary,
+                        found: msg.into(),
+                    }
+                    .into()),
+                },
+                None => Ok(None),
+            };
+        }
+    }
+
+    async fn close(mut self) -> Result<(), ConnectionError> {
+        self.socket
+            .send(WsMessageAxum::Close(None))
+            .await
+            .map_err(ConnectionError::from)
+    }
+}
+
+pub struct WsChannelServer<T: Transport> {
+    transport: T,
+    buffer: Option<Message>,
+    compression: Compression,
+    cache: Option<Arc<BlobCache>>,
+    spool_min_size: Option<usize>,
+    /// Sequence number and time reference of stamped messages
+    seq: u64,
+    started: Instant,
+    /// Total size of all messages received from the client
+    received: u64,
+    /// Announced by the client in the handshake
+    client_capabilities: Capabilities,
+    /// Received for the next `Input`
+    attachments: Vec<Vec<u8>>,
+    /// Sent by the client with the input
+    seed: Option<u64>,
+    /// Stamped messages are logged for a resume, see [`Self::with_log`]
+    log: Option<Arc<JobLog>>,
+    /// The connection dropped, messages are only logged
+    detached: bool,
+    /// Log of the call whose messages the client acknowledges with `Ack`,
+    /// also for a resumed call
+    acked: Option<Arc<JobLog>>,
+}
+
+impl<T: Transport> WsChannelServer<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
             buffer: None,
+            compression: Compression::default(),
+            cache: None,
+            spool_min_size: None,
+            seq: 0,
+            started: Instant::now(),
+            received: 0,
+            client_capabilities: Capabilities::default(),
+            attachments: Vec::new(),
+            seed: None,
+            log: None,
+            acked: None,
+            detached: false,
         }
     }
 
+    /// Cache for large input values, without it they are always transferred
+    pub fn with_cache(mut self, cache: Arc<BlobCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Compression of all messages sent to the client
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Stage messages of at least this size in a temporary file, see
+    /// [`crate::ServerConfig::spool_min_size`]
+    pub fn with_spool(mut self, spool_min_size: Option<usize>) -> Self {
+        self.spool_min_size = spool_min_size;
+        self
+    }
+
+    /// Receive the `Hello` of the client and reply with our own, the first
+    /// thing on every connection. Fails if the protocol versions differ.
+    /// Compression is disabled if the client doesn't support it.
+    pub async fn handshake(&mut self) -> Result<(), ConnectionError> {
+        let frame = self
+            .transport
+            .recv()
+            .await?
+            .ok_or(ConnectionError::ConnectionClosed)?;
+        self.received += frame.len() as u64;
+
+        let Some((client, capabilities)) = peek_hello(&frame) else {
+            // Clients before the handshake start with the input and understand this
+            let msg = format!(
+                "protocol mismatch: server uses protocol version {PROTOCOL_VERSION}, the client an older one"
+            );
+            let output = Message::Output(Err(ToolError::Custom(msg)));
+            send_message(&mut self.transport, &output, &self.compression, None).await?;
+            return Err(violation("client didn't start with a hello"));
+        };
+        let hello = Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Capabilities::server(self.cache.is_some()),
+        };
+        send_message(&mut self.transport, &hello, &self.compression, None).await?;
+        if client != PROTOCOL_VERSION {
+            return Err(ConnectionError::ProtocolMismatch {
+                client,
+                server: PROTOCOL_VERSION,
+            });
+        }
+        self.compression = self.compression.for_peer(&capabilities);
+        self.client_capabilities = capabilities;
+        Ok(())
+    }
+
+    /// Optional features of the client, empty before the [`Self::handshake`]
+    pub fn client_capabilities(&self) -> &Capabilities {
+        &self.client_capabilities
+    }
+
+    /// Seed requested by the client, known after [`Self::read_input`]
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Log all stamped messages from now on, for clients with
+    /// [`Capabilities::RESUME`]. If the connection drops, sending and reading
+    /// no longer fail: messages are only logged and an abort can only be
+    /// requested through the log by a client that resumed the call.
+    pub(crate) fn with_log(&mut self, log: Arc<JobLog>) {
+        self.acked = Some(log.clone());
+        self.log = Some(log);
+    }
+
+    /// Drop the messages acknowledged by the client from the log of a call it
+    /// resumed, see [`Self::with_log`]
+    pub(crate) fn follow(&mut self, log: Arc<JobLog>) {
+        self.acked = Some(log);
+    }
+
+    pub(crate) fn log(&self) -> Option<&JobLog> {
+        self.log.as_deref()
+    }
+
+    /// Keep going without the client if the connection to it was lost
+    fn detach(&mut self, err: ConnectionError) -> Result<(), ConnectionError> {
+        if self.log.is_none() || err.kind() != ErrorKind::ConnectionLost {
+            return Err(err);
+        }
+        println!("DETACH {err}, waiting for the client to resume");
+        self.detached = true;
+        Ok(())
+    }
+
+    /// Tell the client the id and secret to resume the call with
+    pub async fn send_accepted(
+        &mut self,
+        job_id: String,
+        secret: String,
+    ) -> Result<(), ConnectionError> {
+        self.send_stamped(Message::Accepted { job_id, secret })
+            .await
+    }
+
+    /// Send a message logged for another connection, as it is
+    pub(crate) async fn replay(&mut self, msg: &Message) -> Result<(), ConnectionError> {
+        send_message(
+            &mut self.transport,
+            msg,
+            &self.compression,
+            self.spool_min_size,
+        )
+        .await
+    }
+
+    /// Continue numbering stamped messages at `seq`, for a resumed call
+    pub(crate) fn resume_at(&mut self, seq: u64) {
+        self.seq = seq;
+    }
+
     pub async fn send_message(&mut self, msg: String) -> Result<(), ConnectionError> {
-        self.socket
-            .send(Message::ToolMsg(msg).try_into()?)
+        self.send_stamped(Message::ToolMsg(msg)).await
+    }
+
+    pub async fn send_progress(
+        &mut self,
+        fraction: f64,
+        message: Option<String>,
+    ) -> Result<(), ConnectionError> {
+        self.send_stamped(Message::Progress { fraction, message })
             .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))
     }
 
+    pub async fn send_emitted(
+        &mut self,
+        name: String,
+        value: Value,
+    ) -> Result<(), ConnectionError> {
+        self.send_stamped(Message::Emit { name, value }).await
+    }
+
+    pub async fn send_partial(&mut self, partial: Dict) -> Result<(), ConnectionError> {
+        self.send_stamped(Message::PartialResult(partial)).await
+    }
+
+    /// Clients without [`Capabilities::QUEUED`] get it as regular message
+    pub async fn send_queued(&mut self, position: u32) -> Result<(), ConnectionError> {
+        let msg = match self.client_capabilities.contains(Capabilities::QUEUED) {
+            true => Message::Queued { position },
+            false => Message::ToolMsg(format!("queued, {position} calls ahead")),
+        };
+        self.send_stamped(msg).await
+    }
+
+    async fn send_stamped(&mut self, message: Message) -> Result<(), ConnectionError> {
+        let msg = Message::Stamped {
+            seq: self.seq,
+            time: self.started.elapsed().as_secs_f64(),
+            message: Box::new(message),
+        };
+        self.seq += 1;
+        if let Some(log) = &self.log {
+            log.push(msg.clone());
+        }
+        if self.detached {
+            return Ok(());
+        }
+        let sent = send_message(
+            &mut self.transport,
+            &msg,
+            &self.compression,
+            self.spool_min_size,
+        )
+        .await;
+        sent.or_else(|err| self.detach(err))
+    }
+
+    /// Send the result, preceded by `meta` completed with the sizes of the
+    /// input and of the encoded output (including attachments).
     pub async fn send_output(
         &mut self,
         result: Result<Value, ToolError>,
+        mut meta: JobMeta,
     ) -> Result<(), ConnectionError> {
-        self.socket
-            .send(Message::Output(result).try_into()?)
+        let (attachments, frames) = self.encode_output(result)?;
+        meta.input_size = self.received;
+        meta.output_size = (frames.size() + attachments.iter().map(Vec::len).sum::<usize>()) as u64;
+        self.send_stamped(Message::JobMeta(meta)).await?;
+        self.send_encoded(attachments, frames).await
+    }
+
+    /// Send the result without a `JobMeta`, for a resumed call whose
+    /// `JobMeta` was logged
+    pub(crate) async fn send_result(
+        &mut self,
+        result: Result<Value, ToolError>,
+    ) -> Result<(), ConnectionError> {
+        let (attachments, frames) = self.encode_output(result)?;
+        self.send_encoded(attachments, frames).await
+    }
+
+    fn encode_output(
+        &self,
+        mut result: Result<Value, ToolError>,
+    ) -> Result<(Vec<Vec<u8>>, Frames), ConnectionError> {
+        let attachments = match &mut result {
+            Ok(value) if self.client_capabilities.contains(Capabilities::ATTACHMENTS) => {
+                detach(value)
+            }
+            _ => Vec::new(),
+        };
+        let frames = encode_frames(
+            &Message::Output(result),
+            &self.compression,
+            self.spool_min_size,
+        )?;
+        Ok((attachments, frames))
+    }
+
+    async fn send_encoded(
+        &mut self,
+        attachments: Vec<Vec<u8>>,
+        frames: Frames,
+    ) -> Result<(), ConnectionError> {
+        if self.detached {
+            return Ok(());
+        }
+        let mut sent = send_attachments(&mut self.transport, &attachments, &self.compression)
             .await
-            .map_err(|err| ConnectionError::WebSocketError(err.to_string()))
+            .map(drop);
+        if sent.is_ok() {
+            sent = send_frames(&mut self.transport, frames).await;
+        }
+        sent.or_else(|err| self.detach(err))
+    }
+
+    /// Send the result of a call that failed on the server (not in the tool),
+    /// without a `JobMeta`.
+    pub async fn send_error(&mut self, error: ToolError) -> Result<(), ConnectionError> {
+        let output = Message::Output(Err(error));
+        send_message(&mut self.transport, &output, &self.compression, None).await
     }
 
+    /// Fill the message buffer, attachments and the seed are collected until
+    /// the message they belong to arrives.
     async fn read(&mut self) -> Result<(), ConnectionError> {
-        if self.buffer.is_none() {
-            // Difference to tungstenite: there is no can_read() method;
-            // instead None is returned from a closed stream.
-            if let Some(msg) = self.socket.recv().await {
-                let msg = msg.map_err(|err| ConnectionError::WebSocketError(err.to_string()))?;
-                self.buffer = Some(msg.try_into()?)
+        while self.buffer.is_none() {
+            let Some((msg, size)) =
+                recv_message_sized(&mut self.transport, self.spool_min_size).await?
+            else {
+                break;
+            };
+            self.received += size as u64;
+            match msg {
+                Message::Attachment { id, size } => {
+                    recv_attachment(&mut self.transport, id, size, &mut self.attachments).await?;
+                    self.received += size;
+                }
+                Message::Seed(seed) => self.seed = Some(seed),
+                Message::Ack { seq } => {
+                    if let Some(log) = &self.acked {
+                        log.ack(seq);
+                    }
+                }
+                msg => self.buffer = Some(msg),
             }
         }
 
@@ -51,9 +417,35 @@ impl WsChannelServer {
     }
 
     pub async fn read_abort(&mut self) -> Result<Option<()>, ConnectionError> {
+        if !self.detached {
+            let err = match self.read().await {
+                Ok(()) => match self.buffer.take() {
+                    Some(Message::Abort) => return Ok(Some(())),
+                    Some(msg) => {
+                        self.buffer = Some(msg);
+                        return Ok(None);
+                    }
+                    None => ConnectionError::ConnectionClosed,
+                },
+                Err(err) => err,
+            };
+            self.detach(err)?;
+        }
+        let log = self.log.clone().expect("detached connections have a log");
+        log.aborted().await;
+        Ok(Some(()))
+    }
+
+    /// The job id, secret and `seq` of a `Resume` if the client sent one
+    /// instead of an input
+    pub async fn read_resume(&mut self) -> Result<Option<(String, String, u64)>, ConnectionError> {
         self.read().await?;
         match self.buffer.take() {
-            Some(Message::Abort) => Ok(Some(())),
+            Some(Message::Resume {
+                job_id,
+                secret,
+                seq,
+            }) => Ok(Some((job_id, secret, seq))),
             Some(msg) => {
                 self.buffer = Some(msg);
                 Ok(None)
@@ -64,13 +456,131 @@ impl WsChannelServer {
 
     pub async fn read_input(&mut self) -> Result<Option<Value>, ConnectionError> {
         self.read().await?;
-        match self.buffer.take() {
-            Some(Message::Input(x)) => Ok(Some(x)),
+        let input = match self.buffer.take() {
+            Some(Message::Input(mut x)) => {
+                attach(&mut x, std::mem::take(&mut self.attachments))?;
+                x
+            }
+            Some(Message::CachedInput { input, refs }) => self.resolve_refs(input, refs).await?,
             Some(msg) => {
                 self.buffer = Some(msg);
-                Ok(None)
+                return Ok(None);
+            }
+            None => return Err(ConnectionError::ConnectionClosed),
+        };
+        // Stamps are relative to the start of the tool, which follows now
+        self.started = Instant::now();
+        Ok(Some(input))
+    }
+
+    /// Complete a `CachedInput` with cached values and request the missing ones.
+    async fn resolve_refs(
+        &mut self,
+        input: Value,
+        refs: HashMap<String, BlobHash>,
+    ) -> Result<Value, ConnectionError> {
+        let Value::Dict(Dict(mut entries)) = input else {
+            return Err(violation("cached input must be a Dict"));
+        };
+        let Some(cache) = self.cache.clone() else {
+            return Err(violation("cached input without the input_cache capability"));
+        };
+
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for hash in refs.values() {
+            if found.contains_key(hash) || missing.contains(hash) {
+                continue;
+            }
+            match cache.get(hash) {
+                Some(value) => {
+                    found.insert(*hash, value);
+                }
+                None => missing.push(*hash),
             }
-            None => Err(ConnectionError::ConnectionClosed),
         }
+        let missing: Vec<MissingBlob> = missing
+            .into_iter()
+            .map(|hash| MissingBlob {
+                hash,
+                received: cache.received(&hash) as u64,
+            })
+            .collect();
+        let resumed = missing.iter().filter(|blob| blob.received > 0).count();
+        println!(
+            "CACHE {} hit, {} missing ({resumed} resumed)",
+            found.len(),
+            missing.len()
+        );
+
+        let canonical = self.client_capabilities.contains(Capabilities::CANONICAL_HASH);
+        let mut pending = missing.len();
+        send_message(
+            &mut self.transport,
+            &Message::Missing(missing),
+            &self.compression,
+            self.spool_min_size,
+        )
+        .await?;
+        while pending > 0 {
+            self.read().await?;
+            let Some(Message::BlobChunk {
+                hash,
+                offset,
+                size,
+                data,
+            }) = self.buffer.take()
+            else {
+                return Err(violation("expected blob chunk"));
+            };
+            if !refs.values().any(|h| *h == hash) || found.contains_key(&hash) {
+                return Err(violation("blob chunk of an entry that isn't missing"));
+            }
+            let bytes = cache
+                .append(hash, offset as usize, size as usize, &data)
+                .map_err(ConnectionError::ProtocolViolation)?;
+            let Some(bytes) = bytes else {
+                continue;
+            };
+            // Blobs are large by definition, decoding and hashing them on the
+            // runtime would stall other connections
+            let (value, size) = tokio::task::spawn_blocking(move || {
+                let value: Value =
+                    rmp_serde::from_slice(&bytes).map_err(ParseError::DeserializationError)?;
+                // Hashed here and not trusted from the client, which could poison the cache
+                let actual = match canonical {
+                    true => BlobHash(value.canonical_hash()),
+                    false => BlobHash::of(&bytes),
+                };
+                if actual != hash {
+                    return Err(violation("blob doesn't match its hash"));
+                }
+                Ok((value, bytes.len()))
+            })
+            .await??;
+            cache.insert(hash, &value, size);
+            found.insert(hash, Arc::new(value));
+            pending -= 1;
+        }
+
+        // Only clone values that are still used by the cache or by other entries
+        let mut uses = HashMap::<BlobHash, usize>::new();
+        refs.values()
+            .for_each(|hash| *uses.entry(*hash).or_default() += 1);
+        for (key, hash) in refs {
+            let remaining = uses.get_mut(&hash).unwrap();
+            *remaining -= 1;
+            let value = match remaining {
+                0 => found.remove(&hash),
+                _ => found.get(&hash).cloned(),
+            };
+            let value = value.ok_or(violation("blob is missing"))?;
+            entries.insert(key, Arc::unwrap_or_clone(value));
+        }
+        Ok(Value::Dict(Dict(entries)))
     }
 }
+
+fn violation(msg: &str) -> ConnectionError {
+    ConnectionError::ProtocolViolation(msg.to_string())
+}
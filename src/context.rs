@@ -0,0 +1,592 @@
+//! Structured interface between a running tool and the server, see [`ToolCtx`].
+
+use std::{
+    io::Write,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{
+    AbortReason, ExtractionError, MessageFn, OperatorConfig, ToolError, Value,
+    artifacts::ArtifactStore,
+    util::hex,
+    value::{dynamic::Dict, schema::ValueSchema},
+};
+
+/// Severity of a message sent with [`ToolCtx::log`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Context of a single tool call, passed to tools instead of a bare [`MessageFn`].
+///
+/// Tools written against the original signature keep working, see [`ToolHandler`].
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, ToolError};
+/// use toolapi::{LogLevel, ToolCtx};
+///
+/// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+///     ctx.log(LogLevel::Info, format!("job {} started", ctx.job_id()))?;
+///     for i in 0..100 {
+///         ctx.check_abort()?;
+///         // ... heavy computation ...
+///         ctx.send_progress((i + 1) as f64 / 100.0, None)?;
+///     }
+///     Ok(input)
+/// }
+///
+/// fn main() -> Result<(), std::io::Error> {
+///     toolapi::run_server(tool, None)
+/// }
+/// ```
+pub struct ToolCtx<'a> {
+    send_msg: &'a mut MessageFn,
+    send_progress: Option<&'a mut ProgressFn>,
+    send_output: Option<&'a mut OutputFn>,
+    send_partial: Option<&'a mut PartialFn>,
+    job_id: String,
+    scratch_dir: Option<tempfile::TempDir>,
+    artifacts: Option<Arc<ArtifactStore>>,
+    operator_config: OperatorConfig,
+    abort: AbortSignal,
+    seed: u64,
+}
+
+impl<'a> ToolCtx<'a> {
+    pub(crate) fn new(job_id: String, send_msg: &'a mut MessageFn, abort: AbortSignal) -> Self {
+        Self {
+            send_msg,
+            send_progress: None,
+            send_output: None,
+            send_partial: None,
+            job_id,
+            scratch_dir: None,
+            artifacts: None,
+            operator_config: OperatorConfig::default(),
+            abort,
+            seed: 0,
+        }
+    }
+
+    /// Without it, progress is sent as regular message
+    pub(crate) fn with_progress(mut self, send_progress: &'a mut ProgressFn) -> Self {
+        self.send_progress = Some(send_progress);
+        self
+    }
+
+    /// Without it, emitted outputs are only announced as regular message
+    pub(crate) fn with_outputs(mut self, send_output: &'a mut OutputFn) -> Self {
+        self.send_output = Some(send_output);
+        self
+    }
+
+    /// Without it, partial results are only announced as regular message
+    pub(crate) fn with_partials(mut self, send_partial: &'a mut PartialFn) -> Self {
+        self.send_partial = Some(send_partial);
+        self
+    }
+
+    /// Without it, registering artifacts fails
+    pub(crate) fn with_artifacts(mut self, artifacts: Arc<ArtifactStore>) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Without it, there are no settings
+    pub(crate) fn with_operator_config(mut self, operator_config: OperatorConfig) -> Self {
+        self.operator_config = operator_config;
+        self
+    }
+
+    /// Without it, the seed is 0
+    pub(crate) fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Send a message to the client, returns an error if the tool should abort.
+    pub fn send_msg(&mut self, msg: impl Into<String>) -> Result<(), AbortReason> {
+        self.check_abort()?;
+        (self.send_msg)(msg.into()).inspect_err(|reason| self.abort.trigger(reason.clone()))
+    }
+
+    /// Send a message with the given severity, [`LogLevel::Info`] messages
+    /// are sent unchanged and all others are prefixed by their level.
+    pub fn log(&mut self, level: LogLevel, msg: impl AsRef<str>) -> Result<(), AbortReason> {
+        let msg = msg.as_ref();
+        match level {
+            LogLevel::Debug => self.send_msg(format!("DEBUG: {msg}")),
+            LogLevel::Info => self.send_msg(msg),
+            LogLevel::Warn => self.send_msg(format!("WARN: {msg}")),
+            LogLevel::Error => self.send_msg(format!("ERROR: {msg}")),
+        }
+    }
+
+    /// Report the completed `fraction` of the work in `[0, 1]`, optionally
+    /// describing the current stage. Clients receive it separately from
+    /// messages (see [`crate::call_with_progress`]).
+    pub fn send_progress(
+        &mut self,
+        fraction: f64,
+        message: Option<String>,
+    ) -> Result<(), AbortReason> {
+        self.check_abort()?;
+        let fraction = fraction.clamp(0.0, 1.0);
+        let result = match &mut self.send_progress {
+            Some(send_progress) => send_progress(fraction, message),
+            None => (self.send_msg)(progress_text(fraction, message.as_deref())),
+        };
+        result.inspect_err(|reason| self.abort.trigger(reason.clone()))
+    }
+
+    /// Send an intermediate result (e.g. an image, k-space data or a QC report)
+    /// to the client before the tool returns. Can be called any number of
+    /// times, clients receive them as [`crate::ToolEventKind::Emitted`].
+    pub fn emit(&mut self, name: impl Into<String>, value: Value) -> Result<(), AbortReason> {
+        self.check_abort()?;
+        let name = name.into();
+        let result = match &mut self.send_output {
+            Some(send_output) => send_output(name, value),
+            None => (self.send_msg)(format!("emitted output `{name}`")),
+        };
+        result.inspect_err(|reason| self.abort.trigger(reason.clone()))
+    }
+
+    /// Send the entries of the result that are done so far, e.g. the first
+    /// slabs of a simulated volume (see [`crate::value::structured::Volume::chunks`]).
+    /// Can be called any number of times, clients receive them as
+    /// [`crate::ToolEventKind::Partial`]. Unlike [`Self::emit`], partial
+    /// results don't need names and are meant to be merged by the client.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use toolapi::{Value, ToolCtx, ToolError, value::dynamic::Dict};
+    /// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     let mut result = Dict(Default::default());
+    ///     for slab in 0..4 {
+    ///         let signal = Value::from(vec![0.0; 64]); // ... simulation ...
+    ///         let partial = Dict([(format!("slab_{slab}"), signal)].into());
+    ///         ctx.send_partial(partial.clone())?;
+    ///         result.0.extend(partial.0);
+    ///     }
+    ///     Ok(Value::Dict(result))
+    /// }
+    /// ```
+    pub fn send_partial(&mut self, partial: Dict) -> Result<(), AbortReason> {
+        self.check_abort()?;
+        let result = match &mut self.send_partial {
+            Some(send_partial) => send_partial(partial),
+            None => (self.send_msg)(partial_text(&partial)),
+        };
+        result.inspect_err(|reason| self.abort.trigger(reason.clone()))
+    }
+
+    /// Call the tool at `addr` (like [`crate::call`]) from within this tool.
+    ///
+    /// Messages and progress of the sub-tool are forwarded to the client as
+    /// messages prefixed with `[name]`, emitted outputs are forwarded as
+    /// `name/output`. Partial results of the sub-tool are only announced. If this tool is aborted, the sub-tool is aborted too.
+    /// Errors returned by the sub-tool are returned unchanged.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use toolapi::{Value, ToolCtx, ToolError};
+    /// fn simulate(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     let signal = input; // ... simulation ...
+    ///     ctx.call_tool("recon", "ws://localhost:8081/tool", signal)
+    /// }
+    /// ```
+    #[cfg(feature = "client")]
+    pub fn call_tool(&mut self, name: &str, addr: &str, input: Value) -> Result<Value, ToolError> {
+        use crate::{ToolCallError, ToolEventKind, connection::websocket::WsTransportNative};
+
+        let connect = || WsTransportNative::connect(addr)?.with_abort(self.abort.clone());
+        let transport = connect()
+            .map_err(|err| ToolError::internal(format!("failed to connect to `{name}`: {err}")))?;
+        let client = crate::connection::client::WsChannelClient::new(transport);
+        let result = crate::connection::client::block_on(client.call_with_events(input, |event| {
+            match event.kind {
+                ToolEventKind::Message(msg) => self.send_msg(format!("[{name}] {msg}")),
+                ToolEventKind::Progress { fraction, message } => {
+                    let text = progress_text(fraction, message.as_deref());
+                    self.send_msg(format!("[{name}] {text}"))
+                }
+                ToolEventKind::Emitted {
+                    name: output,
+                    value,
+                } => self.emit(format!("{name}/{output}"), *value),
+                ToolEventKind::Partial(partial) => {
+                    self.send_msg(format!("[{name}] {}", partial_text(&partial)))
+                }
+                ToolEventKind::Queued { position } => {
+                    self.send_msg(format!("[{name}] queued, {position} calls ahead"))
+                }
+                ToolEventKind::Accepted { .. } | ToolEventKind::Finished(_) => Ok(()),
+            }
+            .is_ok()
+        }));
+
+        match result {
+            Ok(value) => Ok(value),
+            // The sub-tool finished, only its connection wasn't closed cleanly
+            Err(ToolCallError::CloseFailed { result, .. }) => Ok(*result),
+            Err(ToolCallError::ToolReturnedError(err)) => Err(err),
+            Err(err) => {
+                // Forwarding failed or the connection was closed by `with_abort`
+                self.check_abort()?;
+                Err(ToolError::internal(format!(
+                    "call to `{name}` failed: {err}"
+                )))
+            }
+        }
+    }
+
+    /// Make `data` (e.g. a NIfTI file) downloadable over HTTP at
+    /// `/jobs/{job_id}/artifacts/{name}` for [`crate::ArtifactConfig::ttl`],
+    /// instead of sending it through the WebSocket. Returns the reference to
+    /// put in the result, a Dict with the `path` relative to the server, the
+    /// `size` in bytes and the `ttl` in seconds (see [`crate::artifact_url`]).
+    ///
+    /// `name` may only contain ASCII letters, digits, `.`, `_` and `-`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use toolapi::{Value, ToolCtx, ToolError, value::dynamic::Dict};
+    /// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     let nifti: Vec<u8> = Vec::new(); // ... reconstruction ...
+    ///     let image = ctx.artifact("image.nii", &nifti)?;
+    ///     Ok(Value::Dict(Dict([("image".to_string(), image)].into())))
+    /// }
+    /// ```
+    pub fn artifact(&mut self, name: &str, data: &[u8]) -> Result<Value, ToolError> {
+        self.artifact_store()?
+            .insert(&self.job_id, name, |file| file.write_all(data))
+    }
+
+    /// Like [`Self::artifact`], with the content of the file at `path`, e.g.
+    /// written to the [`Self::scratch_dir`] by an external program.
+    pub fn artifact_file(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<Value, ToolError> {
+        let copy = crate::artifacts::copy_file(path.as_ref());
+        self.artifact_store()?.insert(&self.job_id, name, copy)
+    }
+
+    fn artifact_store(&self) -> Result<&ArtifactStore, ToolError> {
+        self.artifacts.as_deref().ok_or_else(|| {
+            ToolError::internal("artifacts are only supported by the WebSocket server")
+        })
+    }
+
+    /// Setting `key` of the deployment, see [`crate::ServerConfig::with_operator_config`].
+    /// A missing or mistyped setting is an internal error, not the client's fault.
+    pub fn setting<T>(&self, key: &str) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        match self.operator_config.settings().0.get(key) {
+            Some(value) => convert_setting(key, value),
+            None => Err(ToolError::internal(format!(
+                "the server is missing the setting `{key}`"
+            ))),
+        }
+    }
+
+    /// Like [`Self::setting`], returning `default` if the setting doesn't exist.
+    pub fn setting_or<T>(&self, key: &str, default: T) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        match self.operator_config.settings().0.get(key) {
+            Some(value) => convert_setting(key, value),
+            None => Ok(default),
+        }
+    }
+
+    /// All settings of the deployment, e.g. to pass them to an external program.
+    pub fn settings(&self) -> &Dict {
+        self.operator_config.settings()
+    }
+
+    /// Returns an error if the tool should abort, e.g. because the client
+    /// requested it. This is cheap (a single atomic load), so tools that don't
+    /// send messages for a long time should call it regularly.
+    pub fn check_abort(&self) -> Result<(), AbortReason> {
+        self.abort.check()
+    }
+
+    /// Handle for checking aborts from other threads, e.g. parallel workers.
+    pub fn abort_signal(&self) -> AbortSignal {
+        self.abort.clone()
+    }
+
+    /// Unique id of this call, also printed in the server logs.
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Seed for the random number generators of the tool, so that calls of
+    /// stochastic tools can be reproduced. It is chosen by the client (see
+    /// [`crate::call_with_seed`]) or random, and reported in
+    /// [`crate::JobMeta::seed`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use toolapi::{Value, ToolCtx, ToolError};
+    /// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     // e.g. rand::rngs::StdRng::seed_from_u64(ctx.seed())
+    ///     let mut state = ctx.seed();
+    ///     state ^= state << 13;
+    ///     Ok(Value::Int(state as i64))
+    /// }
+    /// ```
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Directory for temporary files of this call, created on first use and
+    /// deleted with all its content when the tool returns.
+    pub fn scratch_dir(&mut self) -> std::io::Result<&Path> {
+        if self.scratch_dir.is_none() {
+            let dir = tempfile::Builder::new()
+                .prefix(&format!("toolapi-{}-", self.job_id))
+                .tempdir()?;
+            self.scratch_dir = Some(dir);
+        }
+        Ok(self.scratch_dir.as_ref().unwrap().path())
+    }
+
+    /// The underlying [`MessageFn`], e.g. for [`crate::stdio::exec`].
+    pub fn message_fn(&mut self) -> &mut MessageFn {
+        self.send_msg
+    }
+}
+
+/// Set once when a tool should abort, shared between the server and the tool.
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, ToolCtx, ToolError};
+/// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+///     let abort = ctx.abort_signal();
+///     let worker = std::thread::spawn(move || {
+///         for _ in 0..1000 {
+///             abort.check()?;
+///             // ... heavy computation ...
+///         }
+///         Ok::<_, ToolError>(())
+///     });
+///     worker.join().unwrap()?;
+///     Ok(input)
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AbortSignal(Arc<OnceLock<AbortReason>>);
+
+impl AbortSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the first reason is kept, later calls have no effect.
+    pub fn trigger(&self, reason: AbortReason) {
+        let _ = self.0.set(reason);
+    }
+
+    /// Returns the reason if the signal was triggered.
+    pub fn check(&self) -> Result<(), AbortReason> {
+        match self.0.get() {
+            Some(reason) => Err(reason.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Receiver of [`ToolCtx::send_progress`], like [`MessageFn`] for messages
+pub(crate) type ProgressFn = dyn FnMut(f64, Option<String>) -> Result<(), AbortReason>;
+
+/// Receiver of [`ToolCtx::emit`], like [`MessageFn`] for messages
+pub(crate) type OutputFn = dyn FnMut(String, Value) -> Result<(), AbortReason>;
+
+/// Receiver of [`ToolCtx::send_partial`], like [`MessageFn`] for messages
+pub(crate) type PartialFn = dyn FnMut(Dict) -> Result<(), AbortReason>;
+
+/// Progress as message text, where it can't be sent separately
+fn convert_setting<T>(key: &str, value: &Value) -> Result<T, ToolError>
+where
+    T: TryFrom<Value, Error = ExtractionError>,
+{
+    T::try_from(value.clone())
+        .map_err(|err| ToolError::internal(format!("invalid server setting `{key}`: {err}")))
+}
+
+pub(crate) fn progress_text(fraction: f64, message: Option<&str>) -> String {
+    match message {
+        Some(message) => format!("{:.0}%: {message}", fraction * 100.0),
+        None => format!("{:.0}%", fraction * 100.0),
+    }
+}
+
+/// Partial result as message text, where it can't be sent separately
+pub(crate) fn partial_text(partial: &Dict) -> String {
+    let mut keys: Vec<String> = partial.0.keys().map(|key| format!("`{key}`")).collect();
+    keys.sort_unstable();
+    format!("partial result {}", keys.join(", "))
+}
+
+/// Random UUID (version 4) of a new tool call. It can't be guessed, since it
+/// is part of artifact urls.
+pub(crate) fn next_job_id() -> String {
+    let mut id: [u8; 16] = random_bytes();
+    // Version 4 (random) and the RFC 4122 variant
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+    let id = hex(&id);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &id[..8],
+        &id[8..12],
+        &id[12..16],
+        &id[16..20],
+        &id[20..]
+    )
+}
+
+/// From the random number generator of the OS
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the OS provides random numbers");
+    bytes
+}
+
+/// Seed of a call without one chosen by the client. Not derived from the
+/// input, hashing large inputs takes too long for every call.
+pub(crate) fn random_seed() -> u64 {
+    u64::from_le_bytes(random_bytes())
+}
+
+/// Functions that can be served as tool, see [`crate::run_server`].
+///
+/// Implemented for functions and closures with either signature:
+/// - `fn(Value, &mut ToolCtx) -> Result<Value, ToolError>`
+/// - `fn(Value, &mut MessageFn) -> Result<Value, ToolError>` (see [`crate::ToolFn`])
+///
+/// and for all [`Tool`]s.
+///
+/// The type parameter only distinguishes these and is inferred.
+pub trait ToolHandler<M>: Send + Sync + 'static {
+    fn run(&self, input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError>;
+}
+
+/// Marker for tools taking a [`ToolCtx`]
+pub struct WithCtx;
+/// Marker for tools taking a [`MessageFn`]
+pub struct WithMessageFn;
+
+impl<F> ToolHandler<WithCtx> for F
+where
+    F: Fn(Value, &mut ToolCtx) -> Result<Value, ToolError> + Send + Sync + 'static,
+{
+    fn run(&self, input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+        self(input, ctx)
+    }
+}
+
+impl<F> ToolHandler<WithMessageFn> for F
+where
+    F: Fn(Value, &mut MessageFn) -> Result<Value, ToolError> + Send + Sync + 'static,
+{
+    fn run(&self, input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+        self(input, ctx.send_msg)
+    }
+}
+
+/// Tool with typed input and output, served with [`crate::run_server_tool`].
+///
+/// The input dict is validated against [`Tool::input_schema`] before it is
+/// converted, so that clients get all violations at once instead of the first
+/// failed conversion. Conversion errors become [`ToolError::InvalidInput`]s.
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{ToolCtx, ToolError, Value};
+/// use toolapi::{Tool, run_server_tool, value::{dynamic::Dict, schema::ValueSchema}};
+///
+/// struct Relaxation;
+///
+/// struct Input {
+///     t1: f64,
+///     times: Vec<f64>,
+/// }
+///
+/// impl TryFrom<Dict> for Input {
+///     type Error = ToolError;
+///
+///     fn try_from(dict: Dict) -> Result<Self, ToolError> {
+///         Ok(Self {
+///             t1: dict.require("t1")?,
+///             times: dict.require("times")?,
+///         })
+///     }
+/// }
+///
+/// impl Tool for Relaxation {
+///     type Input = Input;
+///     type Output = Dict;
+///
+///     fn input_schema(&self) -> ValueSchema {
+///         ValueSchema::dict()
+///             .required("t1", ValueSchema::Float)
+///             .required("times", ValueSchema::list_of(ValueSchema::Float))
+///     }
+///
+///     fn run(&self, input: Input, ctx: &mut ToolCtx) -> Result<Dict, ToolError> {
+///         let mz: Vec<f64> = input.times.iter().map(|t| 1.0 - (-t / input.t1).exp()).collect();
+///         Ok(Dict([("mz".to_string(), Value::from(mz))].into()))
+///     }
+/// }
+///
+/// fn main() -> Result<(), std::io::Error> {
+///     run_server_tool(Relaxation, None)
+/// }
+/// ```
+pub trait Tool: Send + Sync + 'static {
+    type Input: TryFrom<Dict, Error: Into<ToolError>>;
+    type Output: Into<Dict>;
+
+    /// Expected structure of the input, registered as [`crate::ServerConfig::input_schema`]
+    fn input_schema(&self) -> ValueSchema;
+
+    fn run(&self, input: Self::Input, ctx: &mut ToolCtx) -> Result<Self::Output, ToolError>;
+}
+
+/// Marker for [`Tool`]s
+pub struct Typed;
+
+impl<T: Tool> ToolHandler<Typed> for T {
+    fn run(&self, input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+        let input = T::Input::try_from(input.into_input_dict()?).map_err(Into::into)?;
+        let output = Tool::run(self, input, ctx)?;
+        Ok(Value::Dict(output.into()))
+    }
+}
+
+/// Type erased [`ToolHandler`], cheap to clone for every call
+pub(crate) type SharedTool =
+    Arc<dyn Fn(Value, &mut ToolCtx) -> Result<Value, ToolError> + Send + Sync>;
+
+pub(crate) fn shared<M>(tool: impl ToolHandler<M>) -> SharedTool {
+    Arc::new(move |input, ctx| tool.run(input, ctx))
+}
@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{Value, connection::websocket::WsMessageType};
+#[cfg(any(feature = "server", feature = "client"))]
+use crate::connection::websocket::WsMessageType;
+use crate::{Value, value::dynamic::Dict};
 
 /// Sent over the server <-> tool channel to communicate an abort
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum AbortReason {
     #[error("requested by client")]
     RequestedByClient,
@@ -12,10 +14,28 @@ pub enum AbortReason {
     ChannelError(String),
     #[error("connection closed")]
     ConnectionClosed,
+    /// The server requires credentials, it answered the WebSocket upgrade with HTTP 401 or 403
+    #[error("unauthorized (HTTP {status})")]
+    Unauthorized { status: u16 },
+    /// There is no tool at the url, the server answered the WebSocket upgrade with HTTP 404
+    #[error("no tool found at the url (HTTP 404)")]
+    NotFound,
+    /// The server answered the WebSocket upgrade with a redirect to `location`
+    #[error("redirected to {location} (HTTP {status})")]
+    Redirect { status: u16, location: String },
+    /// The tool exceeded a limit of the server, e.g. [`crate::ServerConfig::max_job_memory`]
+    /// or [`crate::ServerConfig::max_cpu_time`].
+    /// The client gets a [`ToolError::ResourceExhausted`] instead.
+    #[error("resource exhausted: {0}")]
+    ResourceExhausted(String),
+    /// The call took longer than [`crate::ServerConfig::timeout`].
+    /// The client gets a [`ToolError::Timeout`] instead.
+    #[error("timeout: {0}")]
+    Timeout(String),
 }
 
 /// Returned when extracting a value fails (wrong type, key not found etc)
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum ExtractionError {
     #[error("dynamic type contained a `{from}`, tried to extract a `{into}`")]
     TypeMismatch { from: String, into: String },
@@ -29,19 +49,22 @@ pub enum ExtractionError {
     IndexForDict,
     #[error("tried to index a List with a string")]
     KeyForList,
+    #[error("{value} is out of the range of `{into}`")]
+    OutOfRange { value: f64, into: String },
 }
 
 /// Created during Message (de)serialization, part of ConnectionError
+#[cfg(any(feature = "server", feature = "client"))]
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("serialization failed: {0}")]
-    SerializationError(rmp_serde::encode::Error),
+    SerializationError(#[source] rmp_serde::encode::Error),
     #[error("deserialization failed: {0}")]
-    DeserializationError(rmp_serde::decode::Error),
+    DeserializationError(#[source] rmp_serde::decode::Error),
     #[error("compression failed: {0}")]
-    CompressionError(std::io::Error),
+    CompressionError(#[source] std::io::Error),
     #[error("decompression failed: {0}")]
-    DecompressionError(std::io::Error),
+    DecompressionError(#[source] std::io::Error),
     #[error("wrong message type (expected {expected:?}, found {found:?})")]
     WrongMessageType {
         expected: WsMessageType,
@@ -49,40 +72,262 @@ pub enum ParseError {
     },
 }
 
+/// Coarse classification of errors, for deciding how to react to them
+/// without matching on transport specific error types.
+///
+/// Only [`ErrorKind::Unreachable`] and [`ErrorKind::ConnectionLost`] are
+/// retryable: the same call might succeed if it is simply attempted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The server could not be reached (connection refused, DNS failure,
+    /// network down) or is temporarily unavailable (HTTP 5xx, 429)
+    Unreachable,
+    /// The connection broke down while the call was running
+    ConnectionLost,
+    /// The server refused the connection for good (invalid url, TLS failure,
+    /// HTTP 4xx response to the WebSocket upgrade)
+    Rejected,
+    /// Messages could not be parsed or arrived out of order, client and
+    /// server are probably not compatible
+    Protocol,
+    /// The tool ran but returned an error
+    Tool,
+    /// The tool call was aborted on request
+    Aborted,
+    /// The tool crashed on the server
+    Crashed,
+}
+
+impl ErrorKind {
+    pub fn is_retryable(self) -> bool {
+        matches!(self, Self::Unreachable | Self::ConnectionLost)
+    }
+}
+
 /// Returned by the WebSocket impls when trying to connect, send, recv
+#[cfg(any(feature = "server", feature = "client"))]
 #[derive(Error, Debug)]
 pub enum ConnectionError {
+    /// Connecting, TLS, HTTP upgrade or IO failure of the native client
+    #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+    #[error("WebSocket error: {0}")]
+    TungsteniteError(#[source] Box<tungstenite::Error>),
+    /// Error of the browser WebSocket used by the wasm client
+    #[cfg(all(feature = "client", target_arch = "wasm32"))]
     #[error("WebSocket error: {0}")]
-    WebSocketError(String),
+    WsStreamError(#[from] ws_stream_wasm::WsErr),
+    /// Error of the WebSocket connection to a client, on the server
+    #[cfg(feature = "server")]
+    #[error("WebSocket error: {0}")]
+    AxumError(#[from] axum::Error),
     #[error("parsing a WebSocket message failed: {0}")]
     ParseError(#[from] ParseError),
     #[error("connection closed")]
     ConnectionClosed,
+    /// The server requires credentials, it answered the WebSocket upgrade with HTTP 401 or 403
+    #[error("unauthorized (HTTP {status})")]
+    Unauthorized { status: u16 },
+    /// There is no tool at the url, the server answered the WebSocket upgrade with HTTP 404
+    #[error("no tool found at the url (HTTP 404)")]
+    NotFound,
+    /// The server answered the WebSocket upgrade with a redirect to `location`
+    #[error("redirected to {location} (HTTP {status})")]
+    Redirect { status: u16, location: String },
+    /// The peer sent a valid message that is not allowed at this point
+    #[error("protocol violation: {0}")]
+    ProtocolViolation(String),
+    /// Client and server use different versions of the wire protocol, see [`crate::PROTOCOL_VERSION`]
+    #[error("protocol mismatch: client uses protocol version {client}, server uses {server}")]
+    ProtocolMismatch { client: u32, server: u32 },
+    /// Waiting for the server was interrupted by the client, see [`crate::call_interruptible`]
+    #[error("interrupted")]
+    Interrupted,
+    /// The peer didn't answer a keepalive ping before the next one was due,
+    /// see [`crate::ServerConfig::heartbeat`]
+    #[error("the peer stopped answering pings")]
+    HeartbeatTimeout,
+    /// Staging a large message in a temporary file failed
+    #[error("spooling message to disk failed: {0}")]
+    SpoolError(#[source] std::io::Error),
     #[cfg(feature = "server")]
     #[error("the tool crashed, err='{0}'")]
     ToolPanic(#[from] tokio::task::JoinError),
 }
 
-// TODO: Value is very big and thus this Error type too
-#[allow(clippy::large_enum_variant)]
+#[cfg(any(feature = "server", feature = "client"))]
+impl ConnectionError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            #[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+            Self::TungsteniteError(err) => tungstenite_kind(err),
+            #[cfg(all(feature = "client", target_arch = "wasm32"))]
+            Self::WsStreamError(err) => match err {
+                ws_stream_wasm::WsErr::ConnectionFailed { .. } => ErrorKind::Unreachable,
+                ws_stream_wasm::WsErr::ConnectionNotOpen => ErrorKind::ConnectionLost,
+                ws_stream_wasm::WsErr::InvalidUrl { .. } => ErrorKind::Rejected,
+                _ => ErrorKind::Protocol,
+            },
+            #[cfg(feature = "server")]
+            Self::AxumError(_) => ErrorKind::ConnectionLost,
+            Self::ParseError(_) | Self::ProtocolViolation(_) | Self::ProtocolMismatch { .. } => {
+                ErrorKind::Protocol
+            }
+            Self::ConnectionClosed | Self::HeartbeatTimeout => ErrorKind::ConnectionLost,
+            Self::Unauthorized { .. } | Self::NotFound | Self::Redirect { .. } => {
+                ErrorKind::Rejected
+            }
+            Self::Interrupted => ErrorKind::Aborted,
+            // Most likely the disk was full, which might be resolved later
+            Self::SpoolError(_) => ErrorKind::ConnectionLost,
+            #[cfg(feature = "server")]
+            Self::ToolPanic(_) => ErrorKind::Crashed,
+        }
+    }
+
+    /// Whether attempting the same operation again might succeed, see [`ErrorKind`].
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+}
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+fn tungstenite_kind(err: &tungstenite::Error) -> ErrorKind {
+    use std::io::ErrorKind as Io;
+    use tungstenite::{
+        Error,
+        error::{ProtocolError, UrlError},
+    };
+
+    match err {
+        Error::Io(err) => match err.kind() {
+            Io::ConnectionRefused
+            | Io::HostUnreachable
+            | Io::NetworkUnreachable
+            | Io::AddrNotAvailable
+            | Io::NotFound
+            | Io::TimedOut => ErrorKind::Unreachable,
+            _ => ErrorKind::ConnectionLost,
+        },
+        Error::Url(UrlError::UnableToConnect(_)) => ErrorKind::Unreachable,
+        Error::Url(_) | Error::Tls(_) => ErrorKind::Rejected,
+        Error::Http(response) => {
+            let status = response.status();
+            if status.is_server_error() || status.as_u16() == 429 {
+                ErrorKind::Unreachable
+            } else {
+                ErrorKind::Rejected
+            }
+        }
+        Error::ConnectionClosed
+        | Error::AlreadyClosed
+        // The TCP connection dropped, e.g. behind a proxy
+        | Error::Protocol(ProtocolError::ResetWithoutClosingHandshake) => ErrorKind::ConnectionLost,
+        _ => ErrorKind::Protocol,
+    }
+}
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+impl From<tungstenite::Error> for ConnectionError {
+    fn from(err: tungstenite::Error) -> Self {
+        // Boxed because it is large, which would bloat all results containing it
+        Self::TungsteniteError(Box::new(err))
+    }
+}
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+impl ConnectionError {
+    /// Like `from`, with specific variants for common HTTP responses to the
+    /// WebSocket upgrade. Other responses stay a [`Self::TungsteniteError`].
+    pub(crate) fn from_handshake(err: tungstenite::Error) -> Self {
+        let tungstenite::Error::Http(response) = &err else {
+            return err.into();
+        };
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(tungstenite::http::header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+        match (status.as_u16(), location) {
+            (401 | 403, _) => Self::Unauthorized {
+                status: status.as_u16(),
+            },
+            (404, _) => Self::NotFound,
+            (_, Some(location)) if status.is_redirection() => Self::Redirect {
+                status: status.as_u16(),
+                location: location.to_string(),
+            },
+            _ => err.into(),
+        }
+    }
+}
+
 /// Returned by the call() function running on the client
+#[cfg(any(feature = "server", feature = "client"))]
 #[derive(Error, Debug)]
 pub enum ToolCallError {
     #[error("connection error: {0}")]
     ConnectionError(#[from] ConnectionError),
     #[error("tool finished but didn't shut down properly: {err}")]
-    CloseFailed { result: Value, err: ConnectionError },
+    CloseFailed {
+        result: Box<Value>,
+        #[source]
+        err: ConnectionError,
+    },
     #[error("tool didn't send a result")]
     ProtocolError,
     #[error("client requested abort in on_message")]
     OnMessageAbort,
     #[error("tool returned an error: {0}")]
     ToolReturnedError(#[from] ToolError),
+    /// No url configured for the tool, see [`crate::tool_url`]
+    #[error("no url for tool {0}")]
+    UnresolvedUrl(String),
+}
+
+#[cfg(any(feature = "server", feature = "client"))]
+impl ToolCallError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ConnectionError(err) | Self::CloseFailed { err, .. } => err.kind(),
+            Self::ProtocolError => ErrorKind::Protocol,
+            Self::OnMessageAbort => ErrorKind::Aborted,
+            Self::ToolReturnedError(err) => err.kind(),
+            Self::UnresolvedUrl(_) => ErrorKind::Rejected,
+        }
+    }
+
+    /// Whether calling the tool again with the same input might succeed.
+    /// Never true for [`ToolCallError::CloseFailed`], which already has the result.
+    ///
+    /// ```
+    /// # use toolapi::{ConnectionError, ErrorKind, ToolCallError, ToolError};
+    /// let err = ToolCallError::from(ConnectionError::ConnectionClosed);
+    /// assert_eq!(err.kind(), ErrorKind::ConnectionLost);
+    /// assert!(err.is_retryable());
+    ///
+    /// let err = ToolCallError::from(ToolError::invalid_input("t1", "negative"));
+    /// assert_eq!(err.kind(), ErrorKind::Tool);
+    /// assert!(!err.is_retryable());
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::CloseFailed { .. } => false,
+            err => err.kind().is_retryable(),
+        }
+    }
+
+    /// Partial result the tool attached to its error, see [`ToolError::with_partial`].
+    pub fn partial_result(&self) -> Option<&Value> {
+        match self {
+            Self::ToolReturnedError(err) => err.partial(),
+            _ => None,
+        }
+    }
 }
 
 /// Returned by the tool in the final result() call as reason if no value was computed.
 /// It is serializable since it is the only error that is actually sent over the WebSocket connection.
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum ToolError {
     #[error("failed to extract (probably a tool input): {0}")]
     Extraction(#[from] ExtractionError),
@@ -90,4 +335,153 @@ pub enum ToolError {
     Abort(#[from] AbortReason),
     #[error("custom tool error: {0}")]
     Custom(String),
+    #[error("invalid input `{path}`: {message}")]
+    InvalidInput {
+        /// Location of the offending input, e.g. `"phantom.tissues.gm.t1"`
+        path: String,
+        message: String,
+        details: Option<Dict>,
+    },
+    #[error("resource exhausted: {message}")]
+    ResourceExhausted {
+        message: String,
+        details: Option<Dict>,
+    },
+    #[error("timeout: {message}")]
+    Timeout {
+        message: String,
+        details: Option<Dict>,
+    },
+    #[error("internal tool error: {message}")]
+    Internal {
+        message: String,
+        details: Option<Dict>,
+    },
+    /// The tool failed but already computed part of the result (e.g. the
+    /// first 90 of 100 repetitions), see [`ToolError::with_partial`].
+    #[error("{error} (partial result attached)")]
+    Partial {
+        error: Box<ToolError>,
+        partial: Box<Value>,
+    },
+}
+
+impl ToolError {
+    pub fn invalid_input(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            path: path.into(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn resource_exhausted(message: impl Into<String>) -> Self {
+        Self::ResourceExhausted {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self::Timeout {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::Internal {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Attach structured details for client scripts, e.g. the allowed range
+    /// of an invalid input. Errors without a `details` field are unchanged.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, Value, value::dynamic::Dict};
+    /// let details = Dict([("max".to_string(), Value::Float(5.0))].into());
+    /// let err = ToolError::invalid_input("t1", "T1 must be below 5 s").with_details(details);
+    /// assert_eq!(err.code(), "invalid_input");
+    /// assert!(err.details().is_some());
+    /// ```
+    pub fn with_details(mut self, dict: Dict) -> Self {
+        if let Some(details) = self.details_mut() {
+            *details = Some(dict);
+        }
+        self
+    }
+
+    fn details_mut(&mut self) -> Option<&mut Option<Dict>> {
+        match self {
+            Self::InvalidInput { details, .. }
+            | Self::ResourceExhausted { details, .. }
+            | Self::Timeout { details, .. }
+            | Self::Internal { details, .. } => Some(details),
+            Self::Partial { error, .. } => error.details_mut(),
+            Self::Extraction(_) | Self::Abort(_) | Self::Custom(_) => None,
+        }
+    }
+
+    pub fn details(&self) -> Option<&Dict> {
+        match self {
+            Self::InvalidInput { details, .. }
+            | Self::ResourceExhausted { details, .. }
+            | Self::Timeout { details, .. }
+            | Self::Internal { details, .. } => details.as_ref(),
+            Self::Partial { error, .. } => error.details(),
+            Self::Extraction(_) | Self::Abort(_) | Self::Custom(_) => None,
+        }
+    }
+
+    /// Attach the part of the result that was computed before the error.
+    /// The client receives it with the error, see [`ToolCallError::partial_result`].
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, Value};
+    /// let err = ToolError::resource_exhausted("out of memory").with_partial(Value::Int(90));
+    /// assert_eq!(err.code(), "resource_exhausted");
+    /// assert!(matches!(err.partial(), Some(Value::Int(90))));
+    /// ```
+    pub fn with_partial(self, partial: Value) -> Self {
+        let error = match self {
+            Self::Partial { error, .. } => error,
+            error => Box::new(error),
+        };
+        Self::Partial {
+            error,
+            partial: Box::new(partial),
+        }
+    }
+
+    pub fn partial(&self) -> Option<&Value> {
+        match self {
+            Self::Partial { partial, .. } => Some(partial),
+            _ => None,
+        }
+    }
+
+    /// [`ErrorKind::Aborted`] for aborts, [`ErrorKind::Tool`] for all other errors.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Abort(_) => ErrorKind::Aborted,
+            Self::Partial { error, .. } => error.kind(),
+            _ => ErrorKind::Tool,
+        }
+    }
+
+    /// Machine-readable error code, stable across versions.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Extraction(_) => "extraction",
+            Self::Abort(_) => "abort",
+            Self::Custom(_) => "custom",
+            Self::InvalidInput { .. } => "invalid_input",
+            Self::ResourceExhausted { .. } => "resource_exhausted",
+            Self::Timeout { .. } => "timeout",
+            Self::Internal { .. } => "internal",
+            Self::Partial { error, .. } => error.code(),
+        }
+    }
 }
@@ -0,0 +1,130 @@
+//! Description of a deployment served on `/info`, see [`ServerInfo`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Capabilities, OutputPolicy, PROTOCOL_VERSION, Routes, ServerConfig,
+    connection::websocket::{FRAME_SIZE, MAX_FRAME_SIZE},
+};
+
+/// Returned as JSON by the `/info` route of [`crate::run_server`], so that
+/// clients and orchestration layers can check a deployment before calling it.
+///
+/// ```json
+/// {
+///   "tools": [{ "name": "mr0-sim", "version": "1.2.0", "route": "/tool" }],
+///   "toolapi_version": "0.5.3",
+///   "protocol_version": 12,
+///   "features": { "compression": ["zstd"], "frame_size": 8388608, "input_cache": true, ... },
+///   "limits": { "max_frame_size": 268435456, "max_output_size": null, ... }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub tools: Vec<ToolInfo>,
+    /// Crate version of the server
+    pub toolapi_version: String,
+    /// See [`crate::PROTOCOL_VERSION`]
+    pub protocol_version: u32,
+    pub features: ServerFeatures,
+    pub limits: ServerLimits,
+}
+
+/// See [`ServerConfig::tool_name`] and [`ServerConfig::tool_version`], tools
+/// of [`crate::run_server_routes`] are named after their route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// WebSocket route of the tool, e.g. `/tool` or `/tool/simulate`
+    pub route: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerFeatures {
+    /// Algorithms the server accepts and uses (see [`crate::Compression`])
+    pub compression: Vec<String>,
+    /// Large messages are split into WebSocket frames of at most this size
+    pub frame_size: usize,
+    /// If large inputs are cached, see [`crate::CacheConfig`]
+    pub input_cache: bool,
+    /// See [`ServerConfig::spool_min_size`]
+    pub spool_min_size: Option<usize>,
+    /// If inputs of any tool are validated, see [`ServerConfig::input_schema`]
+    pub input_schema: bool,
+    /// Announced in the handshake, see [`crate::Capabilities`]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerLimits {
+    /// Larger WebSocket frames are rejected
+    pub max_frame_size: usize,
+    /// See [`ServerConfig::max_output_size`]
+    pub max_output_size: Option<usize>,
+    /// `"error"` or `"truncate"`, see [`ServerConfig::output_policy`]
+    pub output_policy: String,
+    /// See [`crate::CacheConfig::max_size`]
+    pub cache_max_size: usize,
+    /// See [`crate::ArtifactConfig::max_size`]
+    pub artifact_max_size: usize,
+    /// See [`ServerConfig::max_job_memory`]
+    pub max_job_memory: Option<usize>,
+    /// In seconds, see [`ServerConfig::max_cpu_time`]
+    pub max_cpu_time: Option<f64>,
+    /// In seconds, see [`ServerConfig::timeout`]
+    pub timeout: Option<f64>,
+    /// See [`ServerConfig::max_concurrent_tools`]
+    pub max_concurrent_tools: Option<usize>,
+}
+
+impl ServerInfo {
+    pub(crate) fn new(config: &ServerConfig, routes: &Routes) -> Self {
+        let tools = routes
+            .names()
+            .map(|name| match name {
+                "" => ToolInfo {
+                    name: config.tool_name.clone(),
+                    version: config.tool_version.clone(),
+                    route: "/tool".to_string(),
+                },
+                name => ToolInfo {
+                    name: Some(name.to_string()),
+                    version: config.tool_version.clone(),
+                    route: format!("/tool/{name}"),
+                },
+            })
+            .collect();
+        Self {
+            tools,
+            toolapi_version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            features: ServerFeatures {
+                compression: vec!["zstd".to_string()],
+                frame_size: FRAME_SIZE,
+                input_cache: config.cache.max_size > 0,
+                spool_min_size: config.spool_min_size,
+                input_schema: routes.routes.values().any(|r| r.input_schema.is_some()),
+                capabilities: Capabilities::server(config.cache.max_size > 0)
+                    .0
+                    .into_iter()
+                    .collect(),
+            },
+            limits: ServerLimits {
+                max_frame_size: MAX_FRAME_SIZE,
+                max_output_size: config.max_output_size,
+                output_policy: match config.output_policy {
+                    OutputPolicy::Error => "error",
+                    OutputPolicy::Truncate => "truncate",
+                }
+                .to_string(),
+                cache_max_size: config.cache.max_size,
+                artifact_max_size: config.artifacts.max_size,
+                max_job_memory: config.max_job_memory,
+                max_cpu_time: config.max_cpu_time.map(|max| max.as_secs_f64()),
+                timeout: config.timeout.map(|max| max.as_secs_f64()),
+                max_concurrent_tools: config.max_concurrent_tools,
+            },
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Process wide Ctrl-C handler of [`crate::call_with_ctrlc`].
+
+use std::sync::{
+    Arc, OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Set by the handler, checked by the running call
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+/// Whether a call is running, otherwise Ctrl-C exits like without a handler
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Exit code of processes terminated by SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Installs the handler on first use and resets the flag for a new call.
+///
+/// # Panics
+/// If the application installed its own Ctrl-C handler.
+pub(crate) fn start_call() -> Arc<AtomicBool> {
+    let interrupt = INTERRUPT.get_or_init(|| {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let handler_interrupt = interrupt.clone();
+        ctrlc::set_handler(move || {
+            // A second Ctrl-C doesn't wait for the tool to acknowledge the abort
+            if !ACTIVE.load(Ordering::Relaxed) || handler_interrupt.swap(true, Ordering::Relaxed) {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+            eprintln!("aborting the tool, press Ctrl-C again to exit immediately");
+        })
+        .expect("toolapi::call_with_ctrlc can't be combined with another Ctrl-C handler, use toolapi::call_interruptible instead");
+        interrupt
+    });
+    interrupt.store(false, Ordering::Relaxed);
+    ACTIVE.store(true, Ordering::Relaxed);
+    interrupt.clone()
+}
+
+pub(crate) fn end_call() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
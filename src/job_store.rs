@@ -0,0 +1,262 @@
+//! Results of finished calls, kept for [`crate::ServerConfig::job_store`].
+
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    JobMeta, JobStoreConfig, StorageKey, ToolError, Value, at_rest,
+    connection::websocket::{decode, encode},
+};
+
+/// A finished call as kept by a [`JobStore`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredJob {
+    pub job_id: String,
+    /// When the result was ready, in seconds since the Unix epoch
+    pub finished: f64,
+    /// As sent to the client
+    pub output: Result<Value, ToolError>,
+    pub meta: JobMeta,
+    /// Blake3 hash of the secret a client resumes the call with, `None` if
+    /// its client can't resume it
+    pub secret_hash: Option<[u8; 32]>,
+}
+
+/// Where the server keeps the results of finished calls, see [`JobStoreConfig`].
+///
+/// The server calls these methods on a blocking thread, so implementations
+/// backed by e.g. Redis or S3 can use blocking clients. Errors are logged and
+/// otherwise ignored, the call itself doesn't fail.
+///
+/// # Examples
+/// ```
+/// use std::{io, time::SystemTime};
+/// use toolapi::{JobStore, StoredJob};
+///
+/// /// Keeps nothing, e.g. for tools whose results must not be retained
+/// struct Discard;
+///
+/// impl JobStore for Discard {
+///     fn put(&self, _: StoredJob) -> io::Result<()> {
+///         Ok(())
+///     }
+///
+///     fn get(&self, _: &str) -> io::Result<Option<StoredJob>> {
+///         Ok(None)
+///     }
+///
+///     fn expire(&self, _: SystemTime) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait JobStore: Send + Sync {
+    /// Keep `job`, replacing a job with the same id
+    fn put(&self, job: StoredJob) -> io::Result<()>;
+
+    /// `None` for unknown and expired jobs
+    fn get(&self, job_id: &str) -> io::Result<Option<StoredJob>>;
+
+    /// Delete all jobs that finished before `before`. Called periodically by
+    /// the server, with the current time minus [`JobStoreConfig::retention`].
+    fn expire(&self, before: SystemTime) -> io::Result<()>;
+}
+
+/// Keeps jobs in the memory of the server, they are lost when it restarts.
+/// This is the default.
+#[derive(Debug, Default)]
+pub struct MemoryJobStore {
+    jobs: Mutex<HashMap<String, StoredJob>>,
+}
+
+impl JobStore for MemoryJobStore {
+    fn put(&self, job: StoredJob) -> io::Result<()> {
+        self.jobs.lock().unwrap().insert(job.job_id.clone(), job);
+        Ok(())
+    }
+
+    fn get(&self, job_id: &str) -> io::Result<Option<StoredJob>> {
+        Ok(self.jobs.lock().unwrap().get(job_id).cloned())
+    }
+
+    fn expire(&self, before: SystemTime) -> io::Result<()> {
+        let before = unix_time(before);
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.finished >= before);
+        Ok(())
+    }
+}
+
+/// Keeps every job in a file `{job_id}.job` in a directory, so that results
+/// survive restarts of the server. Files are encoded like
+/// [`crate::AuditRecord`]s and encrypted with [`Self::with_key`].
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use toolapi::{FileJobStore, JobStoreConfig, ServerConfig, StorageKey};
+///
+/// # let hex = "00".repeat(32);
+/// let key = StorageKey::from_hex(&hex).unwrap();
+/// let config = ServerConfig {
+///     job_store: JobStoreConfig {
+///         store: Arc::new(FileJobStore::new("/var/lib/tool/jobs").with_key(key.clone())),
+///         ..JobStoreConfig::default()
+///     },
+///     storage_key: Some(key),
+///     ..ServerConfig::default()
+/// };
+/// ```
+#[derive(Debug)]
+pub struct FileJobStore {
+    dir: PathBuf,
+    key: Option<StorageKey>,
+}
+
+impl FileJobStore {
+    /// The directory is created with the first job
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            key: None,
+        }
+    }
+
+    /// Encrypt the files, usually with [`crate::ServerConfig::storage_key`].
+    /// Plain files (e.g. written before the key was set) are rejected, they
+    /// are treated like unreadable ones.
+    pub fn with_key(mut self, key: StorageKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// `None` for ids that could escape the directory, which are never
+    /// assigned by the server
+    fn path(&self, job_id: &str) -> Option<PathBuf> {
+        let valid = !job_id.is_empty()
+            && job_id
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+        valid.then(|| self.dir.join(format!("{job_id}.job")))
+    }
+}
+
+impl JobStore for FileJobStore {
+    fn put(&self, job: StoredJob) -> io::Result<()> {
+        let path = self.path(&job.job_id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "job id is not a file name")
+        })?;
+        std::fs::create_dir_all(&self.dir)?;
+        let raw = at_rest::seal(self.key.as_ref(), encode(&job).map_err(io::Error::other)?)?;
+        // Renamed once complete, so that readers never see a partial job
+        let partial = path.with_extension("job.partial");
+        std::fs::write(&partial, raw)?;
+        std::fs::rename(&partial, &path)
+    }
+
+    fn get(&self, job_id: &str) -> io::Result<Option<StoredJob>> {
+        let Some(path) = self.path(job_id) else {
+            return Ok(None);
+        };
+        let raw = match std::fs::read(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let raw = at_rest::open(self.key.as_ref(), raw)?;
+        decode(&raw).map(Some).map_err(io::Error::other)
+    }
+
+    fn expire(&self, before: SystemTime) -> io::Result<()> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let is_job = path.extension().is_some_and(|ext| ext == "job");
+            if !is_job {
+                continue;
+            }
+            // Another call might be expiring at the same time
+            let modified = match std::fs::metadata(&path).and_then(|meta| meta.modified()) {
+                Ok(modified) => modified,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            if modified < before {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Keep the result of a finished call in the background, failures are only
+/// logged. The handle completes once it was kept. Only calls that can be
+/// resumed with the secret of `secret_hash` are kept, nobody could read the
+/// others.
+pub(crate) fn keep(
+    config: &JobStoreConfig,
+    result: &Result<Value, ToolError>,
+    meta: &JobMeta,
+    secret_hash: [u8; 32],
+) -> Option<JoinHandle<()>> {
+    if config.retention.is_zero() {
+        return None;
+    }
+    let job = StoredJob {
+        job_id: meta.job_id.clone(),
+        finished: unix_time(SystemTime::now()),
+        output: result.clone(),
+        meta: meta.clone(),
+        secret_hash: Some(secret_hash),
+    };
+    let store = config.store.clone();
+    let kept = tokio::task::spawn_blocking(move || {
+        let job_id = job.job_id.clone();
+        if let Err(err) = store.put(job) {
+            println!("STORE {job_id} failed to keep the result: {err}");
+        }
+    });
+    Some(kept)
+}
+
+/// Delete expired jobs until the server stops, instead of after every call,
+/// so that the last results of an idle server are freed as well
+pub(crate) async fn expire_periodically(config: JobStoreConfig) {
+    if config.retention.is_zero() {
+        return;
+    }
+    let mut ticks = tokio::time::interval(config.retention.min(EXPIRE_INTERVAL));
+    loop {
+        ticks.tick().await;
+        let store = config.store.clone();
+        let expired = SystemTime::now()
+            .checked_sub(config.retention)
+            .unwrap_or(UNIX_EPOCH);
+        let result = tokio::task::spawn_blocking(move || store.expire(expired)).await;
+        if let Ok(Err(err)) = result {
+            println!("STORE failed to expire old results: {err}");
+        }
+    }
+}
+
+/// Upper bound of the time between two [`JobStore::expire`]
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
@@ -0,0 +1,358 @@
+//! Calls that are currently running on a server, see [`ServerHandle::active_jobs`].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+use tokio::sync::{Notify, watch};
+
+use crate::{
+    connection::websocket::Message, context::random_bytes, limit::encoded_size, util::hex,
+};
+
+/// A tool call that is running on the server, from receiving the input until
+/// the result is sent.
+#[derive(Debug, Clone)]
+pub struct ActiveJob {
+    pub job_id: String,
+    pub started: SystemTime,
+    /// Last message between the tool and the client (or the start)
+    pub last_activity: SystemTime,
+}
+
+/// Shared by all connections of a server.
+pub(crate) struct JobRegistry {
+    jobs: Mutex<HashMap<String, ActiveJob>>,
+    /// See [`crate::ServerConfig::max_concurrent_tools`]
+    max_running: usize,
+    queue: Mutex<QueueState>,
+    /// Notifies waiting calls whenever a slot is freed or the queue moves
+    queue_changed: watch::Sender<()>,
+    /// Running calls that can be resumed, see [`JobLog`]
+    logs: Mutex<HashMap<String, Arc<JobLog>>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    /// Tickets of the calls waiting for a slot, in the order they arrived
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+impl JobRegistry {
+    pub(crate) fn new(max_running: Option<usize>) -> Self {
+        Self {
+            jobs: Mutex::default(),
+            max_running: max_running.unwrap_or(usize::MAX),
+            queue: Mutex::default(),
+            queue_changed: watch::Sender::new(()),
+            logs: Mutex::default(),
+        }
+    }
+
+    /// Log the messages of a call until the returned guard is dropped, so
+    /// that its client can resume it with the secret of `secret_hash`. See
+    /// [`crate::JobStoreConfig::max_log_size`] for `max_size`.
+    pub(crate) fn track(
+        self: &Arc<Self>,
+        job_id: &str,
+        secret_hash: [u8; 32],
+        max_size: usize,
+    ) -> LogGuard {
+        let log = Arc::new(JobLog::new(secret_hash, max_size));
+        self.logs
+            .lock()
+            .unwrap()
+            .insert(job_id.to_string(), log.clone());
+        LogGuard {
+            registry: self.clone(),
+            job_id: job_id.to_string(),
+            log,
+        }
+    }
+
+    /// `None` once the call finished, if it was never tracked or for the
+    /// wrong secret
+    pub(crate) fn log(&self, job_id: &str, secret: &str) -> Option<Arc<JobLog>> {
+        let log = self.logs.lock().unwrap().get(job_id).cloned()?;
+        secret_matches(secret, &log.secret_hash).then_some(log)
+    }
+
+    /// Line up for a slot to run a tool, see [`QueueTicket::position`]
+    pub(crate) fn enqueue(self: &Arc<Self>) -> QueueTicket {
+        let mut queue = self.queue.lock().unwrap();
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        queue.waiting.push_back(ticket);
+        QueueTicket {
+            registry: self.clone(),
+            ticket,
+            running: false,
+            changed: self.queue_changed.subscribe(),
+        }
+    }
+
+    /// The job is listed until the returned guard is dropped
+    pub(crate) fn register(self: &Arc<Self>, job_id: &str) -> JobGuard {
+        let now = SystemTime::now();
+        let job = ActiveJob {
+            job_id: job_id.to_string(),
+            started: now,
+            last_activity: now,
+        };
+        self.jobs.lock().unwrap().insert(job_id.to_string(), job);
+        JobGuard {
+            registry: self.clone(),
+            job_id: job_id.to_string(),
+        }
+    }
+
+    fn list(&self) -> Vec<ActiveJob> {
+        let mut jobs: Vec<_> = self.jobs.lock().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|job| job.started);
+        jobs
+    }
+}
+
+pub(crate) struct JobGuard {
+    registry: Arc<JobRegistry>,
+    job_id: String,
+}
+
+impl JobGuard {
+    pub(crate) fn touch(&self) {
+        if let Some(job) = self.registry.jobs.lock().unwrap().get_mut(&self.job_id) {
+            job.last_activity = SystemTime::now();
+        }
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.job_id);
+    }
+}
+
+/// Stamped messages sent for a call whose client announced
+/// [`crate::Capabilities::RESUME`], in the order of their `seq`. Kept while the
+/// call runs, clients that resume it get the messages they missed from here
+/// and the result from the [`crate::JobStore`]. Messages the client
+/// acknowledged are dropped, and the oldest ones beyond `max_size`.
+pub(crate) struct JobLog {
+    /// Of the secret sent to the client in `Accepted`
+    secret_hash: [u8; 32],
+    max_size: usize,
+    messages: Mutex<LoggedMessages>,
+    /// Set once the result is in the job store
+    finished: AtomicBool,
+    /// Notified with every message and when the call finished
+    changed: watch::Sender<()>,
+    /// Abort requested by a client that resumed the call
+    abort: Notify,
+}
+
+#[derive(Default)]
+struct LoggedMessages {
+    /// With the size of their encoding
+    messages: VecDeque<(Message, usize)>,
+    /// `seq` of the first message
+    first: u64,
+    size: usize,
+}
+
+impl LoggedMessages {
+    fn pop_front(&mut self) {
+        if let Some((_, size)) = self.messages.pop_front() {
+            self.first += 1;
+            self.size -= size;
+        }
+    }
+}
+
+impl JobLog {
+    fn new(secret_hash: [u8; 32], max_size: usize) -> Self {
+        Self {
+            secret_hash,
+            max_size,
+            messages: Mutex::default(),
+            finished: AtomicBool::default(),
+            changed: watch::Sender::new(()),
+            abort: Notify::new(),
+        }
+    }
+
+    pub(crate) fn secret_hash(&self) -> [u8; 32] {
+        self.secret_hash
+    }
+
+    pub(crate) fn push(&self, msg: Message) {
+        let size = encoded_size(&msg);
+        let mut log = self.messages.lock().unwrap();
+        log.messages.push_back((msg, size));
+        log.size += size;
+        // The newest is kept, it might be the `JobMeta`
+        while log.size > self.max_size && log.messages.len() > 1 {
+            log.pop_front();
+        }
+        drop(log);
+        self.changed.send_replace(());
+    }
+
+    /// The client received all messages before `seq`
+    pub(crate) fn ack(&self, seq: u64) {
+        let mut log = self.messages.lock().unwrap();
+        while log.first < seq && !log.messages.is_empty() {
+            log.pop_front();
+        }
+    }
+
+    /// Messages from `seq` on and whether the call finished after them. Dropped
+    /// messages are missing, which the client sees as a gap in `seq`.
+    pub(crate) fn since(&self, seq: u64) -> (Vec<Message>, bool) {
+        // Read first, a message pushed in between is sent with the next call
+        let finished = self.finished.load(Ordering::Acquire);
+        let log = self.messages.lock().unwrap();
+        let skip = usize::try_from(seq.saturating_sub(log.first)).unwrap_or(usize::MAX);
+        let messages = log.messages.iter().skip(skip).map(|(msg, _)| msg.clone());
+        (messages.collect(), finished)
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// No messages follow, the result is in the job store (if it was kept)
+    fn finish(&self) {
+        self.finished.store(true, Ordering::Release);
+        self.changed.send_replace(());
+    }
+
+    pub(crate) fn request_abort(&self) {
+        self.abort.notify_one();
+    }
+
+    /// Wait for [`Self::request_abort`]
+    pub(crate) async fn aborted(&self) {
+        self.abort.notified().await;
+    }
+}
+
+/// Random secret that a client resumes a call with, it can't be guessed like
+/// the job id of another client
+pub(crate) fn new_secret() -> String {
+    hex(&random_bytes::<16>())
+}
+
+/// Only this is kept of a secret, in the [`JobLog`] and the [`crate::JobStore`]
+pub(crate) fn secret_hash(secret: &str) -> [u8; 32] {
+    *blake3::hash(secret.as_bytes()).as_bytes()
+}
+
+/// Compared in constant time
+pub(crate) fn secret_matches(secret: &str, hash: &[u8; 32]) -> bool {
+    blake3::hash(secret.as_bytes()) == blake3::Hash::from_bytes(*hash)
+}
+
+/// Finishes the [`JobLog`] of a call when dropped, clients resuming it later
+/// only find the job store
+pub(crate) struct LogGuard {
+    registry: Arc<JobRegistry>,
+    job_id: String,
+    log: Arc<JobLog>,
+}
+
+impl LogGuard {
+    pub(crate) fn log(&self) -> Arc<JobLog> {
+        self.log.clone()
+    }
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        self.registry.logs.lock().unwrap().remove(&self.job_id);
+        self.log.finish();
+    }
+}
+
+/// Place of a call in the queue of a server, the slot is held until it is dropped.
+pub(crate) struct QueueTicket {
+    registry: Arc<JobRegistry>,
+    ticket: u64,
+    running: bool,
+    changed: watch::Receiver<()>,
+}
+
+impl QueueTicket {
+    /// Number of calls waiting before this one, `None` once it got a slot
+    pub(crate) fn position(&mut self) -> Option<usize> {
+        if self.running {
+            return None;
+        }
+        // Changes from now on wake up `changed`
+        self.changed.borrow_and_update();
+        let mut queue = self.registry.queue.lock().unwrap();
+        let position = queue
+            .waiting
+            .iter()
+            .position(|ticket| *ticket == self.ticket)
+            .expect("tickets wait until they run");
+        if position > 0 || queue.running >= self.registry.max_running {
+            return Some(position);
+        }
+        queue.waiting.pop_front();
+        queue.running += 1;
+        self.running = true;
+        drop(queue);
+        // The next call might get a slot as well
+        self.registry.queue_changed.send_replace(());
+        None
+    }
+
+    /// Wait until the [`Self::position`] might have changed
+    pub(crate) async fn changed(&mut self) {
+        // The sender lives as long as the registry
+        let _ = self.changed.changed().await;
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        let mut queue = self.registry.queue.lock().unwrap();
+        if self.running {
+            queue.running -= 1;
+        } else {
+            queue.waiting.retain(|ticket| *ticket != self.ticket);
+        }
+        drop(queue);
+        self.registry.queue_changed.send_replace(());
+    }
+}
+
+/// Server running in the background, returned by [`crate::spawn_server_with_config`].
+pub struct ServerHandle {
+    pub(crate) jobs: Arc<JobRegistry>,
+    pub(crate) thread: JoinHandle<Result<(), std::io::Error>>,
+}
+
+impl ServerHandle {
+    /// Calls that are running right now, oldest first. Embedders can build
+    /// dashboards or watchdogs (e.g. for jobs without activity) on top of it.
+    pub fn active_jobs(&self) -> Vec<ActiveJob> {
+        self.jobs.list()
+    }
+
+    /// Block until the server stops, which only happens on an error
+    pub fn wait(self) -> Result<(), std::io::Error> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
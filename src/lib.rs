@@ -4,21 +4,129 @@ use axum::{
     routing::{any, get},
 };
 
+#[cfg(feature = "server")]
+mod artifacts;
+#[cfg(feature = "server")]
+mod at_rest;
+#[cfg(feature = "server")]
+mod audit;
+#[cfg(feature = "server")]
+mod budget;
+#[cfg(feature = "server")]
+mod cache;
+#[cfg(feature = "server")]
+mod coalesce;
+#[cfg(feature = "server")]
+mod config;
+#[cfg(any(feature = "server", feature = "client"))]
 mod connection;
+#[cfg(feature = "server")]
+mod context;
+#[cfg(feature = "values")]
 mod error;
 #[cfg(feature = "server")]
+mod info;
+#[cfg(all(feature = "ctrlc", not(target_arch = "wasm32")))]
+mod interrupt;
+#[cfg(feature = "server")]
+mod job_store;
+#[cfg(feature = "server")]
+mod jobs;
+#[cfg(feature = "server")]
+mod limit;
+#[cfg(feature = "client")]
+mod resolve;
+#[cfg(feature = "server")]
+mod routes;
+#[cfg(feature = "server")]
+mod sizes;
+#[cfg(feature = "server")]
+mod tls;
+#[cfg(feature = "server")]
 mod util;
 
 // =====================================
 // Public API of toolapi
 // =====================================
 
+#[cfg(feature = "wasm")]
+pub mod browser;
+#[cfg(feature = "server")]
+pub mod stdio;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "values")]
 pub mod value;
 
+#[cfg(feature = "server")]
+pub use audit::AuditRecord;
+#[cfg(feature = "server")]
+pub use budget::JobAllocator;
+#[cfg(feature = "server")]
+pub use config::{
+    ArtifactConfig, AuditConfig, BufferPolicy, CacheConfig, Hook, Hooks, JobStoreConfig,
+    OperatorConfig, OutputPolicy, PanicDetail, ServerConfig, StorageKey, TlsConfig,
+};
+#[cfg(feature = "client")]
+pub use connection::client::{ToolEvent, ToolEventKind};
+#[cfg(any(feature = "server", feature = "client"))]
+pub use connection::websocket::{Capabilities, Compression, JobMeta};
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use connection::websocket::{HEARTBEAT_ENV, REDIRECTS_ENV};
+#[cfg(feature = "server")]
+pub use context::{AbortSignal, LogLevel, Tool, ToolCtx, ToolHandler};
+#[cfg(feature = "values")]
 pub use error::*;
+#[cfg(feature = "server")]
+pub use info::{ServerFeatures, ServerInfo, ServerLimits, ToolInfo};
+#[cfg(feature = "server")]
+pub use job_store::{FileJobStore, JobStore, MemoryJobStore, StoredJob};
+#[cfg(feature = "server")]
+pub use jobs::{ActiveJob, ServerHandle};
+#[cfg(feature = "client")]
+pub use resolve::artifact_url;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use resolve::{URL_ENV, config_path, tool_url};
+#[cfg(feature = "server")]
+pub use routes::Routes;
+#[cfg(feature = "values")]
 pub use value::Value;
 // pub use value_legacy::{Value, ValueDict};
 
+/// Version of the wire protocol, independent of the crate version.
+///
+/// Clients and servers with the same protocol version can talk to each other.
+/// Both send it in a `Hello` at the start of every connection, other versions
+/// are rejected with [`ConnectionError::ProtocolMismatch`] naming both of them.
+/// It is bumped with every change to the serialized representation of messages
+/// and [`Value`]s, which is guarded by golden fixtures (see `testing::wire_fixtures`).
+/// Optional features are announced as [`Capabilities`] instead.
+pub const PROTOCOL_VERSION: u32 = 25;
+
+/// Entry of a [`Value::Dict`] input with the number of seconds the client
+/// allows the call to take, shorter than [`ServerConfig::timeout`] (which it
+/// can't extend). The server removes it before the input reaches the tool.
+///
+/// ```no_run
+/// # use toolapi::{TIMEOUT_KEY, Value, value::dynamic::Dict};
+/// let input = Dict([
+///     ("te".to_string(), Value::Float(0.005)),
+///     (TIMEOUT_KEY.to_string(), Value::Float(60.0)),
+/// ].into());
+/// toolapi::call("wss://tool-xxx-flyio.fly.dev/tool", Value::Dict(input), |_| true);
+/// ```
+#[cfg(any(feature = "server", feature = "client"))]
+pub const TIMEOUT_KEY: &str = "_timeout";
+
+/// TypeScript definitions of the wire protocol, for JavaScript clients that
+/// talk to tools without using this crate. Print them with
+/// `cargo run --features typescript --bin toolapi-dts > toolapi.d.ts`.
+///
+/// These are written by hand and must be kept in sync with the `Message` and
+/// [`Value`] types whenever their serialized representation changes.
+#[cfg(feature = "typescript")]
+pub const TYPESCRIPT_DEFINITIONS: &str = include_str!("protocol.d.ts");
+
 /// Function which prints a message, sends it to the client, and returns weather
 /// the client requested to abort the running tool.
 ///
@@ -30,10 +138,11 @@ pub use value::Value;
 #[cfg(feature = "server")]
 pub type MessageFn = dyn FnMut(String) -> Result<(), AbortReason>;
 
-/// Signature of tool functions passed to [`run_server`].
+/// Original signature of tool functions passed to [`run_server`].
 ///
 /// It recieves the inputs of the caller as argument, as well as a instance of
-/// [`MessageFn`] to log messages and abort on request. It returns the computed
+/// [`MessageFn`] to log messages and abort on request. New tools should take
+/// a [`ToolCtx`] instead, which offers more than sending messages. It returns the computed
 /// value (e.g.: a simulation result, a parsed sequence) or an error, which will
 /// be communicated to the client appropriately.
 ///
@@ -54,11 +163,17 @@ pub type ToolFn = fn(Value, &mut MessageFn) -> Result<Value, ToolError>;
 ///
 /// Routes:
 /// - `/` (GET): Returns an optional static web page (`index_html`) or 404
+/// - `/info` (GET): Describes the deployment as JSON, see [`ServerInfo`]
+/// - `/jobs/{id}/artifacts/{name}` (GET): Files registered with [`ToolCtx::artifact`]
 /// - `/tool` (WebSocket): Runs the tool, pass this url to [`call`]
+/// - `/tool/{name}` (WebSocket): Tools of [`run_server_routes`]
+///
+/// All routes but `/` require the [`ServerConfig::auth_token`] if one is set.
 ///
 /// `tool` is a blocking function that implements the actual business logic of
 /// this server. It runs on a separate thread and will not block the server from
-/// hanlding more requests in parallel. See [`ToolFn`] for more details.
+/// hanlding more requests in parallel. See [`ToolFn`] and [`ToolCtx`] for the
+/// supported signatures.
 ///
 /// # Examples
 /// ```no_run
@@ -87,24 +202,167 @@ pub type ToolFn = fn(Value, &mut MessageFn) -> Result<Value, ToolError>;
 /// ";
 /// ```
 #[cfg(feature = "server")]
-pub fn run_server(tool: ToolFn, index_html: Option<&'static str>) -> Result<(), std::io::Error> {
+pub fn run_server<M>(
+    tool: impl ToolHandler<M>,
+    index_html: Option<&'static str>,
+) -> Result<(), std::io::Error> {
+    run_server_with_config(tool, index_html, ServerConfig::default())
+}
+
+/// Like [`run_server`] for a [`Tool`], whose inputs are validated against
+/// its [`Tool::input_schema`]. Other settings can be passed to
+/// [`run_server_with_config`] together with the schema of the tool.
+#[cfg(feature = "server")]
+pub fn run_server_tool(
+    tool: impl Tool,
+    index_html: Option<&'static str>,
+) -> Result<(), std::io::Error> {
+    let config = ServerConfig {
+        input_schema: Some(tool.input_schema()),
+        ..Default::default()
+    };
+    run_server_with_config(tool, index_html, config)
+}
+
+/// Like [`run_server`], but with non-default settings.
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use toolapi::{PanicDetail, ServerConfig, run_server_with_config};
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+///
+/// fn main() -> Result<(), std::io::Error> {
+///     let config = ServerConfig {
+///         panic_detail: PanicDetail::Backtrace,
+///         ..Default::default()
+///     };
+///     run_server_with_config(tool, None, config)
+/// }
+/// ```
+#[cfg(feature = "server")]
+pub fn run_server_with_config<M>(
+    tool: impl ToolHandler<M>,
+    index_html: Option<&'static str>,
+    config: ServerConfig,
+) -> Result<(), std::io::Error> {
+    spawn_server_with_config(tool, index_html, config)?.wait()
+}
+
+/// Like [`run_server_with_config`], but the server runs on a background
+/// thread. The returned handle lists the running calls, e.g. for a dashboard.
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use std::time::{Duration, SystemTime};
+/// use toolapi::{ServerConfig, spawn_server_with_config};
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+///
+/// fn main() -> Result<(), std::io::Error> {
+///     let server = spawn_server_with_config(tool, None, ServerConfig::default())?;
+///     loop {
+///         std::thread::sleep(Duration::from_secs(60));
+///         for job in server.active_jobs() {
+///             let idle = SystemTime::now().duration_since(job.last_activity).unwrap_or_default();
+///             println!("{}: idle for {idle:?}", job.job_id);
+///         }
+///     }
+/// }
+/// ```
+#[cfg(feature = "server")]
+pub fn spawn_server_with_config<M>(
+    tool: impl ToolHandler<M>,
+    index_html: Option<&'static str>,
+    config: ServerConfig,
+) -> Result<ServerHandle, std::io::Error> {
+    let routes = Routes::single(context::shared(tool), config.input_schema.clone());
+    spawn_server_routes(routes, index_html, config)
+}
+
+/// Like [`run_server_with_config`], but serves several tools, each on its
+/// own route `/tool/{name}` (see [`Routes`]). `/info` lists all of them.
+/// [`ServerConfig::input_schema`] is ignored, tools added with
+/// [`Routes::route_tool`] are validated against their own schema.
+#[cfg(feature = "server")]
+pub fn run_server_routes(
+    routes: Routes,
+    index_html: Option<&'static str>,
+    config: ServerConfig,
+) -> Result<(), std::io::Error> {
+    spawn_server_routes(routes, index_html, config)?.wait()
+}
+
+/// Like [`run_server_routes`], but the server runs on a background thread,
+/// see [`spawn_server_with_config`].
+#[cfg(feature = "server")]
+pub fn spawn_server_routes(
+    routes: Routes,
+    index_html: Option<&'static str>,
+    config: ServerConfig,
+) -> Result<ServerHandle, std::io::Error> {
+    // Without the allocator, the memory of one call can't be told from others
+    if config.max_job_memory.is_some()
+        && !budget::JobAllocator::installed()
+        && config.max_concurrent_tools != Some(1)
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "max_job_memory needs toolapi::JobAllocator as global allocator or max_concurrent_tools = Some(1)",
+        ));
+    }
+
     // Setup routes and state to pass data to handlers
-    let state = util::ToolState { tool, index_html };
+    let tls_config = config.tls.clone();
+    let job_store = config.job_store.clone();
+    let jobs = std::sync::Arc::new(jobs::JobRegistry::new(config.max_concurrent_tools));
+    let state = util::ToolState {
+        routes: std::sync::Arc::new(routes),
+        index_html,
+        cache: std::sync::Arc::new(cache::BlobCache::new(config.cache.clone())),
+        artifacts: std::sync::Arc::new(artifacts::ArtifactStore::new(config.artifacts.clone())),
+        jobs: jobs.clone(),
+        config,
+    };
     let routes = Router::new()
         .route("/", get(util::index_handler))
+        .route("/info", get(util::info_handler))
+        .route("/jobs/{id}/artifacts/{name}", get(util::artifact_handler))
         .route("/tool", any(util::socket_handler))
+        .route("/tool/{name}", any(util::route_socket_handler))
         .with_state(state);
 
-    // We can configure the runtime here: single / multithreaded, number of workers...
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            // Server code that runs continuously until the program dies
-            let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
-            axum::serve(listener, routes).await
-        })
+    // Bound here, so that the caller gets the error if the port is in use
+    // or the certificate can't be loaded
+    let listener = std::net::TcpListener::bind("0.0.0.0:8080")?;
+    listener.set_nonblocking(true)?;
+    let acceptor = tls_config.as_ref().map(tls::acceptor).transpose()?;
+    let thread = std::thread::spawn(move || {
+        // We can configure the runtime here: single / multithreaded, number of workers...
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                // Server code that runs continuously until the program dies
+                tokio::spawn(job_store::expire_periodically(job_store));
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                match acceptor {
+                    Some(acceptor) => {
+                        let listener = tls::TlsListener::new(listener, acceptor)?;
+                        axum::serve(listener, routes).await
+                    }
+                    None => axum::serve(listener, routes).await,
+                }
+            })
+    });
+    Ok(ServerHandle { jobs, thread })
 }
 
 /// Execute a tool hosted at url `addr` with inputs `input`.
@@ -115,14 +373,21 @@ pub fn run_server(tool: ToolFn, index_html: Option<&'static str>) -> Result<(),
 /// only hint is the `on_message` callback: A function that will be called on
 /// every message sent by the server, which can request it to abort.
 ///
-/// - `addr`: WebSocket url of the server, e.g.: `"wss://tool-xxx-flyio.fly.dev/tool"`
-/// - `input`: [`ValueDict`] of parameters that are passed to the tool
+/// - `addr`: WebSocket url of the server, e.g.: `"wss://tool-xxx-flyio.fly.dev/tool"`,
+///   or `".../tool/{name}"` for one of several tools of a server (see [`Routes`])
+/// - `input`: [`Value::Dict`] of parameters that are passed to the tool (see [`Value::require`])
 /// - `on_message`: callback function that receives a message string and returns
 ///   `true` if the tool should continue running or `false` if it should abort.
 ///
 /// `on_message` could be a closure containing a stop time, requesting the tool
 /// to abort after a timeout; it could carry a channel to GUI user abort button.
 ///
+/// Progress reports of the tool are ignored, see [`call_with_progress`].
+///
+/// If the connection drops while the tool runs, the call reconnects and
+/// continues where it left off; the tool keeps running on the server
+/// meanwhile (see [`resume`]).
+///
 /// # Example
 /// ```no_run
 /// # use toolapi::call;
@@ -140,43 +405,358 @@ pub fn run_server(tool: ToolFn, index_html: Option<&'static str>) -> Result<(),
 pub fn call(
     addr: &str,
     input: Value,
-    mut on_message: impl FnMut(String) -> bool,
+    on_message: impl FnMut(String) -> bool,
 ) -> Result<Value, ToolCallError> {
-    // Create a connection between client and server over WebSocket
-    let mut ws_client = connection::websocket::WsChannelClientNative::connect(addr)?;
-    // Send the input parameters to the server
-    ws_client.send_input(input)?;
-
-    // Loop over messages sent by the server and ask the callback if we should abort
-    while let Some(msg) = ws_client.read_message()? {
-        if !on_message(msg) {
-            // abort was requested by client callback
-            ws_client.send_abort()?;
-            ws_client.close()?;
-            return Err(ToolCallError::OnMessageAbort);
-        }
-    }
+    connection::client::block_on(async {
+        // Create a connection between client and server over WebSocket
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client.call(input, on_message).await
+    })
+}
+
+/// Like [`call`], but additionally calls `on_progress` with every progress
+/// report of the tool (see [`ToolCtx::send_progress`]): the completed fraction
+/// in `[0, 1]` and an optional description. Returning `false` aborts the tool.
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_with_progress;
+/// let input = todo!();
+///
+/// call_with_progress(
+///     "wss://tool-xxx-flyio.fly.dev/tool",
+///     input,
+///     |msg| {
+///         println!("[TOOL] {msg}");
+///         true
+///     },
+///     |fraction, _| {
+///         println!("{:.0}% done", fraction * 100.0);
+///         true
+///     },
+/// );
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_with_progress(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+    on_progress: impl FnMut(f64, Option<String>) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client
+            .call_with_progress(input, on_message, on_progress)
+            .await
+    })
+}
+
+/// Like [`call`], but additionally calls `on_partial` with every partial result
+/// of the tool (see [`ToolCtx::send_partial`]), e.g. to show the slabs of a
+/// volume while the rest is still computed. Returning `false` aborts the tool.
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_with_partial;
+/// let input = todo!();
+///
+/// let mut partial_result = std::collections::HashMap::new();
+/// call_with_partial(
+///     "wss://tool-xxx-flyio.fly.dev/tool",
+///     input,
+///     |msg| {
+///         println!("[TOOL] {msg}");
+///         true
+///     },
+///     |partial| {
+///         partial_result.extend(partial.0);
+///         true
+///     },
+/// );
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_with_partial(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+    on_partial: impl FnMut(value::dynamic::Dict) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client
+            .call_with_partial(input, on_message, on_partial)
+            .await
+    })
+}
+
+/// Like [`call`], but with a single callback for all messages and progress
+/// reports of the tool, which carry a sequence number and the time since the
+/// tool started (see [`ToolEvent`]). Returning `false` aborts the tool.
+/// Outputs emitted by the tool before the result arrive as events as well,
+/// the last event reports the cost of the call (see [`JobMeta`]).
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_with_events;
+/// use std::collections::HashMap;
+/// use toolapi::ToolEventKind;
+///
+/// let input = todo!();
+///
+/// let mut outputs = HashMap::new();
+/// call_with_events("wss://tool-xxx-flyio.fly.dev/tool", input, |event| {
+///     match event.kind {
+///         ToolEventKind::Message(msg) => println!("[{:>8.3}s] {msg}", event.time.as_secs_f64()),
+///         ToolEventKind::Emitted { name, value } => drop(outputs.insert(name, *value)),
+///         ToolEventKind::Finished(meta) => println!("took {:?}", meta.wall_time),
+///         _ => {}
+///     }
+///     true
+/// });
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_with_events(
+    addr: &str,
+    input: Value,
+    on_event: impl FnMut(ToolEvent) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client.call_with_events(input, on_event).await
+    })
+}
+
+/// Like [`call`], for servers that require a token (see
+/// [`ServerConfig::auth_token`]). It is sent as `Authorization: Bearer` header
+/// of the WebSocket upgrade, use a `wss://` url so that it is encrypted.
+/// Wrong tokens fail with [`ConnectionError::Unauthorized`].
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_with_auth;
+/// let input = todo!();
+/// let token = std::env::var("TOOL_TOKEN").unwrap();
+///
+/// call_with_auth("wss://tool-xxx-flyio.fly.dev/tool", &token, input, |msg| {
+///     println!("[TOOL] {msg}");
+///     true
+/// });
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_with_auth(
+    addr: &str,
+    token: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let transport = connection::websocket::WsTransportNative::connect_with_token(addr, token)?;
+        let ws_client = connection::client::WsChannelClient::new(transport);
+        ws_client.call(input, on_message).await
+    })
+}
 
-    // Read result, handle shutdown, return result
-    let result = ws_client
-        .read_output()?
-        .ok_or(ToolCallError::ProtocolError)?
-        .map_err(ToolCallError::ToolReturnedError)?;
+/// Like [`call`], running the tool with `seed` (see [`ToolCtx::seed`]) instead
+/// of a random one chosen by the server. Calls with the same input and
+/// seed return the same result if the tool only uses this seed for its random
+/// numbers, e.g. to reproduce a call with the [`JobMeta::seed`] it reported.
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_with_seed;
+/// let input = todo!();
+///
+/// call_with_seed("wss://tool-xxx-flyio.fly.dev/tool", 42, input, |msg| {
+///     println!("[TOOL] {msg}");
+///     true
+/// });
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_with_seed(
+    addr: &str,
+    seed: u64,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client.with_seed(seed).call(input, on_message).await
+    })
+}
 
-    // We successfully computed a result - return it even on error!
-    match ws_client.close() {
-        Ok(()) => Ok(result),
-        Err(err) => Err(ToolCallError::CloseFailed { result, err }),
+/// Continue a call whose connection dropped for good, e.g. because the
+/// client process was restarted, with the `job_id` and `secret` of its
+/// [`ToolEventKind::Accepted`] event. While the tool runs, the messages it
+/// sent so far are passed to `on_message` again, followed by the result once
+/// it is ready; finished calls only return their result. Results are only
+/// kept for the [`ServerConfig::job_store`] retention of the server, for
+/// unknown jobs, expired jobs and wrong secrets this fails with a
+/// [`ToolCallError::ToolReturnedError`].
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::resume;
+/// let job_id = "0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24";
+/// let secret = "5be0c2a91d7f4e38b6a0d9c4e1f27a85";
+/// let result = resume("wss://tool-xxx-flyio.fly.dev/tool", job_id, secret, |msg| {
+///     println!("[TOOL] {msg}");
+///     true
+/// });
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn resume(
+    addr: &str,
+    job_id: &str,
+    secret: &str,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+        ws_client
+            .resume_with_events(job_id, secret, message_events(on_message))
+            .await
+    })
+}
+
+/// Pass only the messages of the tool to `on_message`
+#[cfg(feature = "client")]
+fn message_events(mut on_message: impl FnMut(String) -> bool) -> impl FnMut(ToolEvent) -> bool {
+    move |event| match event.kind {
+        ToolEventKind::Message(msg) => on_message(msg),
+        _ => true,
     }
 }
 
+/// Like [`call`], with the url taken from the `TOOLAPI_URL` environment
+/// variable or the `default` entry of the tools config file (see [`tool_url`]).
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_default;
+/// let input = todo!();
+///
+/// // TOOLAPI_URL=ws://localhost:8080/tool python simulate.py
+/// call_default(input, |msg| {
+///     println!("[TOOL] {msg}");
+///     true
+/// });
+/// ```
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_default(
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    call(&tool_url(None)?, input, on_message)
+}
+
+/// Like [`call`], with the url of the tool `name` from the tools config file,
+/// see [`tool_url`]. Fails with [`ToolCallError::UnresolvedUrl`] if it is missing.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_named(
+    name: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    call(&tool_url(Some(name))?, input, on_message)
+}
+
+/// Like [`call`], but stops waiting for the server as soon as `interrupt` is
+/// set (checked every 50 ms), e.g. by a signal handler of the application.
+/// The tool is then aborted like when `on_message` returns `false` and this
+/// returns [`ToolCallError::OnMessageAbort`]. The caller resets `interrupt`.
+///
+/// Sending a large input is not interrupted, only waiting for the server.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub fn call_interruptible(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+    interrupt: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Result<Value, ToolCallError> {
+    connection::client::block_on(async {
+        let transport =
+            connection::websocket::WsTransportNative::connect(addr)?.with_interrupt(interrupt)?;
+        let ws_client = connection::client::WsChannelClient::new(transport);
+        ws_client.call(input, on_message).await
+    })
+}
+
+/// Like [`call`], but Ctrl-C aborts the tool instead of killing the process,
+/// which would leave the job running on the server. For command line
+/// programs, press Ctrl-C twice to exit without waiting for the server.
+/// Outside of calls, Ctrl-C exits the process as usual.
+///
+/// # Panics
+/// If the application installed its own Ctrl-C handler, set an `AtomicBool`
+/// in it and use [`call_interruptible`] instead.
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::{ToolCallError, call_with_ctrlc};
+/// let input = todo!();
+///
+/// match call_with_ctrlc("wss://tool-xxx-flyio.fly.dev/tool", input, |_| true) {
+///     Err(ToolCallError::OnMessageAbort) => println!("cancelled"),
+///     result => println!("{result:?}"),
+/// }
+/// ```
+#[cfg(all(feature = "ctrlc", not(target_arch = "wasm32")))]
+pub fn call_with_ctrlc(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    let interrupt = interrupt::start_call();
+    let result = call_interruptible(addr, input, on_message, interrupt);
+    interrupt::end_call();
+    result
+}
+
+/// Like [`call`], but waits for the tool without blocking the thread, so
+/// async services and GUI apps can call tools from their tokio runtime.
+/// Needs the `async` feature; on `wasm32` targets it is the same as `call`.
+///
+/// The callbacks run on the task that polls the returned future and should
+/// not block it. Dropping the future closes the connection, which aborts
+/// the tool on the server.
+///
+/// # Example
+/// ```no_run
+/// # use toolapi::call_async;
+/// # async fn run() {
+/// let input = todo!();
+///
+/// let handle = tokio::spawn(call_async(
+///     "wss://tool-xxx-flyio.fly.dev/tool",
+///     input,
+///     |msg| {
+///         println!("[TOOL] {msg}");
+///         true
+///     },
+/// ));
+/// let result = handle.await;
+/// # }
+/// ```
+#[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+pub async fn call_async(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    let transport = connection::websocket::WsTransportTokio::connect(addr).await?;
+    let ws_client = connection::client::WsChannelClient::new(transport);
+    ws_client.call(input, on_message).await
+}
+
 /// Execute a tool hosted at url `addr` with inputs `input`.
 ///
 /// This is the async version of [`call`] for use on `wasm32` targets, where
-/// blocking the main thread is not possible. The API is otherwise identical:
+/// blocking the main thread is not possible. Failed connection attempts are
+/// retried a few times with exponential backoff, since browsers on mobile
+/// networks frequently drop connections. The API is otherwise identical:
 ///
 /// - `addr`: WebSocket url of the server, e.g.: `"wss://tool-xxx-flyio.fly.dev/tool"`
-/// - `input`: [`ValueDict`] of parameters that are passed to the tool
+/// - `input`: [`Value::Dict`] of parameters that are passed to the tool (see [`Value::require`])
 /// - `on_message`: callback function that receives a message string and returns
 ///   `true` if the tool should continue running or `false` if it should abort.
 ///
@@ -200,33 +780,84 @@ pub fn call(
 pub async fn call(
     addr: &str,
     input: Value,
-    mut on_message: impl FnMut(String) -> bool,
+    on_message: impl FnMut(String) -> bool,
 ) -> Result<Value, ToolCallError> {
     // Create a connection between client and server over WebSocket
-    let mut ws_client = connection::websocket::WsChannelClientWasm::connect(addr).await?;
-    // Send the input parameters to the server
-    ws_client.send_input(input).await?;
-
-    // Loop over messages sent by the server and ask the callback if we should abort
-    while let Some(msg) = ws_client.read_message().await? {
-        if !on_message(msg) {
-            // abort was requested by client callback
-            ws_client.send_abort().await?;
-            ws_client.close().await?;
-            return Err(ToolCallError::OnMessageAbort);
-        }
-    }
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client.call(input, on_message).await
+}
 
-    // Read result, handle shutdown, return result
-    let result = ws_client
-        .read_output()
-        .await?
-        .ok_or(ToolCallError::ProtocolError)?
-        .map_err(ToolCallError::ToolReturnedError)?;
-
-    // We successfully computed a result - return it even on error!
-    match ws_client.close().await {
-        Ok(()) => Ok(result),
-        Err(err) => Err(ToolCallError::CloseFailed { result, err }),
-    }
+/// Async version of [`call_with_progress`] for use on `wasm32` targets, see [`call`].
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn call_with_progress(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+    on_progress: impl FnMut(f64, Option<String>) -> bool,
+) -> Result<Value, ToolCallError> {
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client
+        .call_with_progress(input, on_message, on_progress)
+        .await
+}
+
+/// Async version of [`call_with_partial`] for use on `wasm32` targets, see [`call`].
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn call_with_partial(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+    on_partial: impl FnMut(value::dynamic::Dict) -> bool,
+) -> Result<Value, ToolCallError> {
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client
+        .call_with_partial(input, on_message, on_partial)
+        .await
+}
+
+/// Async version of [`call_with_seed`] for use on `wasm32` targets, see [`call`].
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn call_with_seed(
+    addr: &str,
+    seed: u64,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client.with_seed(seed).call(input, on_message).await
+}
+
+/// Async version of [`resume`] for use on `wasm32` targets, see [`call`].
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn resume(
+    addr: &str,
+    job_id: &str,
+    secret: &str,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client
+        .resume_with_events(job_id, secret, message_events(on_message))
+        .await
+}
+
+/// Same as [`call`] on `wasm32` targets, for code shared with native async clients.
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn call_async(
+    addr: &str,
+    input: Value,
+    on_message: impl FnMut(String) -> bool,
+) -> Result<Value, ToolCallError> {
+    call(addr, input, on_message).await
+}
+
+/// Async version of [`call_with_events`] for use on `wasm32` targets, see [`call`].
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+pub async fn call_with_events(
+    addr: &str,
+    input: Value,
+    on_event: impl FnMut(ToolEvent) -> bool,
+) -> Result<Value, ToolCallError> {
+    let ws_client = connection::client::WsChannelClient::connect(addr).await?;
+    ws_client.call_with_events(input, on_event).await
 }
@@ -0,0 +1,115 @@
+//! Enforcement of [`ServerConfig::max_output_size`](crate::ServerConfig::max_output_size).
+
+use crate::{OutputPolicy, ToolError, Value, value::typed::TypedList};
+
+/// Counts the bytes of an encoding without storing it
+#[derive(Default)]
+struct SizeCounter(usize);
+
+impl std::io::Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Size of the msgpack encoding (before compression)
+pub(crate) fn encoded_size<T: serde::Serialize + ?Sized>(value: &T) -> usize {
+    let mut counter = SizeCounter::default();
+    // Writing to the counter can't fail and all Value types are serializable
+    let _ = rmp_serde::encode::write(&mut counter, value);
+    counter.0
+}
+
+/// Apply `policy` if the result is larger than `max_size`. Returns the
+/// (possibly truncated) result and a notice for every truncated list.
+pub(crate) fn limit_output(
+    result: Result<Value, ToolError>,
+    max_size: usize,
+    policy: OutputPolicy,
+) -> (Result<Value, ToolError>, Vec<String>) {
+    let Ok(mut value) = result else {
+        return (result, Vec::new());
+    };
+    let size = encoded_size(&value);
+    if size <= max_size {
+        return (Ok(value), Vec::new());
+    }
+
+    let too_large = ToolError::resource_exhausted(format!(
+        "result is {size} bytes, the server allows at most {max_size} bytes"
+    ));
+    match policy {
+        OutputPolicy::Error => (Err(too_large), Vec::new()),
+        OutputPolicy::Truncate => match truncate(&mut value, size, max_size) {
+            Some(notices) => (Ok(value), notices),
+            None => (Err(too_large), Vec::new()),
+        },
+    }
+}
+
+/// Shorten all typed lists by the same factor until `value` fits, `None`
+/// if that is not possible because the rest of the value is too large.
+fn truncate(value: &mut Value, size: usize, max_size: usize) -> Option<Vec<String>> {
+    let mut lists = Vec::new();
+    collect_lists(value, String::new(), &mut lists);
+    let original: Vec<usize> = lists.iter().map(|(_, list)| list.len()).collect();
+    let lists_size = |lists: &[(String, &mut TypedList)]| -> usize {
+        lists.iter().map(|(_, list)| encoded_size(&**list)).sum()
+    };
+    let rest_size = size - lists_size(&lists);
+
+    // The encoding is roughly linear in the length, iterate for the rest
+    let mut factor = max_size.saturating_sub(rest_size) as f64 / (size - rest_size) as f64;
+    loop {
+        for ((_, list), len) in lists.iter_mut().zip(&original) {
+            list.truncate((*len as f64 * factor) as usize);
+        }
+        if rest_size + lists_size(&lists) <= max_size {
+            break;
+        }
+        if lists.iter().all(|(_, list)| list.is_empty()) {
+            return None;
+        }
+        factor *= 0.9;
+    }
+
+    let mut notices: Vec<String> = lists
+        .iter()
+        .zip(&original)
+        .filter(|((_, list), len)| list.len() < **len)
+        .map(|((path, list), len)| format!("`{path}`: kept {} of {len} elements", list.len()))
+        .collect();
+    notices.sort();
+    Some(notices)
+}
+
+/// Typed lists in (nested) Lists and Dicts, not the data of structured types
+fn collect_lists<'a>(
+    value: &'a mut Value,
+    path: String,
+    out: &mut Vec<(String, &'a mut TypedList)>,
+) {
+    let join = |key: &str| match path.as_str() {
+        "" => key.to_string(),
+        path => format!("{path}/{key}"),
+    };
+    match value {
+        Value::TypedList(list) => out.push((path, list)),
+        Value::List(list) => {
+            for (index, value) in list.0.iter_mut().enumerate() {
+                collect_lists(value, join(&index.to_string()), out);
+            }
+        }
+        Value::Dict(dict) => {
+            for (key, value) in dict.0.iter_mut() {
+                collect_lists(value, join(key), out);
+            }
+        }
+        _ => {}
+    }
+}
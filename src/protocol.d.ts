@@ -0,0 +1,319 @@
+// TypeScript definitions of the MRX ToolAPI wire protocol (PROTOCOL_VERSION 25).
+//
+// Every `Message` is the MessagePack encoding, zstd compressed unless it is
+// small, of one of the types below. Compressed data starts with the zstd magic
+// number `28 b5 2f fd`, uncompressed data never does, senders choose freely.
+// Compressed data can consist of multiple concatenated zstd frames.
+//
+// Large messages are split into multiple binary WebSocket frames: all but the
+// last one start with the 4 bytes `"MRX+"`, which are not part of the data.
+// Concatenate the rest of all frames to get the complete message.
+//
+// Servers send WebSocket pings while a call runs (browsers answer them
+// automatically), other clients have to answer with a pong or skip them.
+//
+// The types below describe the decoded MessagePack data (e.g. as returned by
+// `@msgpack/msgpack`):
+// - Rust enums are externally tagged: `{ Variant: data }`, unit variants are
+//   encoded as the plain string `"Variant"`
+// - Rust structs (and struct-like enum variants) are encoded as arrays of
+//   their fields in declaration order, not as maps
+// - Rust `i64` values may be decoded as `number` or `bigint`, depending on
+//   their magnitude and the decoder settings
+
+// =============================================================================
+// Messages
+// =============================================================================
+
+/**
+ * First message of both sides on every connection, the client sends its
+ * `Hello` and waits for the one of the server before sending the input.
+ * `protocol_version` stays the first field in all future versions, a peer
+ * with a different version closes the connection after the exchange.
+ */
+export type Hello = { Hello: [protocol_version: number, capabilities: Capability[]] };
+
+/**
+ * Optional features of a peer, unknown ones are ignored. Peers only use
+ * features the other side announced:
+ * - `zstd`: decompresses zstd, otherwise messages are sent uncompressed
+ * - `progress` (client): otherwise progress is sent as `ToolMsg`
+ * - `emit` (client): otherwise emitted outputs are only announced as `ToolMsg`
+ * - `partial` (client): otherwise partial results are only announced as `ToolMsg`
+ * - `input_cache` (server): otherwise clients send `Input`, not `CachedInput`
+ * - `attachments`: otherwise large `Bytes` are sent as part of the message
+ * - `queued` (client): otherwise the position in the queue is sent as `ToolMsg`
+ * - `resume` (client): otherwise the tool is aborted when the connection drops
+ *   and there is no `Accepted`
+ * - `canonical_hash` (client): `CachedInput` refs are canonical hashes, see `BlobHash`
+ */
+export type Capability =
+  | "zstd"
+  | "progress"
+  | "emit"
+  | "partial"
+  | "input_cache"
+  | "attachments"
+  | "queued"
+  | "resume"
+  | "canonical_hash"
+  | (string & {});
+
+/**
+ * Sent from client to server: tool input, or a request to abort the tool.
+ * `Seed` (for the random numbers of the tool) is optional and sent right
+ * before the input, otherwise the server chooses a random one.
+ * `Resume` is sent instead of the input to continue a call whose connection
+ * dropped: the server sends all stamped messages from `seq` on, then the
+ * output, or an `Err` output if it doesn't know the job (anymore) or the
+ * secret is wrong. After an `Accepted`, clients send `Ack` every few stamped
+ * messages: all messages before `seq` arrived, so the server doesn't keep
+ * them for a `Resume` (it drops the oldest ones beyond a size limit anyway).
+ * A Dict input can shorten the timeout of the server with a `_timeout` entry
+ * (in seconds), which the tool doesn't see.
+ */
+export type ClientMessage =
+  | Hello
+  | { Seed: Int }
+  | { Input: Value }
+  | { Resume: [job_id: string, secret: string, seq: Int] }
+  | { Ack: [seq: Int] }
+  | "Abort"
+  | Attachment
+  | { CachedInput: [input: Value, refs: { [key: string]: BlobHash }] }
+  | { BlobChunk: [hash: BlobHash, offset: Int, size: Int, data: Uint8Array] };
+
+/**
+ * Sent from server to client: log and progress messages, then the `JobMeta`
+ * of the call (always stamped) and exactly one output
+ */
+export type ServerMessage =
+  | Hello
+  | { Stamped: [seq: Int, time: number, message: ToolMessage | Queued | Accepted | { JobMeta: JobMeta }] }
+  | { Output: ToolResult }
+  | Attachment
+  | { Missing: [hash: BlobHash, received: Int][] };
+
+/**
+ * Sent (stamped) by a server with a limit of concurrent tools while the call
+ * waits for a free slot, whenever the number of calls ahead of it changes
+ */
+export type Queued = { Queued: [position: number] };
+
+/**
+ * Sent (stamped) to clients with `resume` once the server received the input.
+ * If the connection drops, the tool keeps running and the client can `Resume`
+ * the call with `job_id` (the one of its `JobMeta`) and the random `secret` on
+ * a new connection. Job ids are random UUIDs, the secret is only sent here.
+ */
+export type Accepted = { Accepted: [job_id: string, secret: string] };
+
+/**
+ * Sent by the tool (`Emit` are named intermediate results and `PartialResult`
+ * the entries of the result done so far, any number of them),
+ * the server wraps these in `Stamped` (but not over stdio):
+ * `seq` counts them per call (starting at 0, gaps mean lost messages) and
+ * `time` is the number of seconds since the server received the input
+ * (monotonic server clock).
+ */
+export type ToolMessage =
+  | { ToolMsg: string }
+  | { Progress: [fraction: number, message: string | null] }
+  | { Emit: [name: string, value: Value] }
+  | { PartialResult: { [key: string]: Value } };
+
+/**
+ * Large `Bytes` in an `Input` or an `Ok` output can be replaced by
+ * `{ AttachmentRef: id }` if the peer announced `attachments`. The bytes are
+ * sent before the message: an `Attachment` for each id (counting from 0 for
+ * every message) followed by `size` bytes in raw binary frames, which are
+ * neither MessagePack encoded, compressed nor split with `"MRX+"`.
+ */
+export type Attachment = { Attachment: [id: number, size: Int] };
+
+/**
+ * Large entries of a Dict input can be sent as `CachedInput` instead: `input`
+ * without these entries and their blake3 hashes in `refs`: of their MessagePack
+ * encoding, or of the canonical encoding of `Value::canonical_hash` in the Rust
+ * crate (which doesn't depend on the order of Dict entries) if the client
+ * announced `canonical_hash`. The server replies with the hashes it has not cached in `Missing`,
+ * together with the number of bytes it `received` of an upload that was
+ * interrupted (e.g. by a dropped connection). The client sends the rest of the
+ * MessagePack encoding of these values in `BlobChunk`s, in order for each value
+ * (starting at `received`). Then the call continues as usual.
+ */
+export type BlobHash = Uint8Array;
+
+/**
+ * Cost of a call measured by the server, times are in seconds. `cpu_time` is
+ * only measured on some platforms, sizes are in bytes as transferred (after
+ * compression), `input_size` including all messages sent by the client.
+ * `truncated` describes lists the server shortened to fit its size limit,
+ * `seed` is the one the tool ran with (null if it didn't run).
+ */
+export type JobMeta = [
+  job_id: string,
+  tool_version: string | null,
+  wall_time: number,
+  cpu_time: number | null,
+  input_size: Int,
+  output_size: Int,
+  truncated: string[],
+  seed: Int | null,
+];
+
+export type Message = ClientMessage | ServerMessage | ToolMessage;
+
+export type ToolResult = { Ok: Value } | { Err: ToolError };
+
+// =============================================================================
+// Errors
+// =============================================================================
+
+export type ToolError =
+  | { Extraction: ExtractionError }
+  | { Abort: AbortReason }
+  | { Custom: string }
+  | { InvalidInput: [path: string, message: string, details: Details] }
+  | { ResourceExhausted: [message: string, details: Details] }
+  | { Timeout: [message: string, details: Details] }
+  | { Internal: [message: string, details: Details] }
+  | { Partial: [error: ToolError, partial: Value] };
+
+/** Optional structured information attached to an error */
+export type Details = { [key: string]: Value } | null;
+
+export type ExtractionError =
+  | { TypeMismatch: [from: string, into: string] }
+  | "TooMuchNesting"
+  | { IndexOutOfBounds: [index: Int, length: Int] }
+  | { KeyNotFound: [key: string] }
+  | "IndexForDict"
+  | "KeyForList"
+  | { OutOfRange: [value: number, into: string] };
+
+export type AbortReason =
+  | "RequestedByClient"
+  | { ChannelError: string }
+  | "ConnectionClosed";
+
+// =============================================================================
+// Values
+// =============================================================================
+
+export type Int = number | bigint;
+
+export type Value =
+  // Atomic types
+  | { None: null }
+  | { Bool: boolean }
+  | { Int: Int }
+  | { Float: number }
+  | { Str: string }
+  | { Bytes: Uint8Array }
+  | { AttachmentRef: number }
+  | { Complex: Complex }
+  | { Vec3: Vec3 }
+  | { Vec4: Vec4 }
+  | { Quat: Quat }
+  // Structured types
+  | { InstantSeqEvent: InstantSeqEvent }
+  | { Volume: Volume }
+  | { SegmentedPhantom: SegmentedPhantom }
+  | { PhantomTissue: PhantomTissue }
+  | { Provenance: Provenance }
+  // Dynamic collections
+  | { Dict: { [key: string]: Value } }
+  | { List: Value[] }
+  // Static collections
+  | { TypedDict: TypedDict }
+  | { TypedList: TypedList };
+
+export type Complex = [re: number, im: number];
+export type Vec3 = [number, number, number];
+export type Vec4 = [number, number, number, number];
+/** Rotation as unit quaternion, scalar first */
+export type Quat = [w: number, x: number, y: number, z: number];
+
+/** Gradient moment and duration of a `Fid` */
+export type Kt = [kx: number, ky: number, kz: number, tau: number];
+
+export type InstantSeqEvent =
+  | { Pulse: [angle: number, phase: number] }
+  | { Fid: [kt: Kt] }
+  | { Adc: [phase: number] };
+
+export type Volume = [
+  shape: [Int, Int, Int],
+  affine: [Vec4, Vec4, Vec4],
+  data: TypedList,
+];
+
+export type SegmentedPhantom = [
+  tissues: { [name: string]: PhantomTissue },
+  b1_tx: Volume[],
+  b1_rx: Volume[],
+];
+
+export type PhantomTissue = [
+  density: Volume,
+  db0: Volume,
+  t1: number,
+  t2: number,
+  t2dash: number,
+  adc: number,
+];
+
+/** Where a result came from, `started` in seconds since the Unix epoch */
+export type Provenance = [
+  tool: string | null,
+  version: string | null,
+  input_hash: string,
+  started: number,
+  duration: number,
+  host: string,
+];
+
+/**
+ * Bytes inside of typed collections are plain arrays of integers.
+ *
+ * Numeric lists are a single binary blob of packed little-endian numbers:
+ * `Int` as int64, `Float` as float64, `Half` as float16 (only sent by peers
+ * with the `half` feature) and `Complex` / `Vec3` / `Vec4` / `Quat`
+ * as 2 / 3 / 4 / 4 consecutive float64 per element. Read them with e.g.
+ * `new Float64Array(bytes.slice().buffer)` (copy to get an aligned buffer).
+ */
+export type TypedList =
+  | { None: null[] }
+  | { Bool: boolean[] }
+  | { Int: Uint8Array }
+  | { Float: Uint8Array }
+  | { Half: Uint8Array }
+  | { Str: string[] }
+  | { Bytes: number[][] }
+  | { Complex: Uint8Array }
+  | { Vec3: Uint8Array }
+  | { Vec4: Uint8Array }
+  | { Quat: Uint8Array }
+  | { InstantSeqEvent: InstantSeqEvent[] }
+  | { Volume: Volume[] }
+  | { SegmentedPhantom: SegmentedPhantom[] }
+  | { PhantomTissue: PhantomTissue[] }
+  | { Provenance: Provenance[] };
+
+export type TypedDict =
+  | { None: { [key: string]: null } }
+  | { Bool: { [key: string]: boolean } }
+  | { Int: { [key: string]: Int } }
+  | { Float: { [key: string]: number } }
+  | { Str: { [key: string]: string } }
+  | { Bytes: { [key: string]: number[] } }
+  | { Complex: { [key: string]: Complex } }
+  | { Vec3: { [key: string]: Vec3 } }
+  | { Vec4: { [key: string]: Vec4 } }
+  | { Quat: { [key: string]: Quat } }
+  | { InstantSeqEvent: { [key: string]: InstantSeqEvent } }
+  | { Volume: { [key: string]: Volume } }
+  | { SegmentedPhantom: { [key: string]: SegmentedPhantom } }
+  | { PhantomTissue: { [key: string]: PhantomTissue } }
+  | { Provenance: { [key: string]: Provenance } };
@@ -0,0 +1,88 @@
+//! Tool urls from the environment or a config file, so scripts don't need to
+//! hard-code deployments, see [`tool_url`], and urls of artifacts of tools.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{collections::HashMap, path::PathBuf};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ToolCallError;
+use crate::Value;
+
+/// Environment variable with the url used by [`crate::call_default`]
+#[cfg(not(target_arch = "wasm32"))]
+pub const URL_ENV: &str = "TOOLAPI_URL";
+
+/// `$XDG_CONFIG_HOME/toolapi/tools.toml`, defaulting to `~/.config/toolapi/tools.toml`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::home_dir()?.join(".config"),
+    };
+    Some(config_dir.join("toolapi").join("tools.toml"))
+}
+
+/// Url of the tool `name` in the [`config_path`] file, a table of names to urls:
+/// ```toml
+/// default = "wss://tool-simulator.fly.dev/tool"
+/// simulator = "wss://tool-simulator.fly.dev/tool"
+/// reconstruction = "ws://localhost:8080/tool"
+/// ```
+/// Without a name, [`URL_ENV`] is used if set and the `default` entry otherwise.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tool_url(name: Option<&str>) -> Result<String, ToolCallError> {
+    if name.is_none()
+        && let Ok(url) = std::env::var(URL_ENV)
+    {
+        return Ok(url);
+    }
+    let key = name.unwrap_or("default");
+    let unresolved = |reason: String| ToolCallError::UnresolvedUrl(format!("`{key}`: {reason}"));
+
+    let path = config_path().ok_or_else(|| unresolved("no home directory".into()))?;
+    let config = match std::fs::read_to_string(&path) {
+        Ok(config) => config,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let env = match name {
+                None => format!("{URL_ENV} is not set and "),
+                Some(_) => String::new(),
+            };
+            return Err(unresolved(format!("{env}{} doesn't exist", path.display())));
+        }
+        Err(err) => return Err(unresolved(format!("{}: {err}", path.display()))),
+    };
+    let mut urls: HashMap<String, String> = toml::from_str(&config)
+        .map_err(|err| unresolved(format!("{}: {}", path.display(), err.message())))?;
+    urls.remove(key)
+        .ok_or_else(|| unresolved(format!("not in {}", path.display())))
+}
+
+/// HTTP url for downloading an artifact (see `ToolCtx::artifact`) returned
+/// by the tool at `addr`. `reference` is the returned Dict or its `path`.
+///
+/// ```
+/// # use toolapi::{Value, artifact_url};
+/// let path = Value::Str("/jobs/0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24/artifacts/image.nii".into());
+/// assert_eq!(
+///     artifact_url("wss://tool-recon.fly.dev/tool", &path).as_deref(),
+///     Some("https://tool-recon.fly.dev/jobs/0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24/artifacts/image.nii"),
+/// );
+/// ```
+pub fn artifact_url(addr: &str, reference: &Value) -> Option<String> {
+    let path = match reference {
+        Value::Dict(dict) => match dict.0.get("path")? {
+            Value::Str(path) => path,
+            _ => return None,
+        },
+        Value::Str(path) => path,
+        _ => return None,
+    };
+    let (scheme, rest) = addr.split_once("://")?;
+    let scheme = match scheme {
+        "wss" | "https" => "https",
+        "ws" | "http" => "http",
+        _ => return None,
+    };
+    let host = rest.split('/').next()?;
+    Some(format!("{scheme}://{host}{path}"))
+}
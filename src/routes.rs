@@ -0,0 +1,111 @@
+//! Several tools served by one server, see [`Routes`].
+
+use std::collections::BTreeMap;
+
+use crate::{
+    Tool, ToolFn, ToolHandler,
+    context::{self, SharedTool},
+    value::schema::ValueSchema,
+};
+
+/// Tools served by [`crate::run_server_routes`], each on its own WebSocket
+/// route `/tool/{name}`. Clients [`crate::call`] the url of the route.
+///
+/// Names must be non-empty and consist of ASCII letters, digits, `-` and
+/// `_`. Like axum's `Router`, adding an invalid or duplicate name panics.
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use toolapi::{Routes, ServerConfig, run_server_routes};
+///
+/// fn simulate(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+///
+/// fn reconstruct(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+///
+/// fn main() -> Result<(), std::io::Error> {
+///     // ws://localhost:8080/tool/simulate and ws://localhost:8080/tool/reconstruct
+///     let routes = Routes::new()
+///         .route("simulate", simulate)
+///         .route("reconstruct", reconstruct);
+///     run_server_routes(routes, None, ServerConfig::default())
+/// }
+/// ```
+///
+/// A map of names to [`ToolFn`]s can be collected into routes as well:
+/// ```
+/// # use toolapi::{Value, MessageFn, ToolError, ToolFn, Routes};
+/// # fn echo(input: Value, _: &mut MessageFn) -> Result<Value, ToolError> { Ok(input) }
+/// let tools: [(&str, ToolFn); 2] = [("simulate", echo), ("reconstruct", echo)];
+/// let routes: Routes = tools.into_iter().collect();
+/// assert_eq!(routes.names().collect::<Vec<_>>(), ["reconstruct", "simulate"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct Routes {
+    pub(crate) routes: BTreeMap<String, Route>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Route {
+    pub(crate) tool: SharedTool,
+    /// Replaces [`crate::ServerConfig::input_schema`] for calls of this route
+    pub(crate) input_schema: Option<ValueSchema>,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `tool` on `/tool/{name}`
+    pub fn route<M>(self, name: impl Into<String>, tool: impl ToolHandler<M>) -> Self {
+        self.insert(name.into(), context::shared(tool), None)
+    }
+
+    /// Like [`Routes::route`] for a [`Tool`], whose inputs are validated
+    /// against its [`Tool::input_schema`]
+    pub fn route_tool(self, name: impl Into<String>, tool: impl Tool) -> Self {
+        let input_schema = Some(tool.input_schema());
+        self.insert(name.into(), context::shared(tool), input_schema)
+    }
+
+    /// Names of the routes, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.routes.keys().map(String::as_str)
+    }
+
+    /// The single tool of [`crate::run_server`], on the `/tool` route
+    pub(crate) fn single(tool: SharedTool, input_schema: Option<ValueSchema>) -> Self {
+        let route = Route { tool, input_schema };
+        Self {
+            routes: BTreeMap::from([(String::new(), route)]),
+        }
+    }
+
+    fn insert(mut self, name: String, tool: SharedTool, input_schema: Option<ValueSchema>) -> Self {
+        assert!(
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "invalid route name `{name}`, use ASCII letters, digits, `-` and `_`"
+        );
+        let route = Route { tool, input_schema };
+        let previous = self.routes.insert(name.clone(), route);
+        assert!(previous.is_none(), "route `{name}` was added twice");
+        self
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, ToolFn)> for Routes {
+    fn from_iter<I: IntoIterator<Item = (S, ToolFn)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Routes::new(), |routes, (name, tool)| {
+                routes.route(name, tool)
+            })
+    }
+}
@@ -0,0 +1,73 @@
+//! Composition of inputs and results in the server log, see
+//! [`ServerConfig::log_sizes`](crate::ServerConfig::log_sizes).
+
+use crate::{Value, limit::encoded_size};
+
+/// Print the type, shape and size of `value` and of each of its entries if
+/// it is a [`Value::Dict`], e.g. for the input:
+///
+/// ```text
+/// SIZE in Dict {2 keys}, 12.4 MB
+/// SIZE   phantom: SegmentedPhantom {3 tissues} [128, 128, 64], 12.4 MB
+/// SIZE   sequence: List<InstantSeqEvent> [1200], 45.1 kB
+/// ```
+pub(crate) fn log_composition(label: &str, value: &Value) {
+    println!("SIZE {label} {}", describe(value));
+    if let Value::Dict(dict) = value {
+        let mut entries: Vec<_> = dict.0.iter().collect();
+        entries.sort_by_key(|(key, _)| *key);
+        for (key, value) in entries {
+            println!("SIZE   {key}: {}", describe(value));
+        }
+    }
+}
+
+/// Type and shape of `value` with the size of its encoding
+fn describe(value: &Value) -> String {
+    let shape = match value {
+        Value::Str(s) => format!("Str [{}]", s.len()),
+        Value::Bytes(bytes) => format!("Bytes [{}]", bytes.len()),
+        Value::Volume(volume) => {
+            format!(
+                "Volume<{}> {:?}",
+                volume.data().element_type(),
+                volume.shape()
+            )
+        }
+        Value::SegmentedPhantom(phantom) => {
+            let tissues = format!("SegmentedPhantom {{{} tissues}}", phantom.tissues.len());
+            match phantom.tissues.values().next() {
+                Some(tissue) => format!("{tissues} {:?}", tissue.density.shape()),
+                None => tissues,
+            }
+        }
+        Value::PhantomTissue(tissue) => format!("PhantomTissue {:?}", tissue.density.shape()),
+        Value::Dict(dict) => format!("Dict {{{} keys}}", dict.0.len()),
+        Value::List(list) => format!("List [{}]", list.0.len()),
+        Value::TypedDict(dict) => {
+            format!(
+                "Dict<{}> {{{} keys}}",
+                dict.element_type(),
+                dict.keys().len()
+            )
+        }
+        Value::TypedList(list) => format!("List<{}> [{}]", list.element_type(), list.len()),
+        _ => value.type_of().to_string(),
+    };
+    format!("{shape}, {}", human_size(encoded_size(value)))
+}
+
+/// `bytes` with an SI prefix, e.g. `12.4 MB`
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
@@ -0,0 +1,211 @@
+//! Tools running as separate executables, talking to the server over stdio.
+//!
+//! This allows hosting tools written in other languages without linking this
+//! crate (see `toolapi-cli serve --exec`). The protocol uses the same messages
+//! as the WebSocket connection, each frame (zstd compressed msgpack, see
+//! `src/protocol.d.ts`) is prefixed by its length as little-endian `u32`:
+//!
+//! 1. The server writes a single `Input` message to the tool's stdin, optionally
+//!    preceded by a `Seed` (otherwise the tool uses a random one)
+//! 2. The tool writes any number of `ToolMsg`, `Progress`, `Emit` and `PartialResult`
+//!    messages to its stdout
+//! 3. The server might write an `Abort` message to the tool's stdin
+//! 4. The tool writes a single `Output` message and exits
+//!
+//! Tools must not print anything else to stdout, stderr can be used for logging.
+
+use std::{
+    io::{BufReader, Read, Write},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    AbortReason, AbortSignal, MessageFn, ToolCtx, ToolError, ToolHandler, Value,
+    connection::websocket::{Message, deserialize, serialize},
+    context::{next_job_id, partial_text, progress_text, random_seed},
+};
+
+fn write_message(w: &mut impl Write, msg: &Message) -> std::io::Result<()> {
+    let frame = serialize(msg).map_err(std::io::Error::other)?;
+    let len = u32::try_from(frame.len()).map_err(std::io::Error::other)?;
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&frame)?;
+    w.flush()
+}
+
+/// Returns `None` if the stream was closed before the next message.
+fn read_message(r: &mut impl Read) -> std::io::Result<Option<Message>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let mut frame = vec![0; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut frame)?;
+    deserialize(&frame).map(Some).map_err(std::io::Error::other)
+}
+
+fn exec_error(context: &str, err: impl std::fmt::Display) -> ToolError {
+    ToolError::internal(format!("{context}: {err}"))
+}
+
+/// Run the executable `command` as tool, forwarding its messages to `send_msg`.
+///
+/// Can be called inside of a [`ToolFn`] to host an external tool:
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use std::process::Command;
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     toolapi::stdio::exec(Command::new("./my_tool"), input, send_msg)
+/// }
+/// ```
+pub fn exec(command: Command, input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+    spawn(command, None, input, send_msg)
+}
+
+/// Like [`exec`], running the tool with the seed of the call (see
+/// [`ToolCtx::seed`]) instead of one it derives from the input.
+/// ```no_run
+/// # use toolapi::{Value, ToolCtx, ToolError};
+/// use std::process::Command;
+///
+/// fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+///     let seed = ctx.seed();
+///     toolapi::stdio::exec_with_seed(Command::new("./my_tool"), seed, input, ctx.message_fn())
+/// }
+/// ```
+pub fn exec_with_seed(
+    command: Command,
+    seed: u64,
+    input: Value,
+    send_msg: &mut MessageFn,
+) -> Result<Value, ToolError> {
+    spawn(command, Some(seed), input, send_msg)
+}
+
+fn spawn(
+    mut command: Command,
+    seed: Option<u64>,
+    input: Value,
+    send_msg: &mut MessageFn,
+) -> Result<Value, ToolError> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| exec_error("failed to start tool", err))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    if let Some(seed) = seed {
+        write_message(&mut stdin, &Message::Seed(seed))
+            .map_err(|err| exec_error("failed to send seed", err))?;
+    }
+    write_message(&mut stdin, &Message::Input(input))
+        .map_err(|err| exec_error("failed to send input", err))?;
+
+    // After an abort, messages are not forwarded anymore but the tool still
+    // gets the chance to return a result
+    let mut aborted = false;
+    let result = loop {
+        let msg = read_message(&mut stdout).map_err(|err| exec_error("failed to read", err))?;
+        let text = match msg {
+            Some(Message::ToolMsg(msg)) => msg,
+            // The MessageFn can only forward progress as text
+            Some(Message::Progress { fraction, message }) => {
+                progress_text(fraction, message.as_deref())
+            }
+            Some(Message::Emit { name, .. }) => format!("emitted output `{name}`"),
+            Some(Message::PartialResult(partial)) => partial_text(&partial),
+            Some(Message::Output(result)) => break result,
+            Some(_) => break Err(ToolError::internal("tool sent unexpected message")),
+            None => break Err(ToolError::internal("tool exited without result")),
+        };
+        if !aborted && send_msg(text).is_err() {
+            aborted = true;
+            write_message(&mut stdin, &Message::Abort)
+                .map_err(|err| exec_error("failed to send abort", err))?;
+        }
+    };
+
+    drop(stdin);
+    child
+        .wait()
+        .map_err(|err| exec_error("failed to wait for tool", err))?;
+    result
+}
+
+/// Run `tool` as executable speaking the stdio protocol, the counterpart to [`exec`].
+///
+/// This makes it possible to host a Rust tool with `toolapi-cli serve --exec`,
+/// e.g. to isolate crashes or to deploy it next to tools in other languages.
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     send_msg("working".to_string())?;
+///     Ok(input)
+/// }
+///
+/// fn main() -> std::io::Result<()> {
+///     toolapi::stdio::run(tool)
+/// }
+/// ```
+pub fn run<M>(tool: impl ToolHandler<M>) -> std::io::Result<()> {
+    let (seed, input) = match read_message(&mut std::io::stdin().lock())? {
+        Some(Message::Seed(seed)) => match read_message(&mut std::io::stdin().lock())? {
+            Some(Message::Input(input)) => (seed, input),
+            _ => return Err(std::io::Error::other("expected input message")),
+        },
+        // Random like on the server
+        Some(Message::Input(input)) => (random_seed(), input),
+        _ => return Err(std::io::Error::other("expected input message")),
+    };
+
+    // Everything sent after the input can only be an abort
+    let abort = AbortSignal::new();
+    let reader_abort = abort.clone();
+    std::thread::spawn(move || {
+        while let Ok(Some(Message::Abort)) = read_message(&mut std::io::stdin().lock()) {
+            reader_abort.trigger(AbortReason::RequestedByClient);
+        }
+    });
+
+    let send_abort = abort.clone();
+    let mut send_msg = move |msg: String| {
+        send_abort.check()?;
+        write_message(&mut std::io::stdout().lock(), &Message::ToolMsg(msg))
+            .map_err(|_| AbortReason::ConnectionClosed)
+    };
+    let mut send_progress = |fraction, message| {
+        write_message(
+            &mut std::io::stdout().lock(),
+            &Message::Progress { fraction, message },
+        )
+        .map_err(|_| AbortReason::ConnectionClosed)
+    };
+    let mut send_output = |name, value| {
+        write_message(
+            &mut std::io::stdout().lock(),
+            &Message::Emit { name, value },
+        )
+        .map_err(|_| AbortReason::ConnectionClosed)
+    };
+    let mut send_partial = |partial| {
+        write_message(
+            &mut std::io::stdout().lock(),
+            &Message::PartialResult(partial),
+        )
+        .map_err(|_| AbortReason::ConnectionClosed)
+    };
+    let mut ctx = ToolCtx::new(next_job_id(), &mut send_msg, abort)
+        .with_progress(&mut send_progress)
+        .with_outputs(&mut send_output)
+        .with_partials(&mut send_partial)
+        .with_seed(seed);
+    let result = tool.run(input, &mut ctx);
+
+    write_message(&mut std::io::stdout().lock(), &Message::Output(result))
+}
@@ -0,0 +1,122 @@
+//! Representative payloads and the individual stages of the wire encoding,
+//! used by the benchmarks in `benches/` to make performance regressions visible.
+
+use num_complex::Complex64;
+
+use crate::{
+    ParseError, Value,
+    connection::websocket,
+    value::{
+        dynamic::List,
+        structured::{InstantSeqEvent, Kt, Volume},
+        typed::TypedList,
+    },
+};
+
+/// Deterministic pseudo random numbers in `[-1, 1)`, so that payloads are not
+/// unrealistically compressible (real data always contains some noise).
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> f64 {
+        // Numerical Recipes LCG, the upper bits are good enough for benchmarks
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+/// 256³ voxel volume: a smooth sphere phantom plus noise.
+pub fn volume_payload() -> Value {
+    let n = 256;
+    let mut noise = Noise(1);
+    let mut data = Vec::with_capacity(n * n * n);
+    for z in 0..n {
+        for y in 0..n {
+            for x in 0..n {
+                let r2 = [x, y, z]
+                    .map(|i| (i as f64 / n as f64 - 0.5).powi(2))
+                    .iter()
+                    .sum::<f64>();
+                let density = if r2 < 0.16 { 1.0 - r2 } else { 0.0 };
+                data.push(density + 0.01 * noise.next());
+            }
+        }
+    }
+
+    Value::Volume(Volume {
+        shape: [n as u64; 3],
+        affine: [
+            [1e-3, 0.0, 0.0, -0.128],
+            [0.0, 1e-3, 0.0, -0.128],
+            [0.0, 0.0, 1e-3, -0.128],
+        ],
+        data: TypedList::Float(data),
+    })
+}
+
+/// Sequence of 1M events: repetitions of a pulse, gradient and ADC samples.
+pub fn sequence_payload() -> Value {
+    let mut noise = Noise(2);
+    let events = (0..1_000_000)
+        .map(|i| match i % 100 {
+            0 => InstantSeqEvent::Pulse {
+                angle: 0.2,
+                phase: noise.next() * std::f64::consts::PI,
+            },
+            1..50 => InstantSeqEvent::Fid {
+                kt: Kt([noise.next(), noise.next(), 0.0, 1e-5]),
+            },
+            _ => InstantSeqEvent::Adc { phase: 0.0 },
+        })
+        .collect();
+
+    Value::TypedList(TypedList::InstantSeqEvent(events))
+}
+
+/// Complex signal of 32 receive coils with 2¹⁶ samples each.
+pub fn signal_payload() -> Value {
+    let mut noise = Noise(3);
+    let coils = (0..32)
+        .map(|coil| {
+            let samples = (0..1 << 16)
+                .map(|i| {
+                    let decay = (-(i as f64) / 20_000.0).exp();
+                    let phase = coil as f64 * 0.1 + i as f64 * 0.01;
+                    Complex64::from_polar(decay, phase)
+                        + Complex64::new(noise.next(), noise.next()) * 1e-3
+                })
+                .collect();
+            Value::TypedList(TypedList::Complex(samples))
+        })
+        .collect();
+
+    Value::List(List(coils))
+}
+
+/// All benchmark payloads with their names.
+pub fn bench_payloads() -> Vec<(&'static str, Value)> {
+    vec![
+        ("volume_256", volume_payload()),
+        ("sequence_1m", sequence_payload()),
+        ("signal_32_coils", signal_payload()),
+    ]
+}
+
+pub fn serialize(value: &Value) -> Result<Vec<u8>, ParseError> {
+    rmp_serde::to_vec(value).map_err(ParseError::SerializationError)
+}
+
+pub fn deserialize(raw: &[u8]) -> Result<Value, ParseError> {
+    rmp_serde::from_slice(raw).map_err(ParseError::DeserializationError)
+}
+
+pub fn compress(raw: &[u8]) -> Vec<u8> {
+    websocket::compress(raw)
+}
+
+pub fn decompress(raw: &[u8]) -> Result<Vec<u8>, ParseError> {
+    websocket::decompress(raw)
+}
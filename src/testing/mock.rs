@@ -0,0 +1,242 @@
+//! Mock tool server with scripted responses on a real port, so frontends and
+//! clients can be developed against deterministic behavior without the tool.
+
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Router,
+    extract::{State, WebSocketUpgrade, ws::WebSocket},
+    response::Response,
+    routing::any,
+};
+use tokio::sync::oneshot;
+
+use super::Recording;
+use crate::{
+    AbortReason, ConnectionError, JobMeta, ToolError, Value,
+    connection::{
+        Transport,
+        websocket::{WsChannelServer, WsTransportAxum},
+    },
+};
+
+/// Scripted behavior of the mock tool for a single call.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    /// Sent to the client in order, waiting `interval` after each of them
+    pub messages: Vec<String>,
+    pub interval: Duration,
+    /// None closes the connection without sending a result
+    pub result: Option<Result<Value, ToolError>>,
+}
+
+impl MockResponse {
+    pub fn ok(value: Value) -> Self {
+        Self {
+            messages: Vec::new(),
+            interval: Duration::ZERO,
+            result: Some(Ok(value)),
+        }
+    }
+
+    pub fn err(err: ToolError) -> Self {
+        Self {
+            messages: Vec::new(),
+            interval: Duration::ZERO,
+            result: Some(Err(err)),
+        }
+    }
+
+    /// Simulate a dropped connection or crashed server.
+    pub fn disconnect() -> Self {
+        Self {
+            messages: Vec::new(),
+            interval: Duration::ZERO,
+            result: None,
+        }
+    }
+
+    pub fn with_messages<S: Into<String>>(mut self, messages: impl IntoIterator<Item = S>) -> Self {
+        self.messages = messages.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl From<Recording> for MockResponse {
+    fn from(recording: Recording) -> Self {
+        Self {
+            messages: recording.messages,
+            interval: Duration::ZERO,
+            result: recording.result,
+        }
+    }
+}
+
+/// Play a scripted response over the given transport, ignoring the input.
+///
+/// Like a real tool, an abort sent by the client is answered with
+/// [`AbortReason::RequestedByClient`] instead of the scripted result.
+pub(crate) async fn serve_response(
+    transport: impl Transport,
+    response: MockResponse,
+) -> Result<(), ConnectionError> {
+    let mut server = WsChannelServer::new(transport);
+    server.handshake().await?;
+    server
+        .read_input()
+        .await?
+        .ok_or(ConnectionError::ConnectionClosed)?;
+    let started = Instant::now();
+    let meta = move || JobMeta {
+        job_id: "mock".to_string(),
+        tool_version: None,
+        wall_time: started.elapsed(),
+        cpu_time: None,
+        input_size: 0,
+        output_size: 0,
+        truncated: Vec::new(),
+        seed: None,
+    };
+
+    for msg in response.messages {
+        server.send_message(msg).await?;
+        tokio::select! {
+            _ = tokio::time::sleep(response.interval) => {},
+            aborted = server.read_abort() => {
+                if aborted?.is_some() {
+                    let reason = AbortReason::RequestedByClient;
+                    return server.send_output(Err(reason.into()), meta()).await;
+                }
+            }
+        }
+    }
+
+    match response.result {
+        Some(result) => server.send_output(result, meta()).await,
+        // The transport is dropped, which closes the connection
+        None => Ok(()),
+    }
+}
+
+#[derive(Clone)]
+struct MockState {
+    responses: Arc<Vec<MockResponse>>,
+    next: Arc<AtomicUsize>,
+}
+
+async fn mock_handler(ws: WebSocketUpgrade, State(state): State<MockState>) -> Response {
+    let index = state.next.fetch_add(1, Ordering::Relaxed) % state.responses.len();
+    let response = state.responses[index].clone();
+    ws.on_upgrade(async move |socket: WebSocket| {
+        if let Err(err) = serve_response(WsTransportAxum::new(socket), response).await {
+            println!("ERR {err:?}");
+        }
+    })
+}
+
+/// Handle to a running [`mock_server`], which is shut down when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// WebSocket url to pass to [`crate::call`].
+    pub fn url(&self) -> String {
+        format!("ws://{}/tool", self.addr)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Serve scripted `responses` on `addr` (same route as [`crate::run_server`])
+/// in a background thread. Every call gets the next response, starting over
+/// after the last one. Bind to port 0 to let the OS choose a free port.
+///
+/// # Examples
+/// ```
+/// # use toolapi::{Value, ToolError, ToolCallError};
+/// use toolapi::testing::{MockResponse, mock_server};
+///
+/// let server = mock_server(
+///     "127.0.0.1:0",
+///     vec![
+///         MockResponse::ok(Value::Int(42)).with_messages(["loading", "simulating"]),
+///         MockResponse::err(ToolError::Custom("out of memory".into())),
+///     ],
+/// )
+/// .unwrap();
+///
+/// let result = toolapi::call(&server.url(), Value::None(()), |_| true);
+/// assert!(matches!(result, Ok(Value::Int(42))));
+///
+/// let result = toolapi::call(&server.url(), Value::None(()), |_| true);
+/// assert!(matches!(result, Err(ToolCallError::ToolReturnedError(_))));
+///
+/// // Starting over with the first response, aborted by the client
+/// let result = toolapi::call(&server.url(), Value::None(()), |_| false);
+/// assert!(matches!(result, Err(ToolCallError::OnMessageAbort)));
+/// ```
+pub fn mock_server(
+    addr: impl ToSocketAddrs,
+    responses: Vec<MockResponse>,
+) -> std::io::Result<MockServer> {
+    assert!(
+        !responses.is_empty(),
+        "mock server needs at least one response"
+    );
+
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let state = MockState {
+        responses: Arc::new(responses),
+        next: Arc::new(AtomicUsize::new(0)),
+    };
+    let routes = Router::new()
+        .route("/tool", any(mock_handler))
+        .with_state(state);
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::spawn(move || {
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            axum::serve(listener, routes)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await
+        })
+    });
+
+    Ok(MockServer {
+        addr,
+        shutdown: Some(shutdown_tx),
+    })
+}
@@ -0,0 +1,527 @@
+//! Utilities for testing tools and clients without deploying a server.
+
+pub mod bench;
+mod mock;
+mod record;
+pub mod strategy;
+pub mod wire_fixtures;
+pub use crate::connection::faulty::FaultConfig;
+pub use mock::{MockResponse, MockServer, mock_server};
+pub use record::Recording;
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::mpsc as tokio_mpsc, task::JoinSet};
+
+use crate::{
+    AbortReason, AbortSignal, ConnectionError, OperatorConfig, ServerConfig, ToolCallError,
+    ToolCtx, ToolError, ToolEvent, ToolHandler, Value,
+    artifacts::ArtifactStore,
+    cache::BlobCache,
+    connection::{
+        client::{WsChannelClient, block_on},
+        faulty::FaultyTransport,
+        memory::{self, MemoryTransport},
+    },
+    context::{self, SharedTool},
+    jobs::JobRegistry,
+    value::dynamic::Dict,
+};
+
+/// Client end of an in-memory connection to a tool, see [`spawn_test_server`].
+pub struct TestClient {
+    client: WsChannelClient<FaultyTransport<MemoryTransport>>,
+}
+
+/// Serve `tool` on a background thread, connected to the returned client by
+/// an in-memory transport instead of a WebSocket. The exact same server and
+/// client code as for [`crate::run_server`] and [`crate::call`] is used, so
+/// the whole flow (messages, aborts, results) can be tested without ports.
+///
+/// Like a WebSocket connection, the returned client can be used for a single call.
+///
+/// # Examples
+/// ```
+/// # use toolapi::{Value, MessageFn, ToolError, ToolCallError};
+/// use toolapi::testing::spawn_test_server;
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     send_msg("working".to_string())?;
+///     Ok(input)
+/// }
+///
+/// let result = spawn_test_server(tool).call(Value::Int(42), |_| true);
+/// assert!(matches!(result, Ok(Value::Int(42))));
+///
+/// let result = spawn_test_server(tool).call(Value::Int(42), |_| false);
+/// assert!(matches!(result, Err(ToolCallError::OnMessageAbort)));
+///
+/// // Panics are reported to the client as internal errors
+/// fn panicking(_: Value, _: &mut MessageFn) -> Result<Value, ToolError> {
+///     panic!("oops")
+/// }
+/// let result = spawn_test_server(panicking).call(Value::Int(42), |_| true);
+/// match result {
+///     Err(ToolCallError::ToolReturnedError(err)) => assert_eq!(err.code(), "internal"),
+///     _ => panic!("expected an error"),
+/// }
+/// ```
+pub fn spawn_test_server<M>(tool: impl ToolHandler<M>) -> TestClient {
+    spawn_test_server_with_faults(tool, FaultConfig::default())
+}
+
+/// Like [`spawn_test_server`], but injects network faults into the frames
+/// sent in both directions, to exercise error and abort paths deterministically.
+///
+/// # Examples
+/// ```
+/// # use toolapi::{Value, MessageFn, ToolError, ToolCallError};
+/// use toolapi::testing::{FaultConfig, spawn_test_server_with_faults};
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+///
+/// let faults = FaultConfig {
+///     truncate_rate: 1.0,
+///     ..Default::default()
+/// };
+/// let result = spawn_test_server_with_faults(tool, faults).call(Value::Int(42), |_| true);
+/// assert!(matches!(result, Err(ToolCallError::ConnectionError(_))));
+/// ```
+pub fn spawn_test_server_with_faults<M>(
+    tool: impl ToolHandler<M>,
+    faults: FaultConfig,
+) -> TestClient {
+    spawn_test_server_with_config(tool, ServerConfig::default()).client_with_faults(faults)
+}
+
+/// Server of [`spawn_test_server_with_config`], any number of clients connect
+/// to it in memory. Like the clients of [`crate::run_server`], they share the
+/// input cache, the queue of [`ServerConfig::max_concurrent_tools`] and the
+/// kept results of [`ServerConfig::job_store`]. The server stops once it and
+/// all of its clients are dropped.
+pub struct TestServer {
+    connections: tokio_mpsc::UnboundedSender<(MemoryTransport, FaultConfig)>,
+}
+
+/// Like [`spawn_test_server`], but with the settings of `config` and for any
+/// number of clients, e.g. to test the queue or resuming calls.
+///
+/// # Examples
+/// ```
+/// # use std::time::Duration;
+/// # use toolapi::{Value, MessageFn, ToolError, ServerConfig, JobStoreConfig};
+/// use toolapi::testing::{FaultConfig, spawn_test_server_with_config};
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     for i in 0..100 {
+///         send_msg(format!("step {i}"))?;
+///     }
+///     Ok(input)
+/// }
+///
+/// let config = ServerConfig {
+///     job_store: JobStoreConfig {
+///         retention: Duration::from_secs(60),
+///         ..JobStoreConfig::default()
+///     },
+///     ..ServerConfig::default()
+/// };
+/// let server = spawn_test_server_with_config(tool, config);
+///
+/// // The connection drops every 20 frames, the call is resumed every time
+/// let faults = FaultConfig {
+///     disconnect_after: Some(20),
+///     ..Default::default()
+/// };
+/// let result = server.client_with_faults(faults).call(Value::Int(42), |_| true);
+/// assert!(matches!(result, Ok(Value::Int(42))));
+/// ```
+pub fn spawn_test_server_with_config<M>(
+    tool: impl ToolHandler<M>,
+    config: ServerConfig,
+) -> TestServer {
+    let tool = context::shared(tool);
+    let (connections, mut accept) = tokio_mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let cache = Arc::new(BlobCache::new(config.cache.clone()));
+                let artifacts = Arc::new(ArtifactStore::new(config.artifacts.clone()));
+                let jobs = Arc::new(JobRegistry::new(config.max_concurrent_tools));
+                let mut running = JoinSet::new();
+                while let Some((server_end, faults)) = accept.recv().await {
+                    running.spawn(crate::util::run_tool(
+                        FaultyTransport::new(server_end, faults),
+                        tool.clone(),
+                        config.clone(),
+                        cache.clone(),
+                        artifacts.clone(),
+                        jobs.clone(),
+                    ));
+                }
+                running.join_all().await;
+            })
+    });
+
+    TestServer { connections }
+}
+
+impl TestServer {
+    /// Client for a single call, like a WebSocket connection
+    pub fn client(&self) -> TestClient {
+        self.client_with_faults(FaultConfig::default())
+    }
+
+    /// Like [`Self::client`], but injects network faults into the frames
+    /// sent in both directions, see [`spawn_test_server_with_faults`]. Calls
+    /// that resume after [`FaultConfig::disconnect_after`] connect again.
+    pub fn client_with_faults(&self, faults: FaultConfig) -> TestClient {
+        // Different seeds, so that both directions see different faults.
+        // Only the client drops the connection, the server notices it.
+        let server_faults = FaultConfig {
+            seed: faults.seed.wrapping_add(1),
+            disconnect_after: None,
+            ..faults.clone()
+        };
+        let connections = self.connections.clone();
+        let connect: memory::Connect = Arc::new(move || {
+            let (client_end, server_end) = memory::pair();
+            connections
+                .send((server_end, server_faults.clone()))
+                .map_err(|_| ConnectionError::ConnectionClosed)?;
+            Ok(client_end)
+        });
+        // The server thread only stops after the clients were dropped
+        let transport = memory::connect(connect).expect("the test server is running");
+        TestClient::new(transport, faults)
+    }
+}
+
+impl TestClient {
+    fn new(transport: MemoryTransport, faults: FaultConfig) -> Self {
+        Self {
+            client: WsChannelClient::new(FaultyTransport::new(transport, faults)),
+        }
+    }
+
+    /// Execute the tool, blocking until it is done. See [`crate::call`].
+    pub fn call(
+        self,
+        input: Value,
+        on_message: impl FnMut(String) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        block_on(self.client.call(input, on_message))
+    }
+
+    /// Like [`Self::call`], see [`crate::call_with_events`].
+    pub fn call_with_events(
+        self,
+        input: Value,
+        on_event: impl FnMut(ToolEvent) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        block_on(self.client.call_with_events(input, on_event))
+    }
+
+    /// Like [`Self::call`], see [`crate::call_with_progress`].
+    pub fn call_with_progress(
+        self,
+        input: Value,
+        on_message: impl FnMut(String) -> bool,
+        on_progress: impl FnMut(f64, Option<String>) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        block_on(
+            self.client
+                .call_with_progress(input, on_message, on_progress),
+        )
+    }
+
+    /// Like [`Self::call_with_events`], but continues the call of another
+    /// client, see [`crate::resume`].
+    pub fn resume_with_events(
+        self,
+        job_id: &str,
+        secret: &str,
+        on_event: impl FnMut(ToolEvent) -> bool,
+    ) -> Result<Value, ToolCallError> {
+        block_on(self.client.resume_with_events(job_id, secret, on_event))
+    }
+}
+
+/// Harness which runs a tool (see [`ToolHandler`]) directly (without any connection) and
+/// records everything it does, to keep unit tests of tools short and uniform.
+///
+/// Aborts are simulated like the server does it: once an abort was requested,
+/// [`ToolCtx::check_abort`] and the next message sent by the tool return an [`AbortReason`].
+///
+/// # Examples
+/// ```
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use toolapi::testing::ToolTester;
+///
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     for i in 0..10 {
+///         send_msg(format!("step {i}"))?;
+///     }
+///     Ok(input)
+/// }
+///
+/// let run = ToolTester::new(tool).run(Value::Int(42));
+/// assert_eq!(run.messages.len(), 10);
+/// assert!(matches!(run.assert_ok(), Value::Int(42)));
+///
+/// let run = ToolTester::new(tool).abort_after_messages(3).run(Value::Int(42));
+/// assert_eq!(run.messages, ["step 0", "step 1", "step 2"]);
+/// run.assert_aborted();
+/// ```
+pub struct ToolTester {
+    tool: SharedTool,
+    abort_after_messages: Option<usize>,
+    abort_after: Option<Duration>,
+    operator_config: OperatorConfig,
+    seed: Option<u64>,
+}
+
+/// Everything recorded during a single [`ToolTester::run`].
+#[derive(Debug)]
+pub struct ToolRun {
+    /// All messages the tool sent successfully, in order
+    pub messages: Vec<String>,
+    /// All progress reports (fraction and message) the tool sent, in order
+    pub progress: Vec<(f64, Option<String>)>,
+    /// All outputs the tool emitted (name and value), in order
+    pub emitted: Vec<(String, Value)>,
+    /// All partial results the tool sent, in order
+    pub partials: Vec<Dict>,
+    /// Value returned by the tool
+    pub result: Result<Value, ToolError>,
+    /// True if an abort was requested (the tool might have ignored it)
+    pub abort_requested: bool,
+    /// Wall time the tool took to return
+    pub duration: Duration,
+}
+
+impl ToolTester {
+    pub fn new<M>(tool: impl ToolHandler<M>) -> Self {
+        Self {
+            tool: context::shared(tool),
+            abort_after_messages: None,
+            abort_after: None,
+            operator_config: OperatorConfig::default(),
+            seed: None,
+        }
+    }
+
+    /// Request an abort after the tool sent `count` messages.
+    pub fn abort_after_messages(mut self, count: usize) -> Self {
+        self.abort_after_messages = Some(count);
+        self
+    }
+
+    /// Request an abort once `duration` has passed since the tool was started.
+    ///
+    /// # Examples
+    /// ```
+    /// # use toolapi::{Value, ToolCtx, ToolError};
+    /// use std::time::Duration;
+    /// use toolapi::testing::ToolTester;
+    ///
+    /// // Never sends a message, but checks for aborts
+    /// fn tool(_: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     loop {
+    ///         ctx.check_abort()?;
+    ///         std::thread::sleep(Duration::from_millis(1));
+    ///     }
+    /// }
+    ///
+    /// let run = ToolTester::new(tool)
+    ///     .abort_after(Duration::from_millis(20))
+    ///     .run(Value::None(()));
+    /// run.assert_aborted();
+    /// ```
+    pub fn abort_after(mut self, duration: Duration) -> Self {
+        self.abort_after = Some(duration);
+        self
+    }
+
+    /// Settings the tool reads with [`crate::ToolCtx::setting`], like
+    /// [`crate::ServerConfig::with_operator_config`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use toolapi::{Value, ToolCtx, ToolError, value::dynamic::Dict};
+    /// use toolapi::testing::ToolTester;
+    ///
+    /// fn tool(_: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     Ok(Value::Int(ctx.setting("gpu")?))
+    /// }
+    ///
+    /// let settings = Dict([("gpu".to_string(), Value::Int(1))].into());
+    /// let run = ToolTester::new(tool).operator_config(settings).run(Value::None(()));
+    /// assert!(matches!(run.assert_ok(), Value::Int(1)));
+    ///
+    /// ToolTester::new(tool).run(Value::None(())).assert_err();
+    /// ```
+    pub fn operator_config(mut self, settings: Dict) -> Self {
+        self.operator_config = ServerConfig::default()
+            .with_operator_config(settings)
+            .operator_config;
+        self
+    }
+
+    /// Seed returned by [`crate::ToolCtx::seed`], like a client calling with
+    /// [`crate::call_with_seed`]. Without it, the seed is random like on the
+    /// server.
+    ///
+    /// # Examples
+    /// ```
+    /// # use toolapi::{Value, ToolCtx, ToolError};
+    /// use toolapi::testing::ToolTester;
+    ///
+    /// fn tool(_: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    ///     Ok(Value::Int(ctx.seed() as i64))
+    /// }
+    ///
+    /// let run = ToolTester::new(tool).seed(7).run(Value::None(()));
+    /// assert!(matches!(run.assert_ok(), Value::Int(7)));
+    /// ```
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Run the tool on the current thread, blocking until it returns.
+    pub fn run(&self, input: Value) -> ToolRun {
+        // MessageFn is 'static, so the recorded state is shared with the closure
+        let start = Instant::now();
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let recorded_progress = Rc::new(RefCell::new(Vec::new()));
+        let recorded_outputs = Rc::new(RefCell::new(Vec::new()));
+        let recorded_partials = Rc::new(RefCell::new(Vec::new()));
+        let abort = AbortSignal::new();
+
+        // Triggers the abort in time even if the tool doesn't send messages
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        if let Some(duration) = self.abort_after {
+            let abort = abort.clone();
+            std::thread::spawn(move || {
+                if done_rx.recv_timeout(duration) == Err(RecvTimeoutError::Timeout) {
+                    abort.trigger(AbortReason::RequestedByClient);
+                }
+            });
+        }
+
+        let result = {
+            let recorded = recorded.clone();
+            let send_abort = abort.clone();
+            let abort_after_messages = self.abort_after_messages;
+            let mut send_msg = move |msg: String| {
+                let messages = &mut *recorded.borrow_mut();
+                if abort_after_messages.is_some_and(|count| messages.len() >= count) {
+                    send_abort.trigger(AbortReason::RequestedByClient);
+                }
+                send_abort.check()?;
+                messages.push(msg);
+                Ok(())
+            };
+            let recorded_progress = recorded_progress.clone();
+            let progress_abort = abort.clone();
+            let mut send_progress = move |fraction, message| {
+                progress_abort.check()?;
+                recorded_progress.borrow_mut().push((fraction, message));
+                Ok(())
+            };
+            let recorded_outputs = recorded_outputs.clone();
+            let output_abort = abort.clone();
+            let mut send_output = move |name, value| {
+                output_abort.check()?;
+                recorded_outputs.borrow_mut().push((name, value));
+                Ok(())
+            };
+            let recorded_partials = recorded_partials.clone();
+            let partial_abort = abort.clone();
+            let mut send_partial = move |partial| {
+                partial_abort.check()?;
+                recorded_partials.borrow_mut().push(partial);
+                Ok(())
+            };
+            let seed = self.seed.unwrap_or_else(context::random_seed);
+            let mut ctx = ToolCtx::new(context::next_job_id(), &mut send_msg, abort.clone())
+                .with_progress(&mut send_progress)
+                .with_outputs(&mut send_output)
+                .with_partials(&mut send_partial)
+                .with_operator_config(self.operator_config.clone())
+                .with_seed(seed);
+            (self.tool)(input, &mut ctx)
+        };
+        let duration = start.elapsed();
+        drop(done_tx);
+        let messages = recorded.take();
+        let progress = recorded_progress.take();
+        let emitted = recorded_outputs.take();
+        let partials = recorded_partials.take();
+        let abort_requested = abort.check().is_err();
+
+        ToolRun {
+            messages,
+            progress,
+            emitted,
+            partials,
+            result,
+            abort_requested,
+            duration,
+        }
+    }
+}
+
+impl ToolRun {
+    /// Panics if the tool returned an error, otherwise returns the output.
+    #[track_caller]
+    pub fn assert_ok(&self) -> &Value {
+        match &self.result {
+            Ok(value) => value,
+            Err(err) => panic!(
+                "tool returned an error: {err}\nmessages: {:#?}",
+                self.messages
+            ),
+        }
+    }
+
+    /// Panics if the tool returned a value, otherwise returns the error.
+    #[track_caller]
+    pub fn assert_err(&self) -> &ToolError {
+        match &self.result {
+            Ok(value) => panic!(
+                "tool returned a value: {value:?}\nmessages: {:#?}",
+                self.messages
+            ),
+            Err(err) => err,
+        }
+    }
+
+    /// Panics unless an abort was requested and the tool returned [`ToolError::Abort`].
+    #[track_caller]
+    pub fn assert_aborted(&self) {
+        assert!(self.abort_requested, "no abort was requested");
+        match &self.result {
+            Err(ToolError::Abort(_)) => {}
+            result => panic!(
+                "tool did not propagate the abort, returned: {result:?}\nmessages: {:#?}",
+                self.messages
+            ),
+        }
+    }
+}
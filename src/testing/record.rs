@@ -0,0 +1,126 @@
+//! Record tool calls to files and replay them later, e.g. to debug a tool
+//! against the exact input that made it fail in production.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{TestClient, ToolRun, ToolTester, mock::serve_response};
+use crate::{
+    ToolCallError, ToolError, ToolHandler, Value,
+    connection::{
+        memory,
+        websocket::{decode, encode},
+    },
+};
+
+/// Everything that was exchanged during a single tool call.
+///
+/// Recordings are stored with the same encoding as the wire protocol
+/// (zstd compressed msgpack).
+///
+/// # Examples
+/// ```no_run
+/// # use toolapi::{Value, MessageFn, ToolError};
+/// use toolapi::testing::Recording;
+///
+/// // Capture a call against a deployed tool ...
+/// let (result, recording) = Recording::call("wss://tool.example.com/tool", Value::Int(42), |_| true);
+/// recording.save("failing_call.bin").unwrap();
+///
+/// // ... and replay it against a local build of the tool
+/// fn tool(input: Value, send_msg: &mut MessageFn) -> Result<Value, ToolError> {
+///     Ok(input)
+/// }
+/// let recording = Recording::load("failing_call.bin").unwrap();
+/// recording.replay(tool).assert_ok();
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub input: Value,
+    /// All messages received from the tool, in order
+    pub messages: Vec<String>,
+    /// None if the call ended without a result (client abort, connection error)
+    pub result: Option<Result<Value, ToolError>>,
+}
+
+impl Recording {
+    /// Like [`crate::call`], but additionally returns a recording of the call.
+    pub fn call(
+        addr: &str,
+        input: Value,
+        mut on_message: impl FnMut(String) -> bool,
+    ) -> (Result<Value, ToolCallError>, Self) {
+        let mut messages = Vec::new();
+        let result = crate::call(addr, input.clone(), |msg| {
+            messages.push(msg.clone());
+            on_message(msg)
+        });
+
+        let recorded_result = match &result {
+            Ok(value) => Some(Ok(value.clone())),
+            Err(ToolCallError::CloseFailed { result, .. }) => Some(Ok(result.as_ref().clone())),
+            Err(ToolCallError::ToolReturnedError(err)) => Some(Err(err.clone())),
+            Err(_) => None,
+        };
+
+        let recording = Self {
+            input,
+            messages,
+            result: recorded_result,
+        };
+        (result, recording)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let raw = encode(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, raw)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let raw = std::fs::read(path)?;
+        decode(&raw).map_err(std::io::Error::other)
+    }
+
+    /// Run `tool` with the recorded input, see [`ToolTester`].
+    pub fn replay<M>(&self, tool: impl ToolHandler<M>) -> ToolRun {
+        ToolTester::new(tool).run(self.input.clone())
+    }
+
+    /// Mock server which ignores the input and replays the recorded messages
+    /// and result to the returned client, see [`super::spawn_test_server`].
+    ///
+    /// Useful to test client code against a captured tool response.
+    ///
+    /// # Examples
+    /// ```
+    /// # use toolapi::Value;
+    /// use toolapi::testing::Recording;
+    ///
+    /// let recording = Recording {
+    ///     input: Value::None(()),
+    ///     messages: vec!["working".to_string()],
+    ///     result: Some(Ok(Value::Int(42))),
+    /// };
+    /// let mut received = Vec::new();
+    /// let result = recording.spawn_replay_server().call(Value::None(()), |msg| {
+    ///     received.push(msg);
+    ///     true
+    /// });
+    /// assert!(matches!(result, Ok(Value::Int(42))));
+    /// assert_eq!(received, ["working"]);
+    /// ```
+    pub fn spawn_replay_server(self) -> TestClient {
+        let (client_end, server_end) = memory::pair();
+
+        std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(serve_response(server_end, self.into()))
+        });
+
+        TestClient::new(client_end, Default::default())
+    }
+}
@@ -0,0 +1,282 @@
+//! [`proptest`] strategies for [`Value`] and the types it contains, to
+//! property-test (de)serialization, conversion and extraction code.
+//!
+//! All types also implement [`Arbitrary`], so `any::<Value>()` or
+//! `any_with::<Value>(SizeConfig { .. })` can be used directly.
+//!
+//! # Examples
+//! ```
+//! use proptest::{prelude::*, test_runner::TestRunner};
+//! use toolapi::Value;
+//!
+//! let mut runner = TestRunner::default();
+//! runner
+//!     .run(&any::<Value>(), |value| {
+//!         let raw = rmp_serde::to_vec(&value).unwrap();
+//!         let decoded: Value = rmp_serde::from_slice(&raw).unwrap();
+//!         // Dict order is not stable, but the encoded size is
+//!         prop_assert_eq!(rmp_serde::to_vec(&decoded).unwrap().len(), raw.len());
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! ```
+
+use std::collections::HashMap;
+
+use num_complex::Complex64;
+use proptest::{arbitrary::Arbitrary, collection, prelude::*};
+
+use crate::value::{
+    Value,
+    atomic::{Quat, Vec3, Vec4},
+    dynamic::{Dict, List},
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
+    typed::{TypedDict, TypedList},
+};
+
+/// Limits for the size of generated values.
+#[derive(Debug, Clone)]
+pub struct SizeConfig {
+    /// Maximum nesting depth of [`Value::Dict`] and [`Value::List`]
+    pub max_depth: u32,
+    /// Maximum number of elements of collections, strings and bytes
+    pub max_len: usize,
+    /// Maximum size of [`Volume`]s along each axis
+    pub max_volume_dim: u64,
+}
+
+impl Default for SizeConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            max_len: 4,
+            max_volume_dim: 4,
+        }
+    }
+}
+
+fn string(config: &SizeConfig) -> BoxedStrategy<String> {
+    collection::vec(any::<char>(), 0..=config.max_len)
+        .prop_map(String::from_iter)
+        .boxed()
+}
+
+fn bytes(config: &SizeConfig) -> BoxedStrategy<Vec<u8>> {
+    collection::vec(any::<u8>(), 0..=config.max_len).boxed()
+}
+
+pub fn complex() -> BoxedStrategy<Complex64> {
+    any::<(f64, f64)>()
+        .prop_map(|(re, im)| Complex64::new(re, im))
+        .boxed()
+}
+
+pub fn vec3() -> BoxedStrategy<Vec3> {
+    any::<[f64; 3]>().prop_map(Vec3).boxed()
+}
+
+pub fn vec4() -> BoxedStrategy<Vec4> {
+    any::<[f64; 4]>().prop_map(Vec4).boxed()
+}
+
+pub fn quat() -> BoxedStrategy<Quat> {
+    any::<[f64; 4]>().prop_map(Quat).boxed()
+}
+
+pub fn instant_seq_event() -> BoxedStrategy<InstantSeqEvent> {
+    prop_oneof![
+        any::<(f64, f64)>().prop_map(|(angle, phase)| InstantSeqEvent::Pulse { angle, phase }),
+        vec4().prop_map(|kt| InstantSeqEvent::Fid { kt: kt.into() }),
+        any::<f64>().prop_map(|phase| InstantSeqEvent::Adc { phase }),
+    ]
+    .boxed()
+}
+
+/// Volume with a random shape, a finite affine and data of a matching length.
+pub fn volume(config: &SizeConfig) -> BoxedStrategy<Volume> {
+    let dim = 1..=config.max_volume_dim.max(1);
+    let finite = proptest::num::f64::NORMAL | proptest::num::f64::ZERO;
+    let affine = proptest::array::uniform3(proptest::array::uniform4(finite));
+    ([dim.clone(), dim.clone(), dim], affine)
+        .prop_flat_map(|(shape, affine)| {
+            let len = shape.iter().product::<u64>() as usize;
+            // Only types that make sense as voxel data
+            prop_oneof![
+                collection::vec(any::<f64>(), len).prop_map(TypedList::Float),
+                collection::vec(any::<i64>(), len).prop_map(TypedList::Int),
+                collection::vec(any::<bool>(), len).prop_map(TypedList::Bool),
+                collection::vec(complex(), len).prop_map(TypedList::Complex),
+                collection::vec(vec3(), len).prop_map(TypedList::Vec3),
+            ]
+            .prop_map(move |data| Volume::new(shape, affine, data).unwrap())
+        })
+        .boxed()
+}
+
+pub fn phantom_tissue(config: &SizeConfig) -> BoxedStrategy<PhantomTissue> {
+    (volume(config), volume(config), any::<[f64; 4]>())
+        .prop_map(|(density, db0, [t1, t2, t2dash, adc])| PhantomTissue {
+            density,
+            db0,
+            t1,
+            t2,
+            t2dash,
+            adc,
+        })
+        .boxed()
+}
+
+pub fn segmented_phantom(config: &SizeConfig) -> BoxedStrategy<SegmentedPhantom> {
+    (
+        dict_of(phantom_tissue(config), config),
+        list_of(volume(config), config),
+        list_of(volume(config), config),
+    )
+        .prop_map(|(tissues, b1_tx, b1_rx)| SegmentedPhantom {
+            tissues,
+            b1_tx,
+            b1_rx,
+        })
+        .boxed()
+}
+
+pub fn provenance() -> BoxedStrategy<Provenance> {
+    (
+        any::<(Option<String>, Option<String>, String)>(),
+        any::<(f64, f64, String)>(),
+    )
+        .prop_map(
+            |((tool, version, input_hash), (started, duration, host))| Provenance {
+                tool,
+                version,
+                input_hash,
+                started,
+                duration,
+                host,
+            },
+        )
+        .boxed()
+}
+
+fn list_of<S: Strategy>(
+    elem: S,
+    config: &SizeConfig,
+) -> impl Strategy<Value = Vec<S::Value>> + use<S> {
+    collection::vec(elem, 0..=config.max_len)
+}
+
+fn dict_of<S: Strategy>(
+    elem: S,
+    config: &SizeConfig,
+) -> impl Strategy<Value = HashMap<String, S::Value>> + use<S> {
+    collection::hash_map(string(config), elem, 0..=config.max_len)
+}
+
+/// One strategy per variant of a typed collection, built with `$coll`.
+macro_rules! typed_collection {
+    ($ty:ident, $coll:ident, $config:expr) => {{
+        let config = $config;
+        prop_oneof![
+            $coll(Just(()), config).prop_map($ty::None),
+            $coll(any::<bool>(), config).prop_map($ty::Bool),
+            $coll(any::<i64>(), config).prop_map($ty::Int),
+            $coll(any::<f64>(), config).prop_map($ty::Float),
+            $coll(string(config), config).prop_map($ty::Str),
+            $coll(bytes(config), config).prop_map($ty::Bytes),
+            $coll(complex(), config).prop_map($ty::Complex),
+            $coll(vec3(), config).prop_map($ty::Vec3),
+            $coll(vec4(), config).prop_map($ty::Vec4),
+            $coll(quat(), config).prop_map($ty::Quat),
+            $coll(instant_seq_event(), config).prop_map($ty::InstantSeqEvent),
+            $coll(volume(config), config).prop_map($ty::Volume),
+            $coll(segmented_phantom(config), config).prop_map($ty::SegmentedPhantom),
+            $coll(provenance(), config).prop_map($ty::Provenance),
+            $coll(phantom_tissue(config), config).prop_map($ty::PhantomTissue),
+        ]
+        .boxed()
+    }};
+}
+
+pub fn typed_list(config: &SizeConfig) -> BoxedStrategy<TypedList> {
+    let typed_list = typed_collection!(TypedList, list_of, config);
+    // Half only exists as list, weighted like a single variant of the others
+    #[cfg(feature = "half")]
+    let typed_list = prop_oneof![
+        14 => typed_list,
+        1 => list_of(any::<u16>().prop_map(half::f16::from_bits), config).prop_map(TypedList::Half),
+    ]
+    .boxed();
+    typed_list
+}
+
+pub fn typed_dict(config: &SizeConfig) -> BoxedStrategy<TypedDict> {
+    typed_collection!(TypedDict, dict_of, config)
+}
+
+/// Any [`Value`], with Dicts and Lists nested up to `config.max_depth` levels.
+pub fn value(config: &SizeConfig) -> BoxedStrategy<Value> {
+    let leaf = prop_oneof![
+        Just(Value::None(())),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::Int),
+        any::<f64>().prop_map(Value::Float),
+        string(config).prop_map(Value::Str),
+        bytes(config).prop_map(Value::Bytes),
+        complex().prop_map(Value::Complex),
+        vec3().prop_map(Value::Vec3),
+        vec4().prop_map(Value::Vec4),
+        quat().prop_map(Value::Quat),
+        instant_seq_event().prop_map(Value::InstantSeqEvent),
+        volume(config).prop_map(Value::Volume),
+        segmented_phantom(config).prop_map(Value::SegmentedPhantom),
+        provenance().prop_map(Value::Provenance),
+        phantom_tissue(config).prop_map(Value::PhantomTissue),
+        typed_dict(config).prop_map(Value::TypedDict),
+        typed_list(config).prop_map(Value::TypedList),
+    ];
+
+    let config = config.clone();
+    let max_len = config.max_len as u32;
+    leaf.prop_recursive(config.max_depth, 256, max_len, move |inner| {
+        prop_oneof![
+            dict_of(inner.clone(), &config).prop_map(|map| Value::Dict(Dict(map))),
+            list_of(inner, &config).prop_map(|list| Value::List(List(list))),
+        ]
+    })
+    .boxed()
+}
+
+macro_rules! impl_arbitrary {
+    ($ty:ty, $strategy:ident) => {
+        impl Arbitrary for $ty {
+            type Parameters = ();
+            type Strategy = BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: ()) -> Self::Strategy {
+                $strategy()
+            }
+        }
+    };
+    ($ty:ty, $strategy:ident, SizeConfig) => {
+        impl Arbitrary for $ty {
+            type Parameters = SizeConfig;
+            type Strategy = BoxedStrategy<Self>;
+
+            fn arbitrary_with(config: SizeConfig) -> Self::Strategy {
+                $strategy(&config)
+            }
+        }
+    };
+}
+
+impl_arbitrary!(Vec3, vec3);
+impl_arbitrary!(Vec4, vec4);
+impl_arbitrary!(Quat, quat);
+impl_arbitrary!(InstantSeqEvent, instant_seq_event);
+impl_arbitrary!(Volume, volume, SizeConfig);
+impl_arbitrary!(PhantomTissue, phantom_tissue, SizeConfig);
+impl_arbitrary!(SegmentedPhantom, segmented_phantom, SizeConfig);
+impl_arbitrary!(Provenance, provenance);
+impl_arbitrary!(TypedList, typed_list, SizeConfig);
+impl_arbitrary!(TypedDict, typed_dict, SizeConfig);
+impl_arbitrary!(Value, value, SizeConfig);
@@ -0,0 +1,324 @@
+//! Golden fixtures of the wire format, to catch accidental breaking changes.
+//!
+//! A canonical set of [`Value`]s and messages is serialized and compared to
+//! the bytes checked in to `fixtures/wire/`. Any difference means that the
+//! serialized representation changed and [`crate::PROTOCOL_VERSION`] must be
+//! bumped. Crate users can run [`check`] in their own tests to make sure a
+//! toolapi update did not change the protocol without them noticing.
+//!
+//! Only the msgpack encoding is compared: the zstd compression on top of it
+//! is standardized, but the exact compressed bytes depend on the encoder.
+//!
+//! # Examples
+//! ```
+//! use toolapi::testing::wire_fixtures;
+//!
+//! if let Err(changed) = wire_fixtures::check() {
+//!     panic!("wire format changed for: {changed:?}");
+//! }
+//! ```
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use num_complex::Complex64;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+
+use crate::{
+    ExtractionError, ToolError, Value,
+    connection::websocket::{BlobHash, Capabilities, JobMeta, Message, MissingBlob},
+    value::{
+        atomic::{Quat, Vec3, Vec4},
+        dynamic::{Dict, List},
+        structured::{InstantSeqEvent, Kt, PhantomTissue, Provenance, SegmentedPhantom, Volume},
+        typed::{TypedDict, TypedList},
+    },
+};
+
+/// A single canonical value with its current and its golden encoding.
+pub struct Fixture {
+    pub name: &'static str,
+    /// Serialized with the current version of the crate
+    pub encoded: Vec<u8>,
+    /// Checked in to `fixtures/wire/{name}.msgpack`
+    pub golden: &'static [u8],
+}
+
+impl Fixture {
+    pub fn matches(&self) -> bool {
+        self.encoded == self.golden
+    }
+}
+
+macro_rules! fixture {
+    ($name:literal, $value:expr) => {
+        Fixture {
+            name: $name,
+            encoded: encode(&$value),
+            golden: include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/fixtures/wire/",
+                $name,
+                ".msgpack"
+            )),
+        }
+    };
+}
+
+fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    rmp_serde::to_vec(value).expect("fixtures are always serializable")
+}
+
+// Dicts only contain a single entry, HashMap iteration order is random
+fn single<T>(key: &str, value: T) -> HashMap<String, T> {
+    HashMap::from([(key.to_string(), value)])
+}
+
+fn volume() -> Volume {
+    Volume {
+        shape: [2, 1, 1],
+        affine: [
+            [1.0, 0.0, 0.0, -0.5],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+        ],
+        data: TypedList::Float(vec![0.25, 0.75]),
+    }
+}
+
+fn phantom_tissue() -> PhantomTissue {
+    PhantomTissue {
+        density: volume(),
+        db0: volume(),
+        t1: 1.5,
+        t2: 0.1,
+        t2dash: 0.05,
+        adc: 1e-9,
+    }
+}
+
+/// All fixtures, one for every [`Value`] variant and message type.
+/// `typed_list_half` is only checked with the `half` feature.
+pub fn fixtures() -> Vec<Fixture> {
+    #[allow(unused_mut)]
+    let mut fixtures = vec![
+        fixture!("none", Value::None(())),
+        fixture!("bool", Value::Bool(true)),
+        fixture!("int", Value::Int(-1_234_567_890_123)),
+        fixture!("float", Value::Float(0.1)),
+        fixture!("str", Value::Str("toolapi".to_string())),
+        fixture!("bytes", Value::Bytes(vec![0, 1, 255])),
+        fixture!("attachment_ref", Value::AttachmentRef(2)),
+        fixture!("complex", Value::Complex(Complex64::new(1.5, -2.0))),
+        fixture!("vec3", Value::Vec3(Vec3([1.0, 2.0, 3.0]))),
+        fixture!("vec4", Value::Vec4(Vec4([1.0, 2.0, 3.0, 4.0]))),
+        fixture!("quat", Value::Quat(Quat([0.5, 0.5, -0.5, 0.5]))),
+        fixture!(
+            "instant_seq_event",
+            Value::TypedList(TypedList::InstantSeqEvent(vec![
+                InstantSeqEvent::Pulse {
+                    angle: 1.5,
+                    phase: 0.0
+                },
+                InstantSeqEvent::Fid {
+                    kt: Kt([0.0, 10.0, 20.0, 1e-3])
+                },
+                InstantSeqEvent::Adc { phase: 0.5 },
+            ]))
+        ),
+        fixture!("volume", Value::Volume(volume())),
+        fixture!("phantom_tissue", Value::PhantomTissue(phantom_tissue())),
+        fixture!(
+            "segmented_phantom",
+            Value::SegmentedPhantom(SegmentedPhantom {
+                tissues: single("gm", phantom_tissue()),
+                b1_tx: vec![volume()],
+                b1_rx: vec![volume(), volume()],
+            })
+        ),
+        fixture!(
+            "provenance",
+            Value::Provenance(Provenance {
+                tool: Some("mr0-sim".to_string()),
+                version: None,
+                input_hash: "af1349b9".to_string(),
+                started: 1_700_000_000.5,
+                duration: 2.25,
+                host: "worker-1".to_string(),
+            })
+        ),
+        fixture!(
+            "dict",
+            Value::Dict(Dict(single("key", Value::Str("value".to_string()))))
+        ),
+        fixture!(
+            "list",
+            Value::List(List(vec![Value::Int(1), Value::Float(2.0)]))
+        ),
+        fixture!(
+            "typed_dict",
+            Value::TypedDict(TypedDict::Int(single("a", 1)))
+        ),
+        fixture!(
+            "typed_list",
+            Value::TypedList(TypedList::Bytes(vec![vec![1, 2], vec![]]))
+        ),
+        fixture!(
+            "typed_list_complex",
+            Value::TypedList(TypedList::Complex(vec![
+                Complex64::new(1.0, 0.5),
+                Complex64::new(-1.0, 0.0),
+            ]))
+        ),
+        fixture!("msg_input", Message::Input(Value::Int(42))),
+        fixture!("msg_output_ok", Message::Output(Ok(Value::Float(0.5)))),
+        fixture!(
+            "msg_output_err",
+            Message::Output(Err(ToolError::Extraction(ExtractionError::KeyNotFound {
+                key: "t1".to_string()
+            })))
+        ),
+        fixture!(
+            "msg_output_invalid_input",
+            Message::Output(Err(ToolError::invalid_input("t1", "negative")
+                .with_details(Dict(single("min", Value::Float(0.0))))))
+        ),
+        fixture!(
+            "msg_output_partial",
+            Message::Output(Err(
+                ToolError::timeout("deadline").with_partial(Value::Int(90))
+            ))
+        ),
+        fixture!("msg_tool_msg", Message::ToolMsg("working".to_string())),
+        fixture!("msg_abort", Message::Abort),
+        fixture!(
+            "msg_attachment",
+            Message::Attachment {
+                id: 0,
+                size: 1024 * 1024,
+            }
+        ),
+        fixture!(
+            "msg_cached_input",
+            Message::CachedInput {
+                input: Value::Dict(Dict(single("te", Value::Float(0.01)))),
+                refs: single("phantom", BlobHash([7; 32])),
+            }
+        ),
+        fixture!(
+            "msg_missing",
+            Message::Missing(vec![MissingBlob {
+                hash: BlobHash([7; 32]),
+                received: 8 * 1024 * 1024,
+            }])
+        ),
+        fixture!(
+            "msg_blob_chunk",
+            Message::BlobChunk {
+                hash: BlobHash([7; 32]),
+                offset: 0,
+                size: 6,
+                data: ByteBuf::from(encode(&Value::Int(1))),
+            }
+        ),
+        fixture!(
+            "msg_stamped",
+            Message::Stamped {
+                seq: 3,
+                time: 1.5,
+                message: Box::new(Message::ToolMsg("working".to_string())),
+            }
+        ),
+        fixture!(
+            "msg_hello",
+            Message::Hello {
+                protocol_version: 13,
+                capabilities: Capabilities::from_iter([Capabilities::ZSTD, Capabilities::EMIT]),
+            }
+        ),
+        fixture!(
+            "msg_job_meta",
+            Message::JobMeta(JobMeta {
+                job_id: "19a2b3c4d5e-7".to_string(),
+                tool_version: Some("1.2.0".to_string()),
+                wall_time: Duration::from_millis(1500),
+                cpu_time: Some(Duration::from_millis(1250)),
+                input_size: 2048,
+                output_size: 512,
+                truncated: vec!["`signal`: kept 1000 of 4000 elements".to_string()],
+                seed: Some(0x5eed),
+            })
+        ),
+        fixture!("msg_seed", Message::Seed(42)),
+        fixture!("msg_queued", Message::Queued { position: 3 }),
+        fixture!(
+            "msg_accepted",
+            Message::Accepted {
+                job_id: "0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24".to_string(),
+                secret: "5be0c2a91d7f4e38b6a0d9c4e1f27a85".to_string(),
+            }
+        ),
+        fixture!(
+            "msg_resume",
+            Message::Resume {
+                job_id: "0f4c7a3e-2b9d-4e61-a8c5-93d1f06b7e24".to_string(),
+                secret: "5be0c2a91d7f4e38b6a0d9c4e1f27a85".to_string(),
+                seq: 4,
+            }
+        ),
+        fixture!("msg_ack", Message::Ack { seq: 16 }),
+        fixture!(
+            "msg_emit",
+            Message::Emit {
+                name: "qc_report".to_string(),
+                value: Value::Str("all good".to_string()),
+            }
+        ),
+        fixture!(
+            "msg_partial_result",
+            Message::PartialResult(Dict(single("slab_0", Value::Float(0.5))))
+        ),
+        fixture!(
+            "msg_progress",
+            Message::Progress {
+                fraction: 0.5,
+                message: Some("simulating".to_string()),
+            }
+        ),
+    ];
+    #[cfg(feature = "half")]
+    fixtures.push(fixture!(
+        "typed_list_half",
+        Value::TypedList(TypedList::Half(
+            [0.0, 1.0, -65504.0].map(half::f16::from_f64).to_vec()
+        ))
+    ));
+    fixtures
+}
+
+/// Compare all fixtures to their golden encoding.
+/// Returns the names of all fixtures that do not match.
+pub fn check() -> Result<(), Vec<&'static str>> {
+    let changed: Vec<_> = fixtures()
+        .into_iter()
+        .filter(|fixture| !fixture.matches())
+        .map(|fixture| fixture.name)
+        .collect();
+
+    if changed.is_empty() {
+        Ok(())
+    } else {
+        Err(changed)
+    }
+}
+
+/// Write the current encoding of all fixtures to `dir`, e.g. to update the
+/// golden files in `fixtures/wire/` after an intentional protocol change.
+pub fn write_to(dir: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::create_dir_all(&dir)?;
+    for fixture in fixtures() {
+        let path = dir.as_ref().join(format!("{}.msgpack", fixture.name));
+        std::fs::write(path, fixture.encoded)?;
+    }
+    Ok(())
+}
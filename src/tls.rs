@@ -0,0 +1,99 @@
+//! `wss://` served by the server itself, see [`crate::ServerConfig::tls`].
+
+use std::{io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use axum::serve::Listener;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        self,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+    server::TlsStream,
+};
+
+use crate::TlsConfig;
+
+/// Clients that didn't finish the handshake by then are disconnected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Load the certificate and key of `config`
+pub(crate) fn acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| pem_error(&config.cert_path, err))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|err| pem_error(&config.key_path, err))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut server_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // WebSocket upgrades need HTTP/1.1
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn pem_error(path: &Path, err: rustls::pki_types::pem::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}: {err}", path.display()),
+    )
+}
+
+/// TCP listener that hands out connections after their TLS handshake.
+/// Handshakes run concurrently, so a slow client doesn't stall the others.
+pub(crate) struct TlsListener {
+    local_addr: SocketAddr,
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub(crate) fn new(mut listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, accepted) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while !sender.is_closed() {
+                // Retries (and logs) failed accepts itself
+                let (stream, addr) = Listener::accept(&mut listener).await;
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(err)) => println!("TLS handshake with {addr} failed: {err}"),
+                        Err(_) => println!("TLS handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            accepted,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(accepted) => accepted,
+            // The accept loop only ends after this listener was dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
@@ -1,15 +1,49 @@
 use axum::{
-    extract::{State, WebSocketUpgrade, ws::WebSocket},
-    http::StatusCode,
+    Json,
+    body::{Body, Bytes},
+    extract::{Path, State, WebSocketUpgrade, ws::WebSocket},
+    http::{HeaderMap, StatusCode, header},
     response::{Html, IntoResponse, Response},
 };
 
-use crate::{AbortReason, ConnectionError, ToolFn};
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Once},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    AbortReason, Capabilities, ConnectionError, JobMeta, JobStoreConfig, PanicDetail, Routes,
+    ServerConfig, ToolCtx, ToolError, Value,
+    artifacts::ArtifactStore,
+    audit::AuditEntry,
+    budget::{self, CpuBudget, MemoryBudget},
+    cache::BlobCache,
+    coalesce::Coalescer,
+    config::Hooks,
+    connection::{
+        Transport,
+        websocket::{MAX_FRAME_SIZE, Message, WsChannelServer, WsTransportAxum},
+    },
+    context::{AbortSignal, SharedTool, next_job_id, random_seed},
+    info::ServerInfo,
+    job_store,
+    jobs::{self, JobRegistry, QueueTicket},
+    limit, sizes,
+    value::{dynamic::Dict, schema::SchemaViolation, structured::Provenance},
+};
 
 #[derive(Clone)]
 pub struct ToolState {
-    pub tool: ToolFn,
+    /// The tool of `/tool` has an empty name
+    pub routes: Arc<Routes>,
     pub index_html: Option<&'static str>,
+    pub config: ServerConfig,
+    pub cache: Arc<BlobCache>,
+    pub artifacts: Arc<ArtifactStore>,
+    pub jobs: Arc<JobRegistry>,
 }
 
 pub async fn index_handler(State(state): State<ToolState>) -> Response {
@@ -19,65 +53,739 @@ pub async fn index_handler(State(state): State<ToolState>) -> Response {
     }
 }
 
-pub async fn socket_handler(ws: WebSocketUpgrade, State(state): State<ToolState>) -> Response {
+pub async fn info_handler(headers: HeaderMap, State(state): State<ToolState>) -> Response {
+    if let Some(rejected) = check_token(&headers, &state.config, "/info") {
+        return rejected;
+    }
+    Json(ServerInfo::new(&state.config, &state.routes)).into_response()
+}
+
+pub async fn artifact_handler(
+    headers: HeaderMap,
+    Path((job_id, name)): Path<(String, String)>,
+    State(state): State<ToolState>,
+) -> Response {
+    let path = format!("/jobs/{job_id}/artifacts/{name}");
+    if let Some(rejected) = check_token(&headers, &state.config, &path) {
+        return rejected;
+    }
+    let Some(data) = state.artifacts.get(&job_id, &name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // Names are restricted to characters that don't need quoting
+    let disposition = format!("attachment; filename=\"{name}\"");
+    let headers = [
+        (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+    ];
+    (headers, Body::from(Bytes::from_owner(data))).into_response()
+}
+
+pub async fn socket_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(state): State<ToolState>,
+) -> Response {
+    serve_route(ws, &headers, state, String::new())
+}
+
+pub async fn route_socket_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    State(state): State<ToolState>,
+) -> Response {
+    // The empty name is `/tool`, it can't be requested as `/tool/`
+    if name.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    serve_route(ws, &headers, state, name)
+}
+
+fn serve_route(
+    ws: WebSocketUpgrade,
+    headers: &HeaderMap,
+    state: ToolState,
+    name: String,
+) -> Response {
+    // Checked first, so that unauthorized clients can't probe for routes
+    let path = match name.is_empty() {
+        true => "/tool".to_string(),
+        false => format!("/tool/{name}"),
+    };
+    if let Some(rejected) = check_token(headers, &state.config, &path) {
+        return rejected;
+    }
+    let Some(route) = state.routes.routes.get(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let tool = route.tool.clone();
+    let mut config = state.config;
+    config.input_schema = route.input_schema.clone();
+    if !name.is_empty() {
+        config.tool_name = Some(name);
+    }
     // print errors to stdout (logged by fly.io, might need explicit logging for other platforms)
-    ws.max_message_size(256 * 1024 * 1024)
-        .max_frame_size(256 * 1024 * 1024)
-        .on_upgrade(async move |socket| {
-            if let Err(err) = tool_handler(socket, state.tool).await {
-                // TODO: we should send the error to the tool as well!
-                println!("ERR {err:?}");
-            }
+    ws.max_message_size(MAX_FRAME_SIZE)
+        .max_frame_size(MAX_FRAME_SIZE)
+        .on_upgrade(async move |socket: WebSocket| {
+            let transport = WsTransportAxum::new(socket).with_heartbeat(config.heartbeat);
+            run_tool(
+                transport,
+                tool,
+                config,
+                state.cache,
+                state.artifacts,
+                state.jobs,
+            )
+            .await
         })
 }
 
-async fn tool_handler(socket: WebSocket, tool: ToolFn) -> Result<(), ConnectionError> {
+/// HTTP 401 if the server has a [`ServerConfig::auth_token`] and `headers`
+/// don't authenticate with it
+fn check_token(headers: &HeaderMap, config: &ServerConfig, path: &str) -> Option<Response> {
+    let token = config.auth_token.as_ref()?;
+    if has_token(headers, token) {
+        return None;
+    }
+    println!("AUTH rejected a request of `{path}`");
+    let challenge = [(header::WWW_AUTHENTICATE, "Bearer")];
+    Some((StatusCode::UNAUTHORIZED, challenge).into_response())
+}
+
+/// Whether `headers` authenticate with `token`. Compares hashes, which takes
+/// the same time no matter how much of the token is right.
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| blake3::hash(given.as_bytes()) == blake3::hash(token.as_bytes()))
+}
+
+/// Serve a single tool call over the given transport, logging errors to stdout.
+pub async fn run_tool(
+    transport: impl Transport,
+    tool: SharedTool,
+    config: ServerConfig,
+    cache: Arc<BlobCache>,
+    artifacts: Arc<ArtifactStore>,
+    jobs: Arc<JobRegistry>,
+) {
+    install_panic_hook();
+    // Wrap the transport in a helper struct
+    let mut ws_server = WsChannelServer::new(transport)
+        .with_compression(config.compression.clone())
+        .with_spool(config.spool_min_size);
+    if config.cache.max_size > 0 {
+        ws_server = ws_server.with_cache(cache);
+    }
+    if let Err(err) = tool_handler(&mut ws_server, tool, config, artifacts, jobs).await {
+        println!("ERR {err:?}");
+        // Tell the client why the call failed, unless the connection is broken
+        if can_reply(&err) {
+            let error = ToolError::Custom(format!("server error: {err}"));
+            if let Err(err) = ws_server.send_error(error).await {
+                println!("ERR failed to send the error to the client: {err:?}");
+            }
+        }
+    }
+}
+
+/// Whether the connection still works after `err`, so that it can be sent to the
+/// client. Not after a protocol mismatch, the client already knows from the hello.
+fn can_reply(err: &ConnectionError) -> bool {
+    matches!(
+        err,
+        ConnectionError::ParseError(_)
+            | ConnectionError::ProtocolViolation(_)
+            | ConnectionError::SpoolError(_)
+            | ConnectionError::ToolPanic(_)
+    )
+}
+
+async fn tool_handler<T: Transport>(
+    ws_server: &mut WsChannelServer<T>,
+    tool: SharedTool,
+    config: ServerConfig,
+    artifacts: Arc<ArtifactStore>,
+    jobs: Arc<JobRegistry>,
+) -> Result<(), ConnectionError> {
     // TODO: would it help the code to split the socket into read and write?
     // https://docs.rs/axum/latest/axum/extract/ws/index.html#read-and-write-concurrently
 
-    // Wrap the socket in a helper struct
-    let mut ws_server = crate::connection::websocket::WsChannelServer::new(socket);
-    // First, read the input from the socket
-    let input = ws_server
+    // First, make sure the client speaks our protocol version and read the input
+    ws_server.handshake().await?;
+    if let Some((job_id, secret, seq)) = ws_server.read_resume().await? {
+        return resume(ws_server, &jobs, &config.job_store, job_id, &secret, seq).await;
+    }
+    let mut input = ws_server
         .read_input()
         .await?
         .ok_or(ConnectionError::ConnectionClosed)?;
+    let started = Instant::now();
+    let job_id = next_job_id();
+    let job = jobs.register(&job_id);
+    println!("JOB {job_id}");
+    // Clients that can resume the call find its result in the job store
+    let _log = match ws_server
+        .client_capabilities()
+        .contains(Capabilities::RESUME)
+        && !config.job_store.retention.is_zero()
+    {
+        true => {
+            let secret = jobs::new_secret();
+            let secret_hash = jobs::secret_hash(&secret);
+            let log = jobs.track(&job_id, secret_hash, config.job_store.max_log_size);
+            ws_server.with_log(log.log());
+            ws_server.send_accepted(job_id.clone(), secret).await?;
+            Some(log)
+        }
+        false => None,
+    };
     println!("IN  {input:?}");
+    if config.log_sizes {
+        sizes::log_composition("in", &input);
+    }
+    let store = config.job_store.clone();
+    // The entry of the client is neither part of the input the tool declares
+    // nor of its hash, an invalid one fails once the call is audited
+    let timeout = budget::take_timeout(&mut input, config.timeout);
+    // Hashed once as received, before the server changes the input. On a
+    // blocking thread, large inputs would stall other connections otherwise.
+    let input_hash = match config.provenance || config.audit.is_some() {
+        true => {
+            let (hashed, hash) = tokio::task::spawn_blocking(move || {
+                let hash = input.canonical_hash();
+                (input, hash)
+            })
+            .await?;
+            input = hashed;
+            Some(hex(&hash))
+        }
+        false => None,
+    };
+    let audit = Option::zip(config.audit.as_ref(), input_hash.clone())
+        .map(|(audit, input_hash)| AuditEntry::start(audit, &config, &input, input_hash));
+    let timeout = match timeout {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            println!("ERR {err}");
+            let meta = job_meta(job_id, config.tool_version, started, None);
+            return finish(ws_server, &store, audit, Err(err), meta).await;
+        }
+    };
+    // Invalid inputs are rejected before a blocking thread is spawned
+    if let Some(schema) = &config.input_schema
+        && let Err(err) = schema.validate(&input)
+    {
+        println!("ERR {err}");
+        let meta = job_meta(job_id, config.tool_version, started, None);
+        return finish(ws_server, &store, audit, Err(err), meta).await;
+    }
+    if let Err(violations) = config.float_policy.apply(&mut input) {
+        let err = ToolError::invalid_input(
+            violations[0].path.clone(),
+            SchemaViolation::summary(&violations),
+        )
+        .with_details(SchemaViolation::details(&violations));
+        println!("ERR {err}");
+        let meta = job_meta(job_id, config.tool_version, started, None);
+        return finish(ws_server, &store, audit, Err(err), meta).await;
+    }
+    let seed = ws_server.seed().unwrap_or_else(random_seed);
+    let input_hash = input_hash.filter(|_| config.provenance);
+    println!("SEED {seed}");
+    // Held by the tool thread until it ends
+    let Some(slot) = wait_for_slot(ws_server, &jobs, &job_id).await? else {
+        let err = ToolError::Abort(AbortReason::RequestedByClient);
+        println!("ERR {err}");
+        let meta = job_meta(job_id, config.tool_version, started, None);
+        return finish(ws_server, &store, audit, Err(err), meta).await;
+    };
+    let started_at = SystemTime::now();
     // Channel for sending messages to the client and abort signal back
-    let (mut msg_tx, mut msg_rx) = crate::connection::channel::connect();
+    let abort = AbortSignal::new();
+    let (mut msg_tx, mut msg_rx) =
+        crate::connection::channel::connect(abort.clone(), config.buffer_policy);
+    let mut progress_tx = msg_tx.clone();
+    let mut output_tx = msg_tx.clone();
+    let mut partial_tx = msg_tx.clone();
     // Run the tool, give it the input and the channel to send messages
     let mut send_msg = move |msg| {
         println!(" > {msg}");
         msg_tx.send(msg)
     };
-    let result = tokio::task::spawn_blocking(move || tool(input, &mut send_msg));
+    let mut send_progress = move |fraction, message| progress_tx.send_progress(fraction, message);
+    let mut send_output = move |name: String, value| {
+        println!(" > EMIT {name}");
+        output_tx.emit(name, value)
+    };
+    let mut send_partial = move |partial: Dict| {
+        println!(" > PARTIAL {}", partial.0.len());
+        partial_tx.send_partial(partial)
+    };
+    let tool = with_hooks(tool, &config.hooks);
+    let tool_job_id = job_id.clone();
+    // Started by the tool thread, which the server can't measure otherwise
+    let cpu = config.max_cpu_time.map(CpuBudget::new);
+    let tool_cpu = cpu.clone();
+    // Counts the allocations of the tool thread if `JobAllocator` is installed
+    let memory = config.max_job_memory.and_then(|max| {
+        let budget = MemoryBudget::new(max);
+        if budget.is_none() {
+            println!("LIMIT max_job_memory can't be measured for this call, ignored");
+        }
+        budget
+    });
+    let tool_memory = memory.clone();
+    // Clients without these capabilities get progress and outputs as regular messages
+    let client = ws_server.client_capabilities().clone();
+    let result = tokio::task::spawn_blocking(move || {
+        // Also if the handler abandons the tool after the abort grace period,
+        // a thread that keeps running still occupies its slot
+        let _slot = slot;
+        let mut ctx = ToolCtx::new(tool_job_id, &mut send_msg, abort)
+            .with_artifacts(artifacts)
+            .with_operator_config(config.operator_config)
+            .with_seed(seed);
+        if client.contains(Capabilities::PROGRESS) {
+            ctx = ctx.with_progress(&mut send_progress);
+        }
+        if client.contains(Capabilities::EMIT) {
+            ctx = ctx.with_outputs(&mut send_output);
+        }
+        if client.contains(Capabilities::PARTIAL) {
+            ctx = ctx.with_partials(&mut send_partial);
+        }
+        if let Some(cpu) = &tool_cpu
+            && !cpu.start()
+        {
+            println!("LIMIT max_cpu_time is not supported on this platform, ignored");
+        }
+        let _counting = tool_memory.as_ref().map(MemoryBudget::start);
+        // The blocking thread is reused, so only the difference is meaningful
+        let cpu_start = thread_cpu_time();
+        let result = run_catching(&tool, input, &mut ctx, config.panic_detail);
+        let cpu_time = Option::zip(cpu_start, thread_cpu_time()).map(|(a, b)| b - a);
+        (result, cpu_time)
+    });
+
+    // Checked while the tool runs, limits are ignored where they can't be measured
+    let mut budget_poll = tokio::time::interval(budget::POLL_INTERVAL);
+    let timeout_at = timeout.map(|timeout| Instant::now() + timeout);
 
     // Run a loop which forwards tool messages to the client or abort messages to the tool
+    let mut aborted = None;
+    let mut coalescer = Coalescer::new(config.coalesce_interval);
     loop {
+        let deadline = coalescer.deadline();
         // WARN: axum does not document this - we assume WebSocket.send() and .recv() is cancel safe
-        // TODO: tool thread should have a timeout!
         tokio::select! {
             tool_msg = msg_rx.recv() => {
+                job.touch();
                 match tool_msg {
-                    Some(msg) => ws_server.send_message(msg).await?,
+                    Some(msg) => forward(ws_server, coalescer.push(msg)).await?,
                     None => break,  // msg_rx was closed: tool no longer running
                 }
             },
-            aborted = ws_server.read_abort() => {
-                if aborted?.is_some() {
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                forward(ws_server, coalescer.flush()).await?
+            },
+            abort = ws_server.read_abort() => {
+                if abort?.is_some() {
+                    job.touch();
                     msg_rx.abort(AbortReason::RequestedByClient);
+                    aborted = Some(AbortReason::RequestedByClient);
+                    break;
+                }
+            }
+            _ = budget_poll.tick(), if memory.is_some() || cpu.is_some() => {
+                let exceeded = Option::or(
+                    memory.as_ref().and_then(|memory| memory.check().err()),
+                    cpu.as_ref().and_then(|cpu| cpu.check().err()),
+                );
+                if let Some(message) = exceeded {
+                    println!("LIMIT {job_id} {message}, aborted");
+                    let reason = AbortReason::ResourceExhausted(message);
+                    msg_rx.abort(reason.clone());
+                    aborted = Some(reason);
                     break;
                 }
             }
+            _ = tokio::time::sleep_until(timeout_at.unwrap_or_else(Instant::now).into()), if timeout_at.is_some() => {
+                let timeout = timeout.unwrap_or_default();
+                let message = format!("the call took longer than {:.1} s", timeout.as_secs_f64());
+                println!("LIMIT {job_id} {message}, aborted");
+                let reason = AbortReason::Timeout(message);
+                msg_rx.abort(reason.clone());
+                aborted = Some(reason);
+                break;
+            }
         }
     }
+    forward(ws_server, coalescer.flush()).await?;
 
-    // Wait for tool completion and collect result - panics if tool panicked
-    let result = result.await?;
+    // Wait for tool completion and collect result - panics if tool panicked.
+    // After an abort, the tool only gets the grace period to notice it.
+    let (result, cpu_time) = match (config.abort_grace_period, &aborted) {
+        (Some(grace_period), Some(reason)) => {
+            match tokio::time::timeout(grace_period, result).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    println!(
+                        "STUCK {job_id} still running {grace_period:?} after the abort, abandoned"
+                    );
+                    (Err(ToolError::Abort(reason.clone())), None)
+                }
+            }
+        }
+        _ => result.await?,
+    };
+    // Whatever the tool returned after exceeding a limit of the server
+    let result = match aborted {
+        Some(AbortReason::ResourceExhausted(message)) => {
+            Err(ToolError::resource_exhausted(message))
+        }
+        Some(AbortReason::Timeout(message)) => Err(ToolError::timeout(message)),
+        _ => result,
+    };
+    let result = result.and_then(|mut value| match config.float_policy.apply(&mut value) {
+        Ok(()) => Ok(value),
+        Err(violations) => {
+            let paths: Vec<String> = violations.iter().map(|v| format!("`{}`", v.path)).collect();
+            Err(ToolError::Internal {
+                message: format!("result has NaN or infinite floats in {}", paths.join(", ")),
+                details: Some(SchemaViolation::details(&violations)),
+            })
+        }
+    });
+    let result = match input_hash {
+        Some(input_hash) => result.map(|value| {
+            let provenance = Provenance {
+                tool: config.tool_name,
+                version: config.tool_version.clone(),
+                input_hash,
+                started: started_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+                duration: started.elapsed().as_secs_f64(),
+                host: hostname(),
+            };
+            with_provenance(value, provenance)
+        }),
+        None => result,
+    };
+    let (result, truncated) = match config.max_output_size {
+        Some(max_size) => limit::limit_output(result, max_size, config.output_policy),
+        None => (result, Vec::new()),
+    };
     match &result {
-        Ok(value) => println!("OUT {value:?}"),
+        Ok(value) => {
+            println!("OUT {value:?}");
+            if config.log_sizes {
+                sizes::log_composition("out", value);
+            }
+        }
         Err(err) => println!("ERR {err}"),
     }
     // Return the output to the client
-    ws_server.send_output(result).await
+    let mut meta = job_meta(job_id, config.tool_version, started, cpu_time);
+    meta.truncated = truncated;
+    meta.seed = Some(seed);
+    finish(ws_server, &store, audit, result, meta).await
+}
+
+/// Send the result to the client, after recording it for audits and
+/// keeping it in the job store
+async fn finish<T: Transport>(
+    ws_server: &mut WsChannelServer<T>,
+    store: &JobStoreConfig,
+    audit: Option<AuditEntry>,
+    mut result: Result<Value, ToolError>,
+    meta: JobMeta,
+) -> Result<(), ConnectionError> {
+    if let Some(audit) = audit {
+        // On a blocking thread like the hash of the input
+        let (hashed, output_hash) = tokio::task::spawn_blocking(move || {
+            let hash = result
+                .as_ref()
+                .ok()
+                .map(|value| hex(&value.canonical_hash()));
+            (result, hash)
+        })
+        .await?;
+        result = hashed;
+        audit.finish(&result, output_hash, &meta);
+    }
+    // Only resumable calls are kept, their clients look for the result once
+    // the log is finished
+    let kept = ws_server
+        .log()
+        .and_then(|log| job_store::keep(store, &result, &meta, log.secret_hash()));
+    let sent = ws_server.send_output(result, meta).await;
+    if let Some(kept) = kept {
+        let _ = kept.await;
+    }
+    sent
+}
+
+/// Continue a call whose connection dropped: send the stamped messages from
+/// `seq` on while it runs, then its result from the job store.
+async fn resume<T: Transport>(
+    ws_server: &mut WsChannelServer<T>,
+    jobs: &JobRegistry,
+    store: &JobStoreConfig,
+    job_id: String,
+    secret: &str,
+    mut seq: u64,
+) -> Result<(), ConnectionError> {
+    println!("RESUME {job_id} from message {seq}");
+    // Jobs of other clients look like unknown ones
+    let log = jobs.log(&job_id, secret);
+    if let Some(log) = &log {
+        log.ack(seq);
+        ws_server.follow(log.clone());
+        let mut changed = log.subscribe();
+        loop {
+            let (messages, finished) = log.since(seq);
+            for msg in &messages {
+                ws_server.replay(msg).await?;
+                seq += 1;
+            }
+            if finished {
+                break;
+            }
+            tokio::select! {
+                _ = changed.changed() => {},
+                abort = ws_server.read_abort() => {
+                    if abort?.is_some() {
+                        println!("RESUME {job_id} aborted by the client");
+                        log.request_abort();
+                    }
+                }
+            }
+        }
+    }
+    let kept = {
+        let (store, job_id) = (store.store.clone(), job_id.clone());
+        tokio::task::spawn_blocking(move || store.get(&job_id)).await?
+    };
+    let job = kept
+        .unwrap_or_else(|err| {
+            println!("STORE {job_id} failed to read the result: {err}");
+            None
+        })
+        .filter(|job| {
+            job.secret_hash
+                .is_some_and(|hash| jobs::secret_matches(secret, &hash))
+        });
+    match job {
+        // The JobMeta was logged with the other messages
+        Some(job) if log.is_some() => ws_server.send_result(job.output).await,
+        Some(job) => {
+            ws_server.resume_at(seq);
+            ws_server.send_output(job.output, job.meta).await
+        }
+        None => {
+            let err = ToolError::Custom(format!("job {job_id} is unknown or expired"));
+            println!("ERR {err}");
+            ws_server.send_error(err).await
+        }
+    }
+}
+
+/// Wait for a slot of [`ServerConfig::max_concurrent_tools`], telling the
+/// client its position in the queue. `None` if the client aborted meanwhile.
+async fn wait_for_slot<T: Transport>(
+    ws_server: &mut WsChannelServer<T>,
+    jobs: &Arc<JobRegistry>,
+    job_id: &str,
+) -> Result<Option<QueueTicket>, ConnectionError> {
+    let mut ticket = jobs.enqueue();
+    let mut reported = None;
+    loop {
+        let Some(position) = ticket.position() else {
+            return Ok(Some(ticket));
+        };
+        if reported != Some(position) {
+            println!("QUEUE {job_id} {position} calls ahead");
+            ws_server
+                .send_queued(u32::try_from(position).unwrap_or(u32::MAX))
+                .await?;
+            reported = Some(position);
+        }
+        tokio::select! {
+            _ = ticket.changed() => {},
+            abort = ws_server.read_abort() => {
+                if abort?.is_some() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Send messages of the tool to the client
+async fn forward<T: Transport>(
+    ws_server: &mut WsChannelServer<T>,
+    messages: Vec<Message>,
+) -> Result<(), ConnectionError> {
+    for msg in messages {
+        match msg {
+            Message::ToolMsg(msg) => ws_server.send_message(msg).await?,
+            Message::Progress { fraction, message } => {
+                ws_server.send_progress(fraction, message).await?
+            }
+            Message::Emit { name, value } => ws_server.send_emitted(name, value).await?,
+            Message::PartialResult(partial) => ws_server.send_partial(partial).await?,
+            _ => {
+                unreachable!("the tool only sends messages, progress, outputs and partial results")
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The sizes are filled in by [`WsChannelServer::send_output`]
+fn job_meta(
+    job_id: String,
+    tool_version: Option<String>,
+    started: Instant,
+    cpu_time: Option<Duration>,
+) -> JobMeta {
+    JobMeta {
+        job_id,
+        tool_version,
+        wall_time: started.elapsed(),
+        cpu_time,
+        input_size: 0,
+        output_size: 0,
+        truncated: Vec::new(),
+        seed: None,
+    }
+}
+
+/// Apply the configured hooks around `tool`, inside of the panic handling
+fn with_hooks(tool: SharedTool, hooks: &Hooks) -> SharedTool {
+    if hooks.is_empty() {
+        return tool;
+    }
+    let hooks = hooks.clone();
+    Arc::new(move |input, ctx| {
+        let input = hooks.apply_input(input)?;
+        hooks.apply_output(tool(input, ctx)?)
+    })
+}
+
+/// CPU time consumed by the current thread so far, if the platform provides it
+fn thread_cpu_time() -> Option<Duration> {
+    #[cfg(unix)]
+    {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: the pointer is valid for writes for the duration of the call
+        let ret = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+        if ret == 0 {
+            return Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32));
+        }
+    }
+    None
+}
+
+/// Only Dict results get a provenance, see [`ServerConfig::provenance`]
+fn with_provenance(value: Value, provenance: Provenance) -> Value {
+    match value {
+        Value::Dict(mut dict) => {
+            dict.0
+                .insert("provenance".to_string(), Value::Provenance(provenance));
+            Value::Dict(dict)
+        }
+        value => value,
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Name of this machine, empty if unknown
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        // SAFETY: the buffer is valid for writes of its length
+        let ret = unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) };
+        if ret == 0 {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..len]).into_owned();
+        }
+    }
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_default()
+}
+
+/// Filled by the panic hook with the report of the last panic on this thread
+struct PanicReport {
+    message: String,
+    location: String,
+    backtrace: Backtrace,
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// Record panic reports for [`run_catching`], the previous hook still runs.
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let report = PanicReport {
+                message: info.payload_as_str().unwrap_or("Box<dyn Any>").to_string(),
+                location: info.location().map(|l| l.to_string()).unwrap_or_default(),
+                backtrace: Backtrace::force_capture(),
+            };
+            LAST_PANIC.with(|last| *last.borrow_mut() = Some(report));
+            previous(info);
+        }));
+    });
+}
+
+/// Run the tool, converting a panic into a [`ToolError::Internal`] that
+/// contains as much information as allowed by `detail`.
+fn run_catching(
+    tool: &SharedTool,
+    input: Value,
+    ctx: &mut ToolCtx,
+    detail: PanicDetail,
+) -> Result<Value, ToolError> {
+    let panic = match std::panic::catch_unwind(AssertUnwindSafe(|| tool(input, ctx))) {
+        Ok(result) => return result,
+        Err(_) => LAST_PANIC.with(|last| last.borrow_mut().take()),
+    };
+    let Some(report) = panic else {
+        return Err(ToolError::internal("tool panicked"));
+    };
+
+    println!(
+        "PANIC at {}: {}\n{}",
+        report.location, report.message, report.backtrace
+    );
+    let message = format!("tool panicked at {}: {}", report.location, report.message);
+    Err(match detail {
+        PanicDetail::Hidden => ToolError::internal("tool panicked"),
+        PanicDetail::Message => ToolError::internal(message),
+        PanicDetail::Backtrace => {
+            let backtrace = Value::Str(report.backtrace.to_string());
+            ToolError::internal(message)
+                .with_details(Dict([("backtrace".to_string(), backtrace)].into()))
+        }
+    })
 }
@@ -0,0 +1,140 @@
+//! Chunk-wise iteration over [`Volume`]s, e.g. to process a large volume slab
+//! by slab and send every slab as soon as it is done.
+//!
+//! A chunk is a sub-volume of consecutive slices along one axis. Its affine
+//! is the one of the volume, translated to the first slice of the chunk, so
+//! voxels keep their world coordinates.
+
+use std::ops::Range;
+
+use super::{structured::Volume, typed::TypedList};
+use crate::ToolError;
+
+impl Volume {
+    /// Sub-volumes of `n` slices along `axis` (0, 1 or 2 for x, y and z),
+    /// the last one holds the remaining slices. Chunks are copied lazily
+    /// while iterating. Fails if `axis` or `n` are invalid.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::Volume, typed::TypedList}};
+    /// let volume = Volume::new(
+    ///     [2, 3, 1],
+    ///     [[2.0, 0.0, 0.0, -1.0], [0.0, 2.0, 0.0, -2.0], [0.0, 0.0, 2.0, 0.0]],
+    ///     TypedList::Int((0..6).collect()),
+    /// ).unwrap();
+    /// let chunks: Vec<Volume> = volume.chunks(1, 2)?.collect();
+    /// assert_eq!(chunks.len(), 2);
+    /// assert_eq!(chunks[1].shape(), [2, 1, 1]);
+    /// // The second chunk starts at y = 2, which is 2 · 2 mm further along y
+    /// assert_eq!(chunks[1].affine()[1][3], 2.0);
+    /// let TypedList::Int(data) = chunks[1].data() else { unreachable!() };
+    /// assert_eq!(data, &[4, 5]);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn chunks(&self, axis: usize, n: usize) -> Result<Chunks<'_>, ToolError> {
+        if axis > 2 {
+            return Err(ToolError::invalid_input(
+                "axis",
+                format!("axis must be 0, 1 or 2, got {axis}"),
+            ));
+        }
+        if n == 0 {
+            return Err(ToolError::invalid_input(
+                "n",
+                "chunks must have at least one slice",
+            ));
+        }
+        Ok(Chunks {
+            volume: self,
+            axis,
+            n,
+            start: 0,
+        })
+    }
+}
+
+/// Iterator returned by [`Volume::chunks`]
+pub struct Chunks<'a> {
+    volume: &'a Volume,
+    axis: usize,
+    n: usize,
+    start: usize,
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = Volume;
+
+    fn next(&mut self) -> Option<Volume> {
+        let [nx, ny, nz] = self.volume.shape.map(|n| n as usize);
+        let len = [nx, ny, nz][self.axis];
+        if self.start >= len {
+            return None;
+        }
+        let slices = self.start..(self.start + self.n).min(len);
+        self.start = slices.end;
+
+        // Voxels are x-fastest, so a chunk is a set of contiguous runs:
+        // parts of rows for x, parts of planes for y and one run for z
+        let (runs, stride, run) = match self.axis {
+            0 => (ny * nz, nx, slices.clone()),
+            1 => (nz, nx * ny, nx * slices.start..nx * slices.end),
+            _ => (1, 0, nx * ny * slices.start..nx * ny * slices.end),
+        };
+        let ranges: Vec<Range<usize>> = (0..runs)
+            .map(|i| i * stride + run.start..i * stride + run.end)
+            .collect();
+
+        let mut shape = self.volume.shape;
+        shape[self.axis] = slices.len() as u64;
+        let mut affine = self.volume.affine;
+        for row in &mut affine {
+            row[3] += row[self.axis] * slices.start as f64;
+        }
+        Some(Volume {
+            shape,
+            affine,
+            data: self.volume.data.gather(&ranges),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.volume.shape[self.axis] as usize;
+        let remaining = len.saturating_sub(self.start).div_ceil(self.n);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Chunks<'_> {}
+
+impl TypedList {
+    /// The elements in `ranges`, concatenated
+    fn gather(&self, ranges: &[Range<usize>]) -> TypedList {
+        fn gather<T: Clone>(items: &[T], ranges: &[Range<usize>]) -> Vec<T> {
+            ranges
+                .iter()
+                .flat_map(|range| items[range.clone()].iter().cloned())
+                .collect()
+        }
+        match self {
+            TypedList::None(items) => TypedList::None(gather(items, ranges)),
+            TypedList::Bool(items) => TypedList::Bool(gather(items, ranges)),
+            TypedList::Int(items) => TypedList::Int(gather(items, ranges)),
+            TypedList::Float(items) => TypedList::Float(gather(items, ranges)),
+            #[cfg(feature = "half")]
+            TypedList::Half(items) => TypedList::Half(gather(items, ranges)),
+            TypedList::Complex(items) => TypedList::Complex(gather(items, ranges)),
+            TypedList::Vec3(items) => TypedList::Vec3(gather(items, ranges)),
+            TypedList::Vec4(items) => TypedList::Vec4(gather(items, ranges)),
+            TypedList::Quat(items) => TypedList::Quat(gather(items, ranges)),
+            TypedList::Str(items) => TypedList::Str(gather(items, ranges)),
+            TypedList::Bytes(items) => TypedList::Bytes(gather(items, ranges)),
+            TypedList::InstantSeqEvent(items) => TypedList::InstantSeqEvent(gather(items, ranges)),
+            TypedList::Volume(items) => TypedList::Volume(gather(items, ranges)),
+            TypedList::SegmentedPhantom(items) => {
+                TypedList::SegmentedPhantom(gather(items, ranges))
+            }
+            TypedList::PhantomTissue(items) => TypedList::PhantomTissue(gather(items, ranges)),
+            TypedList::Provenance(items) => TypedList::Provenance(gather(items, ranges)),
+        }
+    }
+}
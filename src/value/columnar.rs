@@ -0,0 +1,151 @@
+//! Compact serde representation of numeric [`super::typed::TypedList`]s.
+//!
+//! Instead of a sequence with one entry per element, the list is serialized
+//! as a single byte string containing the raw little-endian numbers (length
+//! prefixed by msgpack). This is much faster to encode and decode and avoids
+//! the per-element type markers for lists with millions of samples.
+//!
+//! Used as `#[serde(with = "columnar")]` on the `TypedList` variants.
+
+use std::fmt;
+
+use num_complex::Complex64;
+use serde::{
+    Deserializer, Serializer,
+    de::{Error, SeqAccess, Visitor},
+};
+
+use super::atomic::{Quat, Vec3, Vec4};
+
+/// Element type with a fixed size little-endian byte representation.
+pub trait Columnar: Sized {
+    const SIZE: usize;
+    /// `out` and `bytes` are exactly [`Self::SIZE`] long
+    fn write(&self, out: &mut [u8]);
+    fn read(bytes: &[u8]) -> Self;
+}
+
+fn write_f64s(values: &[f64], out: &mut [u8]) {
+    for (x, out) in values.iter().zip(out.chunks_exact_mut(8)) {
+        out.copy_from_slice(&x.to_le_bytes());
+    }
+}
+
+fn read_f64s<const N: usize>(bytes: &[u8]) -> [f64; N] {
+    std::array::from_fn(|i| f64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap()))
+}
+
+impl Columnar for i64 {
+    const SIZE: usize = 8;
+    fn write(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+    fn read(bytes: &[u8]) -> Self {
+        i64::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl Columnar for f64 {
+    const SIZE: usize = 8;
+    fn write(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+    fn read(bytes: &[u8]) -> Self {
+        f64::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+#[cfg(feature = "half")]
+impl Columnar for half::f16 {
+    const SIZE: usize = 2;
+    fn write(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+    fn read(bytes: &[u8]) -> Self {
+        half::f16::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl Columnar for Complex64 {
+    const SIZE: usize = 16;
+    fn write(&self, out: &mut [u8]) {
+        write_f64s(&[self.re, self.im], out);
+    }
+    fn read(bytes: &[u8]) -> Self {
+        let [re, im] = read_f64s(bytes);
+        Complex64::new(re, im)
+    }
+}
+
+impl Columnar for Vec3 {
+    const SIZE: usize = 24;
+    fn write(&self, out: &mut [u8]) {
+        write_f64s(&self.0, out);
+    }
+    fn read(bytes: &[u8]) -> Self {
+        Vec3(read_f64s(bytes))
+    }
+}
+
+impl Columnar for Vec4 {
+    const SIZE: usize = 32;
+    fn write(&self, out: &mut [u8]) {
+        write_f64s(&self.0, out);
+    }
+    fn read(bytes: &[u8]) -> Self {
+        Vec4(read_f64s(bytes))
+    }
+}
+
+impl Columnar for Quat {
+    const SIZE: usize = 32;
+    fn write(&self, out: &mut [u8]) {
+        write_f64s(&self.0, out);
+    }
+    fn read(bytes: &[u8]) -> Self {
+        Quat(read_f64s(bytes))
+    }
+}
+
+pub fn serialize<T: Columnar, S: Serializer>(
+    values: &[T],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut bytes = vec![0; values.len() * T::SIZE];
+    for (x, out) in values.iter().zip(bytes.chunks_exact_mut(T::SIZE)) {
+        x.write(out);
+    }
+    serializer.serialize_bytes(&bytes)
+}
+
+pub fn deserialize<'de, T: Columnar, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<T>, D::Error> {
+    deserializer.deserialize_bytes(ColumnarVisitor(std::marker::PhantomData))
+}
+
+struct ColumnarVisitor<T>(std::marker::PhantomData<T>);
+
+impl<'de, T: Columnar> Visitor<'de> for ColumnarVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte string with a multiple of {} bytes", T::SIZE)
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        if !bytes.len().is_multiple_of(T::SIZE) {
+            return Err(E::invalid_length(bytes.len(), &self));
+        }
+        Ok(bytes.chunks_exact(T::SIZE).map(T::read).collect())
+    }
+
+    // Formats without native byte strings serialize them as sequence of `u8`
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
@@ -0,0 +1,198 @@
+//! Conversion between dynamic ([`List`], [`Dict`]) and typed ([`TypedList`],
+//! [`TypedDict`]) containers. Clients often can't choose which one they send
+//! (e.g. a Python list of floats), so tools normalize with one call.
+
+use std::collections::HashMap;
+
+use super::{
+    Value,
+    dynamic::{Dict, List},
+    extract::value_variant_name,
+    typed::{TypedDict, TypedList},
+};
+use crate::ExtractionError;
+
+impl List {
+    /// Succeeds if all elements have the same type that a [`TypedList`] can
+    /// store, fails for empty lists since their element type is unknown.
+    ///
+    /// ```
+    /// # use toolapi::{Value, ExtractionError, value::{dynamic::List, typed::TypedList}};
+    /// let list = List(vec![Value::Float(0.5), Value::Float(1.0)]);
+    /// let TypedList::Float(data) = list.try_into_typed()? else { unreachable!() };
+    /// assert_eq!(data, [0.5, 1.0]);
+    ///
+    /// let mixed = List(vec![Value::Float(0.5), Value::Int(1)]);
+    /// assert!(mixed.try_into_typed().is_err());
+    /// # Ok::<(), ExtractionError>(())
+    /// ```
+    pub fn try_into_typed(self) -> Result<TypedList, ExtractionError> {
+        let Some(first) = self.0.first() else {
+            return Err(empty("List", "TypedList"));
+        };
+        macro_rules! collect {
+            ($variant:ident) => {
+                self.0
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::$variant(x) => Ok(x),
+                        other => Err(mismatch(
+                            &other,
+                            concat!("TypedList::", stringify!($variant)),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(TypedList::$variant)
+            };
+        }
+        match first {
+            Value::None(_) => collect!(None),
+            Value::Bool(_) => collect!(Bool),
+            Value::Int(_) => collect!(Int),
+            Value::Float(_) => collect!(Float),
+            Value::Str(_) => collect!(Str),
+            Value::Bytes(_) => collect!(Bytes),
+            Value::Complex(_) => collect!(Complex),
+            Value::Vec3(_) => collect!(Vec3),
+            Value::Vec4(_) => collect!(Vec4),
+            Value::Quat(_) => collect!(Quat),
+            Value::InstantSeqEvent(_) => collect!(InstantSeqEvent),
+            Value::Volume(_) => collect!(Volume),
+            Value::SegmentedPhantom(_) => collect!(SegmentedPhantom),
+            Value::PhantomTissue(_) => collect!(PhantomTissue),
+            Value::Provenance(_) => collect!(Provenance),
+            Value::AttachmentRef(_)
+            | Value::Dict(_)
+            | Value::List(_)
+            | Value::TypedDict(_)
+            | Value::TypedList(_) => Err(mismatch(first, "TypedList")),
+        }
+    }
+}
+
+impl TypedList {
+    /// Every element as a [`Value`], `Half`s become `Float`s
+    pub fn into_list(self) -> List {
+        fn wrap<T>(items: Vec<T>, variant: fn(T) -> Value) -> List {
+            List(items.into_iter().map(variant).collect())
+        }
+        match self {
+            TypedList::None(items) => wrap(items, Value::None),
+            TypedList::Bool(items) => wrap(items, Value::Bool),
+            TypedList::Int(items) => wrap(items, Value::Int),
+            TypedList::Float(items) => wrap(items, Value::Float),
+            #[cfg(feature = "half")]
+            TypedList::Half(items) => wrap(items, |x| Value::Float(x.to_f64())),
+            TypedList::Str(items) => wrap(items, Value::Str),
+            TypedList::Bytes(items) => wrap(items, Value::Bytes),
+            TypedList::Complex(items) => wrap(items, Value::Complex),
+            TypedList::Vec3(items) => wrap(items, Value::Vec3),
+            TypedList::Vec4(items) => wrap(items, Value::Vec4),
+            TypedList::Quat(items) => wrap(items, Value::Quat),
+            TypedList::InstantSeqEvent(items) => wrap(items, Value::InstantSeqEvent),
+            TypedList::Volume(items) => wrap(items, Value::Volume),
+            TypedList::SegmentedPhantom(items) => wrap(items, Value::SegmentedPhantom),
+            TypedList::PhantomTissue(items) => wrap(items, Value::PhantomTissue),
+            TypedList::Provenance(items) => wrap(items, Value::Provenance),
+        }
+    }
+}
+
+impl Dict {
+    /// Like [`List::try_into_typed`], succeeds if all values have the same type
+    ///
+    /// ```
+    /// # use toolapi::{Value, ExtractionError, value::{dynamic::Dict, typed::TypedDict}};
+    /// let dict = Dict([("t1".to_string(), Value::Float(1.2))].into());
+    /// let TypedDict::Float(t1) = dict.try_into_typed()? else { unreachable!() };
+    /// assert_eq!(t1["t1"], 1.2);
+    /// # Ok::<(), ExtractionError>(())
+    /// ```
+    pub fn try_into_typed(self) -> Result<TypedDict, ExtractionError> {
+        let Some(first) = self.0.values().next() else {
+            return Err(empty("Dict", "TypedDict"));
+        };
+        macro_rules! collect {
+            ($variant:ident) => {
+                self.0
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        Value::$variant(x) => Ok((key, x)),
+                        other => Err(mismatch(
+                            &other,
+                            concat!("TypedDict::", stringify!($variant)),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(TypedDict::$variant)
+            };
+        }
+        match first {
+            Value::None(_) => collect!(None),
+            Value::Bool(_) => collect!(Bool),
+            Value::Int(_) => collect!(Int),
+            Value::Float(_) => collect!(Float),
+            Value::Str(_) => collect!(Str),
+            Value::Bytes(_) => collect!(Bytes),
+            Value::Complex(_) => collect!(Complex),
+            Value::Vec3(_) => collect!(Vec3),
+            Value::Vec4(_) => collect!(Vec4),
+            Value::Quat(_) => collect!(Quat),
+            Value::InstantSeqEvent(_) => collect!(InstantSeqEvent),
+            Value::Volume(_) => collect!(Volume),
+            Value::SegmentedPhantom(_) => collect!(SegmentedPhantom),
+            Value::PhantomTissue(_) => collect!(PhantomTissue),
+            Value::Provenance(_) => collect!(Provenance),
+            Value::AttachmentRef(_)
+            | Value::Dict(_)
+            | Value::List(_)
+            | Value::TypedDict(_)
+            | Value::TypedList(_) => Err(mismatch(first, "TypedDict")),
+        }
+    }
+}
+
+impl TypedDict {
+    /// Every value as a [`Value`]
+    pub fn into_dict(self) -> Dict {
+        fn wrap<T>(items: HashMap<String, T>, variant: fn(T) -> Value) -> Dict {
+            Dict(
+                items
+                    .into_iter()
+                    .map(|(key, x)| (key, variant(x)))
+                    .collect(),
+            )
+        }
+        match self {
+            TypedDict::None(items) => wrap(items, Value::None),
+            TypedDict::Bool(items) => wrap(items, Value::Bool),
+            TypedDict::Int(items) => wrap(items, Value::Int),
+            TypedDict::Float(items) => wrap(items, Value::Float),
+            TypedDict::Str(items) => wrap(items, Value::Str),
+            TypedDict::Bytes(items) => wrap(items, Value::Bytes),
+            TypedDict::Complex(items) => wrap(items, Value::Complex),
+            TypedDict::Vec3(items) => wrap(items, Value::Vec3),
+            TypedDict::Vec4(items) => wrap(items, Value::Vec4),
+            TypedDict::Quat(items) => wrap(items, Value::Quat),
+            TypedDict::InstantSeqEvent(items) => wrap(items, Value::InstantSeqEvent),
+            TypedDict::Volume(items) => wrap(items, Value::Volume),
+            TypedDict::SegmentedPhantom(items) => wrap(items, Value::SegmentedPhantom),
+            TypedDict::PhantomTissue(items) => wrap(items, Value::PhantomTissue),
+            TypedDict::Provenance(items) => wrap(items, Value::Provenance),
+        }
+    }
+}
+
+fn mismatch(value: &Value, into: &str) -> ExtractionError {
+    ExtractionError::TypeMismatch {
+        from: value_variant_name(value).to_string(),
+        into: into.to_string(),
+    }
+}
+
+fn empty(from: &str, into: &str) -> ExtractionError {
+    ExtractionError::TypeMismatch {
+        from: format!("empty {from}"),
+        into: into.to_string(),
+    }
+}
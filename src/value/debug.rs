@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 
 use crate::value::{
-    Value, dynamic::{Dict, List}, typed::{TypedDict, TypedList}
+    Value,
+    dynamic::{Dict, List},
+    typed::{TypedDict, TypedList},
 };
 
 impl Debug for Value {
@@ -13,13 +15,16 @@ impl Debug for Value {
             Self::Float(x) => write!(f, "{x}f64"),
             Self::Str(x) => x.fmt(f),
             Self::Bytes(x) => write!(f, "<{} bytes>", x.len()),
+            Self::AttachmentRef(id) => write!(f, "<attachment {id}>"),
             Self::Complex(x) => write!(f, "({} + {}i)", x.re, x.im),
             Self::Vec3(x) => write!(f, "v3{:?}", x.0),
             Self::Vec4(x) => write!(f, "v4{:?}", x.0),
+            Self::Quat(x) => write!(f, "q{:?}", x.0),
             Self::InstantSeqEvent(x) => x.fmt(f),
             Self::Volume(x) => x.fmt(f),
             Self::SegmentedPhantom(x) => x.fmt(f),
             Self::PhantomTissue(x) => x.fmt(f),
+            Self::Provenance(x) => x.fmt(f),
             Self::Dict(x) => x.fmt(f),
             Self::List(x) => x.fmt(f),
             Self::TypedDict(x) => x.fmt(f),
@@ -56,15 +61,19 @@ impl Debug for TypedList {
             Self::Bool(x) => fmt_typed_list(x, "", f),
             Self::Int(x) => fmt_typed_list(x, "i64", f),
             Self::Float(x) => fmt_typed_list(x, "f64", f),
+            #[cfg(feature = "half")]
+            Self::Half(x) => fmt_typed_list(x, "f16", f),
             Self::Str(x) => fmt_typed_list(x, "", f),
             Self::Bytes(x) => fmt_typed_list(x, "bytes", f),
             Self::Complex(x) => fmt_typed_list(x, "complex", f),
             Self::Vec3(x) => fmt_typed_list(x, "v3", f),
             Self::Vec4(x) => fmt_typed_list(x, "v4", f),
+            Self::Quat(x) => fmt_typed_list(x, "q", f),
             Self::InstantSeqEvent(x) => fmt_typed_list(x, "", f),
             Self::Volume(x) => fmt_typed_list(x, "", f),
             Self::SegmentedPhantom(x) => fmt_typed_list(x, "", f),
             Self::PhantomTissue(x) => fmt_typed_list(x, "", f),
+            Self::Provenance(x) => fmt_typed_list(x, "", f),
         }
     }
 }
@@ -81,10 +90,12 @@ impl Debug for TypedDict {
             Self::Complex(x) => fmt_typed_map(x, "complex", f),
             Self::Vec3(x) => fmt_typed_map(x, "v3", f),
             Self::Vec4(x) => fmt_typed_map(x, "v4", f),
+            Self::Quat(x) => fmt_typed_map(x, "q", f),
             Self::InstantSeqEvent(x) => fmt_typed_map(x, "", f),
             Self::Volume(x) => fmt_typed_map(x, "", f),
             Self::SegmentedPhantom(x) => fmt_typed_map(x, "", f),
             Self::PhantomTissue(x) => fmt_typed_map(x, "", f),
+            Self::Provenance(x) => fmt_typed_map(x, "", f),
         }
     }
 }
@@ -0,0 +1,149 @@
+//! Timing with [`Duration`], which is always stored in seconds.
+//!
+//! Sequence timings are often written in ms or µs, especially in Python
+//! scripts. Constructing them with [`Duration::from_ms`] or
+//! [`Duration::from_us`] makes the unit explicit instead of relying on every
+//! `f64` being in seconds. On the wire, a duration is a `Float` of seconds.
+
+use std::{
+    any::type_name,
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+};
+
+use super::{
+    Value,
+    atomic::{Duration, Vec3},
+    extract::value_variant_name,
+    structured::Kt,
+};
+use crate::ExtractionError;
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0.0);
+
+    pub fn from_secs(secs: f64) -> Self {
+        Duration(secs)
+    }
+
+    /// ```
+    /// # use toolapi::value::atomic::Duration;
+    /// assert_eq!(Duration::from_ms(2.5), Duration::from_us(2500.0));
+    /// assert_eq!(Duration::from_ms(2.5).secs(), 2.5e-3);
+    /// ```
+    pub fn from_ms(ms: f64) -> Self {
+        Duration(ms / 1e3)
+    }
+
+    pub fn from_us(us: f64) -> Self {
+        Duration(us / 1e6)
+    }
+
+    pub fn secs(&self) -> f64 {
+        self.0
+    }
+
+    pub fn ms(&self) -> f64 {
+        self.0 * 1e3
+    }
+
+    pub fn us(&self) -> f64 {
+        self.0 * 1e6
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Duration {
+    type Output = Duration;
+
+    fn neg(self) -> Duration {
+        Duration(-self.0)
+    }
+}
+
+impl Mul<f64> for Duration {
+    type Output = Duration;
+
+    fn mul(self, rhs: f64) -> Duration {
+        Duration(self.0 * rhs)
+    }
+}
+
+impl Sum for Duration {
+    fn sum<I: Iterator<Item = Duration>>(iter: I) -> Duration {
+        iter.fold(Duration::ZERO, Add::add)
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(value: std::time::Duration) -> Self {
+        Duration(value.as_secs_f64())
+    }
+}
+
+impl From<Duration> for Value {
+    fn from(value: Duration) -> Self {
+        Value::Float(value.0)
+    }
+}
+
+/// Only from a `Float`, which is in seconds
+impl TryFrom<Value> for Duration {
+    type Error = ExtractionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(secs) => Ok(Duration(secs)),
+            _ => Err(ExtractionError::TypeMismatch {
+                from: value_variant_name(&value).to_string(),
+                into: type_name::<Duration>().to_string(),
+            }),
+        }
+    }
+}
+
+impl Kt {
+    /// [`Kt::new`] with an explicit unit for the duration.
+    ///
+    /// ```
+    /// # use toolapi::value::{atomic::{Duration, Vec3}, structured::Kt};
+    /// let kt = Kt::with_duration(Vec3([10.0, 0.0, 0.0]), Duration::from_us(20.0));
+    /// assert_eq!(kt.tau(), 20e-6);
+    /// assert!((kt.duration().us() - 20.0).abs() < 1e-9);
+    /// ```
+    pub fn with_duration(k: Vec3, duration: Duration) -> Self {
+        Kt::new(k, duration.secs())
+    }
+
+    /// [`Kt::tau`] as a [`Duration`]
+    pub fn duration(&self) -> Duration {
+        Duration(self.tau())
+    }
+}
@@ -7,13 +7,16 @@ use std::collections::HashMap;
 use num_complex::Complex64;
 
 use crate::{
-    ExtractionError,
-    value::typed::{TypedDict, TypedList},
+    ExtractionError, ToolError,
+    value::{
+        dynamic::Dict,
+        typed::{TypedDict, TypedList},
+    },
 };
 
 use super::Value;
 
-fn value_variant_name(v: &Value) -> &'static str {
+pub(super) fn value_variant_name(v: &Value) -> &'static str {
     match v {
         Value::None(_) => "Value::None",
         Value::Bool(_) => "Value::Bool",
@@ -21,13 +24,16 @@ fn value_variant_name(v: &Value) -> &'static str {
         Value::Float(_) => "Value::Float",
         Value::Str(_) => "Value::Str",
         Value::Bytes(_) => "Value::Bytes",
+        Value::AttachmentRef(_) => "Value::AttachmentRef",
         Value::Complex(_) => "Value::Complex",
         Value::Vec3(_) => "Value::Vec3",
         Value::Vec4(_) => "Value::Vec4",
+        Value::Quat(_) => "Value::Quat",
         Value::InstantSeqEvent(_) => "Value::InstantSeqEvent",
         Value::Volume(_) => "Value::Volume",
         Value::SegmentedPhantom(_) => "Value::SegmentedPhantom",
         Value::PhantomTissue(_) => "Value::PhantomTissue",
+        Value::Provenance(_) => "Value::Provenance",
         Value::Dict(_) => "Value::Dict",
         Value::List(_) => "Value::List",
         Value::TypedDict(d) => typed_dict_variant_name(d),
@@ -35,25 +41,29 @@ fn value_variant_name(v: &Value) -> &'static str {
     }
 }
 
-fn typed_list_variant_name(v: &TypedList) -> &'static str {
+pub(super) fn typed_list_variant_name(v: &TypedList) -> &'static str {
     match v {
         TypedList::None(_) => "TypedList::None",
         TypedList::Bool(_) => "TypedList::Bool",
         TypedList::Int(_) => "TypedList::Int",
         TypedList::Float(_) => "TypedList::Float",
+        #[cfg(feature = "half")]
+        TypedList::Half(_) => "TypedList::Half",
         TypedList::Str(_) => "TypedList::Str",
         TypedList::Bytes(_) => "TypedList::Bytes",
         TypedList::Complex(_) => "TypedList::Complex",
         TypedList::Vec3(_) => "TypedList::Vec3",
         TypedList::Vec4(_) => "TypedList::Vec4",
+        TypedList::Quat(_) => "TypedList::Quat",
         TypedList::InstantSeqEvent(_) => "TypedList::InstantSeqEvent",
         TypedList::Volume(_) => "TypedList::Volume",
         TypedList::SegmentedPhantom(_) => "TypedList::SegmentedPhantom",
         TypedList::PhantomTissue(_) => "TypedList::PhantomTissue",
+        TypedList::Provenance(_) => "TypedList::Provenance",
     }
 }
 
-fn typed_dict_variant_name(v: &TypedDict) -> &'static str {
+pub(super) fn typed_dict_variant_name(v: &TypedDict) -> &'static str {
     match v {
         TypedDict::None(_) => "TypedDict::None",
         TypedDict::Bool(_) => "TypedDict::Bool",
@@ -64,33 +74,113 @@ fn typed_dict_variant_name(v: &TypedDict) -> &'static str {
         TypedDict::Complex(_) => "TypedDict::Complex",
         TypedDict::Vec3(_) => "TypedDict::Vec3",
         TypedDict::Vec4(_) => "TypedDict::Vec4",
+        TypedDict::Quat(_) => "TypedDict::Quat",
         TypedDict::InstantSeqEvent(_) => "TypedDict::InstantSeqEvent",
         TypedDict::Volume(_) => "TypedDict::Volume",
         TypedDict::SegmentedPhantom(_) => "TypedDict::SegmentedPhantom",
         TypedDict::PhantomTissue(_) => "TypedDict::PhantomTissue",
+        TypedDict::Provenance(_) => "TypedDict::Provenance",
     }
 }
 
 impl Value {
+    /// Extract a copy of the value at `ptr`, see [`Pointer`]. Unlike
+    /// [`Value::get_ref`], this can also extract elements of typed containers.
     pub fn get(&self, ptr: impl Into<Pointer>) -> Result<Value, ExtractionError> {
-        self._get(&ptr.into().0)
+        let ptr = ptr.into().0;
+        // Elements of typed List / Dict are not stored as Value, create one
+        if let [parent @ .., last] = ptr.as_slice()
+            && let Ok(container) = self._get_ref(parent)
+        {
+            match (container, last) {
+                (Value::TypedList(list), Index::Idx(idx)) => return get_typed_list(list, idx),
+                (Value::TypedDict(dict), Index::Key(key)) => return get_typed_dict(dict, key),
+                _ => {}
+            }
+        }
+        self._get_ref(&ptr).cloned()
+    }
+
+    /// Like `T::try_from(value.get(ptr)?)`, but converts between integer and
+    /// float numbers if the value has the wrong one, as inputs from Python or
+    /// JavaScript often do. Other conversions are not attempted.
+    ///
+    /// Coercion rules, also applied to the elements of typed Lists and Dicts:
+    /// - `Int` to `Float`: always (rounded above 2⁵³)
+    /// - `Float` to `Int`: only if exact, i.e. integral and in the `i64` range
+    /// - `Half` (feature `half`) to `Float`: always (exact)
+    /// - `List` / `Dict` containing only numbers: like a typed one
+    ///
+    /// ```
+    /// # use toolapi::{Value, ExtractionError, value::{dynamic::List, typed::TypedList}};
+    /// let value = Value::TypedList(TypedList::Float(vec![1.0, 2.0]));
+    /// assert_eq!(value.get_lossy::<Vec<i64>>("")?, [1, 2]);
+    /// assert_eq!(Value::Int(3).get_lossy::<f64>("")?, 3.0);
+    /// assert!(Value::Float(2.5).get_lossy::<i64>("").is_err());
+    ///
+    /// let mixed = Value::List(List(vec![Value::Int(1), Value::Float(0.5)]));
+    /// assert_eq!(mixed.get_lossy::<Vec<f64>>("")?, [1.0, 0.5]);
+    /// # Ok::<(), ExtractionError>(())
+    /// ```
+    pub fn get_lossy<T>(&self, ptr: impl Into<Pointer>) -> Result<T, ExtractionError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        let ptr = ptr.into();
+        let err = match T::try_from(self.get(ptr.clone())?) {
+            Err(err @ ExtractionError::TypeMismatch { .. }) => err,
+            result => return result,
+        };
+        // Only reached for mismatches, so the exact path stays as fast as `get`
+        let value = self.get(ptr)?;
+        [Coercion::IntToFloat, Coercion::FloatToInt]
+            .into_iter()
+            .filter_map(|coercion| coerce(&value, coercion))
+            .find_map(|value| T::try_from(value).ok())
+            .ok_or(err)
+    }
+
+    /// Borrow the value at `ptr` instead of copying it like [`Value::get`],
+    /// which avoids duplicating bulk data (e.g. phantoms) that is only read.
+    /// Combine with the `TryFrom<&Value>` impls to extract references:
+    /// ```
+    /// # use toolapi::{Value, ExtractionError, value::{dynamic::Dict, typed::TypedList}};
+    /// # let input = Value::Dict(Dict([(
+    /// #     "signal".to_string(),
+    /// #     Value::TypedList(TypedList::Float(vec![1.0, 2.0])),
+    /// # )].into()));
+    /// let signal: &[f64] = input.get_ref("signal")?.try_into()?;
+    /// assert_eq!(signal, [1.0, 2.0]);
+    /// # Ok::<(), ExtractionError>(())
+    /// ```
+    /// Elements of typed containers (e.g. `"signal/0"`) are not stored as
+    /// [`Value`] and can't be borrowed, this returns a type mismatch for them.
+    pub fn get_ref(&self, ptr: impl Into<Pointer>) -> Result<&Value, ExtractionError> {
+        self._get_ref(&ptr.into().0)
     }
 
-    fn _get(&self, ptr: &[Index]) -> Result<Value, ExtractionError> {
+    fn _get_ref(&self, ptr: &[Index]) -> Result<&Value, ExtractionError> {
         let index = ptr.first();
-        let rest = ptr.get(1..);
+        // None at the end of the path, so that typed List / Dict can be indexed
+        let rest = ptr.get(1..).filter(|rest| !rest.is_empty());
 
         use ExtractionError::*;
         match (self, index, rest) {
             // no indexing: return Value even if it could have contained more nesting
-            (value, None, None) => Ok(value.clone()),
+            (value, None, None) => Ok(value),
 
             // simple indexing into List / Dict - call recurively into them
             (Value::List(list), Some(Index::Idx(idx)), rest) => get_list(list, idx, rest),
             (Value::Dict(dict), Some(Index::Key(key)), rest) => get_dict(dict, key, rest),
             // typed List / Dict: contain atomic types, must be end of path
-            (Value::TypedList(list), Some(Index::Idx(idx)), None) => get_typed_list(list, idx),
-            (Value::TypedDict(dict), Some(Index::Key(key)), None) => get_typed_dict(dict, key),
+            (Value::TypedList(list), Some(Index::Idx(_)), None) => Err(TypeMismatch {
+                from: format!("element of {}", typed_list_variant_name(list)),
+                into: type_name::<&Value>().to_string(),
+            }),
+            (Value::TypedDict(dict), Some(Index::Key(_)), None) => Err(TypeMismatch {
+                from: format!("element of {}", typed_dict_variant_name(dict)),
+                into: type_name::<&Value>().to_string(),
+            }),
             (Value::TypedList(_), Some(Index::Idx(_)), Some(_)) => Err(TooMuchNesting),
             (Value::TypedDict(_), Some(Index::Key(_)), Some(_)) => Err(TooMuchNesting),
 
@@ -103,37 +193,225 @@ impl Value {
             // Trying to index into a non-list/dict value
             (_, Some(_), _) => Err(TooMuchNesting),
 
-            // ptr.get(0) = None && ptr.get(1..) = Some: impossible
+            // ptr.first() = None && rest = Some: impossible
             (_, None, Some(_)) => unreachable!(),
         }
     }
 }
 
-fn get_list(
-    list: &super::dynamic::List,
+/// Extraction of tool parameters, the errors name the offending key.
+///
+/// ```
+/// # use toolapi::{Value, ToolError, value::dynamic::Dict};
+/// # let input = Dict([
+/// #     ("t1".to_string(), Value::Float(1.2)),
+/// #     ("t2".to_string(), Value::Float(0.08)),
+/// # ].into());
+/// let t1: f64 = input.require("t1")?;
+/// let steps: i64 = input.optional("steps", 100)?;
+/// assert_eq!((t1, steps), (1.2, 100));
+///
+/// let err = input.require::<f64>("pd").unwrap_err();
+/// assert_eq!(err.to_string(), "invalid input `pd`: missing, available keys: `t1`, `t2`");
+/// # Ok::<(), ToolError>(())
+/// ```
+impl Dict {
+    /// Extract the entry `key`, which must exist and have type `T`.
+    pub fn require<T>(&self, key: &str) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        match self.0.get(key) {
+            Some(value) => convert_entry(key, value),
+            None => Err(self.missing(key)),
+        }
+    }
+
+    /// Extract the entry `key` if it exists and is not `Value::None`,
+    /// otherwise return `default`. An entry of the wrong type is an error.
+    pub fn optional<T>(&self, key: &str, default: T) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        match self.0.get(key) {
+            None | Some(Value::None(())) => Ok(default),
+            Some(value) => convert_entry(key, value),
+        }
+    }
+
+    fn missing(&self, key: &str) -> ToolError {
+        let mut keys: Vec<String> = self.0.keys().cloned().collect();
+        keys.sort();
+        let message = match keys.is_empty() {
+            true => "missing, the input is empty".to_string(),
+            false => {
+                let quoted: Vec<String> = keys.iter().map(|key| format!("`{key}`")).collect();
+                format!("missing, available keys: {}", quoted.join(", "))
+            }
+        };
+        let available = Value::TypedList(TypedList::Str(keys));
+        ToolError::invalid_input(key, message)
+            .with_details(Dict([("available".to_string(), available)].into()))
+    }
+}
+
+impl Value {
+    /// [`Dict::require`] for a [`Value::Dict`], e.g. the input of a tool.
+    pub fn require<T>(&self, key: &str) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        self.input_dict()?.require(key)
+    }
+
+    /// [`Dict::optional`] for a [`Value::Dict`], e.g. the input of a tool.
+    pub fn optional<T>(&self, key: &str, default: T) -> Result<T, ToolError>
+    where
+        T: TryFrom<Value, Error = ExtractionError>,
+    {
+        self.input_dict()?.optional(key, default)
+    }
+
+    fn input_dict(&self) -> Result<&Dict, ToolError> {
+        match self {
+            Value::Dict(dict) => Ok(dict),
+            value => Err(not_a_dict(value)),
+        }
+    }
+
+    /// The input of a [`crate::Tool`], entries of a `TypedDict` become dynamic
+    #[cfg(feature = "server")]
+    pub(crate) fn into_input_dict(self) -> Result<Dict, ToolError> {
+        match self {
+            Value::Dict(dict) => Ok(dict),
+            Value::TypedDict(dict) => Ok(Dict(
+                dict.keys()
+                    .into_iter()
+                    .map(|key| (key.clone(), get_typed_dict(&dict, key).unwrap()))
+                    .collect(),
+            )),
+            value => Err(not_a_dict(&value)),
+        }
+    }
+}
+
+fn not_a_dict(value: &Value) -> ToolError {
+    ToolError::invalid_input(
+        "",
+        format!(
+            "expected a Value::Dict, found a {}",
+            value_variant_name(value)
+        ),
+    )
+}
+
+fn convert_entry<T>(key: &str, value: &Value) -> Result<T, ToolError>
+where
+    T: TryFrom<Value, Error = ExtractionError>,
+{
+    T::try_from(value.clone()).map_err(|err| ToolError::invalid_input(key, err.to_string()))
+}
+
+#[derive(Clone, Copy)]
+enum Coercion {
+    IntToFloat,
+    FloatToInt,
+}
+
+fn float_to_int(x: f64) -> Option<i64> {
+    // i64::MAX is not representable, 2⁶³ is the first float out of range
+    let exact = x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64;
+    exact.then_some(x as i64)
+}
+
+/// Apply `coercion` to all numbers in `value`, `None` if one is not exact
+fn coerce(value: &Value, coercion: Coercion) -> Option<Value> {
+    use Coercion::*;
+    Some(match (value, coercion) {
+        (Value::Int(x), IntToFloat) => Value::Float(*x as f64),
+        (Value::Float(x), FloatToInt) => Value::Int(float_to_int(*x)?),
+        (Value::TypedList(TypedList::Int(v)), IntToFloat) => {
+            Value::TypedList(TypedList::Float(v.iter().map(|&x| x as f64).collect()))
+        }
+        (Value::TypedList(TypedList::Float(v)), FloatToInt) => Value::TypedList(TypedList::Int(
+            v.iter().map(|&x| float_to_int(x)).collect::<Option<_>>()?,
+        )),
+        #[cfg(feature = "half")]
+        (Value::TypedList(TypedList::Half(v)), IntToFloat) => {
+            Value::TypedList(TypedList::Float(v.iter().map(|x| x.to_f64()).collect()))
+        }
+        (Value::TypedDict(TypedDict::Int(v)), IntToFloat) => Value::TypedDict(TypedDict::Float(
+            v.iter().map(|(k, &x)| (k.clone(), x as f64)).collect(),
+        )),
+        (Value::TypedDict(TypedDict::Float(v)), FloatToInt) => Value::TypedDict(TypedDict::Int(
+            v.iter()
+                .map(|(k, &x)| Some((k.clone(), float_to_int(x)?)))
+                .collect::<Option<_>>()?,
+        )),
+        // Dynamic containers of numbers, e.g. Python lists, become typed
+        (Value::List(list), IntToFloat) => Value::TypedList(TypedList::Float(
+            list.0.iter().map(as_float).collect::<Option<_>>()?,
+        )),
+        (Value::List(list), FloatToInt) => Value::TypedList(TypedList::Int(
+            list.0.iter().map(as_int).collect::<Option<_>>()?,
+        )),
+        (Value::Dict(dict), IntToFloat) => Value::TypedDict(TypedDict::Float(
+            dict.0
+                .iter()
+                .map(|(k, v)| Some((k.clone(), as_float(v)?)))
+                .collect::<Option<_>>()?,
+        )),
+        (Value::Dict(dict), FloatToInt) => Value::TypedDict(TypedDict::Int(
+            dict.0
+                .iter()
+                .map(|(k, v)| Some((k.clone(), as_int(v)?)))
+                .collect::<Option<_>>()?,
+        )),
+        _ => return None,
+    })
+}
+
+fn as_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(x) => Some(*x as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn as_int(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int(x) => Some(*x),
+        Value::Float(x) => float_to_int(*x),
+        _ => None,
+    }
+}
+
+fn get_list<'a>(
+    list: &'a super::dynamic::List,
     index: &usize,
     rest: Option<&[Index]>,
-) -> Result<Value, ExtractionError> {
+) -> Result<&'a Value, ExtractionError> {
     list.0
         .get(*index)
         .ok_or(ExtractionError::IndexOutOfBounds {
             index: *index,
             length: list.0.len(),
         })
-        .and_then(|value| value._get(rest.unwrap_or_default()))
+        .and_then(|value| value._get_ref(rest.unwrap_or_default()))
 }
 
-fn get_dict(
-    dict: &super::dynamic::Dict,
+fn get_dict<'a>(
+    dict: &'a super::dynamic::Dict,
     key: &str,
     rest: Option<&[Index]>,
-) -> Result<Value, ExtractionError> {
+) -> Result<&'a Value, ExtractionError> {
     dict.0
         .get(key)
         .ok_or_else(|| ExtractionError::KeyNotFound {
             key: key.to_string(),
         })
-        .and_then(|value| value._get(rest.unwrap_or_default()))
+        .and_then(|value| value._get_ref(rest.unwrap_or_default()))
 }
 
 fn get_typed_list(list: &TypedList, idx: &usize) -> Result<Value, ExtractionError> {
@@ -142,15 +420,19 @@ fn get_typed_list(list: &TypedList, idx: &usize) -> Result<Value, ExtractionErro
         TypedList::Bool(items) => items.get(*idx).cloned().map(Value::Bool),
         TypedList::Int(items) => items.get(*idx).cloned().map(Value::Int),
         TypedList::Float(items) => items.get(*idx).cloned().map(Value::Float),
+        #[cfg(feature = "half")]
+        TypedList::Half(items) => items.get(*idx).map(|x| Value::Float(x.to_f64())),
         TypedList::Str(items) => items.get(*idx).cloned().map(Value::Str),
         TypedList::Bytes(items) => items.get(*idx).cloned().map(Value::Bytes),
         TypedList::Complex(items) => items.get(*idx).cloned().map(Value::Complex),
         TypedList::Vec3(items) => items.get(*idx).cloned().map(Value::Vec3),
         TypedList::Vec4(items) => items.get(*idx).cloned().map(Value::Vec4),
+        TypedList::Quat(items) => items.get(*idx).cloned().map(Value::Quat),
         TypedList::InstantSeqEvent(items) => items.get(*idx).cloned().map(Value::InstantSeqEvent),
         TypedList::Volume(items) => items.get(*idx).cloned().map(Value::Volume),
         TypedList::SegmentedPhantom(items) => items.get(*idx).cloned().map(Value::SegmentedPhantom),
         TypedList::PhantomTissue(items) => items.get(*idx).cloned().map(Value::PhantomTissue),
+        TypedList::Provenance(items) => items.get(*idx).cloned().map(Value::Provenance),
     }
     .ok_or(ExtractionError::IndexOutOfBounds {
         index: *idx,
@@ -158,7 +440,7 @@ fn get_typed_list(list: &TypedList, idx: &usize) -> Result<Value, ExtractionErro
     })
 }
 
-fn get_typed_dict(dict: &TypedDict, key: &str) -> Result<Value, ExtractionError> {
+pub(super) fn get_typed_dict(dict: &TypedDict, key: &str) -> Result<Value, ExtractionError> {
     match dict {
         TypedDict::None(items) => items.get(key).cloned().map(Value::None),
         TypedDict::Bool(items) => items.get(key).cloned().map(Value::Bool),
@@ -169,10 +451,12 @@ fn get_typed_dict(dict: &TypedDict, key: &str) -> Result<Value, ExtractionError>
         TypedDict::Complex(items) => items.get(key).cloned().map(Value::Complex),
         TypedDict::Vec3(items) => items.get(key).cloned().map(Value::Vec3),
         TypedDict::Vec4(items) => items.get(key).cloned().map(Value::Vec4),
+        TypedDict::Quat(items) => items.get(key).cloned().map(Value::Quat),
         TypedDict::InstantSeqEvent(items) => items.get(key).cloned().map(Value::InstantSeqEvent),
         TypedDict::Volume(items) => items.get(key).cloned().map(Value::Volume),
         TypedDict::SegmentedPhantom(items) => items.get(key).cloned().map(Value::SegmentedPhantom),
         TypedDict::PhantomTissue(items) => items.get(key).cloned().map(Value::PhantomTissue),
+        TypedDict::Provenance(items) => items.get(key).cloned().map(Value::Provenance),
     }
     .ok_or_else(|| ExtractionError::KeyNotFound {
         key: key.to_string(),
@@ -194,8 +478,10 @@ fn get_typed_dict(dict: &TypedDict, key: &str) -> Result<Value, ExtractionError>
 /// "" // returns whole `Value` unchanged
 /// "empty//key" // Empty key in `Dict` at second level
 /// ```
+#[derive(Clone)]
 pub struct Pointer(Vec<Index>);
 
+#[derive(Clone)]
 enum Index {
     Key(String),
     Idx(usize),
@@ -209,6 +495,9 @@ impl From<usize> for Pointer {
 
 impl From<&str> for Pointer {
     fn from(value: &str) -> Self {
+        if value.is_empty() {
+            return Self(Vec::new());
+        }
         Self(
             value
                 .split('/')
@@ -324,6 +613,49 @@ macro_rules! impl_conversion {
                 }
             }
         }
+
+        // ============================
+        // &Value -> &Rust (borrowed, see Value::get_ref)
+        // ============================
+        impl<'a> TryFrom<&'a Value> for &'a $typ {
+            type Error = ExtractionError;
+
+            fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::$variant(value) => Ok(value),
+                    _ => Err(ExtractionError::TypeMismatch {
+                        from: value_variant_name(value).to_string(),
+                        into: type_name::<&$typ>().to_string(),
+                    }),
+                }
+            }
+        }
+        impl<'a> TryFrom<&'a Value> for &'a [$typ] {
+            type Error = ExtractionError;
+
+            fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::TypedList(TypedList::$variant(value)) => Ok(value),
+                    _ => Err(ExtractionError::TypeMismatch {
+                        from: value_variant_name(value).to_string(),
+                        into: type_name::<&[$typ]>().to_string(),
+                    }),
+                }
+            }
+        }
+        impl<'a> TryFrom<&'a Value> for &'a HashMap<String, $typ> {
+            type Error = ExtractionError;
+
+            fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::TypedDict(TypedDict::$variant(value)) => Ok(value),
+                    _ => Err(ExtractionError::TypeMismatch {
+                        from: value_variant_name(value).to_string(),
+                        into: type_name::<&HashMap<String, $typ>>().to_string(),
+                    }),
+                }
+            }
+        }
     };
 }
 
@@ -337,7 +669,39 @@ impl_conversion!(Vec<u8>, Bytes);
 impl_conversion!(Complex64, Complex);
 impl_conversion!(atomic::Vec3, Vec3);
 impl_conversion!(atomic::Vec4, Vec4);
+impl_conversion!(atomic::Quat, Quat);
 impl_conversion!(structured::InstantSeqEvent, InstantSeqEvent);
 impl_conversion!(structured::Volume, Volume);
 impl_conversion!(structured::SegmentedPhantom, SegmentedPhantom);
 impl_conversion!(structured::PhantomTissue, PhantomTissue);
+impl_conversion!(structured::Provenance, Provenance);
+
+/// `Value::None` becomes `None`, everything else is extracted as `T`. Together
+/// with [`Dict::optional`], parameters can be left out, set to `None` or given.
+///
+/// ```
+/// # use toolapi::{Value, ToolError, value::dynamic::Dict};
+/// # let input = Dict([
+/// #     ("t2".to_string(), Value::Float(0.08)),
+/// #     ("t2dash".to_string(), Value::None(())),
+/// # ].into());
+/// let t2: Option<f64> = input.require("t2")?;
+/// let t2dash: Option<f64> = input.require("t2dash")?;
+/// let adc: Option<f64> = input.optional("adc", None)?;
+/// assert_eq!((t2, t2dash, adc), (Some(0.08), None, None));
+/// assert!(Option::<f64>::try_from(Value::Int(1)).is_err());
+/// # Ok::<(), ToolError>(())
+/// ```
+impl<T> TryFrom<Value> for Option<T>
+where
+    T: TryFrom<Value, Error = ExtractionError>,
+{
+    type Error = ExtractionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::None(()) => Ok(None),
+            value => T::try_from(value).map(Some),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Fitting of field maps from measured [`Volume`]s, which become the `db0` of
+//! a [`super::structured::PhantomTissue`] or the `b1_tx` of a
+//! [`super::structured::SegmentedPhantom`].
+//!
+//! Images are either `Complex` (the phase or magnitude is used) or `Float`
+//! (phases in radians or magnitudes). All of them must have the same shape,
+//! the fitted map has the shape and affine of the first one.
+
+use std::{
+    any::type_name,
+    f64::consts::{PI, TAU},
+};
+
+use num_complex::Complex64;
+
+use super::{extract::typed_list_variant_name, structured::Volume, typed::TypedList};
+use crate::{ExtractionError, ToolError};
+
+impl Volume {
+    /// Off-resonance ΔB0 in Hz from the phase images of a multi-echo
+    /// acquisition, `echo_times` in seconds (ascending). Phase differences of
+    /// consecutive echoes are unwrapped, so the phase must change by less than
+    /// π between them. The frequency is the least squares slope of the
+    /// unwrapped phase over time.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::Volume, typed::TypedList}};
+    /// # use std::f64::consts::PI;
+    /// let echo = |phase: f64| Volume::new(
+    ///     [1, 1, 1],
+    ///     [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    ///     TypedList::Float(vec![phase]),
+    /// ).unwrap();
+    /// // 100 Hz advance the phase by 0.4π every 2 ms, the last one is wrapped
+    /// let phases = [echo(0.9 * PI), echo(1.3 * PI - 2.0 * PI), echo(1.7 * PI - 2.0 * PI)];
+    /// let db0 = Volume::fit_db0(&phases, &[2e-3, 4e-3, 6e-3])?;
+    /// let TypedList::Float(hz) = db0.into_data() else { unreachable!() };
+    /// assert!((hz[0] - 100.0).abs() < 1e-9);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn fit_db0(phases: &[Volume], echo_times: &[f64]) -> Result<Volume, ToolError> {
+        if phases.len() < 2 || phases.len() != echo_times.len() {
+            return Err(ToolError::invalid_input(
+                "echo_times",
+                format!(
+                    "need at least 2 echoes with a phase image each, got {} echo times and {} images",
+                    echo_times.len(),
+                    phases.len()
+                ),
+            ));
+        }
+        if echo_times.windows(2).any(|te| te[1] <= te[0]) {
+            return Err(ToolError::invalid_input(
+                "echo_times",
+                "echo times must be ascending",
+            ));
+        }
+        let images = phases
+            .iter()
+            .enumerate()
+            .map(|(i, phase)| samples(phase, &phases[0], &format!("phases[{i}]"), |z| z.arg()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mean_te = echo_times.iter().sum::<f64>() / echo_times.len() as f64;
+        let var_te: f64 = echo_times.iter().map(|te| (te - mean_te).powi(2)).sum();
+        let mut unwrapped = vec![0.0; images.len()];
+        let db0 = (0..images[0].len())
+            .map(|voxel| {
+                unwrapped[0] = images[0][voxel];
+                for echo in 1..images.len() {
+                    let step = wrap(images[echo][voxel] - images[echo - 1][voxel]);
+                    unwrapped[echo] = unwrapped[echo - 1] + step;
+                }
+                let mean_phase = unwrapped.iter().sum::<f64>() / unwrapped.len() as f64;
+                let cov: f64 = echo_times
+                    .iter()
+                    .zip(&unwrapped)
+                    .map(|(te, phase)| (te - mean_te) * (phase - mean_phase))
+                    .sum();
+                cov / var_te / TAU
+            })
+            .collect();
+        Ok(with_data(&phases[0], db0))
+    }
+
+    /// Relative B1+ (actual / nominal flip angle) with the double angle method:
+    /// `alpha` and `double_alpha` are magnitude images acquired with the
+    /// `nominal_angle` (radians) and twice of it, with a long TR. Then their
+    /// ratio is `2 cos(α)`. Voxels without signal in `alpha` get the nominal
+    /// B1 of 1, flip angles beyond 90° can't be distinguished from smaller ones.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::Volume, typed::TypedList}};
+    /// let image = |signal: Vec<f64>| Volume::new(
+    ///     [2, 1, 1],
+    ///     [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    ///     TypedList::Float(signal),
+    /// ).unwrap();
+    /// // Nominal 60°, the first voxel gets 20% more
+    /// let (actual, nominal) = (72f64.to_radians(), 60f64.to_radians());
+    /// let alpha = image(vec![actual.sin(), nominal.sin()]);
+    /// let double_alpha = image(vec![(2.0 * actual).sin(), (2.0 * nominal).sin()]);
+    /// let b1 = Volume::fit_b1_double_angle(&alpha, &double_alpha, nominal)?;
+    /// let TypedList::Float(b1) = b1.into_data() else { unreachable!() };
+    /// assert!((b1[0] - 1.2).abs() < 1e-9 && (b1[1] - 1.0).abs() < 1e-9);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn fit_b1_double_angle(
+        alpha: &Volume,
+        double_alpha: &Volume,
+        nominal_angle: f64,
+    ) -> Result<Volume, ToolError> {
+        if !(nominal_angle > 0.0 && nominal_angle < PI / 2.0) {
+            return Err(ToolError::invalid_input(
+                "nominal_angle",
+                format!("{nominal_angle} is not between 0 and π/2"),
+            ));
+        }
+        let single = samples(alpha, alpha, "alpha", |z| z.norm())?;
+        let double = samples(double_alpha, alpha, "double_alpha", |z| z.norm())?;
+        let b1 = single
+            .iter()
+            .zip(&double)
+            .map(|(&single, &double)| match single.abs() > 0.0 {
+                true => (double / (2.0 * single)).clamp(-1.0, 1.0).acos() / nominal_angle,
+                false => 1.0,
+            })
+            .collect();
+        Ok(with_data(alpha, b1))
+    }
+}
+
+/// Wrap a phase into `(-π, π]`
+fn wrap(phase: f64) -> f64 {
+    let wrapped = phase - TAU * (phase / TAU).round();
+    match wrapped <= -PI {
+        true => wrapped + TAU,
+        false => wrapped,
+    }
+}
+
+/// Voxels of `volume` as floats, `Complex` ones are converted with `complex`.
+/// Fails if the shape differs from the one of `first`.
+pub(super) fn samples(
+    volume: &Volume,
+    first: &Volume,
+    path: &str,
+    complex: fn(&Complex64) -> f64,
+) -> Result<Vec<f64>, ToolError> {
+    if volume.shape != first.shape {
+        return Err(ToolError::invalid_input(
+            path,
+            format!("shape {:?} differs from {:?}", volume.shape, first.shape),
+        ));
+    }
+    match &volume.data {
+        TypedList::Float(v) => Ok(v.clone()),
+        #[cfg(feature = "half")]
+        TypedList::Half(v) => Ok(v.iter().map(|x| x.to_f64()).collect()),
+        TypedList::Complex(v) => Ok(v.iter().map(complex).collect()),
+        list => Err(ExtractionError::TypeMismatch {
+            from: typed_list_variant_name(list).to_string(),
+            into: type_name::<Vec<f64>>().to_string(),
+        }
+        .into()),
+    }
+}
+
+pub(super) fn with_data(like: &Volume, data: Vec<f64>) -> Volume {
+    Volume {
+        shape: like.shape,
+        affine: like.affine,
+        data: TypedList::Float(data),
+    }
+}
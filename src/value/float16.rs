@@ -0,0 +1,108 @@
+//! Conversions of [`TypedList::Half`], the 16 bit float storage of the `half` feature.
+//!
+//! Tools keep computing with `f32` / `f64` and only use [`TypedList::to_half`]
+//! for outputs (e.g. the data of a [`super::structured::Volume`]) that tolerate
+//! the reduced precision. Reading it back with [`TypedList::to_f64`] or
+//! [`Value::get_lossy`] is exact.
+
+use std::any::type_name;
+
+use half::f16;
+
+use super::{Value, extract::typed_list_variant_name, typed::TypedList};
+use crate::ExtractionError;
+
+/// Narrow `x`, finite values beyond ±65504 would silently become infinite
+fn checked_f16(x: f64) -> Result<f16, ExtractionError> {
+    let half = f16::from_f64(x);
+    match half.is_infinite() && x.is_finite() {
+        true => Err(ExtractionError::OutOfRange {
+            value: x,
+            into: type_name::<f16>().to_string(),
+        }),
+        false => Ok(half),
+    }
+}
+
+fn checked_f32(x: f64) -> Result<f32, ExtractionError> {
+    let single = x as f32;
+    match single.is_infinite() && x.is_finite() {
+        true => Err(ExtractionError::OutOfRange {
+            value: x,
+            into: type_name::<f32>().to_string(),
+        }),
+        false => Ok(single),
+    }
+}
+
+impl TypedList {
+    /// Store a `Float` list as `Half`, rounding to the nearest 16 bit float.
+    /// Fails if a finite value is out of range, small values become zero.
+    ///
+    /// ```
+    /// # use toolapi::{ExtractionError, value::typed::TypedList};
+    /// let b1 = TypedList::Float(vec![0.0, 0.98, 1.02]).to_half()?;
+    /// assert_eq!(b1.to_f64()?, [0.0, 0.97998046875, 1.01953125]);
+    ///
+    /// assert!(TypedList::Float(vec![1e5]).to_half().is_err());
+    /// # Ok::<(), ExtractionError>(())
+    /// ```
+    pub fn to_half(&self) -> Result<TypedList, ExtractionError> {
+        match self {
+            TypedList::Half(v) => Ok(TypedList::Half(v.clone())),
+            TypedList::Float(v) => Ok(TypedList::Half(
+                v.iter()
+                    .map(|&x| checked_f16(x))
+                    .collect::<Result<_, _>>()?,
+            )),
+            list => Err(mismatch::<Vec<f16>>(list)),
+        }
+    }
+
+    /// Elements of a `Float` or `Half` list, the latter are converted exactly.
+    pub fn to_f64(&self) -> Result<Vec<f64>, ExtractionError> {
+        match self {
+            TypedList::Float(v) => Ok(v.clone()),
+            TypedList::Half(v) => Ok(v.iter().map(|x| x.to_f64()).collect()),
+            list => Err(mismatch::<Vec<f64>>(list)),
+        }
+    }
+
+    /// Elements of a `Float` or `Half` list, fails if a finite `Float` is out
+    /// of range. `Half` elements are converted exactly.
+    pub fn to_f32(&self) -> Result<Vec<f32>, ExtractionError> {
+        match self {
+            TypedList::Float(v) => v.iter().map(|&x| checked_f32(x)).collect(),
+            TypedList::Half(v) => Ok(v.iter().map(|x| x.to_f32()).collect()),
+            list => Err(mismatch::<Vec<f32>>(list)),
+        }
+    }
+}
+
+fn mismatch<T>(list: &TypedList) -> ExtractionError {
+    ExtractionError::TypeMismatch {
+        from: typed_list_variant_name(list).to_string(),
+        into: type_name::<T>().to_string(),
+    }
+}
+
+impl From<Vec<f16>> for Value {
+    fn from(value: Vec<f16>) -> Self {
+        Self::TypedList(TypedList::Half(value))
+    }
+}
+
+impl TryFrom<Value> for Vec<f16> {
+    type Error = ExtractionError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::TypedList(TypedList::Half(value)) => Ok(value),
+            Value::TypedList(list) => Err(mismatch::<Vec<f16>>(&list)),
+            value => Err(ExtractionError::TypeMismatch {
+                from: super::extract::value_variant_name(&value).to_string(),
+                into: type_name::<Vec<f16>>().to_string(),
+            }),
+        }
+    }
+}
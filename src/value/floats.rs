@@ -0,0 +1,283 @@
+//! Handling of NaN and infinite floats, see [`FloatPolicy`].
+
+use std::collections::BTreeMap;
+
+use num_complex::Complex64;
+
+use super::{
+    Value,
+    schema::SchemaViolation,
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
+    typed::{TypedDict, TypedList},
+};
+
+/// What happens to NaN and infinite floats in a value, e.g. in the voxels of a
+/// [`Volume`]. MessagePack transfers them fine, but JSON has no representation
+/// for them and many consumers don't expect them. Applied by servers to inputs
+/// and results, see [`crate::ServerConfig::float_policy`].
+///
+/// ```
+/// # use toolapi::{Value, value::{FloatPolicy, dynamic::Dict}};
+/// let mut value = Value::Dict(Dict([
+///     ("t1".to_string(), Value::Float(f64::NAN)),
+///     ("signal".to_string(), Value::from(vec![1.0, f64::INFINITY, f64::NAN])),
+/// ].into()));
+///
+/// let violations: Vec<String> = FloatPolicy::Error
+///     .apply(&mut value)
+///     .unwrap_err()
+///     .iter()
+///     .map(|v| v.to_string())
+///     .collect();
+/// assert_eq!(violations, ["`signal`: 2 NaN or infinite values", "`t1`: NaN or infinite value"]);
+///
+/// FloatPolicy::Replace(0.0).apply(&mut value).unwrap();
+/// assert!(FloatPolicy::Error.apply(&mut value).is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FloatPolicy {
+    /// Kept as they are
+    #[default]
+    Allow,
+    /// Values containing them are rejected
+    Error,
+    /// They are replaced by this (finite) value
+    Replace(f64),
+}
+
+impl FloatPolicy {
+    /// Check or replace the NaN and infinite floats in `value`. With
+    /// [`FloatPolicy::Error`], the error lists every place that contains them,
+    /// ordered by path. Typed lists are reported once, with the number of them.
+    pub fn apply(&self, value: &mut Value) -> Result<(), Vec<SchemaViolation>> {
+        let mut found = BTreeMap::<String, usize>::new();
+        visit(value, "", &mut |path, x| {
+            if !x.is_finite() {
+                match self {
+                    FloatPolicy::Replace(with) => *x = *with,
+                    _ => *found.entry(path.to_string()).or_default() += 1,
+                }
+            }
+        });
+        if *self != FloatPolicy::Error || found.is_empty() {
+            return Ok(());
+        }
+        let violations = found
+            .into_iter()
+            .map(|(path, count)| SchemaViolation {
+                path,
+                message: match count {
+                    1 => "NaN or infinite value".to_string(),
+                    n => format!("{n} NaN or infinite values"),
+                },
+            })
+            .collect();
+        Err(violations)
+    }
+}
+
+/// Calls `f` with every float in `value` and the path of the value or list it is in
+fn visit(value: &mut Value, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    match value {
+        Value::Float(x) => f(path, x),
+        Value::Complex(x) => visit_complex(x, path, f),
+        Value::Vec3(x) => x.0.iter_mut().for_each(|x| f(path, x)),
+        Value::Vec4(x) => x.0.iter_mut().for_each(|x| f(path, x)),
+        Value::Quat(x) => x.0.iter_mut().for_each(|x| f(path, x)),
+        Value::InstantSeqEvent(event) => visit_event(event, path, f),
+        Value::Volume(volume) => visit_volume(volume, path, f),
+        Value::PhantomTissue(tissue) => visit_tissue(tissue, path, f),
+        Value::SegmentedPhantom(phantom) => visit_phantom(phantom, path, f),
+        Value::Provenance(provenance) => visit_provenance(provenance, path, f),
+        Value::Dict(dict) => {
+            for (key, value) in &mut dict.0 {
+                visit(value, &join(path, key), f);
+            }
+        }
+        Value::List(list) => {
+            for (index, value) in list.0.iter_mut().enumerate() {
+                visit(value, &join(path, &index.to_string()), f);
+            }
+        }
+        Value::TypedList(list) => visit_list(list, path, f),
+        Value::TypedDict(dict) => visit_dict(dict, path, f),
+        Value::None(_)
+        | Value::Bool(_)
+        | Value::Int(_)
+        | Value::Str(_)
+        | Value::Bytes(_)
+        | Value::AttachmentRef(_) => {}
+    }
+}
+
+fn visit_complex(x: &mut Complex64, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    f(path, &mut x.re);
+    f(path, &mut x.im);
+}
+
+fn visit_event(event: &mut InstantSeqEvent, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    match event {
+        InstantSeqEvent::Pulse { angle, phase } => {
+            f(&join(path, "angle"), angle);
+            f(&join(path, "phase"), phase);
+        }
+        InstantSeqEvent::Fid { kt } => {
+            let kt_path = join(path, "kt");
+            kt.0.iter_mut().for_each(|x| f(&kt_path, x));
+        }
+        InstantSeqEvent::Adc { phase } => f(&join(path, "phase"), phase),
+    }
+}
+
+fn visit_volume(volume: &mut Volume, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    let affine = join(path, "affine");
+    volume
+        .affine
+        .iter_mut()
+        .flatten()
+        .for_each(|x| f(&affine, x));
+    visit_list(&mut volume.data, &join(path, "data"), f);
+}
+
+fn visit_tissue(tissue: &mut PhantomTissue, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    visit_volume(&mut tissue.density, &join(path, "density"), f);
+    visit_volume(&mut tissue.db0, &join(path, "db0"), f);
+    f(&join(path, "t1"), &mut tissue.t1);
+    f(&join(path, "t2"), &mut tissue.t2);
+    f(&join(path, "t2dash"), &mut tissue.t2dash);
+    f(&join(path, "adc"), &mut tissue.adc);
+}
+
+fn visit_phantom(phantom: &mut SegmentedPhantom, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    let tissues = join(path, "tissues");
+    for (name, tissue) in &mut phantom.tissues {
+        visit_tissue(tissue, &join(&tissues, name), f);
+    }
+    let b1_tx = join(path, "b1_tx");
+    for (index, volume) in phantom.b1_tx.iter_mut().enumerate() {
+        visit_volume(volume, &join(&b1_tx, &index.to_string()), f);
+    }
+    let b1_rx = join(path, "b1_rx");
+    for (index, volume) in phantom.b1_rx.iter_mut().enumerate() {
+        visit_volume(volume, &join(&b1_rx, &index.to_string()), f);
+    }
+}
+
+fn visit_provenance(provenance: &mut Provenance, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    f(&join(path, "started"), &mut provenance.started);
+    f(&join(path, "duration"), &mut provenance.duration);
+}
+
+/// Numbers of a list are reported at the path of the list, structured
+/// values at their own path.
+fn visit_list(list: &mut TypedList, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    let item = |index: usize| join(path, &index.to_string());
+    match list {
+        TypedList::Float(items) => items.iter_mut().for_each(|x| f(path, x)),
+        #[cfg(feature = "half")]
+        TypedList::Half(items) => {
+            for half in items {
+                let mut x = half.to_f64();
+                if !x.is_finite() {
+                    f(path, &mut x);
+                    *half = half::f16::from_f64(x);
+                }
+            }
+        }
+        TypedList::Complex(items) => items.iter_mut().for_each(|x| visit_complex(x, path, f)),
+        TypedList::Vec3(items) => items
+            .iter_mut()
+            .flat_map(|x| &mut x.0)
+            .for_each(|x| f(path, x)),
+        TypedList::Vec4(items) => items
+            .iter_mut()
+            .flat_map(|x| &mut x.0)
+            .for_each(|x| f(path, x)),
+        TypedList::Quat(items) => items
+            .iter_mut()
+            .flat_map(|x| &mut x.0)
+            .for_each(|x| f(path, x)),
+        TypedList::InstantSeqEvent(items) => {
+            for (index, x) in items.iter_mut().enumerate() {
+                visit_event(x, &item(index), f);
+            }
+        }
+        TypedList::Volume(items) => {
+            for (index, x) in items.iter_mut().enumerate() {
+                visit_volume(x, &item(index), f);
+            }
+        }
+        TypedList::PhantomTissue(items) => {
+            for (index, x) in items.iter_mut().enumerate() {
+                visit_tissue(x, &item(index), f);
+            }
+        }
+        TypedList::SegmentedPhantom(items) => {
+            for (index, x) in items.iter_mut().enumerate() {
+                visit_phantom(x, &item(index), f);
+            }
+        }
+        TypedList::Provenance(items) => {
+            for (index, x) in items.iter_mut().enumerate() {
+                visit_provenance(x, &item(index), f);
+            }
+        }
+        TypedList::None(_)
+        | TypedList::Bool(_)
+        | TypedList::Int(_)
+        | TypedList::Str(_)
+        | TypedList::Bytes(_) => {}
+    }
+}
+
+fn visit_dict(dict: &mut TypedDict, path: &str, f: &mut impl FnMut(&str, &mut f64)) {
+    let entry = |key: &str| join(path, key);
+    match dict {
+        TypedDict::Float(items) => items.iter_mut().for_each(|(k, x)| f(&entry(k), x)),
+        TypedDict::Complex(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_complex(x, &entry(k), f)),
+        TypedDict::Vec3(items) => {
+            for (k, x) in items {
+                x.0.iter_mut().for_each(|x| f(&entry(k), x));
+            }
+        }
+        TypedDict::Vec4(items) => {
+            for (k, x) in items {
+                x.0.iter_mut().for_each(|x| f(&entry(k), x));
+            }
+        }
+        TypedDict::Quat(items) => {
+            for (k, x) in items {
+                x.0.iter_mut().for_each(|x| f(&entry(k), x));
+            }
+        }
+        TypedDict::InstantSeqEvent(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_event(x, &entry(k), f)),
+        TypedDict::Volume(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_volume(x, &entry(k), f)),
+        TypedDict::PhantomTissue(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_tissue(x, &entry(k), f)),
+        TypedDict::SegmentedPhantom(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_phantom(x, &entry(k), f)),
+        TypedDict::Provenance(items) => items
+            .iter_mut()
+            .for_each(|(k, x)| visit_provenance(x, &entry(k), f)),
+        TypedDict::None(_)
+        | TypedDict::Bool(_)
+        | TypedDict::Int(_)
+        | TypedDict::Str(_)
+        | TypedDict::Bytes(_) => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        path => format!("{path}/{key}"),
+    }
+}
@@ -0,0 +1,352 @@
+//! Stable digests of [`Value`]s, see [`Value::canonical_hash`].
+//!
+//! The canonical encoding writes the variant name of every enum, lengths as
+//! u64 and numbers as little-endian bytes. Dict entries are sorted by key.
+//! Floats are normalized: `-0.0` becomes `0.0` and all NaNs the same NaN.
+//! Struct fields are written in declaration order, without names.
+
+use std::collections::HashMap;
+
+use num_complex::Complex64;
+
+use super::{
+    Value, atomic,
+    dynamic::{Dict, List},
+    structured,
+    typed::{TypedDict, TypedList},
+};
+
+impl Value {
+    /// blake3 hash of the canonical encoding of this value. Equal values have
+    /// equal hashes, independent of the order of their dict entries. Values of
+    /// different types differ, e.g. `Int(1)` and `Float(1.0)` or a `List` and
+    /// a `TypedList` of the same numbers.
+    ///
+    /// Clients use it for the cached inputs (see [`crate::CacheConfig`]), it is
+    /// also suited to deduplicate results or to tag where they came from.
+    ///
+    /// ```
+    /// # use toolapi::{Value, value::dynamic::Dict};
+    /// let a = Value::Dict(Dict([
+    ///     ("t1".to_string(), Value::Float(0.0)),
+    ///     ("t2".to_string(), Value::Float(0.08)),
+    /// ].into()));
+    /// let b = Value::Dict(Dict([
+    ///     ("t2".to_string(), Value::Float(0.08)),
+    ///     ("t1".to_string(), Value::Float(-0.0)),
+    /// ].into()));
+    /// assert_eq!(a.canonical_hash(), b.canonical_hash());
+    /// assert_ne!(Value::Int(1).canonical_hash(), Value::Float(1.0).canonical_hash());
+    /// ```
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        let mut writer = Writer {
+            hasher: blake3::Hasher::new(),
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+        };
+        self.encode(&mut writer);
+        writer.hasher.update(&writer.buffer);
+        *writer.hasher.finalize().as_bytes()
+    }
+}
+
+/// Small writes are collected, the hasher is faster with larger updates
+const BUFFER_SIZE: usize = 64 * 1024;
+
+struct Writer {
+    hasher: blake3::Hasher,
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        if bytes.len() >= BUFFER_SIZE {
+            self.hasher.update(&self.buffer);
+            self.buffer.clear();
+            self.hasher.update(bytes);
+            return;
+        }
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.hasher.update(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+
+    fn len(&mut self, len: usize) {
+        self.bytes(&(len as u64).to_le_bytes());
+    }
+
+    fn tag(&mut self, name: &str) {
+        self.len(name.len());
+        self.bytes(name.as_bytes());
+    }
+
+    /// Enum variant `name` holding `value`
+    fn variant(&mut self, name: &str, value: &impl Canonical) {
+        self.tag(name);
+        value.encode(self);
+    }
+}
+
+trait Canonical: Sized {
+    fn encode(&self, out: &mut Writer);
+
+    /// Elements of a list, overwritten to write bytes at once
+    fn encode_slice(items: &[Self], out: &mut Writer) {
+        items.iter().for_each(|x| x.encode(out));
+    }
+}
+
+impl Canonical for () {
+    fn encode(&self, _: &mut Writer) {}
+}
+
+impl Canonical for bool {
+    fn encode(&self, out: &mut Writer) {
+        out.bytes(&[*self as u8]);
+    }
+}
+
+impl Canonical for u8 {
+    fn encode(&self, out: &mut Writer) {
+        out.bytes(&[*self]);
+    }
+
+    fn encode_slice(items: &[Self], out: &mut Writer) {
+        out.bytes(items);
+    }
+}
+
+impl Canonical for u32 {
+    fn encode(&self, out: &mut Writer) {
+        out.bytes(&self.to_le_bytes());
+    }
+}
+
+impl Canonical for u64 {
+    fn encode(&self, out: &mut Writer) {
+        out.bytes(&self.to_le_bytes());
+    }
+}
+
+impl Canonical for i64 {
+    fn encode(&self, out: &mut Writer) {
+        out.bytes(&self.to_le_bytes());
+    }
+}
+
+impl Canonical for f64 {
+    fn encode(&self, out: &mut Writer) {
+        let x = if *self == 0.0 {
+            0.0
+        } else if self.is_nan() {
+            f64::NAN
+        } else {
+            *self
+        };
+        out.bytes(&x.to_le_bytes());
+    }
+}
+
+#[cfg(feature = "half")]
+impl Canonical for half::f16 {
+    fn encode(&self, out: &mut Writer) {
+        self.to_f64().encode(out);
+    }
+}
+
+impl Canonical for String {
+    fn encode(&self, out: &mut Writer) {
+        out.tag(self);
+    }
+}
+
+impl Canonical for Complex64 {
+    fn encode(&self, out: &mut Writer) {
+        self.re.encode(out);
+        self.im.encode(out);
+    }
+}
+
+impl<T: Canonical> Canonical for Option<T> {
+    fn encode(&self, out: &mut Writer) {
+        self.is_some().encode(out);
+        if let Some(x) = self {
+            x.encode(out);
+        }
+    }
+}
+
+impl<T: Canonical, const N: usize> Canonical for [T; N] {
+    fn encode(&self, out: &mut Writer) {
+        self.iter().for_each(|x| x.encode(out));
+    }
+}
+
+impl<T: Canonical> Canonical for Vec<T> {
+    fn encode(&self, out: &mut Writer) {
+        out.len(self.len());
+        T::encode_slice(self, out);
+    }
+}
+
+impl<T: Canonical> Canonical for HashMap<String, T> {
+    fn encode(&self, out: &mut Writer) {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        out.len(entries.len());
+        for (key, value) in entries {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+}
+
+impl Canonical for atomic::Vec3 {
+    fn encode(&self, out: &mut Writer) {
+        self.0.encode(out);
+    }
+}
+
+impl Canonical for atomic::Vec4 {
+    fn encode(&self, out: &mut Writer) {
+        self.0.encode(out);
+    }
+}
+
+impl Canonical for atomic::Quat {
+    fn encode(&self, out: &mut Writer) {
+        self.0.encode(out);
+    }
+}
+
+impl Canonical for structured::InstantSeqEvent {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Self::Pulse { angle, phase } => {
+                out.tag("Pulse");
+                angle.encode(out);
+                phase.encode(out);
+            }
+            Self::Fid { kt } => {
+                out.tag("Fid");
+                kt.0.encode(out);
+            }
+            Self::Adc { phase } => {
+                out.tag("Adc");
+                phase.encode(out);
+            }
+        }
+    }
+}
+
+impl Canonical for structured::Volume {
+    fn encode(&self, out: &mut Writer) {
+        self.shape.encode(out);
+        self.affine.encode(out);
+        self.data.encode(out);
+    }
+}
+
+impl Canonical for structured::SegmentedPhantom {
+    fn encode(&self, out: &mut Writer) {
+        self.tissues.encode(out);
+        self.b1_tx.encode(out);
+        self.b1_rx.encode(out);
+    }
+}
+
+impl Canonical for structured::PhantomTissue {
+    fn encode(&self, out: &mut Writer) {
+        self.density.encode(out);
+        self.db0.encode(out);
+        self.t1.encode(out);
+        self.t2.encode(out);
+        self.t2dash.encode(out);
+        self.adc.encode(out);
+    }
+}
+
+impl Canonical for structured::Provenance {
+    fn encode(&self, out: &mut Writer) {
+        self.tool.encode(out);
+        self.version.encode(out);
+        self.input_hash.encode(out);
+        self.started.encode(out);
+        self.duration.encode(out);
+        self.host.encode(out);
+    }
+}
+
+impl Canonical for TypedList {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Self::None(v) => out.variant("None", v),
+            Self::Bool(v) => out.variant("Bool", v),
+            Self::Int(v) => out.variant("Int", v),
+            Self::Float(v) => out.variant("Float", v),
+            #[cfg(feature = "half")]
+            Self::Half(v) => out.variant("Half", v),
+            Self::Str(v) => out.variant("Str", v),
+            Self::Bytes(v) => out.variant("Bytes", v),
+            Self::Complex(v) => out.variant("Complex", v),
+            Self::Vec3(v) => out.variant("Vec3", v),
+            Self::Vec4(v) => out.variant("Vec4", v),
+            Self::Quat(v) => out.variant("Quat", v),
+            Self::InstantSeqEvent(v) => out.variant("InstantSeqEvent", v),
+            Self::Volume(v) => out.variant("Volume", v),
+            Self::SegmentedPhantom(v) => out.variant("SegmentedPhantom", v),
+            Self::PhantomTissue(v) => out.variant("PhantomTissue", v),
+            Self::Provenance(v) => out.variant("Provenance", v),
+        }
+    }
+}
+
+impl Canonical for TypedDict {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Self::None(v) => out.variant("None", v),
+            Self::Bool(v) => out.variant("Bool", v),
+            Self::Int(v) => out.variant("Int", v),
+            Self::Float(v) => out.variant("Float", v),
+            Self::Str(v) => out.variant("Str", v),
+            Self::Bytes(v) => out.variant("Bytes", v),
+            Self::Complex(v) => out.variant("Complex", v),
+            Self::Vec3(v) => out.variant("Vec3", v),
+            Self::Vec4(v) => out.variant("Vec4", v),
+            Self::Quat(v) => out.variant("Quat", v),
+            Self::InstantSeqEvent(v) => out.variant("InstantSeqEvent", v),
+            Self::Volume(v) => out.variant("Volume", v),
+            Self::SegmentedPhantom(v) => out.variant("SegmentedPhantom", v),
+            Self::PhantomTissue(v) => out.variant("PhantomTissue", v),
+            Self::Provenance(v) => out.variant("Provenance", v),
+        }
+    }
+}
+
+impl Canonical for Value {
+    fn encode(&self, out: &mut Writer) {
+        match self {
+            Value::None(v) => out.variant("None", v),
+            Value::Bool(v) => out.variant("Bool", v),
+            Value::Int(v) => out.variant("Int", v),
+            Value::Float(v) => out.variant("Float", v),
+            Value::Str(v) => out.variant("Str", v),
+            Value::Bytes(v) => out.variant("Bytes", v),
+            Value::AttachmentRef(v) => out.variant("AttachmentRef", v),
+            Value::Complex(v) => out.variant("Complex", v),
+            Value::Vec3(v) => out.variant("Vec3", v),
+            Value::Vec4(v) => out.variant("Vec4", v),
+            Value::Quat(v) => out.variant("Quat", v),
+            Value::InstantSeqEvent(v) => out.variant("InstantSeqEvent", v),
+            Value::Volume(v) => out.variant("Volume", v),
+            Value::SegmentedPhantom(v) => out.variant("SegmentedPhantom", v),
+            Value::PhantomTissue(v) => out.variant("PhantomTissue", v),
+            Value::Provenance(v) => out.variant("Provenance", v),
+            Value::Dict(Dict(v)) => out.variant("Dict", v),
+            Value::List(List(v)) => out.variant("List", v),
+            Value::TypedDict(v) => out.variant("TypedDict", v),
+            Value::TypedList(v) => out.variant("TypedList", v),
+        }
+    }
+}
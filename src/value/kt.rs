@@ -0,0 +1,115 @@
+//! Encoding arithmetic with [`Kt`], the `[kx, ky, kz, tau]` of sequence events.
+//!
+//! All four components are added, subtracted and scaled together, so that
+//! the sum of consecutive [`super::structured::InstantSeqEvent::Fid`]s is the
+//! accumulated gradient moment together with the elapsed time.
+
+use std::{
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+};
+
+use super::{
+    atomic::{Vec3, Vec4},
+    structured::Kt,
+};
+
+impl Kt {
+    pub const ZERO: Kt = Kt([0.0; 4]);
+
+    pub fn new(k: Vec3, tau: f64) -> Self {
+        let [kx, ky, kz] = k.0;
+        Kt([kx, ky, kz, tau])
+    }
+
+    /// Gradient moment without the duration
+    pub fn k(&self) -> Vec3 {
+        let [kx, ky, kz, _] = self.0;
+        Vec3([kx, ky, kz])
+    }
+
+    pub fn tau(&self) -> f64 {
+        self.0[3]
+    }
+
+    pub fn scale(&self, factor: f64) -> Self {
+        Kt(self.0.map(|x| x * factor))
+    }
+
+    /// Running sum, starting with the first element (not with [`Kt::ZERO`]).
+    ///
+    /// ```
+    /// # use toolapi::value::structured::Kt;
+    /// let fids = [Kt([1.0, 0.0, 0.0, 1e-3]), Kt([-2.0, 1.0, 0.0, 2e-3])];
+    /// let trajectory: Vec<Kt> = Kt::accumulate(fids).collect();
+    /// assert_eq!(trajectory, [Kt([1.0, 0.0, 0.0, 1e-3]), Kt([-1.0, 1.0, 0.0, 3e-3])]);
+    /// ```
+    pub fn accumulate(kts: impl IntoIterator<Item = Kt>) -> impl Iterator<Item = Kt> {
+        kts.into_iter().scan(Kt::ZERO, |sum, kt| {
+            *sum += kt;
+            Some(*sum)
+        })
+    }
+}
+
+impl Add for Kt {
+    type Output = Kt;
+
+    fn add(self, rhs: Kt) -> Kt {
+        Kt(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+}
+
+impl Sub for Kt {
+    type Output = Kt;
+
+    fn sub(self, rhs: Kt) -> Kt {
+        Kt(std::array::from_fn(|i| self.0[i] - rhs.0[i]))
+    }
+}
+
+impl AddAssign for Kt {
+    fn add_assign(&mut self, rhs: Kt) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Kt {
+    fn sub_assign(&mut self, rhs: Kt) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Kt {
+    type Output = Kt;
+
+    fn neg(self) -> Kt {
+        self.scale(-1.0)
+    }
+}
+
+impl Mul<f64> for Kt {
+    type Output = Kt;
+
+    fn mul(self, rhs: f64) -> Kt {
+        self.scale(rhs)
+    }
+}
+
+impl Sum for Kt {
+    fn sum<I: Iterator<Item = Kt>>(iter: I) -> Kt {
+        iter.fold(Kt::ZERO, Add::add)
+    }
+}
+
+impl From<Vec4> for Kt {
+    fn from(value: Vec4) -> Self {
+        Kt(value.0)
+    }
+}
+
+impl From<Kt> for Vec4 {
+    fn from(value: Kt) -> Self {
+        Vec4(value.0)
+    }
+}
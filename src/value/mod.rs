@@ -9,14 +9,40 @@
 use num_complex::Complex64;
 use serde::{Deserialize, Serialize};
 
+mod chunks;
+mod columnar;
+mod convert;
+mod debug;
+mod duration;
 mod extract;
+mod fieldmap;
+#[cfg(feature = "half")]
+mod float16;
+mod floats;
+#[cfg(any(feature = "server", feature = "client"))]
+mod hash;
+mod kt;
+mod rotation;
+pub mod schema;
+mod signal;
+mod spectrum;
 mod utils;
-mod debug;
+mod value_type;
+mod volume;
+
+pub use chunks::Chunks;
+pub use floats::FloatPolicy;
+pub use spectrum::{ChemicalShift, GAMMA_BAR, Peak, Spectrum};
+pub use value_type::ValueType;
 
 #[cfg(feature = "pyo3")]
 mod pyo3_extract;
 #[cfg(feature = "pyo3")]
 mod pyo3_wrap;
+#[cfg(feature = "wasm")]
+mod wasm_extract;
+#[cfg(feature = "wasm")]
+mod wasm_wrap;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum Value {
@@ -28,14 +54,19 @@ pub enum Value {
     Str(String),
     #[serde(with = "serde_bytes")]
     Bytes(Vec<u8>),
+    /// Large `Bytes` sent as attachment of the message, replaced by the bytes
+    /// when it arrives. Tools and callers never see it.
+    AttachmentRef(u32),
     Complex(Complex64),
     Vec3(atomic::Vec3),
     Vec4(atomic::Vec4),
+    Quat(atomic::Quat),
     // Structured types - (MRI) types with semantic meaning
     InstantSeqEvent(structured::InstantSeqEvent),
     Volume(structured::Volume),
     SegmentedPhantom(structured::SegmentedPhantom),
     PhantomTissue(structured::PhantomTissue),
+    Provenance(structured::Provenance),
     // Dynamic collections - each value can have a different type
     Dict(dynamic::Dict),
     List(dynamic::List),
@@ -51,28 +82,44 @@ pub mod atomic {
     pub struct Vec3(pub [f64; 3]);
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Vec4(pub [f64; 4]);
+    /// Rotation as unit quaternion `[w, x, y, z]`, see `rotation.rs` for its methods
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Quat(pub [f64; 4]);
+    /// Time span in seconds, see `duration.rs` for its methods. Not a variant
+    /// of [`super::Value`] but converts to and from `Float` (seconds).
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
+    pub struct Duration(pub f64);
 }
 
 pub mod structured {
     use std::collections::HashMap;
 
-    use super::atomic::*;
     use super::typed::*;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub enum InstantSeqEvent {
         Pulse { angle: f64, phase: f64 },
-        Fid { kt: Vec4 },
+        Fid { kt: Kt },
         Adc { phase: f64 },
     }
 
-    /// 3D voxel volume (with affine) of arbitrary (but singular) type
+    /// Gradient moment and duration `[kx, ky, kz, tau]` of a [`InstantSeqEvent::Fid`],
+    /// see `kt.rs` for its arithmetic. Converts to and from [`super::atomic::Vec4`], which
+    /// is also its representation in Python and JS.
+    #[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+    pub struct Kt(pub [f64; 4]);
+
+    /// 3D voxel volume (with affine) of arbitrary (but singular) type. The
+    /// first index changes fastest: voxel `[x, y, z]` is `data[x + nx · (y + ny · z)]`.
+    /// Created with [`Volume::new`], which checks that the data fits the shape
+    /// (as does deserialization), see `volume.rs`.
     #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(try_from = "super::volume::RawVolume")]
     pub struct Volume {
-        pub shape: [u64; 3],
-        pub affine: [[f64; 4]; 3],
-        pub data: TypedList,
+        pub(crate) shape: [u64; 3],
+        pub(crate) affine: [[f64; 4]; 3],
+        pub(crate) data: TypedList,
     }
 
     /// This does not follow the NIfTI standard exactly because that allows to
@@ -96,6 +143,24 @@ pub mod structured {
         pub t2dash: f64,
         pub adc: f64,
     }
+
+    /// Where a result came from, attached to results by servers with
+    /// [`crate::ServerConfig::provenance`] so that archived datasets stay traceable.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Provenance {
+        /// See [`crate::ServerConfig::tool_name`]
+        pub tool: Option<String>,
+        /// See [`crate::ServerConfig::tool_version`]
+        pub version: Option<String>,
+        /// Hex encoded [`super::Value::canonical_hash`] of the input
+        pub input_hash: String,
+        /// Unix time in seconds when the tool started
+        pub started: f64,
+        /// Seconds from the start until the tool returned
+        pub duration: f64,
+        /// Name of the machine that ran the tool
+        pub host: String,
+    }
 }
 
 pub mod dynamic {
@@ -121,21 +186,36 @@ pub mod typed {
     // efficiently packing values of a single type and do not support
     // nested indexing (see extract.rs). All other Value types are supported.
 
+    /// Numeric lists are serialized as raw little-endian bytes, see `columnar.rs`
     #[derive(Clone, Serialize, Deserialize)]
     pub enum TypedList {
         None(Vec<()>),
         Bool(Vec<bool>),
+        #[serde(with = "super::columnar")]
         Int(Vec<i64>),
+        #[serde(with = "super::columnar")]
         Float(Vec<f64>),
+        /// Compact storage for data that tolerates 16 bit precision, e.g. B1
+        /// or density maps, see `float16.rs`. Without the feature, received
+        /// `Half` lists fail to deserialize.
+        #[cfg(feature = "half")]
+        #[serde(with = "super::columnar")]
+        Half(Vec<half::f16>),
         Str(Vec<String>),
         Bytes(Vec<Vec<u8>>),
+        #[serde(with = "super::columnar")]
         Complex(Vec<Complex64>),
+        #[serde(with = "super::columnar")]
         Vec3(Vec<atomic::Vec3>),
+        #[serde(with = "super::columnar")]
         Vec4(Vec<atomic::Vec4>),
+        #[serde(with = "super::columnar")]
+        Quat(Vec<atomic::Quat>),
         InstantSeqEvent(Vec<structured::InstantSeqEvent>),
         Volume(Vec<structured::Volume>),
         SegmentedPhantom(Vec<structured::SegmentedPhantom>),
         PhantomTissue(Vec<structured::PhantomTissue>),
+        Provenance(Vec<structured::Provenance>),
     }
 
     impl TypedList {
@@ -145,15 +225,19 @@ pub mod typed {
                 Self::Bool(v) => v.len(),
                 Self::Int(v) => v.len(),
                 Self::Float(v) => v.len(),
+                #[cfg(feature = "half")]
+                Self::Half(v) => v.len(),
                 Self::Str(v) => v.len(),
                 Self::Bytes(v) => v.len(),
                 Self::Complex(v) => v.len(),
                 Self::Vec3(v) => v.len(),
                 Self::Vec4(v) => v.len(),
+                Self::Quat(v) => v.len(),
                 Self::InstantSeqEvent(v) => v.len(),
                 Self::Volume(v) => v.len(),
                 Self::SegmentedPhantom(v) => v.len(),
                 Self::PhantomTissue(v) => v.len(),
+                Self::Provenance(v) => v.len(),
             }
         }
     }
@@ -169,9 +253,11 @@ pub mod typed {
         Complex(HashMap<String, Complex64>),
         Vec3(HashMap<String, atomic::Vec3>),
         Vec4(HashMap<String, atomic::Vec4>),
+        Quat(HashMap<String, atomic::Quat>),
         InstantSeqEvent(HashMap<String, structured::InstantSeqEvent>),
         Volume(HashMap<String, structured::Volume>),
         SegmentedPhantom(HashMap<String, structured::SegmentedPhantom>),
         PhantomTissue(HashMap<String, structured::PhantomTissue>),
+        Provenance(HashMap<String, structured::Provenance>),
     }
 }
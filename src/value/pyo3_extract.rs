@@ -13,16 +13,16 @@ use std::collections::HashMap;
 
 use num_complex::Complex64;
 use pyo3::{
-    exceptions::PyTypeError,
+    exceptions::{PyTypeError, PyValueError},
     prelude::*,
     types::{PyBytes, PyDict, PyList},
 };
 
 use super::{
     Value,
-    atomic::{Vec3, Vec4},
+    atomic::{Quat, Vec3, Vec4},
     dynamic::{Dict, List},
-    structured::{InstantSeqEvent, PhantomTissue, SegmentedPhantom, Volume},
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
     typed::{TypedDict, TypedList},
 };
 
@@ -54,6 +54,18 @@ impl FromPyObject<'_, '_> for Vec4 {
     }
 }
 
+impl FromPyObject<'_, '_> for Quat {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, '_, PyAny>) -> PyResult<Self> {
+        let data: Vec<f64> = obj.getattr("data")?.extract()?;
+        let arr: [f64; 4] = data
+            .try_into()
+            .map_err(|_| PyTypeError::new_err("Quat.data must have 4 elements"))?;
+        Ok(Quat(arr))
+    }
+}
+
 // =============================================================================
 // Dynamic collections
 // =============================================================================
@@ -103,7 +115,7 @@ impl FromPyObject<'_, '_> for InstantSeqEvent {
             }),
             "Fid" => {
                 let kt: Vec4 = fields.get_item("kt")?.extract()?;
-                Ok(InstantSeqEvent::Fid { kt })
+                Ok(InstantSeqEvent::Fid { kt: kt.into() })
             }
             "Adc" => Ok(InstantSeqEvent::Adc {
                 phase: fields.get_item("phase")?.extract()?,
@@ -127,11 +139,7 @@ impl FromPyObject<'_, '_> for Volume {
         let affine = extract_affine(&obj.getattr("affine")?)?;
         let data: TypedList = obj.getattr("data")?.extract()?;
 
-        Ok(Volume {
-            shape,
-            affine,
-            data,
-        })
+        Volume::new(shape, affine, data).map_err(|err| PyValueError::new_err(err.to_string()))
     }
 }
 
@@ -150,6 +158,21 @@ impl FromPyObject<'_, '_> for PhantomTissue {
     }
 }
 
+impl FromPyObject<'_, '_> for Provenance {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, '_, PyAny>) -> PyResult<Self> {
+        Ok(Provenance {
+            tool: obj.getattr("tool")?.extract()?,
+            version: obj.getattr("version")?.extract()?,
+            input_hash: obj.getattr("input_hash")?.extract()?,
+            started: obj.getattr("started")?.extract()?,
+            duration: obj.getattr("duration")?.extract()?,
+            host: obj.getattr("host")?.extract()?,
+        })
+    }
+}
+
 impl FromPyObject<'_, '_> for SegmentedPhantom {
     type Error = PyErr;
 
@@ -214,6 +237,10 @@ impl FromPyObject<'_, '_> for TypedList {
                     let data: Vec<Vec4> = list.extract()?;
                     return Ok(TypedList::Vec4(data));
                 }
+                "Quat" => {
+                    let data: Vec<Quat> = list.extract()?;
+                    return Ok(TypedList::Quat(data));
+                }
                 "InstantSeqEvent" => {
                     let data: Vec<InstantSeqEvent> = list.extract()?;
                     return Ok(TypedList::InstantSeqEvent(data));
@@ -230,6 +257,10 @@ impl FromPyObject<'_, '_> for TypedList {
                     let data: Vec<SegmentedPhantom> = list.extract()?;
                     return Ok(TypedList::SegmentedPhantom(data));
                 }
+                "Provenance" => {
+                    let data: Vec<Provenance> = list.extract()?;
+                    return Ok(TypedList::Provenance(data));
+                }
                 _ => {}
             }
         }
@@ -292,6 +323,10 @@ impl FromPyObject<'_, '_> for TypedDict {
                     let data: HashMap<String, Vec4> = dict.extract()?;
                     return Ok(TypedDict::Vec4(data));
                 }
+                "Quat" => {
+                    let data: HashMap<String, Quat> = dict.extract()?;
+                    return Ok(TypedDict::Quat(data));
+                }
                 "InstantSeqEvent" => {
                     let data: HashMap<String, InstantSeqEvent> = dict.extract()?;
                     return Ok(TypedDict::InstantSeqEvent(data));
@@ -308,6 +343,10 @@ impl FromPyObject<'_, '_> for TypedDict {
                     let data: HashMap<String, SegmentedPhantom> = dict.extract()?;
                     return Ok(TypedDict::SegmentedPhantom(data));
                 }
+                "Provenance" => {
+                    let data: HashMap<String, Provenance> = dict.extract()?;
+                    return Ok(TypedDict::Provenance(data));
+                }
                 _ => {}
             }
         }
@@ -328,7 +367,14 @@ fn py_list_is_structured_type(obj: &Bound<'_, PyAny>) -> bool {
         .map(|name| {
             matches!(
                 name.to_string().as_str(),
-                "InstantSeqEvent" | "Vec3" | "Vec4" | "Volume" | "PhantomTissue" | "SegmentedPhantom"
+                "InstantSeqEvent"
+                    | "Vec3"
+                    | "Vec4"
+                    | "Quat"
+                    | "Volume"
+                    | "PhantomTissue"
+                    | "SegmentedPhantom"
+                    | "Provenance"
             )
         })
         .unwrap_or(false)
@@ -427,9 +473,11 @@ fn value_from_py(obj: &Bound<'_, PyAny>, force_dynamic: bool) -> PyResult<Value>
     match type_name.as_str() {
         "Vec3" => Ok(Value::Vec3(obj.extract()?)),
         "Vec4" => Ok(Value::Vec4(obj.extract()?)),
+        "Quat" => Ok(Value::Quat(obj.extract()?)),
         "Volume" => Ok(Value::Volume(obj.extract()?)),
         "PhantomTissue" => Ok(Value::PhantomTissue(obj.extract()?)),
         "SegmentedPhantom" => Ok(Value::SegmentedPhantom(obj.extract()?)),
+        "Provenance" => Ok(Value::Provenance(obj.extract()?)),
         "InstantSeqEvent" => Ok(Value::InstantSeqEvent(obj.extract()?)),
         other => Err(PyTypeError::new_err(format!(
             "unknown toolapi value type: {other}"
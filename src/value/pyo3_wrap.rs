@@ -19,9 +19,9 @@ use pyo3::{
 
 use super::{
     Value,
-    atomic::{Vec3, Vec4},
+    atomic::{Quat, Vec3, Vec4},
     dynamic::{Dict, List},
-    structured::{InstantSeqEvent, PhantomTissue, SegmentedPhantom, Volume},
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
     typed::{TypedDict, TypedList},
 };
 
@@ -47,6 +47,8 @@ fn typed_list_to_py_list<'py>(py: Python<'py>, tl: TypedList) -> PyResult<Bound<
         TypedList::Bool(v) => PyList::new(py, v),
         TypedList::Int(v) => PyList::new(py, v),
         TypedList::Float(v) => PyList::new(py, v),
+        #[cfg(feature = "half")]
+        TypedList::Half(v) => PyList::new(py, v.into_iter().map(|x| x.to_f64())),
         TypedList::Str(v) => PyList::new(py, v),
         TypedList::Bytes(v) => PyList::new(py, v),
         TypedList::Complex(v) => PyList::new(py, v),
@@ -66,6 +68,14 @@ fn typed_list_to_py_list<'py>(py: Python<'py>, tl: TypedList) -> PyResult<Bound<
             }
             Ok(l)
         }
+        TypedList::Quat(v) => {
+            let l = PyList::empty(py);
+            let cls = value_class(py, "Quat")?;
+            for item in v {
+                l.append(cls.call1((item.0.to_vec(),))?)?;
+            }
+            Ok(l)
+        }
         TypedList::InstantSeqEvent(v) => {
             let l = PyList::empty(py);
             for item in v {
@@ -94,6 +104,13 @@ fn typed_list_to_py_list<'py>(py: Python<'py>, tl: TypedList) -> PyResult<Bound<
             }
             Ok(l)
         }
+        TypedList::Provenance(v) => {
+            let l = PyList::empty(py);
+            for item in v {
+                l.append(item.into_pyobject(py)?)?;
+            }
+            Ok(l)
+        }
     }
 }
 
@@ -123,6 +140,17 @@ impl<'py> IntoPyObject<'py> for Vec4 {
     }
 }
 
+impl<'py> IntoPyObject<'py> for Quat {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let cls = value_class(py, "Quat")?;
+        cls.call1((self.0.to_vec(),))
+    }
+}
+
 // =============================================================================
 // Dynamic collections
 // =============================================================================
@@ -171,7 +199,7 @@ impl<'py> IntoPyObject<'py> for InstantSeqEvent {
         match self {
             InstantSeqEvent::Pulse { angle, phase } => cls.call_method1("Pulse", (angle, phase)),
             InstantSeqEvent::Fid { kt } => {
-                let kt_obj = kt.into_pyobject(py)?;
+                let kt_obj = Vec4::from(kt).into_pyobject(py)?;
                 cls.call_method1("Fid", (kt_obj,))
             }
             InstantSeqEvent::Adc { phase } => cls.call_method1("Adc", (phase,)),
@@ -230,6 +258,24 @@ impl<'py> IntoPyObject<'py> for SegmentedPhantom {
     }
 }
 
+impl<'py> IntoPyObject<'py> for Provenance {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        let cls = value_class(py, "Provenance")?;
+        cls.call1((
+            self.tool,
+            self.version,
+            self.input_hash,
+            self.started,
+            self.duration,
+            self.host,
+        ))
+    }
+}
+
 // =============================================================================
 // TypedList
 // =============================================================================
@@ -303,6 +349,12 @@ impl<'py> IntoPyObject<'py> for TypedDict {
                     dict.set_item(k, cls.call1((v.0.to_vec(),))?)?;
                 }
             }
+            TypedDict::Quat(m) => {
+                let cls = value_class(py, "Quat")?;
+                for (k, v) in m {
+                    dict.set_item(k, cls.call1((v.0.to_vec(),))?)?;
+                }
+            }
             TypedDict::InstantSeqEvent(m) => {
                 for (k, v) in m {
                     dict.set_item(k, v.into_pyobject(py)?)?;
@@ -323,6 +375,11 @@ impl<'py> IntoPyObject<'py> for TypedDict {
                     dict.set_item(k, v.into_pyobject(py)?)?;
                 }
             }
+            TypedDict::Provenance(m) => {
+                for (k, v) in m {
+                    dict.set_item(k, v.into_pyobject(py)?)?;
+                }
+            }
         }
         Ok(dict)
     }
@@ -345,13 +402,18 @@ impl<'py> IntoPyObject<'py> for Value {
             Value::Float(f) => f.into_bound_py_any(py),
             Value::Str(s) => s.into_bound_py_any(py),
             Value::Bytes(b) => b.into_bound_py_any(py),
+            Value::AttachmentRef(id) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "attachment {id} was not resolved"
+            ))),
             Value::Complex(c) => c.into_bound_py_any(py),
             Value::Vec3(v) => v.into_bound_py_any(py),
             Value::Vec4(v) => v.into_bound_py_any(py),
+            Value::Quat(q) => q.into_bound_py_any(py),
             Value::InstantSeqEvent(e) => e.into_bound_py_any(py),
             Value::Volume(v) => v.into_bound_py_any(py),
             Value::PhantomTissue(pt) => pt.into_bound_py_any(py),
             Value::SegmentedPhantom(sp) => sp.into_bound_py_any(py),
+            Value::Provenance(p) => p.into_bound_py_any(py),
             Value::Dict(d) => d.into_bound_py_any(py),
             Value::List(l) => l.into_bound_py_any(py),
             Value::TypedList(tl) => tl.into_bound_py_any(py),
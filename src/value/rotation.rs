@@ -0,0 +1,139 @@
+//! Rotations with [`Quat`], e.g. slice orientations or motion states.
+//!
+//! Quaternions are stored as `[w, x, y, z]` (scalar first) and rotate vectors
+//! actively in a right-handed coordinate system. Composition uses the Hamilton
+//! product, so `a.compose(&b)` first applies `b`, then `a` (like matrices).
+
+use super::atomic::{Quat, Vec3};
+
+impl Quat {
+    /// No rotation
+    pub const IDENTITY: Quat = Quat([1.0, 0.0, 0.0, 0.0]);
+
+    /// Rotation by `angle` (radians) around `axis`, which doesn't need to be normalized.
+    ///
+    /// ```
+    /// # use toolapi::value::atomic::{Quat, Vec3};
+    /// let rot = Quat::from_axis_angle(Vec3([0.0, 0.0, 2.0]), std::f64::consts::FRAC_PI_2);
+    /// let Vec3([x, y, z]) = rot.rotate(&Vec3([1.0, 0.0, 0.0]));
+    /// assert!(x.abs() < 1e-12 && (y - 1.0).abs() < 1e-12 && z.abs() < 1e-12);
+    /// ```
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Self {
+        let [x, y, z] = axis.0;
+        let norm = (x * x + y * y + z * z).sqrt();
+        let s = (angle / 2.0).sin() / norm;
+        Quat([(angle / 2.0).cos(), x * s, y * s, z * s])
+    }
+
+    /// Rotation with the given 3×3 matrix (rows), which must be orthonormal.
+    pub fn from_matrix(m: [[f64; 3]; 3]) -> Self {
+        // Shepperd's method: divide by the largest of the four candidates
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            [
+                s / 4.0,
+                (m[2][1] - m[1][2]) / s,
+                (m[0][2] - m[2][0]) / s,
+                (m[1][0] - m[0][1]) / s,
+            ]
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            [
+                (m[2][1] - m[1][2]) / s,
+                s / 4.0,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+            ]
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            [
+                (m[0][2] - m[2][0]) / s,
+                (m[0][1] + m[1][0]) / s,
+                s / 4.0,
+                (m[1][2] + m[2][1]) / s,
+            ]
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            [
+                (m[1][0] - m[0][1]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                s / 4.0,
+            ]
+        };
+        Quat(q).normalized()
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.0.iter().map(|x| x * x).sum::<f64>().sqrt()
+    }
+
+    /// Scaled to unit length, which all other methods expect. Quaternions
+    /// received from clients should be normalized before they are used.
+    /// The zero quaternion has no direction and results in NaNs.
+    pub fn normalized(&self) -> Self {
+        let norm = self.norm();
+        Quat(self.0.map(|x| x / norm))
+    }
+
+    /// Rotation in the opposite direction
+    pub fn inverse(&self) -> Self {
+        let [w, x, y, z] = self.0;
+        Quat([w, -x, -y, -z])
+    }
+
+    /// Rotation that first applies `other` and then `self`.
+    ///
+    /// ```
+    /// # use toolapi::value::atomic::{Quat, Vec3};
+    /// let quarter = Quat::from_axis_angle(Vec3([1.0, 0.0, 0.0]), std::f64::consts::FRAC_PI_2);
+    /// let half = quarter.compose(&quarter);
+    /// let Vec3([_, y, _]) = half.rotate(&Vec3([0.0, 1.0, 0.0]));
+    /// assert!((y + 1.0).abs() < 1e-12);
+    /// ```
+    pub fn compose(&self, other: &Quat) -> Self {
+        let [w1, x1, y1, z1] = self.0;
+        let [w2, x2, y2, z2] = other.0;
+        Quat([
+            w1 * w2 - x1 * x2 - y1 * y2 - z1 * z2,
+            w1 * x2 + x1 * w2 + y1 * z2 - z1 * y2,
+            w1 * y2 - x1 * z2 + y1 * w2 + z1 * x2,
+            w1 * z2 + x1 * y2 - y1 * x2 + z1 * w2,
+        ])
+    }
+
+    pub fn rotate(&self, v: &Vec3) -> Vec3 {
+        let m = self.to_matrix();
+        Vec3(m.map(|row| row[0] * v.0[0] + row[1] * v.0[1] + row[2] * v.0[2]))
+    }
+
+    /// 3×3 rotation matrix (rows)
+    pub fn to_matrix(&self) -> [[f64; 3]; 3] {
+        let [w, x, y, z] = self.0;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Affine that rotates and then translates, in the layout of
+    /// [`super::structured::Volume::affine`] (3 rows of a 4×4 matrix)
+    pub fn to_affine(&self, translation: Vec3) -> [[f64; 4]; 3] {
+        let m = self.to_matrix();
+        std::array::from_fn(|i| [m[i][0], m[i][1], m[i][2], translation.0[i]])
+    }
+}
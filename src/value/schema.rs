@@ -0,0 +1,338 @@
+//! Description of the expected structure of tool inputs, see [`ValueSchema`].
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+};
+
+use super::{
+    Value, ValueType,
+    extract::{get_typed_dict, value_variant_name},
+};
+use crate::{ToolError, value::dynamic::Dict};
+
+/// Expected structure of a [`Value`], checked by [`ValueSchema::validate`].
+///
+/// Atomic and structured variants match values of the same variant. Typed
+/// containers match [`ValueSchema::List`] and [`ValueSchema::DictOf`] if their
+/// element type matches. Registered with [`crate::ServerConfig::input_schema`],
+/// the server rejects invalid inputs without starting the tool.
+///
+/// ```
+/// # use toolapi::{Value, value::{dynamic::Dict, schema::ValueSchema}};
+/// let schema = ValueSchema::dict()
+///     .required("t1", ValueSchema::Float)
+///     .required("flip_angles", ValueSchema::list_of(ValueSchema::Float))
+///     .optional("steps", ValueSchema::Int);
+///
+/// let input = Value::Dict(Dict([
+///     ("t1".to_string(), Value::Int(1)),
+///     ("steps".to_string(), Value::Int(100)),
+///     ("stesp".to_string(), Value::Int(100)),
+/// ].into()));
+/// let violations: Vec<String> = schema
+///     .violations(&input)
+///     .iter()
+///     .map(|v| v.to_string())
+///     .collect();
+/// assert_eq!(violations, [
+///     "`flip_angles`: missing",
+///     "`stesp`: unknown key",
+///     "`t1`: expected Float, found Value::Int",
+/// ]);
+/// ```
+#[derive(Debug, Clone)]
+pub enum ValueSchema {
+    /// Accepts every value
+    Any,
+    None,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Bytes,
+    Complex,
+    Vec3,
+    Vec4,
+    Quat,
+    InstantSeqEvent,
+    Volume,
+    SegmentedPhantom,
+    PhantomTissue,
+    Provenance,
+    /// List or TypedList with all elements matching
+    List(Box<ValueSchema>),
+    /// Dict or TypedDict with all values matching, keys are arbitrary
+    DictOf(Box<ValueSchema>),
+    /// Dict with known keys, all others are rejected (catches typos)
+    Record {
+        required: BTreeMap<String, ValueSchema>,
+        optional: BTreeMap<String, ValueSchema>,
+    },
+    /// Any of the given schemas matches
+    OneOf(Vec<ValueSchema>),
+}
+
+/// Location and description of a value that doesn't match a [`ValueSchema`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// '/' separated, like [`super::extract::Pointer`], empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`: {}", self.path, self.message)
+    }
+}
+
+impl SchemaViolation {
+    /// The message of a single violation, otherwise all of them
+    pub(crate) fn summary(violations: &[SchemaViolation]) -> String {
+        match violations {
+            [single] => single.message.clone(),
+            all => {
+                let all: Vec<String> = all.iter().map(|v| v.to_string()).collect();
+                format!("{} violations: {}", all.len(), all.join(", "))
+            }
+        }
+    }
+
+    /// Error details with a `violations` list of `path` and `message` dicts
+    pub(crate) fn details(violations: &[SchemaViolation]) -> Dict {
+        let list = violations
+            .iter()
+            .map(|v| {
+                Value::Dict(Dict(
+                    [
+                        ("path".to_string(), Value::Str(v.path.clone())),
+                        ("message".to_string(), Value::Str(v.message.clone())),
+                    ]
+                    .into(),
+                ))
+            })
+            .collect();
+        Dict(
+            [(
+                "violations".to_string(),
+                Value::List(super::dynamic::List(list)),
+            )]
+            .into(),
+        )
+    }
+}
+
+impl ValueSchema {
+    /// Empty [`ValueSchema::Record`], add entries with [`Self::required`] and [`Self::optional`].
+    pub fn dict() -> Self {
+        Self::Record {
+            required: BTreeMap::new(),
+            optional: BTreeMap::new(),
+        }
+    }
+
+    pub fn list_of(schema: ValueSchema) -> Self {
+        Self::List(Box::new(schema))
+    }
+
+    pub fn dict_of(schema: ValueSchema) -> Self {
+        Self::DictOf(Box::new(schema))
+    }
+
+    /// Add a required entry to a [`ValueSchema::Record`].
+    ///
+    /// # Panics
+    /// If `self` is not a [`ValueSchema::Record`].
+    pub fn required(mut self, key: impl Into<String>, schema: ValueSchema) -> Self {
+        match &mut self {
+            Self::Record { required, .. } => required.insert(key.into(), schema),
+            _ => panic!("only a ValueSchema::Record has entries"),
+        };
+        self
+    }
+
+    /// Add an optional entry to a [`ValueSchema::Record`], it may also be `Value::None`.
+    ///
+    /// # Panics
+    /// If `self` is not a [`ValueSchema::Record`].
+    pub fn optional(mut self, key: impl Into<String>, schema: ValueSchema) -> Self {
+        match &mut self {
+            Self::Record { optional, .. } => optional.insert(key.into(), schema),
+            _ => panic!("only a ValueSchema::Record has entries"),
+        };
+        self
+    }
+
+    /// Check `value`, the error lists all violations (see [`Self::violations`]).
+    pub fn validate(&self, value: &Value) -> Result<(), ToolError> {
+        let violations = self.violations(value);
+        match violations.first() {
+            Some(first) => Err(ToolError::invalid_input(
+                first.path.clone(),
+                SchemaViolation::summary(&violations),
+            )
+            .with_details(SchemaViolation::details(&violations))),
+            None => Ok(()),
+        }
+    }
+
+    /// All places where `value` doesn't match, ordered by path (keys
+    /// alphabetically, list elements by index).
+    pub fn violations(&self, value: &Value) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.check(value, "", &mut violations);
+        violations
+    }
+
+    fn check(&self, value: &Value, path: &str, out: &mut Vec<SchemaViolation>) {
+        let mut violation = |message: String| {
+            out.push(SchemaViolation {
+                path: path.to_string(),
+                message,
+            })
+        };
+        let expected = |what: &str| format!("expected {what}, found {}", value_variant_name(value));
+
+        match (self, value) {
+            (Self::Any, _) => {}
+            (Self::OneOf(options), _) => {
+                if !options.iter().any(|s| s.violations(value).is_empty()) {
+                    violation(expected(&self.describe()));
+                }
+            }
+
+            (Self::List(schema), Value::List(list)) => {
+                for (index, value) in list.0.iter().enumerate() {
+                    schema.check(value, &join(path, &index.to_string()), out);
+                }
+            }
+            (Self::List(schema), Value::TypedList(list)) => {
+                if !schema.accepts_typed(list.element_type(), list.is_empty()) {
+                    violation(expected(&self.describe()));
+                }
+            }
+            (Self::DictOf(schema), Value::Dict(dict)) => {
+                for (key, value) in sorted(&dict.0) {
+                    schema.check(value, &join(path, key), out);
+                }
+            }
+            (Self::DictOf(schema), Value::TypedDict(dict)) => {
+                if !schema.accepts_typed(dict.element_type(), dict.is_empty()) {
+                    violation(expected(&self.describe()));
+                }
+            }
+            (Self::Record { required, optional }, Value::Dict(dict)) => {
+                let keys = dict.0.keys().collect();
+                let entry = |key: &str| dict.0.get(key).map(Cow::Borrowed);
+                check_record(required, optional, keys, entry, path, out);
+            }
+            (Self::Record { required, optional }, Value::TypedDict(dict)) => {
+                let entry = |key: &str| get_typed_dict(dict, key).ok().map(Cow::Owned);
+                check_record(required, optional, dict.keys(), entry, path, out);
+            }
+
+            (schema, value) => match schema.leaf_type() {
+                Some(leaf) if value.type_of() == leaf => {}
+                _ => violation(expected(&schema.describe())),
+            },
+        }
+    }
+
+    /// If elements of a typed container of `element` type match
+    fn accepts_typed(&self, element: ValueType, is_empty: bool) -> bool {
+        match self {
+            Self::Any => true,
+            Self::OneOf(options) => options.iter().any(|s| s.accepts_typed(element, is_empty)),
+            // Typed containers only hold atomic and structured values
+            _ => is_empty || self.leaf_type() == Some(element),
+        }
+    }
+
+    fn leaf_type(&self) -> Option<ValueType> {
+        Some(match self {
+            Self::None => ValueType::None,
+            Self::Bool => ValueType::Bool,
+            Self::Int => ValueType::Int,
+            Self::Float => ValueType::Float,
+            Self::Str => ValueType::Str,
+            Self::Bytes => ValueType::Bytes,
+            Self::Complex => ValueType::Complex,
+            Self::Vec3 => ValueType::Vec3,
+            Self::Vec4 => ValueType::Vec4,
+            Self::Quat => ValueType::Quat,
+            Self::InstantSeqEvent => ValueType::InstantSeqEvent,
+            Self::Volume => ValueType::Volume,
+            Self::SegmentedPhantom => ValueType::SegmentedPhantom,
+            Self::PhantomTissue => ValueType::PhantomTissue,
+            Self::Provenance => ValueType::Provenance,
+            _ => return None,
+        })
+    }
+
+    /// Short description for error messages, e.g. `List of Float`
+    fn describe(&self) -> String {
+        match self {
+            Self::Any => "any value".to_string(),
+            Self::List(schema) => format!("List of {}", schema.describe()),
+            Self::DictOf(schema) => format!("Dict of {}", schema.describe()),
+            Self::Record { .. } => "Dict".to_string(),
+            Self::OneOf(options) => {
+                let options: Vec<String> = options.iter().map(Self::describe).collect();
+                format!("one of ({})", options.join(", "))
+            }
+            leaf => leaf.leaf_type().map(|t| t.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Check the entries of a [`ValueSchema::Record`], `entry` looks up values
+fn check_record<'a>(
+    required: &BTreeMap<String, ValueSchema>,
+    optional: &BTreeMap<String, ValueSchema>,
+    keys: Vec<&String>,
+    entry: impl Fn(&str) -> Option<Cow<'a, Value>>,
+    path: &str,
+    out: &mut Vec<SchemaViolation>,
+) {
+    let mut entries: BTreeMap<&str, Option<&ValueSchema>> = BTreeMap::new();
+    for key in keys {
+        entries.insert(key, None);
+    }
+    for (key, schema) in required.iter().chain(optional) {
+        entries.insert(key, Some(schema));
+    }
+
+    for (key, schema) in entries {
+        let path = join(path, key);
+        let Some(schema) = schema else {
+            out.push(SchemaViolation {
+                path,
+                message: "unknown key".to_string(),
+            });
+            continue;
+        };
+        match entry(key).as_deref() {
+            Some(Value::None(())) if optional.contains_key(key) => {}
+            Some(value) => schema.check(value, &path, out),
+            None if required.contains_key(key) => out.push(SchemaViolation {
+                path,
+                message: "missing".to_string(),
+            }),
+            None => {}
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        path => format!("{path}/{key}"),
+    }
+}
+
+fn sorted<T>(map: &HashMap<String, T>) -> Vec<(&String, &T)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
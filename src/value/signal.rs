@@ -0,0 +1,306 @@
+//! Closed-form steady-state signals of gradient echo sequences, a quick
+//! preview of the contrast of a [`PhantomTissue`] or [`SegmentedPhantom`]
+//! without simulating the sequence.
+//!
+//! Times are in seconds, flip angles in radians and `db0` in Hz. The result
+//! has the shape and affine of the density, the signal is its magnitude
+//! times the steady state of the tissue. The flip angle is the nominal one,
+//! the `b1_tx` of a phantom is not applied. With a [`ChemicalShift`], the
+//! peaks of every tissue are summed with their phase at the echo time.
+
+use std::f64::consts::{PI, TAU};
+
+use num_complex::Complex64;
+
+use super::{
+    fieldmap::{samples, with_data},
+    spectrum::ChemicalShift,
+    structured::{PhantomTissue, SegmentedPhantom, Volume},
+    typed::TypedList,
+};
+use crate::ToolError;
+
+impl PhantomTissue {
+    /// Spoiled gradient echo (FLASH / SPGR) with perfect spoiling:
+    /// `sin α (1 - E1) / (1 - cos α E1) e^{-TE / T2*}`, where T2* combines
+    /// T2 and T2'.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::*, typed::TypedList}};
+    /// # let volume = |x: f64| Volume::new(
+    /// #     [1, 1, 1],
+    /// #     [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    /// #     TypedList::Float(vec![x]),
+    /// # ).unwrap();
+    /// # let tissue = PhantomTissue { density: volume(0.8), db0: volume(0.0), t1: 1.0, t2: 0.08, t2dash: 0.05, adc: 0.0 };
+    /// // The Ernst angle gives the highest signal
+    /// let (tr, e1) = (0.01, (-0.01f64).exp());
+    /// let signal = |angle| -> Result<f64, ToolError> {
+    ///     let TypedList::Float(s) = tissue.flash_signal(tr, 0.0, angle)?.into_data() else { unreachable!() };
+    ///     Ok(s[0])
+    /// };
+    /// let ernst = e1.acos();
+    /// assert!(signal(ernst)? > signal(ernst * 0.9)? && signal(ernst)? > signal(ernst * 1.1)?);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn flash_signal(&self, tr: f64, te: f64, angle: f64) -> Result<Volume, ToolError> {
+        check_times(tr, te)?;
+        let e1 = (-tr / self.t1).exp();
+        let r2_star = 1.0 / self.t2 + 1.0 / self.t2dash;
+        let steady = angle.sin() * (1.0 - e1) / (1.0 - angle.cos() * e1) * (-te * r2_star).exp();
+        let density = samples(&self.density, &self.density, "density", |z| z.norm())?;
+        Ok(with_data(
+            &self.density,
+            density.iter().map(|rho| rho * steady).collect(),
+        ))
+    }
+
+    /// Balanced SSFP with alternating RF phase and the echo at TE = TR/2,
+    /// including the off-resonance banding of `db0` (Freeman-Hill).
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::*, typed::TypedList}};
+    /// # let volume = |x: Vec<f64>| Volume::new(
+    /// #     [2, 1, 1],
+    /// #     [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    /// #     TypedList::Float(x),
+    /// # ).unwrap();
+    /// let tr = 5e-3;
+    /// // The second voxel is in the band at 1 / (2 TR) off-resonance
+    /// let tissue = PhantomTissue {
+    ///     density: volume(vec![1.0, 1.0]),
+    ///     db0: volume(vec![0.0, 1.0 / (2.0 * tr)]),
+    ///     t1: 1.0,
+    ///     t2: 0.1,
+    ///     t2dash: 0.05,
+    ///     adc: 0.0,
+    /// };
+    /// let TypedList::Float(s) = tissue.bssfp_signal(tr, 1.0)?.into_data() else { unreachable!() };
+    /// let (e1, e2) = ((-tr / 1.0f64).exp(), (-tr / 0.1f64).exp());
+    /// let on_resonance = 1f64.sin() * (1.0 - e1) / (1.0 - (e1 - e2) * 1f64.cos() - e1 * e2);
+    /// assert!((s[0] - on_resonance * (-tr / 2.0 / 0.1f64).exp()).abs() < 1e-12);
+    /// assert!(s[1] < 0.1 * s[0]);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn bssfp_signal(&self, tr: f64, angle: f64) -> Result<Volume, ToolError> {
+        let signal = self.bssfp_echo(tr, angle, 0.0)?;
+        Ok(with_data(
+            &self.density,
+            signal.iter().map(|x| x.abs()).collect(),
+        ))
+    }
+
+    /// Signal at TE = TR/2 for every voxel with an additional off-resonance
+    /// (Hz). It is real, the sign flips from one band to the next.
+    fn bssfp_echo(&self, tr: f64, angle: f64, offset: f64) -> Result<Vec<f64>, ToolError> {
+        check_times(tr, 0.0)?;
+        let (e1, e2) = ((-tr / self.t1).exp(), (-tr / self.t2).exp());
+        let (sin, cos) = angle.sin_cos();
+        let c = e2 * (e1 - 1.0) * (1.0 + cos);
+        let d = (1.0 - e1 * cos) - (e1 - cos) * e2 * e2;
+        let decay = (-tr / 2.0 / self.t2).exp();
+
+        let density = samples(&self.density, &self.density, "density", |z| z.norm())?;
+        let db0 = samples(&self.db0, &self.density, "db0", |z| z.re)?;
+        let signal = density
+            .iter()
+            .zip(&db0)
+            .map(|(rho, df)| {
+                let df = df + offset;
+                // Precession per TR, the alternating RF phase shifts it by π
+                let theta = TAU * df * tr + PI;
+                let numerator = (1.0 - 2.0 * e2 * theta.cos() + e2 * e2).sqrt();
+                let sign = (PI * df * tr).cos().signum();
+                sign * rho * (1.0 - e1) * sin * numerator / (c * theta.cos() + d) * decay
+            })
+            .collect();
+        Ok(signal)
+    }
+}
+
+impl SegmentedPhantom {
+    /// [`PhantomTissue::flash_signal`] summed over the tissues, which must
+    /// all have the same shape.
+    pub fn flash_signal(&self, tr: f64, te: f64, angle: f64) -> Result<Volume, ToolError> {
+        check_times(tr, te)?;
+        self.sum_tissues(|tissue| tissue.flash_signal(tr, te, angle))
+    }
+
+    /// [`PhantomTissue::bssfp_signal`] summed over the tissues, which must
+    /// all have the same shape.
+    pub fn bssfp_signal(&self, tr: f64, angle: f64) -> Result<Volume, ToolError> {
+        check_times(tr, 0.0)?;
+        self.sum_tissues(|tissue| tissue.bssfp_signal(tr, angle))
+    }
+
+    /// [`SegmentedPhantom::flash_signal`] with the chemical shift and `db0`
+    /// of the tissues, the result is `Complex`. For example, the signal of
+    /// voxels with water and fat depends on whether they are in phase at TE.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{ChemicalShift, Spectrum, structured::*, typed::TypedList}};
+    /// # use std::collections::HashMap;
+    /// # let volume = |x: f64| Volume::new(
+    /// #     [1, 1, 1],
+    /// #     [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]],
+    /// #     TypedList::Float(vec![x]),
+    /// # ).unwrap();
+    /// let tissue = |density| PhantomTissue {
+    ///     density: volume(density),
+    ///     db0: volume(0.0),
+    ///     t1: 1.0,
+    ///     t2: 0.1,
+    ///     t2dash: f64::INFINITY,
+    ///     adc: 0.0,
+    /// };
+    /// let phantom = SegmentedPhantom {
+    ///     tissues: HashMap::from([("water".to_string(), tissue(0.5)), ("fat".to_string(), tissue(0.5))]),
+    ///     b1_tx: vec![],
+    ///     b1_rx: vec![],
+    /// };
+    /// let shift = ChemicalShift::new(3.0).with_spectrum("fat", Spectrum::fat());
+    /// let magnitude = |te| -> Result<f64, ToolError> {
+    ///     let volume = phantom.flash_signal_with(0.01, te, 0.2, &shift)?;
+    ///     let TypedList::Complex(s) = volume.into_data() else { unreachable!() };
+    ///     Ok(s[0].norm())
+    /// };
+    /// // The main fat peak is opposed to water at about 1.15 ms and in phase at 2.3 ms
+    /// assert!(magnitude(1.15e-3)? < 0.5 * magnitude(2.3e-3)?);
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn flash_signal_with(
+        &self,
+        tr: f64,
+        te: f64,
+        angle: f64,
+        shift: &ChemicalShift,
+    ) -> Result<Volume, ToolError> {
+        check_times(tr, te)?;
+        self.sum_complex(|name, tissue| {
+            let magnitude = tissue.flash_signal(tr, te, angle)?;
+            let magnitude = samples(&magnitude, &tissue.density, "density", |z| z.re)?;
+            let db0 = samples(&tissue.db0, &tissue.density, "db0", |z| z.re)?;
+            let peaks = shift.peaks(name);
+            Ok(magnitude
+                .iter()
+                .zip(&db0)
+                .map(|(m, df)| {
+                    peaks
+                        .iter()
+                        .map(|(f, a)| m * a * Complex64::from_polar(1.0, -TAU * (df + f) * te))
+                        .sum()
+                })
+                .collect())
+        })
+    }
+
+    /// [`SegmentedPhantom::bssfp_signal`] with the chemical shift of the
+    /// tissues, every peak has its own banding. The result is `Complex`.
+    pub fn bssfp_signal_with(
+        &self,
+        tr: f64,
+        angle: f64,
+        shift: &ChemicalShift,
+    ) -> Result<Volume, ToolError> {
+        check_times(tr, 0.0)?;
+        self.sum_complex(|name, tissue| {
+            let mut sum = vec![Complex64::ZERO; tissue.density.data.len()];
+            for (f, a) in shift.peaks(name) {
+                let echo = tissue.bssfp_echo(tr, angle, f)?;
+                sum.iter_mut().zip(echo).for_each(|(sum, x)| *sum += a * x);
+            }
+            Ok(sum)
+        })
+    }
+
+    /// Complex sum of the voxels of every tissue
+    fn sum_complex(
+        &self,
+        signal: impl Fn(&str, &PhantomTissue) -> Result<Vec<Complex64>, ToolError>,
+    ) -> Result<Volume, ToolError> {
+        let names = self.sorted_tissues()?;
+        let first = &self.tissues[names[0]].density;
+        let mut sum = vec![Complex64::ZERO; first.data.len()];
+        for name in names {
+            let tissue = &self.tissues[name];
+            if tissue.density.shape != first.shape {
+                return Err(shape_mismatch(name, &tissue.density, first));
+            }
+            let values =
+                signal(name, tissue).map_err(|err| prefix(err, &format!("tissues/{name}")))?;
+            sum.iter_mut().zip(values).for_each(|(sum, x)| *sum += x);
+        }
+        Ok(Volume {
+            shape: first.shape,
+            affine: first.affine,
+            data: TypedList::Complex(sum),
+        })
+    }
+
+    fn sorted_tissues(&self) -> Result<Vec<&String>, ToolError> {
+        let mut names: Vec<&String> = self.tissues.keys().collect();
+        names.sort();
+        match names.is_empty() {
+            true => Err(ToolError::invalid_input(
+                "tissues",
+                "the phantom has no tissues",
+            )),
+            false => Ok(names),
+        }
+    }
+
+    fn sum_tissues(
+        &self,
+        signal: impl Fn(&PhantomTissue) -> Result<Volume, ToolError>,
+    ) -> Result<Volume, ToolError> {
+        let names = self.sorted_tissues()?;
+        let first = &self.tissues[names[0]].density;
+        let mut sum = vec![0.0; first.data.len()];
+        for name in names {
+            let volume = signal(&self.tissues[name])
+                .map_err(|err| prefix(err, &format!("tissues/{name}")))?;
+            let values = samples(&volume, first, &format!("tissues/{name}/density"), |z| z.re)?;
+            sum.iter_mut().zip(values).for_each(|(sum, x)| *sum += x);
+        }
+        Ok(with_data(first, sum))
+    }
+}
+
+fn check_times(tr: f64, te: f64) -> Result<(), ToolError> {
+    if tr.is_nan() || tr <= 0.0 {
+        return Err(ToolError::invalid_input(
+            "tr",
+            format!("{tr} is not positive"),
+        ));
+    }
+    if te.is_nan() || te < 0.0 || te > tr {
+        return Err(ToolError::invalid_input(
+            "te",
+            format!("{te} is not between 0 and TR"),
+        ));
+    }
+    Ok(())
+}
+
+fn shape_mismatch(name: &str, volume: &Volume, first: &Volume) -> ToolError {
+    ToolError::invalid_input(
+        format!("tissues/{name}/density"),
+        format!("shape {:?} differs from {:?}", volume.shape, first.shape),
+    )
+}
+
+/// Invalid inputs of a tissue name the tissue in their path
+fn prefix(err: ToolError, parent: &str) -> ToolError {
+    match err {
+        ToolError::InvalidInput {
+            path,
+            message,
+            details,
+        } => ToolError::InvalidInput {
+            path: format!("{parent}/{path}"),
+            message,
+            details,
+        },
+        err => err,
+    }
+}
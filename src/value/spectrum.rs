@@ -0,0 +1,101 @@
+//! Chemical shift of tissues with several resonances, e.g. fat.
+//!
+//! [`PhantomTissue`](super::structured::PhantomTissue) has a single
+//! resonance (water, shifted by `db0`). A [`ChemicalShift`] assigns a
+//! [`Spectrum`] to tissues of a phantom by name, it is accepted by the
+//! signal equations (`signal.rs`).
+//! It is not part of the phantom on the wire, tools take it as parameters.
+
+use std::collections::HashMap;
+
+/// Gyromagnetic ratio of hydrogen divided by 2π, in Hz/T
+pub const GAMMA_BAR: f64 = 42.577_478_461e6;
+
+/// One resonance of a [`Spectrum`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    /// Relative to water, in ppm
+    pub shift: f64,
+    /// Fraction of the density, the amplitudes of a spectrum sum to 1
+    pub amplitude: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub peaks: Vec<Peak>,
+}
+
+impl Spectrum {
+    /// A single peak without shift
+    pub fn water() -> Self {
+        Spectrum {
+            peaks: vec![Peak {
+                shift: 0.0,
+                amplitude: 1.0,
+            }],
+        }
+    }
+
+    /// The common 6 peak fat model (Hamilton et al., 2011)
+    pub fn fat() -> Self {
+        let peaks = [
+            (-3.80, 0.087),
+            (-3.40, 0.693),
+            (-2.60, 0.128),
+            (-1.94, 0.004),
+            (-0.39, 0.039),
+            (0.60, 0.048),
+        ];
+        Spectrum {
+            peaks: peaks
+                .map(|(shift, amplitude)| Peak { shift, amplitude })
+                .to_vec(),
+        }
+    }
+
+    /// Off-resonance in Hz and amplitude of every peak at the field `b0` (T)
+    pub fn frequencies(&self, b0: f64) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.peaks
+            .iter()
+            .map(move |peak| (peak.shift * 1e-6 * GAMMA_BAR * b0, peak.amplitude))
+    }
+}
+
+/// Spectra of the tissues of a phantom at the field strength `b0` (T).
+/// Tissues without a spectrum are water.
+///
+/// ```
+/// # use toolapi::value::{ChemicalShift, Spectrum};
+/// let shift = ChemicalShift::new(3.0).with_spectrum("fat", Spectrum::fat());
+/// // The main fat peak is about 435 Hz below water at 3 T
+/// let (main, _) = shift.peaks("fat")[1];
+/// assert!((main + 434.3).abs() < 0.1);
+/// assert_eq!(shift.peaks("wm"), [(0.0, 1.0)]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChemicalShift {
+    pub b0: f64,
+    pub spectra: HashMap<String, Spectrum>,
+}
+
+impl ChemicalShift {
+    pub fn new(b0: f64) -> Self {
+        ChemicalShift {
+            b0,
+            spectra: HashMap::new(),
+        }
+    }
+
+    pub fn with_spectrum(mut self, tissue: impl Into<String>, spectrum: Spectrum) -> Self {
+        self.spectra.insert(tissue.into(), spectrum);
+        self
+    }
+
+    /// Off-resonance (Hz) and amplitude of every peak of the tissue `name`
+    pub fn peaks(&self, name: &str) -> Vec<(f64, f64)> {
+        match self.spectra.get(name) {
+            Some(spectrum) => spectrum.frequencies(self.b0).collect(),
+            None => Spectrum::water().frequencies(self.b0).collect(),
+        }
+    }
+}
@@ -1,21 +1,74 @@
-use crate::value::typed::TypedList;
+use crate::value::typed::{TypedDict, TypedList};
 
 impl TypedList {
+    /// Keep the first `len` elements, like [`Vec::truncate`].
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            TypedList::None(items) => items.truncate(len),
+            TypedList::Bool(items) => items.truncate(len),
+            TypedList::Int(items) => items.truncate(len),
+            TypedList::Float(items) => items.truncate(len),
+            #[cfg(feature = "half")]
+            TypedList::Half(items) => items.truncate(len),
+            TypedList::Complex(items) => items.truncate(len),
+            TypedList::Vec3(items) => items.truncate(len),
+            TypedList::Vec4(items) => items.truncate(len),
+            TypedList::Quat(items) => items.truncate(len),
+            TypedList::Str(items) => items.truncate(len),
+            TypedList::Bytes(items) => items.truncate(len),
+            TypedList::InstantSeqEvent(items) => items.truncate(len),
+            TypedList::Volume(items) => items.truncate(len),
+            TypedList::SegmentedPhantom(items) => items.truncate(len),
+            TypedList::PhantomTissue(items) => items.truncate(len),
+            TypedList::Provenance(items) => items.truncate(len),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         match self {
             TypedList::None(items) => items.is_empty(),
             TypedList::Bool(items) => items.is_empty(),
             TypedList::Int(items) => items.is_empty(),
             TypedList::Float(items) => items.is_empty(),
+            #[cfg(feature = "half")]
+            TypedList::Half(items) => items.is_empty(),
             TypedList::Complex(items) => items.is_empty(),
             TypedList::Vec3(items) => items.is_empty(),
             TypedList::Vec4(items) => items.is_empty(),
+            TypedList::Quat(items) => items.is_empty(),
             TypedList::Str(items) => items.is_empty(),
             TypedList::Bytes(items) => items.is_empty(),
             TypedList::InstantSeqEvent(items) => items.is_empty(),
             TypedList::Volume(items) => items.is_empty(),
             TypedList::SegmentedPhantom(items) => items.is_empty(),
             TypedList::PhantomTissue(items) => items.is_empty(),
+            TypedList::Provenance(items) => items.is_empty(),
+        }
+    }
+}
+
+impl TypedDict {
+    pub fn is_empty(&self) -> bool {
+        self.keys().is_empty()
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&String> {
+        match self {
+            TypedDict::None(items) => items.keys().collect(),
+            TypedDict::Bool(items) => items.keys().collect(),
+            TypedDict::Int(items) => items.keys().collect(),
+            TypedDict::Float(items) => items.keys().collect(),
+            TypedDict::Complex(items) => items.keys().collect(),
+            TypedDict::Vec3(items) => items.keys().collect(),
+            TypedDict::Vec4(items) => items.keys().collect(),
+            TypedDict::Quat(items) => items.keys().collect(),
+            TypedDict::Str(items) => items.keys().collect(),
+            TypedDict::Bytes(items) => items.keys().collect(),
+            TypedDict::InstantSeqEvent(items) => items.keys().collect(),
+            TypedDict::Volume(items) => items.keys().collect(),
+            TypedDict::SegmentedPhantom(items) => items.keys().collect(),
+            TypedDict::PhantomTissue(items) => items.keys().collect(),
+            TypedDict::Provenance(items) => items.keys().collect(),
         }
     }
 }
@@ -0,0 +1,143 @@
+//! Types of [`Value`]s and of the elements of typed containers, see [`ValueType`].
+
+use super::{
+    Value,
+    typed::{TypedDict, TypedList},
+};
+
+/// Type of a [`Value`] as seen by tools, e.g. for validation and error
+/// messages. Typed containers are the `List` or `Dict` they store, their
+/// elements are described by [`TypedList::element_type`] and
+/// [`TypedDict::element_type`]. Storage details are hidden: `Half` lists
+/// contain `Float`s and attachments are `Bytes`.
+///
+/// ```
+/// # use toolapi::value::{Value, ValueType, typed::TypedList};
+/// let signal = Value::TypedList(TypedList::Float(vec![0.5, 1.0]));
+/// assert_eq!(signal.type_of(), ValueType::List);
+/// let Value::TypedList(list) = &signal else { unreachable!() };
+/// assert_eq!(list.element_type(), ValueType::Float);
+/// assert_eq!(ValueType::Float.to_string(), "Float");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ValueType {
+    None,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Bytes,
+    Complex,
+    Vec3,
+    Vec4,
+    Quat,
+    InstantSeqEvent,
+    Volume,
+    SegmentedPhantom,
+    PhantomTissue,
+    Provenance,
+    Dict,
+    List,
+}
+
+impl ValueType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Bool => "Bool",
+            Self::Int => "Int",
+            Self::Float => "Float",
+            Self::Str => "Str",
+            Self::Bytes => "Bytes",
+            Self::Complex => "Complex",
+            Self::Vec3 => "Vec3",
+            Self::Vec4 => "Vec4",
+            Self::Quat => "Quat",
+            Self::InstantSeqEvent => "InstantSeqEvent",
+            Self::Volume => "Volume",
+            Self::SegmentedPhantom => "SegmentedPhantom",
+            Self::PhantomTissue => "PhantomTissue",
+            Self::Provenance => "Provenance",
+            Self::Dict => "Dict",
+            Self::List => "List",
+        }
+    }
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Value {
+    pub fn type_of(&self) -> ValueType {
+        match self {
+            Value::None(_) => ValueType::None,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Int(_) => ValueType::Int,
+            Value::Float(_) => ValueType::Float,
+            Value::Str(_) => ValueType::Str,
+            Value::Bytes(_) | Value::AttachmentRef(_) => ValueType::Bytes,
+            Value::Complex(_) => ValueType::Complex,
+            Value::Vec3(_) => ValueType::Vec3,
+            Value::Vec4(_) => ValueType::Vec4,
+            Value::Quat(_) => ValueType::Quat,
+            Value::InstantSeqEvent(_) => ValueType::InstantSeqEvent,
+            Value::Volume(_) => ValueType::Volume,
+            Value::SegmentedPhantom(_) => ValueType::SegmentedPhantom,
+            Value::PhantomTissue(_) => ValueType::PhantomTissue,
+            Value::Provenance(_) => ValueType::Provenance,
+            Value::Dict(_) | Value::TypedDict(_) => ValueType::Dict,
+            Value::List(_) | Value::TypedList(_) => ValueType::List,
+        }
+    }
+}
+
+impl TypedList {
+    /// Type of every element, also of empty lists
+    pub fn element_type(&self) -> ValueType {
+        match self {
+            TypedList::None(_) => ValueType::None,
+            TypedList::Bool(_) => ValueType::Bool,
+            TypedList::Int(_) => ValueType::Int,
+            TypedList::Float(_) => ValueType::Float,
+            #[cfg(feature = "half")]
+            TypedList::Half(_) => ValueType::Float,
+            TypedList::Str(_) => ValueType::Str,
+            TypedList::Bytes(_) => ValueType::Bytes,
+            TypedList::Complex(_) => ValueType::Complex,
+            TypedList::Vec3(_) => ValueType::Vec3,
+            TypedList::Vec4(_) => ValueType::Vec4,
+            TypedList::Quat(_) => ValueType::Quat,
+            TypedList::InstantSeqEvent(_) => ValueType::InstantSeqEvent,
+            TypedList::Volume(_) => ValueType::Volume,
+            TypedList::SegmentedPhantom(_) => ValueType::SegmentedPhantom,
+            TypedList::PhantomTissue(_) => ValueType::PhantomTissue,
+            TypedList::Provenance(_) => ValueType::Provenance,
+        }
+    }
+}
+
+impl TypedDict {
+    /// Type of every value, also of empty dicts
+    pub fn element_type(&self) -> ValueType {
+        match self {
+            TypedDict::None(_) => ValueType::None,
+            TypedDict::Bool(_) => ValueType::Bool,
+            TypedDict::Int(_) => ValueType::Int,
+            TypedDict::Float(_) => ValueType::Float,
+            TypedDict::Str(_) => ValueType::Str,
+            TypedDict::Bytes(_) => ValueType::Bytes,
+            TypedDict::Complex(_) => ValueType::Complex,
+            TypedDict::Vec3(_) => ValueType::Vec3,
+            TypedDict::Vec4(_) => ValueType::Vec4,
+            TypedDict::Quat(_) => ValueType::Quat,
+            TypedDict::InstantSeqEvent(_) => ValueType::InstantSeqEvent,
+            TypedDict::Volume(_) => ValueType::Volume,
+            TypedDict::SegmentedPhantom(_) => ValueType::SegmentedPhantom,
+            TypedDict::PhantomTissue(_) => ValueType::PhantomTissue,
+            TypedDict::Provenance(_) => ValueType::Provenance,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Checked construction of [`Volume`]s.
+//!
+//! Every volume has one voxel value per element of its shape and a finite
+//! affine, which is checked by [`Volume::new`] and when deserializing, so
+//! consumers can index the data without checks of their own. NaN or infinite
+//! voxels are allowed, see [`super::FloatPolicy`].
+
+use serde::Deserialize;
+
+use super::{structured::Volume, typed::TypedList};
+use crate::ToolError;
+
+impl Volume {
+    /// Fails with a [`ToolError::InvalidInput`] if the length of `data` is not
+    /// the number of voxels of `shape` or `affine` isn't finite.
+    ///
+    /// ```
+    /// # use toolapi::{ToolError, value::{structured::Volume, typed::TypedList}};
+    /// let affine = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]];
+    /// let volume = Volume::new([2, 1, 1], affine, TypedList::Float(vec![0.5, 1.0]))?;
+    /// assert_eq!(volume.data().len(), 2);
+    ///
+    /// let err = Volume::new([2, 2, 1], affine, TypedList::Float(vec![0.5, 1.0])).unwrap_err();
+    /// assert_eq!(err.to_string(), "invalid input `data`: 2 values for shape [2, 2, 1]");
+    /// # Ok::<(), ToolError>(())
+    /// ```
+    pub fn new(shape: [u64; 3], affine: [[f64; 4]; 3], data: TypedList) -> Result<Self, ToolError> {
+        let voxels = shape
+            .iter()
+            .try_fold(1u64, |voxels, &n| voxels.checked_mul(n));
+        if voxels != Some(data.len() as u64) {
+            return Err(ToolError::invalid_input(
+                "data",
+                format!("{} values for shape {shape:?}", data.len()),
+            ));
+        }
+        if affine.iter().flatten().any(|x| !x.is_finite()) {
+            return Err(ToolError::invalid_input("affine", "affine must be finite"));
+        }
+        Ok(Volume {
+            shape,
+            affine,
+            data,
+        })
+    }
+
+    /// Number of voxels along x, y and z
+    pub fn shape(&self) -> [u64; 3] {
+        self.shape
+    }
+
+    /// Maps voxel indices `[x, y, z, 1]` to positions in meters
+    pub fn affine(&self) -> &[[f64; 4]; 3] {
+        &self.affine
+    }
+
+    /// One value per voxel, see [`Volume`] for the order
+    pub fn data(&self) -> &TypedList {
+        &self.data
+    }
+
+    pub fn into_data(self) -> TypedList {
+        self.data
+    }
+}
+
+/// Deserialized like [`Volume`], before it is checked
+#[derive(Deserialize)]
+#[serde(rename = "Volume")]
+pub(super) struct RawVolume {
+    shape: [u64; 3],
+    affine: [[f64; 4]; 3],
+    data: TypedList,
+}
+
+impl TryFrom<RawVolume> for Volume {
+    type Error = ToolError;
+
+    fn try_from(raw: RawVolume) -> Result<Self, ToolError> {
+        Volume::new(raw.shape, raw.affine, raw.data)
+    }
+}
@@ -0,0 +1,333 @@
+//! `TryFrom<JsValue>` conversion into Value types.
+//!
+//! Gated behind the `wasm` feature. This is the inverse of the mapping
+//! documented in `wasm_wrap.rs`: `number`s become floats and `bigint`s become
+//! ints. Arrays and plain objects whose items all share a single non-collection
+//! type are packed into a [`TypedList`] / [`TypedDict`], like the Python
+//! bindings do. Objects with a `$type` property are parsed as that type.
+
+use js_sys::{Array, BigInt64Array, Float64Array, Object, Reflect, TypeError, Uint8Array};
+use num_complex::Complex64;
+use std::collections::HashMap;
+use wasm_bindgen::{JsCast, JsValue};
+
+use super::{
+    Value,
+    atomic::{Quat, Vec3, Vec4},
+    dynamic::{Dict, List},
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
+    typed::{TypedDict, TypedList},
+    wasm_wrap::TYPE_TAG,
+};
+
+fn type_error(msg: impl AsRef<str>) -> JsValue {
+    TypeError::new(msg.as_ref()).into()
+}
+
+fn get(obj: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    let value = Reflect::get(obj, &key.into())?;
+    if value.is_undefined() {
+        Err(type_error(format!("missing property `{key}`")))
+    } else {
+        Ok(value)
+    }
+}
+
+fn get_f64(obj: &JsValue, key: &str) -> Result<f64, JsValue> {
+    get(obj, key)?
+        .as_f64()
+        .ok_or_else(|| type_error(format!("property `{key}` must be a number")))
+}
+
+fn get_string(obj: &JsValue, key: &str) -> Result<String, JsValue> {
+    get(obj, key)?
+        .as_string()
+        .ok_or_else(|| type_error(format!("property `{key}` must be a string")))
+}
+
+/// `null` is `None`
+fn get_opt_string(obj: &JsValue, key: &str) -> Result<Option<String>, JsValue> {
+    match get(obj, key)? {
+        value if value.is_null() => Ok(None),
+        _ => get_string(obj, key).map(Some),
+    }
+}
+
+fn f64_array<const N: usize>(value: &JsValue, what: &str) -> Result<[f64; N], JsValue> {
+    let items: Vec<f64> = Array::from(value)
+        .iter()
+        .map(|x| x.as_f64())
+        .collect::<Option<_>>()
+        .ok_or_else(|| type_error(format!("{what} must only contain numbers")))?;
+    items
+        .try_into()
+        .map_err(|_| type_error(format!("{what} must have {N} elements")))
+}
+
+// =============================================================================
+// Atomic and structured types
+// =============================================================================
+
+fn vec3_from_js(obj: &JsValue) -> Result<Vec3, JsValue> {
+    Ok(Vec3(f64_array(&get(obj, "data")?, "Vec3.data")?))
+}
+
+fn vec4_from_js(obj: &JsValue) -> Result<Vec4, JsValue> {
+    Ok(Vec4(f64_array(&get(obj, "data")?, "Vec4.data")?))
+}
+
+fn quat_from_js(obj: &JsValue) -> Result<Quat, JsValue> {
+    Ok(Quat(f64_array(&get(obj, "data")?, "Quat.data")?))
+}
+
+fn complex_from_js(obj: &JsValue) -> Result<Complex64, JsValue> {
+    Ok(Complex64::new(get_f64(obj, "re")?, get_f64(obj, "im")?))
+}
+
+fn instant_seq_event_from_js(obj: &JsValue) -> Result<InstantSeqEvent, JsValue> {
+    let variant = get(obj, "variant")?.as_string().unwrap_or_default();
+    match variant.as_str() {
+        "Pulse" => Ok(InstantSeqEvent::Pulse {
+            angle: get_f64(obj, "angle")?,
+            phase: get_f64(obj, "phase")?,
+        }),
+        "Fid" => Ok(InstantSeqEvent::Fid {
+            kt: vec4_from_js(&get(obj, "kt")?)?.into(),
+        }),
+        "Adc" => Ok(InstantSeqEvent::Adc {
+            phase: get_f64(obj, "phase")?,
+        }),
+        other => Err(type_error(format!(
+            "unknown InstantSeqEvent variant: {other}"
+        ))),
+    }
+}
+
+fn volume_from_js(obj: &JsValue) -> Result<Volume, JsValue> {
+    let shape = f64_array::<3>(&get(obj, "shape")?, "Volume.shape")?.map(|x| x as u64);
+    let rows = Array::from(&get(obj, "affine")?);
+    if rows.length() != 3 {
+        return Err(type_error("affine must have 3 rows"));
+    }
+    let mut affine = [[0.0f64; 4]; 3];
+    for (i, row) in rows.iter().enumerate() {
+        affine[i] = f64_array(&row, "each affine row")?;
+    }
+    let data = match js_to_value(get(obj, "data")?)? {
+        Value::TypedList(data) => data,
+        _ => return Err(type_error("Volume.data must be a list of a single type")),
+    };
+
+    Volume::new(shape, affine, data).map_err(|err| type_error(err.to_string()))
+}
+
+fn phantom_tissue_from_js(obj: &JsValue) -> Result<PhantomTissue, JsValue> {
+    Ok(PhantomTissue {
+        density: volume_from_js(&get(obj, "density")?)?,
+        db0: volume_from_js(&get(obj, "db0")?)?,
+        t1: get_f64(obj, "t1")?,
+        t2: get_f64(obj, "t2")?,
+        t2dash: get_f64(obj, "t2dash")?,
+        adc: get_f64(obj, "adc")?,
+    })
+}
+
+fn segmented_phantom_from_js(obj: &JsValue) -> Result<SegmentedPhantom, JsValue> {
+    let tissues = get(obj, "tissues")?;
+    let tissues = Object::entries(tissues.unchecked_ref())
+        .iter()
+        .map(|entry| {
+            let entry = Array::from(&entry);
+            let key = entry.get(0).as_string().unwrap_or_default();
+            Ok((key, phantom_tissue_from_js(&entry.get(1))?))
+        })
+        .collect::<Result<HashMap<_, _>, JsValue>>()?;
+    let volumes = |key| -> Result<Vec<Volume>, JsValue> {
+        Array::from(&get(obj, key)?)
+            .iter()
+            .map(|v| volume_from_js(&v))
+            .collect()
+    };
+
+    Ok(SegmentedPhantom {
+        tissues,
+        b1_tx: volumes("b1_tx")?,
+        b1_rx: volumes("b1_rx")?,
+    })
+}
+
+fn provenance_from_js(obj: &JsValue) -> Result<Provenance, JsValue> {
+    Ok(Provenance {
+        tool: get_opt_string(obj, "tool")?,
+        version: get_opt_string(obj, "version")?,
+        input_hash: get_string(obj, "input_hash")?,
+        started: get_f64(obj, "started")?,
+        duration: get_f64(obj, "duration")?,
+        host: get_string(obj, "host")?,
+    })
+}
+
+// =============================================================================
+// Collections (first-element heuristic like the Python bindings)
+// =============================================================================
+
+/// True if all values have the same variant, which is not a collection.
+fn is_homogeneous<'a>(mut values: impl Iterator<Item = &'a Value>) -> bool {
+    let Some(first) = values.next() else {
+        return true;
+    };
+    if matches!(
+        first,
+        Value::Dict(_) | Value::List(_) | Value::TypedDict(_) | Value::TypedList(_)
+    ) {
+        return false;
+    }
+    let variant = std::mem::discriminant(first);
+    values.all(|value| std::mem::discriminant(value) == variant)
+}
+
+/// Only call this on homogeneous items, otherwise items are silently dropped.
+fn unwrap_list<T: TryFrom<Value>>(items: Vec<Value>) -> Vec<T> {
+    items
+        .into_iter()
+        .filter_map(|x| x.try_into().ok())
+        .collect()
+}
+
+/// Only call this on homogeneous items, otherwise items are silently dropped.
+fn unwrap_dict<T: TryFrom<Value>>(items: HashMap<String, Value>) -> HashMap<String, T> {
+    items
+        .into_iter()
+        .filter_map(|(k, v)| Some((k, v.try_into().ok()?)))
+        .collect()
+}
+
+/// Pack homogeneous items into a [`TypedList`], otherwise return a [`List`].
+fn pack_list(items: Vec<Value>) -> Value {
+    if !is_homogeneous(items.iter()) {
+        return Value::List(List(items));
+    }
+    Value::TypedList(match items.first() {
+        Some(Value::None(_)) => TypedList::None(unwrap_list(items)),
+        Some(Value::Bool(_)) => TypedList::Bool(unwrap_list(items)),
+        Some(Value::Int(_)) => TypedList::Int(unwrap_list(items)),
+        Some(Value::Str(_)) => TypedList::Str(unwrap_list(items)),
+        Some(Value::Bytes(_)) => TypedList::Bytes(unwrap_list(items)),
+        Some(Value::Complex(_)) => TypedList::Complex(unwrap_list(items)),
+        Some(Value::Vec3(_)) => TypedList::Vec3(unwrap_list(items)),
+        Some(Value::Vec4(_)) => TypedList::Vec4(unwrap_list(items)),
+        Some(Value::Quat(_)) => TypedList::Quat(unwrap_list(items)),
+        Some(Value::InstantSeqEvent(_)) => TypedList::InstantSeqEvent(unwrap_list(items)),
+        Some(Value::Volume(_)) => TypedList::Volume(unwrap_list(items)),
+        Some(Value::SegmentedPhantom(_)) => TypedList::SegmentedPhantom(unwrap_list(items)),
+        Some(Value::PhantomTissue(_)) => TypedList::PhantomTissue(unwrap_list(items)),
+        Some(Value::Provenance(_)) => TypedList::Provenance(unwrap_list(items)),
+        // Float and empty lists, collections were excluded by is_homogeneous
+        _ => TypedList::Float(unwrap_list(items)),
+    })
+}
+
+/// Pack homogeneous items into a [`TypedDict`], otherwise return a [`Dict`].
+fn pack_dict(items: HashMap<String, Value>) -> Value {
+    if !is_homogeneous(items.values()) {
+        return Value::Dict(Dict(items));
+    }
+    Value::TypedDict(match items.values().next() {
+        Some(Value::None(_)) => TypedDict::None(unwrap_dict(items)),
+        Some(Value::Bool(_)) => TypedDict::Bool(unwrap_dict(items)),
+        Some(Value::Int(_)) => TypedDict::Int(unwrap_dict(items)),
+        Some(Value::Str(_)) => TypedDict::Str(unwrap_dict(items)),
+        Some(Value::Bytes(_)) => TypedDict::Bytes(unwrap_dict(items)),
+        Some(Value::Complex(_)) => TypedDict::Complex(unwrap_dict(items)),
+        Some(Value::Vec3(_)) => TypedDict::Vec3(unwrap_dict(items)),
+        Some(Value::Vec4(_)) => TypedDict::Vec4(unwrap_dict(items)),
+        Some(Value::Quat(_)) => TypedDict::Quat(unwrap_dict(items)),
+        Some(Value::InstantSeqEvent(_)) => TypedDict::InstantSeqEvent(unwrap_dict(items)),
+        Some(Value::Volume(_)) => TypedDict::Volume(unwrap_dict(items)),
+        Some(Value::SegmentedPhantom(_)) => TypedDict::SegmentedPhantom(unwrap_dict(items)),
+        Some(Value::PhantomTissue(_)) => TypedDict::PhantomTissue(unwrap_dict(items)),
+        Some(Value::Provenance(_)) => TypedDict::Provenance(unwrap_dict(items)),
+        // Float and empty dicts, collections were excluded by is_homogeneous
+        _ => TypedDict::Float(unwrap_dict(items)),
+    })
+}
+
+// =============================================================================
+// Value (top-level dispatcher)
+// =============================================================================
+
+fn js_to_value(value: JsValue) -> Result<Value, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        return Ok(Value::None(()));
+    }
+    if let Some(b) = value.as_bool() {
+        return Ok(Value::Bool(b));
+    }
+    if value.is_bigint() {
+        return i64::try_from(value)
+            .map(Value::Int)
+            .map_err(|_| type_error("bigint does not fit into 64 bits"));
+    }
+    if let Some(f) = value.as_f64() {
+        return Ok(Value::Float(f));
+    }
+    if let Some(s) = value.as_string() {
+        return Ok(Value::Str(s));
+    }
+    if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+        return Ok(Value::Bytes(bytes.to_vec()));
+    }
+    if let Some(data) = value.dyn_ref::<Float64Array>() {
+        return Ok(Value::TypedList(TypedList::Float(data.to_vec())));
+    }
+    if let Some(data) = value.dyn_ref::<BigInt64Array>() {
+        return Ok(Value::TypedList(TypedList::Int(data.to_vec())));
+    }
+    if Array::is_array(&value) {
+        let items = Array::from(&value)
+            .iter()
+            .map(js_to_value)
+            .collect::<Result<_, _>>()?;
+        return Ok(pack_list(items));
+    }
+    if !value.is_object() {
+        return Err(type_error(format!(
+            "unsupported JavaScript value for Value conversion: {value:?}"
+        )));
+    }
+
+    // Tagged objects created from non-collection types
+    let tag = Reflect::get(&value, &TYPE_TAG.into())?;
+    if let Some(type_name) = tag.as_string() {
+        return match type_name.as_str() {
+            "Complex" => complex_from_js(&value).map(Value::Complex),
+            "Vec3" => vec3_from_js(&value).map(Value::Vec3),
+            "Vec4" => vec4_from_js(&value).map(Value::Vec4),
+            "Quat" => quat_from_js(&value).map(Value::Quat),
+            "InstantSeqEvent" => instant_seq_event_from_js(&value).map(Value::InstantSeqEvent),
+            "Volume" => volume_from_js(&value).map(Value::Volume),
+            "PhantomTissue" => phantom_tissue_from_js(&value).map(Value::PhantomTissue),
+            "SegmentedPhantom" => segmented_phantom_from_js(&value).map(Value::SegmentedPhantom),
+            "Provenance" => provenance_from_js(&value).map(Value::Provenance),
+            other => Err(type_error(format!("unknown toolapi value type: {other}"))),
+        };
+    }
+
+    let items = Object::entries(value.unchecked_ref())
+        .iter()
+        .map(|entry| {
+            let entry = Array::from(&entry);
+            let key = entry.get(0).as_string().unwrap_or_default();
+            Ok((key, js_to_value(entry.get(1))?))
+        })
+        .collect::<Result<_, JsValue>>()?;
+    Ok(pack_dict(items))
+}
+
+impl TryFrom<JsValue> for Value {
+    type Error = JsValue;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        js_to_value(value)
+    }
+}
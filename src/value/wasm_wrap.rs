@@ -0,0 +1,286 @@
+//! `From<Value> for JsValue` conversion for all Value types.
+//!
+//! Gated behind the `wasm` feature. Browser frontends can hand tool results
+//! directly to JavaScript without a JSON detour. The mapping is:
+//!
+//! - `None` -> `null`, `Bool` -> `boolean`, `Float` -> `number`, `Str` -> `string`
+//! - `Int` -> `bigint` (exact, a `number` would lose precision above 2^53)
+//! - `Bytes` -> `Uint8Array`
+//! - `Dict` / `TypedDict` -> plain object, `List` / `TypedList` -> `Array`
+//! - `TypedList::Float` -> `Float64Array`, `TypedList::Int` -> `BigInt64Array`,
+//!   `TypedList::Half` -> `Float32Array`
+//! - all other types -> plain object tagged with their type name in a `$type`
+//!   property, e.g. `{ $type: "Complex", re: 1, im: 0 }`
+
+use js_sys::{Array, BigInt64Array, Float64Array, Object, Reflect, Uint8Array};
+use num_complex::Complex64;
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+
+use super::{
+    Value,
+    atomic::{Quat, Vec3, Vec4},
+    dynamic::{Dict, List},
+    structured::{InstantSeqEvent, PhantomTissue, Provenance, SegmentedPhantom, Volume},
+    typed::{TypedDict, TypedList},
+};
+
+/// Name of the property which tags objects created from non-collection types.
+pub const TYPE_TAG: &str = "$type";
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Create an object tagged with `type_name`, filled with the given properties.
+fn tagged_object(type_name: &str, props: &[(&str, JsValue)]) -> JsValue {
+    let obj = Object::new();
+    set(&obj, TYPE_TAG, type_name.into());
+    for (key, value) in props {
+        set(&obj, key, value.clone());
+    }
+    obj.into()
+}
+
+fn set(obj: &Object, key: &str, value: JsValue) {
+    // Only fails if obj is not an object, which can't happen here
+    let _ = Reflect::set(obj, &key.into(), &value);
+}
+
+fn array_of<T: Into<JsValue>>(items: impl IntoIterator<Item = T>) -> JsValue {
+    items.into_iter().map(Into::into).collect::<Array>().into()
+}
+
+fn object_of<T: Into<JsValue>>(items: HashMap<String, T>) -> JsValue {
+    let obj = Object::new();
+    for (key, value) in items {
+        set(&obj, &key, value.into());
+    }
+    obj.into()
+}
+
+fn complex_to_js(value: Complex64) -> JsValue {
+    tagged_object(
+        "Complex",
+        &[("re", value.re.into()), ("im", value.im.into())],
+    )
+}
+
+// =============================================================================
+// Atomic types
+// =============================================================================
+
+impl From<Vec3> for JsValue {
+    fn from(value: Vec3) -> Self {
+        tagged_object("Vec3", &[("data", array_of(value.0))])
+    }
+}
+
+impl From<Vec4> for JsValue {
+    fn from(value: Vec4) -> Self {
+        tagged_object("Vec4", &[("data", array_of(value.0))])
+    }
+}
+
+impl From<Quat> for JsValue {
+    fn from(value: Quat) -> Self {
+        tagged_object("Quat", &[("data", array_of(value.0))])
+    }
+}
+
+// =============================================================================
+// Dynamic collections
+// =============================================================================
+
+impl From<Dict> for JsValue {
+    fn from(value: Dict) -> Self {
+        object_of(value.0)
+    }
+}
+
+impl From<List> for JsValue {
+    fn from(value: List) -> Self {
+        array_of(value.0)
+    }
+}
+
+// =============================================================================
+// Structured types
+// =============================================================================
+
+impl From<InstantSeqEvent> for JsValue {
+    fn from(value: InstantSeqEvent) -> Self {
+        match value {
+            InstantSeqEvent::Pulse { angle, phase } => tagged_object(
+                "InstantSeqEvent",
+                &[
+                    ("variant", "Pulse".into()),
+                    ("angle", angle.into()),
+                    ("phase", phase.into()),
+                ],
+            ),
+            InstantSeqEvent::Fid { kt } => tagged_object(
+                "InstantSeqEvent",
+                &[("variant", "Fid".into()), ("kt", Vec4::from(kt).into())],
+            ),
+            InstantSeqEvent::Adc { phase } => tagged_object(
+                "InstantSeqEvent",
+                &[("variant", "Adc".into()), ("phase", phase.into())],
+            ),
+        }
+    }
+}
+
+impl From<Volume> for JsValue {
+    fn from(value: Volume) -> Self {
+        let affine = array_of(value.affine.iter().map(|row| array_of(*row)));
+        tagged_object(
+            "Volume",
+            &[
+                ("shape", array_of(value.shape.map(|x| x as f64))),
+                ("affine", affine),
+                ("data", value.data.into()),
+            ],
+        )
+    }
+}
+
+impl From<PhantomTissue> for JsValue {
+    fn from(value: PhantomTissue) -> Self {
+        tagged_object(
+            "PhantomTissue",
+            &[
+                ("density", value.density.into()),
+                ("db0", value.db0.into()),
+                ("t1", value.t1.into()),
+                ("t2", value.t2.into()),
+                ("t2dash", value.t2dash.into()),
+                ("adc", value.adc.into()),
+            ],
+        )
+    }
+}
+
+impl From<SegmentedPhantom> for JsValue {
+    fn from(value: SegmentedPhantom) -> Self {
+        tagged_object(
+            "SegmentedPhantom",
+            &[
+                ("tissues", object_of(value.tissues)),
+                ("b1_tx", array_of(value.b1_tx)),
+                ("b1_rx", array_of(value.b1_rx)),
+            ],
+        )
+    }
+}
+
+impl From<Provenance> for JsValue {
+    fn from(value: Provenance) -> Self {
+        tagged_object(
+            "Provenance",
+            &[
+                ("tool", value.tool.into()),
+                ("version", value.version.into()),
+                ("input_hash", value.input_hash.into()),
+                ("started", value.started.into()),
+                ("duration", value.duration.into()),
+                ("host", value.host.into()),
+            ],
+        )
+    }
+}
+
+// =============================================================================
+// TypedList
+// =============================================================================
+
+impl From<TypedList> for JsValue {
+    fn from(value: TypedList) -> Self {
+        match value {
+            TypedList::None(v) => array_of(v.into_iter().map(|_| JsValue::NULL)),
+            TypedList::Bool(v) => array_of(v),
+            TypedList::Int(v) => BigInt64Array::from(v.as_slice()).into(),
+            TypedList::Float(v) => Float64Array::from(v.as_slice()).into(),
+            #[cfg(feature = "half")]
+            TypedList::Half(v) => {
+                let v: Vec<f32> = v.into_iter().map(|x| x.to_f32()).collect();
+                js_sys::Float32Array::from(v.as_slice()).into()
+            }
+            TypedList::Str(v) => array_of(v),
+            TypedList::Bytes(v) => array_of(v.iter().map(|b| Uint8Array::from(b.as_slice()))),
+            TypedList::Complex(v) => array_of(v.into_iter().map(complex_to_js)),
+            TypedList::Vec3(v) => array_of(v),
+            TypedList::Vec4(v) => array_of(v),
+            TypedList::Quat(v) => array_of(v),
+            TypedList::InstantSeqEvent(v) => array_of(v),
+            TypedList::Volume(v) => array_of(v),
+            TypedList::SegmentedPhantom(v) => array_of(v),
+            TypedList::PhantomTissue(v) => array_of(v),
+            TypedList::Provenance(v) => array_of(v),
+        }
+    }
+}
+
+// =============================================================================
+// TypedDict
+// =============================================================================
+
+impl From<TypedDict> for JsValue {
+    fn from(value: TypedDict) -> Self {
+        match value {
+            TypedDict::None(m) => object_of(m.into_keys().map(|k| (k, JsValue::NULL)).collect()),
+            TypedDict::Bool(m) => object_of(m),
+            TypedDict::Int(m) => object_of(m),
+            TypedDict::Float(m) => object_of(m),
+            TypedDict::Str(m) => object_of(m),
+            TypedDict::Bytes(m) => object_of(
+                m.into_iter()
+                    .map(|(k, v)| (k, Uint8Array::from(v.as_slice())))
+                    .collect(),
+            ),
+            TypedDict::Complex(m) => {
+                object_of(m.into_iter().map(|(k, v)| (k, complex_to_js(v))).collect())
+            }
+            TypedDict::Vec3(m) => object_of(m),
+            TypedDict::Vec4(m) => object_of(m),
+            TypedDict::Quat(m) => object_of(m),
+            TypedDict::InstantSeqEvent(m) => object_of(m),
+            TypedDict::Volume(m) => object_of(m),
+            TypedDict::SegmentedPhantom(m) => object_of(m),
+            TypedDict::PhantomTissue(m) => object_of(m),
+            TypedDict::Provenance(m) => object_of(m),
+        }
+    }
+}
+
+// =============================================================================
+// Value (top-level dispatcher)
+// =============================================================================
+
+impl From<Value> for JsValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::None(()) => JsValue::NULL,
+            Value::Bool(b) => b.into(),
+            Value::Int(i) => i.into(),
+            Value::Float(f) => f.into(),
+            Value::Str(s) => s.into(),
+            Value::Bytes(b) => Uint8Array::from(b.as_slice()).into(),
+            // Replaced by the bytes on arrival, never passed to JS
+            Value::AttachmentRef(_) => JsValue::NULL,
+            Value::Complex(c) => complex_to_js(c),
+            Value::Vec3(v) => v.into(),
+            Value::Vec4(v) => v.into(),
+            Value::Quat(q) => q.into(),
+            Value::InstantSeqEvent(e) => e.into(),
+            Value::Volume(v) => v.into(),
+            Value::SegmentedPhantom(sp) => sp.into(),
+            Value::PhantomTissue(pt) => pt.into(),
+            Value::Provenance(p) => p.into(),
+            Value::Dict(d) => d.into(),
+            Value::List(l) => l.into(),
+            Value::TypedDict(td) => td.into(),
+            Value::TypedList(tl) => tl.into(),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! [`toolapi::ServerConfig::max_job_memory`] with [`toolapi::JobAllocator`]:
+//! memory counts for the call that allocated it, whichever thread frees it.
+
+use std::time::{Duration, Instant};
+
+use toolapi::{
+    JobAllocator, ServerConfig, ToolCallError, ToolCtx, ToolError, Value,
+    testing::{TestServer, spawn_test_server_with_config},
+};
+
+#[global_allocator]
+static ALLOCATOR: JobAllocator = JobAllocator;
+
+const MAX_JOB_MEMORY: usize = 4 * 1024 * 1024;
+
+fn server(
+    tool: impl Fn(Value, &mut ToolCtx) -> Result<Value, ToolError> + Send + Sync + 'static,
+) -> TestServer {
+    let config = ServerConfig {
+        max_job_memory: Some(MAX_JOB_MEMORY),
+        ..ServerConfig::default()
+    };
+    spawn_test_server_with_config(tool, config)
+}
+
+/// Gives the budget, checked every 100 ms, time to abort the tool
+fn wait(ctx: &mut ToolCtx, duration: Duration) -> Result<(), ToolError> {
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        ctx.check_abort()?;
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+#[test]
+fn messages_freed_by_the_server_dont_count() {
+    // Sends five times the limit, which the server frees after sending it.
+    // Progress rather than messages, the server echoes those to stdout,
+    // which the test captures on the tool thread.
+    let server = server(|input, ctx| {
+        for i in 0..20 {
+            let stage = "x".repeat(MAX_JOB_MEMORY / 4);
+            ctx.send_progress(i as f64 / 20.0, Some(stage))?;
+            wait(ctx, Duration::from_millis(50))?;
+        }
+        wait(ctx, Duration::from_millis(500))?;
+        Ok(input)
+    });
+    let result = server.client().call(Value::Int(42), |_| true);
+    assert!(matches!(result, Ok(Value::Int(42))));
+}
+
+#[test]
+fn freeing_the_input_makes_no_room() {
+    // The input is allocated by the server, so dropping it frees nothing of
+    // the tool, which then holds more than the limit
+    let server = server(|input, ctx| {
+        drop(input);
+        let held = vec![1u8; MAX_JOB_MEMORY * 2];
+        wait(ctx, Duration::from_secs(2))?;
+        Ok(Value::Int(held.len() as i64))
+    });
+    let input = Value::Bytes(vec![0; MAX_JOB_MEMORY * 4]);
+    let result = server.client().call(input, |_| true);
+    assert!(matches!(
+        result,
+        Err(ToolCallError::ToolReturnedError(
+            ToolError::ResourceExhausted { .. }
+        ))
+    ));
+}
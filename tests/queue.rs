@@ -0,0 +1,85 @@
+//! Calls beyond [`toolapi::ServerConfig::max_concurrent_tools`] wait in a queue.
+
+use std::{
+    sync::{Mutex, mpsc},
+    thread::{self, JoinHandle},
+};
+
+use toolapi::{
+    ServerConfig, ToolCallError, ToolCtx, ToolError, ToolEventKind, Value,
+    testing::{TestServer, spawn_test_server_with_config},
+};
+
+/// Reports its input on `started`, then waits for `finish`
+fn tool(
+    started: mpsc::Sender<Value>,
+    finish: mpsc::Receiver<()>,
+) -> impl Fn(Value, &mut ToolCtx) -> Result<Value, ToolError> + Send + Sync + 'static {
+    let finish = Mutex::new(finish);
+    move |input, _| {
+        started.send(input.clone()).unwrap();
+        finish.lock().unwrap().recv().unwrap();
+        Ok(input)
+    }
+}
+
+/// Call with `Int(id)` on another thread, queue positions are sent to `positions`
+fn call(
+    server: &TestServer,
+    id: i64,
+    positions: mpsc::Sender<u32>,
+) -> JoinHandle<Result<Value, ToolCallError>> {
+    let client = server.client();
+    thread::spawn(move || {
+        client.call_with_events(Value::Int(id), |event| {
+            if let ToolEventKind::Queued { position } = event.kind {
+                positions.send(position).unwrap();
+            }
+            true
+        })
+    })
+}
+
+fn started_id(started: &mpsc::Receiver<Value>) -> i64 {
+    match started.recv().unwrap() {
+        Value::Int(id) => id,
+        _ => unreachable!("the calls send ids"),
+    }
+}
+
+#[test]
+fn queued_calls_run_in_order() {
+    let (started_tx, started) = mpsc::channel();
+    let (finish, finish_rx) = mpsc::channel();
+    let config = ServerConfig {
+        max_concurrent_tools: Some(1),
+        ..ServerConfig::default()
+    };
+    let server = spawn_test_server_with_config(tool(started_tx, finish_rx), config);
+
+    let (positions, first_positions) = mpsc::channel();
+    let first = call(&server, 0, positions);
+    assert_eq!(started_id(&started), 0);
+
+    // Each call is queued behind the ones before it
+    let (positions, second_positions) = mpsc::channel();
+    let second = call(&server, 1, positions);
+    assert_eq!(second_positions.recv().unwrap(), 0);
+    let (positions, third_positions) = mpsc::channel();
+    let third = call(&server, 2, positions);
+    assert_eq!(third_positions.recv().unwrap(), 1);
+
+    // And moves up once a call finishes
+    finish.send(()).unwrap();
+    assert_eq!(started_id(&started), 1);
+    assert_eq!(third_positions.recv().unwrap(), 0);
+    finish.send(()).unwrap();
+    assert_eq!(started_id(&started), 2);
+    finish.send(()).unwrap();
+
+    for (id, call) in [first, second, third].into_iter().enumerate() {
+        assert!(matches!(call.join().unwrap(), Ok(Value::Int(i)) if i == id as i64));
+    }
+    // The first call ran right away
+    assert!(first_positions.try_recv().is_err());
+}
@@ -0,0 +1,138 @@
+//! Redirects of the WebSocket upgrade, followed if [`toolapi::REDIRECTS_ENV`] is set.
+//!
+//! The servers only answer the HTTP upgrade request, so the calls fail
+//! after the last hop, which records the headers it received.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread::{self, JoinHandle},
+};
+
+use toolapi::{ConnectionError, ToolCallError, Value};
+
+/// Listen on a free port of `127.0.0.1`
+fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+/// Answer the next upgrade requests with `responses`, returns the headers
+/// of every request with lowercase names
+fn serve(listener: TcpListener, responses: Vec<String>) -> JoinHandle<Vec<Vec<(String, String)>>> {
+    thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut headers = Vec::new();
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.push((name.to_lowercase(), value.trim().to_string()));
+                    }
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                headers
+            })
+            .collect()
+    })
+}
+
+fn redirect(location: &str) -> String {
+    format!("HTTP/1.1 307 Temporary Redirect\r\nLocation: {location}\r\nContent-Length: 0\r\n\r\n")
+}
+
+const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn follow_redirects() {
+    // SAFETY: every test of this file sets the same value
+    unsafe { std::env::set_var(toolapi::REDIRECTS_ENV, "2") };
+}
+
+fn assert_not_found(result: Result<Value, ToolCallError>) {
+    assert!(matches!(
+        result,
+        Err(ToolCallError::ConnectionError(ConnectionError::NotFound))
+    ));
+}
+
+#[test]
+fn redirect_to_another_host_sends_its_host() {
+    follow_redirects();
+    let (first, first_port) = listen();
+    let (second, second_port) = listen();
+    let location = format!("ws://localhost:{second_port}/tool");
+    let first = serve(first, vec![redirect(&location)]);
+    let second = serve(second, vec![NOT_FOUND.to_string()]);
+
+    let addr = format!("ws://127.0.0.1:{first_port}/tool");
+    assert_not_found(toolapi::call(&addr, Value::None(()), |_| true));
+
+    let first = first.join().unwrap();
+    let second = second.join().unwrap();
+    assert_eq!(
+        header(&first[0], "host"),
+        Some(format!("127.0.0.1:{first_port}").as_str())
+    );
+    assert_eq!(
+        header(&second[0], "host"),
+        Some(format!("localhost:{second_port}").as_str())
+    );
+    assert_ne!(
+        header(&first[0], "sec-websocket-key"),
+        header(&second[0], "sec-websocket-key")
+    );
+}
+
+#[test]
+fn redirect_to_another_host_drops_the_token() {
+    follow_redirects();
+    let (first, first_port) = listen();
+    let (second, second_port) = listen();
+    let location = format!("ws://localhost:{second_port}/tool");
+    let first = serve(first, vec![redirect(&location)]);
+    let second = serve(second, vec![NOT_FOUND.to_string()]);
+
+    let addr = format!("ws://127.0.0.1:{first_port}/tool");
+    assert_not_found(toolapi::call_with_auth(
+        &addr,
+        "secret",
+        Value::None(()),
+        |_| true,
+    ));
+
+    let first = first.join().unwrap();
+    let second = second.join().unwrap();
+    assert_eq!(header(&first[0], "authorization"), Some("Bearer secret"));
+    assert_eq!(header(&second[0], "authorization"), None);
+}
+
+#[test]
+fn redirect_on_the_same_host_keeps_the_token() {
+    follow_redirects();
+    let (listener, port) = listen();
+    let server = serve(listener, vec![redirect("/other"), NOT_FOUND.to_string()]);
+
+    let addr = format!("ws://127.0.0.1:{port}/tool");
+    assert_not_found(toolapi::call_with_auth(
+        &addr,
+        "secret",
+        Value::None(()),
+        |_| true,
+    ));
+
+    let requests = server.join().unwrap();
+    assert_eq!(header(&requests[1], "authorization"), Some("Bearer secret"));
+}
@@ -0,0 +1,187 @@
+//! Calls that continue after their connection dropped, with the messages the
+//! server logged for them (see [`toolapi::JobStoreConfig`]).
+//!
+//! The tool sends its messages right away and returns once the test tells it
+//! to, so the server still has the log whenever a client resumes.
+
+use std::{
+    sync::{Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use toolapi::{
+    JobStoreConfig, ServerConfig, ToolCtx, ToolError, ToolEvent, ToolEventKind, Value,
+    testing::{FaultConfig, spawn_test_server_with_config},
+};
+
+const MESSAGES: usize = 100;
+
+fn config(max_log_size: usize) -> ServerConfig {
+    ServerConfig {
+        job_store: JobStoreConfig {
+            retention: Duration::from_secs(60),
+            max_log_size,
+            ..JobStoreConfig::default()
+        },
+        ..ServerConfig::default()
+    }
+}
+
+/// Sends the messages "0" to "99" and reports it on `sent`, then waits for `done`
+fn tool(
+    sent: mpsc::Sender<()>,
+    done: mpsc::Receiver<()>,
+) -> impl Fn(Value, &mut ToolCtx) -> Result<Value, ToolError> + Send + Sync + 'static {
+    let done = Mutex::new(done);
+    move |input, ctx| {
+        for i in 0..MESSAGES {
+            ctx.send_msg(i.to_string())?;
+        }
+        let _ = sent.send(());
+        let _ = done.lock().unwrap().recv();
+        Ok(input)
+    }
+}
+
+fn all_messages() -> Vec<String> {
+    (0..MESSAGES).map(|i| i.to_string()).collect()
+}
+
+fn last_message() -> String {
+    (MESSAGES - 1).to_string()
+}
+
+fn message(event: &ToolEvent) -> Option<&str> {
+    match &event.kind {
+        ToolEventKind::Message(msg) => Some(msg),
+        _ => None,
+    }
+}
+
+#[test]
+fn dropped_connections_lose_no_messages() {
+    let (sent, _) = mpsc::channel();
+    let (done_tx, done) = mpsc::channel();
+    let server = spawn_test_server_with_config(tool(sent, done), config(16 * 1024 * 1024));
+
+    let faults = FaultConfig {
+        disconnect_after: Some(20),
+        ..FaultConfig::default()
+    };
+    let mut messages = Vec::new();
+    let result = server
+        .client_with_faults(faults)
+        .call(Value::Int(42), |msg| {
+            messages.push(msg);
+            if messages.len() == MESSAGES {
+                done_tx.send(()).unwrap();
+            }
+            true
+        });
+
+    assert!(matches!(result, Ok(Value::Int(42))));
+    // Received once and in order, although the call was resumed several times
+    assert_eq!(messages, all_messages());
+}
+
+#[test]
+fn acknowledged_messages_are_not_sent_again() {
+    let (sent, _) = mpsc::channel();
+    let (done_tx, done) = mpsc::channel();
+    let server = spawn_test_server_with_config(tool(sent, done), config(16 * 1024 * 1024));
+
+    // The first client receives everything, then waits. Its connection
+    // drops, and resuming acknowledges what it received before.
+    let (ids_tx, ids) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let faults = FaultConfig {
+        disconnect_after: Some(50),
+        ..FaultConfig::default()
+    };
+    let first = server.client_with_faults(faults);
+    let first = thread::spawn(move || {
+        let mut accepted = None;
+        let mut received = 0;
+        first.call_with_events(Value::Int(42), |event| {
+            if let ToolEventKind::Accepted { job_id, secret } = &event.kind {
+                accepted = Some((job_id.clone(), secret.clone()));
+            }
+            if message(&event).is_some() {
+                received += 1;
+                if received == MESSAGES {
+                    ids_tx.send(accepted.take().unwrap()).unwrap();
+                    released.recv().unwrap();
+                }
+            }
+            true
+        })
+    });
+
+    let (job_id, secret) = ids.recv().unwrap();
+    let mut messages = Vec::new();
+    let result = server
+        .client()
+        .resume_with_events(&job_id, &secret, |event| {
+            messages.extend(message(&event).map(str::to_string));
+            if messages.last().is_some_and(|msg| *msg == last_message()) {
+                done_tx.send(()).unwrap();
+            }
+            true
+        });
+    release.send(()).unwrap();
+
+    assert!(matches!(result, Ok(Value::Int(42))));
+    assert!(matches!(first.join().unwrap(), Ok(Value::Int(42))));
+    // Only the messages after the last acknowledgement
+    assert!(!messages.is_empty() && messages.len() < MESSAGES);
+    assert!(all_messages().ends_with(&messages));
+}
+
+#[test]
+fn messages_beyond_the_log_size_are_dropped() {
+    let (sent_tx, sent) = mpsc::channel();
+    let (done_tx, done) = mpsc::channel();
+    // Room for a few of the messages
+    let server = spawn_test_server_with_config(tool(sent_tx, done), config(200));
+
+    // The first client receives nothing (and acknowledges nothing) until released
+    let (ids_tx, ids) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let first = server.client();
+    let first = thread::spawn(move || {
+        let mut messages = Vec::new();
+        let result = first.call_with_events(Value::Int(42), |event| {
+            if let ToolEventKind::Accepted { job_id, secret } = &event.kind {
+                ids_tx.send((job_id.clone(), secret.clone())).unwrap();
+                released.recv().unwrap();
+            }
+            messages.extend(message(&event).map(str::to_string));
+            true
+        });
+        (result, messages)
+    });
+
+    let (job_id, secret) = ids.recv().unwrap();
+    sent.recv().unwrap();
+    let mut messages = Vec::new();
+    let result = server
+        .client()
+        .resume_with_events(&job_id, &secret, |event| {
+            messages.extend(message(&event).map(str::to_string));
+            if messages.last().is_some_and(|msg| *msg == last_message()) {
+                done_tx.send(()).unwrap();
+            }
+            true
+        });
+    release.send(()).unwrap();
+
+    assert!(matches!(result, Ok(Value::Int(42))));
+    // Only the newest messages were kept
+    assert!(!messages.is_empty() && messages.len() < MESSAGES);
+    assert!(all_messages().ends_with(&messages));
+    // Sent to the connected client regardless of the log
+    let (result, first_messages) = first.join().unwrap();
+    assert!(matches!(result, Ok(Value::Int(42))));
+    assert_eq!(first_messages, all_messages());
+}
@@ -0,0 +1,148 @@
+//! A real server on port 8080, started once for all tests of this file: the
+//! bearer token of [`toolapi::ServerConfig::auth_token`] and the hashes of
+//! cached inputs, which need a client that sends its own (wrong) hashes.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use toolapi::{
+    ConnectionError, MessageFn, PROTOCOL_VERSION, ServerConfig, ServerHandle, ToolCallError,
+    ToolError, Value, value::dynamic::Dict,
+};
+use tungstenite::{client::IntoClientRequest, http::header};
+
+const TOKEN: &str = "secret";
+const URL: &str = "ws://127.0.0.1:8080/tool";
+
+fn echo(input: Value, _: &mut MessageFn) -> Result<Value, ToolError> {
+    Ok(input)
+}
+
+/// Started by the first test, the port can't be bound twice
+fn server() {
+    static SERVER: OnceLock<ServerHandle> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let config = ServerConfig {
+            auth_token: Some(TOKEN.to_string()),
+            ..ServerConfig::default()
+        };
+        toolapi::spawn_server_with_config(echo, None, config).expect("port 8080 is free")
+    });
+}
+
+/// Status code of a `GET` of `path`
+fn get(path: &str, token: Option<&str>) -> u16 {
+    let mut stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+    let auth = token.map_or(String::new(), |token| {
+        format!("Authorization: Bearer {token}\r\n")
+    });
+    let request =
+        format!("GET {path} HTTP/1.1\r\nHost: 127.0.0.1\r\n{auth}Connection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split(' ').nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn requests_without_the_token_are_rejected() {
+    server();
+    assert_eq!(get("/info", None), 401);
+    assert_eq!(get("/info", Some("wrong")), 401);
+    assert_eq!(get("/info", Some(TOKEN)), 200);
+}
+
+#[test]
+fn calls_without_the_token_are_rejected() {
+    server();
+    let result = toolapi::call(URL, Value::Int(42), |_| true);
+    assert!(matches!(
+        result,
+        Err(ToolCallError::ConnectionError(
+            ConnectionError::Unauthorized { status: 401 }
+        ))
+    ));
+    let result = toolapi::call_with_auth(URL, "wrong", Value::Int(42), |_| true);
+    assert!(matches!(
+        result,
+        Err(ToolCallError::ConnectionError(
+            ConnectionError::Unauthorized { status: 401 }
+        ))
+    ));
+    let result = toolapi::call_with_auth(URL, TOKEN, Value::Int(42), |_| true);
+    assert!(matches!(result, Ok(Value::Int(42))));
+}
+
+/// The messages of the wire protocol that a cached input needs
+/// (see `src/protocol.d.ts`), with the hashes as raw bytes
+#[derive(Serialize, Deserialize)]
+enum Message {
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+    },
+    Output(Result<Value, ToolError>),
+    CachedInput {
+        input: Value,
+        refs: HashMap<String, ByteBuf>,
+    },
+    Missing(Vec<(ByteBuf, u64)>),
+    BlobChunk {
+        hash: ByteBuf,
+        offset: u64,
+        size: u64,
+        data: ByteBuf,
+    },
+}
+
+#[test]
+fn blobs_that_dont_match_their_hash_are_rejected() {
+    server();
+    let mut request = URL.into_client_request().unwrap();
+    let bearer = format!("Bearer {TOKEN}").parse().unwrap();
+    request.headers_mut().insert(header::AUTHORIZATION, bearer);
+    let (mut socket, _) = tungstenite::connect(request).unwrap();
+    let mut send = |msg: &Message| {
+        let frame = rmp_serde::to_vec(msg).unwrap();
+        socket.send(tungstenite::Message::binary(frame)).unwrap();
+    };
+
+    // Without the `canonical_hash` capability, blobs are hashed as uploaded
+    send(&Message::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        capabilities: vec!["input_cache".to_string()],
+    });
+    let hash = ByteBuf::from([7; 32]);
+    send(&Message::CachedInput {
+        input: Value::Dict(Dict(HashMap::new())),
+        refs: HashMap::from([("entry".to_string(), hash.clone())]),
+    });
+    let blob = rmp_serde::to_vec(&Value::Int(42)).unwrap();
+    send(&Message::BlobChunk {
+        hash,
+        offset: 0,
+        size: blob.len() as u64,
+        data: ByteBuf::from(blob),
+    });
+
+    let mut missing = None;
+    let output = loop {
+        let frame = socket.read().unwrap().into_data();
+        match rmp_serde::from_slice(&frame).unwrap() {
+            Message::Missing(blobs) => missing = Some(blobs),
+            Message::Output(output) => break output,
+            _ => {}
+        }
+    };
+    assert_eq!(missing.map(|blobs| blobs.len()), Some(1));
+    assert!(matches!(
+        output,
+        Err(ToolError::Custom(err)) if err.contains("blob doesn't match its hash")
+    ));
+}
@@ -0,0 +1,101 @@
+//! [`toolapi::ServerConfig::timeout`] and the [`toolapi::TIMEOUT_KEY`] entry
+//! with which clients shorten it.
+
+use std::time::{Duration, Instant};
+
+use toolapi::{
+    ServerConfig, TIMEOUT_KEY, ToolCallError, ToolCtx, ToolError, Value,
+    testing::{TestServer, spawn_test_server_with_config},
+    value::dynamic::Dict,
+};
+
+const SERVER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Waits until it is aborted if the input has a `wait` entry, returns the input otherwise
+fn tool(input: Value, ctx: &mut ToolCtx) -> Result<Value, ToolError> {
+    let Value::Dict(dict) = &input else {
+        return Ok(input);
+    };
+    if dict.0.contains_key("wait") {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(10) {
+            ctx.check_abort()?;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    Ok(input)
+}
+
+fn server() -> TestServer {
+    let config = ServerConfig {
+        timeout: Some(SERVER_TIMEOUT),
+        ..ServerConfig::default()
+    };
+    spawn_test_server_with_config(tool, config)
+}
+
+fn input(entries: impl IntoIterator<Item = (&'static str, Value)>) -> Value {
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value));
+    Value::Dict(Dict(entries.collect()))
+}
+
+/// Duration of a call that timed out
+fn timed_out(server: &TestServer, input: Value) -> Duration {
+    let started = Instant::now();
+    let result = server.client().call(input, |_| true);
+    assert!(matches!(
+        result,
+        Err(ToolCallError::ToolReturnedError(ToolError::Timeout { .. }))
+    ));
+    started.elapsed()
+}
+
+#[test]
+fn calls_time_out_after_the_server_timeout() {
+    let took = timed_out(&server(), input([("wait", Value::Bool(true))]));
+    assert!(took >= SERVER_TIMEOUT);
+}
+
+#[test]
+fn clients_shorten_the_timeout() {
+    let input = input([
+        ("wait", Value::Bool(true)),
+        (TIMEOUT_KEY, Value::Float(0.2)),
+    ]);
+    let took = timed_out(&server(), input);
+    assert!(took >= Duration::from_millis(200) && took < SERVER_TIMEOUT);
+}
+
+#[test]
+fn clients_cant_extend_the_timeout() {
+    let input = input([("wait", Value::Bool(true)), (TIMEOUT_KEY, Value::Int(10))]);
+    let took = timed_out(&server(), input);
+    assert!(took >= SERVER_TIMEOUT && took < Duration::from_secs(10));
+}
+
+#[test]
+fn invalid_timeouts_are_rejected() {
+    for timeout in [Value::Float(0.0), Value::Int(-1), Value::Str("soon".into())] {
+        let result = server()
+            .client()
+            .call(input([(TIMEOUT_KEY, timeout)]), |_| true);
+        assert!(matches!(
+            result,
+            Err(ToolCallError::ToolReturnedError(ToolError::InvalidInput { path, .. }))
+                if path == TIMEOUT_KEY
+        ));
+    }
+}
+
+#[test]
+fn tools_dont_get_the_timeout_entry() {
+    let result = server()
+        .client()
+        .call(input([(TIMEOUT_KEY, Value::Int(5))]), |_| true);
+    let Ok(Value::Dict(output)) = result else {
+        panic!("expected the input back");
+    };
+    assert!(!output.0.contains_key(TIMEOUT_KEY));
+}